@@ -51,6 +51,9 @@ impl<'a> InstanceBuilder<'a> {
     }
 }
 
+/// The log target of the vulkan validation messages.
+pub const LOG_TARGET: &str = "core_graphics::vulkan";
+
 /// The vulkan debug callback.
 unsafe extern "system" fn vulkan_debug_callback(
     message_severity: ash::vk::DebugUtilsMessageSeverityFlagsEXT,
@@ -73,12 +76,22 @@ unsafe extern "system" fn vulkan_debug_callback(
         std::ffi::CStr::from_ptr(callback_data.p_message).to_string_lossy()
     };
 
-    println!(
-        "{:?}:\n{:?} [{} ({})] : {}\n",
-        message_severity,
+    // Map the vulkan severity to the log level.
+    let level = if message_severity.contains(ash::vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if message_severity.contains(ash::vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else {
+        log::Level::Info
+    };
+
+    log::log!(
+        target: LOG_TARGET,
+        level,
+        "{:?} [{} ({})] : {}",
         message_type,
         message_id_name,
-        &message_id_number.to_string(),
+        message_id_number,
         message,
     );
 
@@ -1,70 +1,32 @@
-pub mod camera;
 pub mod voxel_octree;
 
-use std::sync::{Arc, Mutex};
-
-use camera::Camera;
+use nalgebra::Vector3;
 use voxel_engine::engine::{
-    renderer::{RendererTrait, BufferUsage},
+    camera::{Camera, CameraPlugin},
+    controllers::{CameraControllerPlugin, FlyController},
+    globals::globals_wgsl,
+    input::{Input, InputPlugin},
+    renderer::RendererTrait,
     renderers::wgpu_renderer::WGPURenderer, 
+    voxel::{
+        edit::VoxelEditor,
+        gpu::{chunks_wgsl, GpuChunkStore},
+        denoise::{gbuffer_wgsl, DenoiseSettings, Denoiser},
+        isosurface::{IsosurfaceMethod, IsosurfaceSettings},
+        material::{materials_wgsl, GpuMaterialPalette},
+        mesh_renderer::ChunkMesher,
+        plugin::{VoxelLightPlugin, VoxelMeshPlugin, VoxelPathTracePlugin, VoxelWorldPlugin},
+        raycast::{raycast, Ray},
+        region::RegionStorage,
+        shading::{shading_wgsl, ShadingSettings},
+        streaming::StreamingSettings,
+        terrain::{materials, TerrainGenerator, TerrainSettings},
+        world::VoxelWorld,
+    },
     Engine
 };
 use voxel_octree::Node;
-
-#[repr(C)]
-#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Default)]
-pub struct UniformScreenData {
-    width   : f32,
-    height  : f32,
-}
-
-#[repr(C)]
-#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Default)]
-pub struct UniformTimeData {
-    time        : f32,
-    delta_time  : f32,
-}
-
-#[repr(C)]
-#[derive(bytemuck::Pod, bytemuck::Zeroable, Clone, Copy, Default)]
-pub struct InData {
-    inv_proj_view_matrix: nalgebra::Matrix4<f32>,
-    screen_data: nalgebra::Vector2<f32>,
-    time_data: UniformTimeData,
-    near: f32,
-    far: f32,
-
-    // Extra padding of 8bytes to align with
-    // GPU memory...
-    _padding: [u8; 8],
-}
-
-impl InData {
-    pub fn new(size: (u32, u32), near: f32, far: f32) -> Self {
-        let width   = size.0 as f32;
-        let height  = size.1 as f32;
-
-        let mut camera = Camera::new(width, height, 0.1, 1000.0, 45.0);
-        camera.translate(&nalgebra_glm::vec3(1f32, 1.0, -15.0));
-
-        Self {
-            screen_data: nalgebra_glm::Vec2::new(width, height),//UniformScreenData { width, height },
-            time_data: UniformTimeData::default(),
-            inv_proj_view_matrix: camera.get_proj_view_matrix().try_inverse().unwrap(),
-            near,
-            far,
-            ..Default::default()
-        }
-    }
-
-    pub fn update_proj_view_matrix(&mut self, camera: &Camera) {
-        self.inv_proj_view_matrix = camera.get_proj_view_matrix().try_inverse().unwrap();
-    }
-
-    pub fn add_delta(&mut self, d: f32) {
-        self.time_data.time += d;
-    }
-}
+use winit::event::{MouseButton, VirtualKeyCode};
 
 fn main() {
     let mut node = Node::new((0.0, 0.0, 0.0), 4.0);
@@ -72,95 +34,181 @@ fn main() {
     println!("Point is added !");
     println!("{:#?}", node);
 
-    // const WGPU_ALIGNMENT: usize = 8;
-    // const WGPU_ALIGNMENT_MASK: usize = WGPU_ALIGNMENT - 1;
-
-    // let buffer_size = std::mem::size_of::<Test>();
-    // let buffer_padded_size = ((buffer_size + WGPU_ALIGNMENT_MASK) & !WGPU_ALIGNMENT_MASK).max(WGPU_ALIGNMENT);
-
-    // println!("additional bytes for Test is {} bytes", buffer_padded_size - buffer_size);
-
     let mut engine = Engine::<WGPURenderer>::new();
 
+    // The camera is stored as an engine resource, the
+    // camera plugin keep its aspect ratio in sync with
+    // the window size.
     let camera = engine.with_renderer_ref(|renderer| {
         let near = 0.1f32;
         let far  = 1000.0f32;
         let (width, height) = renderer.get_size();
 
-        let mut camera = Camera::new(width as f32, height as f32, near, far, 45.0);
-        camera.translate(&nalgebra_glm::vec3(1f32, 1.0, -15.0));
-
-        // Create an arc mutex for the camera because
-        // it will be potentialy shared across different
-        // threads.
-        Arc::new(Mutex::new(camera))
+        let mut camera = Camera::perspective(width as f32, height as f32, near, far, 45.0);
+        camera.set_position(Vector3::new(1.0, 32.0, -15.0));
+        camera
     });
 
-    // Used to calculate time and delta time.
-    let mut last_time = std::time::Instant::now();
-
-    // Initialize our uniform data.
-    let mut uniform_data = engine.with_renderer_ref(|renderer| {
-        let camera = camera.lock().unwrap();
-        InData::new(renderer.get_size(), camera.get_near(), camera.get_far())
+    // The chunks are generated around the camera by the
+    // terrain generator (on the chunk streaming threads), the
+    // modified chunks are saved in region files and loaded
+    // from them the next time.
+    let terrain = TerrainGenerator::new(TerrainSettings {
+        seed: 42,
+        ..Default::default()
     });
 
-    // Create the uniform buffer and the compute pipeline.
-    let (uniform_buffer, pipeline) = engine.with_renderer_mut(|renderer| {
-        // Read and compile the wgsl shader.
-        let shader = renderer.compile_shader(include_str!("shaders/test.wgsl"));
-
-        // Create the compute pipeline that will use the shader
-        // created above.
-        let pipeline = renderer.create_compute_pipeline(shader,None);
+    let storage = RegionStorage::new("saves/world")
+        .expect("Failed to open the world directory")
+        .with_fallback(terrain);
+
+    // Fly around with WASD, Space/Left Shift to go up/down
+    // and the mouse (right button held) to look around.
+    engine
+        .add_plugin(InputPlugin)
+        .add_plugin(CameraPlugin::new(camera))
+        .add_plugin(CameraControllerPlugin::new(FlyController::default()))
+        .add_plugin(VoxelWorldPlugin::new(storage, StreamingSettings::default()).with_palette(materials::palette()))
+        .add_plugin(VoxelLightPlugin::new());
+
+    // The keys 1 to 4 switch the quality of the shadows and of the
+    // ambient occlusion, uploaded with the engine globals.
+    engine.with_resources_mut(|resources| resources.insert(ShadingSettings::default()));
+
+    engine.add_update_system(|resources| {
+        let Some(input) = resources.get::<Input>() else { return };
+
+        let presets = [
+            (VirtualKeyCode::Key1, ShadingSettings::disabled()),
+            (VirtualKeyCode::Key2, ShadingSettings::low()),
+            (VirtualKeyCode::Key3, ShadingSettings::medium()),
+            (VirtualKeyCode::Key4, ShadingSettings::high()),
+        ];
+
+        if let Some((_, settings)) = presets.into_iter().find(|(key, _)| input.is_key_pressed(*key)) {
+            resources.insert(settings);
+        }
+    });
 
-        // Create the uniform buffer that will use the pipeline
-        // created above.
-        let data = [0i32];
-        let uniform_buffer = renderer.create_buffer_with_data(&uniform_data, BufferUsage::UNIFORM, true);
-        let octree_buffer = renderer.create_buffer_with_data(&data, BufferUsage::STORAGE, true);
+    // Left click removes the voxel under the cursor, middle
+    // click places a stone voxel against it, Z/Y undo/redo.
+    engine.add_render_system(|resources, renderer| {
+        let (width, height) = renderer.get_size();
 
-        renderer.set_binding_data(pipeline, 1, &[uniform_buffer, octree_buffer]);
+        let Some(input) = resources.get::<Input>() else { return };
+        let Some(camera) = resources.get::<Camera>() else { return };
+
+        let remove = input.is_button_pressed(MouseButton::Left);
+        let place = input.is_button_pressed(MouseButton::Middle);
+        let undo = input.is_key_pressed(VirtualKeyCode::Z);
+        let redo = input.is_key_pressed(VirtualKeyCode::Y);
+        let ray = Ray::from_screen(camera, input.cursor_position(), (width as f32, height as f32));
+
+        let Some(mut editor) = resources.remove::<VoxelEditor>() else { return };
+
+        if let Some(world) = resources.get_mut::<VoxelWorld>() {
+            if let Some(hit) = raycast(world, &ray, 64.0).filter(|_| remove || place) {
+                if remove {
+                    editor.clear_voxel(world, hit.voxel.x, hit.voxel.y, hit.voxel.z);
+                } else {
+                    editor.set_voxel(world, hit.adjacent.x, hit.adjacent.y, hit.adjacent.z, materials::STONE);
+                }
+            }
+
+            if undo {
+                editor.undo(world);
+            } else if redo {
+                editor.redo(world);
+            }
+        }
 
-        (uniform_buffer, pipeline)
+        resources.insert(editor);
     });
 
-    let camera_one = camera.clone();
-    let camera_two = camera.clone();
+    // With `--raster` the chunks are meshed and drawn with
+    // triangles, instead of being raymarched by the compute
+    // shader below. With `--smooth` the surface of the voxels
+    // is smoothed.
+    let args = std::env::args().collect::<Vec<_>>();
+
+    if args.iter().any(|arg| arg == "--raster" || arg == "--smooth") {
+        let mesher = if args.iter().any(|arg| arg == "--smooth") {
+            ChunkMesher::Isosurface(IsosurfaceSettings {
+                method      : IsosurfaceMethod::MarchingCubes,
+                smoothing   : 2,
+            })
+        } else {
+            ChunkMesher::Greedy
+        };
+
+        engine.add_plugin(VoxelMeshPlugin::new().with_mesher(mesher));
+        engine.run();
+        return;
+    }
 
-    engine.set_on_update_callback(move || {
-        // This callback is called once each frame.
-        // So in this callback you must put all your
-        // game logic.
-        
-        let dt = last_time.elapsed();
-        last_time = std::time::Instant::now();
+    // With `--path-trace` the voxels are rendered by a progressive
+    // path tracer, the image converges while the camera is still.
+    if args.iter().any(|arg| arg == "--path-trace") {
+        engine.add_plugin(VoxelPathTracePlugin::new());
+        engine.run();
+        return;
+    }
 
-        uniform_data.add_delta(dt.as_secs_f32());
-        uniform_data.time_data.delta_time = dt.as_secs_f32();
+    // Create the compute pipeline.
+    let pipeline = engine.with_renderer_mut(|renderer| {
+        // Read and compile the wgsl shader, the engine globals
+        // (camera, time...), the G-buffer, the chunk accessors, the
+        // materials and the shadow rays are declared before the
+        // shader code.
+        let source = globals_wgsl() + &gbuffer_wgsl(0) + &chunks_wgsl(1) + &materials_wgsl(2) + &shading_wgsl()
+                   + include_str!("shaders/test.wgsl");
+        let shader = renderer.compile_shader(source);
 
-        let mut camera = camera_one.lock().unwrap();
-        let r = 2.0f32;
-        let t = uniform_data.time_data.time;
+        // Create the compute pipeline that will use the shader
+        // created above.
+        renderer.create_compute_pipeline(shader, None)
+    });
 
-        camera.set_position(&nalgebra_glm::Vec3::new(1f32 + t.cos() * r, 1f32 + t.sin() * r, -10.0));
+    // The pipeline writes a noisy G-buffer, the denoiser dispatches
+    // it then reprojects and filters it into the render texture.
+    let mut denoiser = engine.with_renderer_mut(|renderer| {
+        let mut denoiser = Denoiser::new(renderer, DenoiseSettings::default());
+        denoiser.add_source(renderer, pipeline);
+        denoiser
     });
 
-    engine.set_on_render_callback(move |renderer| {
-        // This callback is called on each frame after
-        // the update callback (so after the game logic)
-        // and after rendering operations. So you
-        // can interact with the renderer here.
+    // The chunks and the materials are uploaded to the GPU by the
+    // voxel world plugin, bind its buffers once it is started.
+    engine.add_startup_system(move |resources, renderer| {
+        let store = resources.get::<GpuChunkStore>().unwrap();
+        renderer.set_binding_data(pipeline, 1, &store.buffers());
 
-        let camera = camera_two.lock().unwrap();
-        uniform_data.inv_proj_view_matrix = camera.get_proj_view_matrix().try_inverse().unwrap();
+        let materials = resources.get::<GpuMaterialPalette>().unwrap();
+        renderer.set_binding_data(pipeline, 2, &materials.buffers());
+    });
 
-        renderer.update_buffer(uniform_buffer, &uniform_data, 0);
+    engine.add_render_system(move |resources, renderer| {
+        // This system is called on each frame after the
+        // update systems (so after the game logic) and
+        // after rendering operations. So you can interact
+        // with the renderer here.
+
+        // The key N switches the denoising on and off.
+        if resources.get::<Input>().is_some_and(|input| input.is_key_pressed(VirtualKeyCode::N)) {
+            let settings = if denoiser.settings() == DenoiseSettings::disabled() {
+                DenoiseSettings::default()
+            } else {
+                DenoiseSettings::disabled()
+            };
+
+            denoiser.set_settings(settings);
+        }
 
-        // Execute the compute shader each time we render a frame.
-        renderer.dispatch_post_process_compute_pipeline(pipeline, (8, 8, 1));
+        // Execute the compute shader and the denoising passes
+        // each time we render a frame.
+        denoiser.render(renderer, resources.get::<Camera>());
     });
 
     // Run the engine.
     engine.run();
-}
\ No newline at end of file
+}
@@ -1,89 +1,181 @@
-@group(0) @binding(0)
-var render_texture : texture_storage_2d<rgba8unorm, write>;
+// The `globals` uniform (camera, resolution, time...), the
+// `write_gbuffer` function, the `voxel_at`/`mip_at`/`light_at`
+// functions, the `material_at`/`shade_material` functions and the
+// `sun_shadow`/`ambient_occlusion` functions are declared by the
+// engine, see `globals_wgsl()`, `gbuffer_wgsl()`, `chunks_wgsl()`,
+// `materials_wgsl()` and `shading_wgsl()`. The G-buffer is then
+// denoised into the render texture.
 
-struct InData {
-    inv_proj_view_matrix : mat4x4<f32>,
-    screen_size          : vec2<f32>,
-    time                 : f32,
-    delta_time           : f32,
-    near                 : f32,
-    far                  : f32,
-};
+// A level of detail is drawn once its cells cover less than
+// `LOD_PIXELS` pixels, a larger value draws the coarse levels
+// closer to the camera.
+let LOD_PIXELS: f32 = 4.0;
 
-@group(1) @binding(0)
-var<uniform> in_data: InData;
+// The occupancy from which a cell is solid.
+let LOD_THRESHOLD: f32 = 0.5;
 
-@group(1) @binding(1)
-var<storage, read_write> octree_data: array<i32>;
+let SUN_RADIANCE: vec3<f32>  = vec3<f32>(3.0, 2.9, 2.7);
+let AMBIENT: vec3<f32>       = vec3<f32>(0.12, 0.14, 0.18);
+let BLOCK_LIGHT: vec3<f32>   = vec3<f32>(1.0, 0.75, 0.45);
+let SKY_COLOR: vec3<f32>     = vec3<f32>(0.5, 0.7, 1.0);
 
-let MAX_RAY_STEPS: i32 = 128;
+// Get the albedo and the occupancy of the cell of a level that
+// contains a voxel, the level `0` is the voxels.
+fn sample_level(voxel: vec3<i32>, level: u32) -> vec4<f32> {
+    if (level == 0u) {
+        let value = voxel_at(voxel);
+
+        if (value == 0u) {
+            return vec4<f32>(0.0);
+        }
+
+        return vec4<f32>(material_at(value).albedo, 1.0);
+    }
+
+    return mip_at(voxel, level);
+}
+
+// Get the point of the screen (from -1 to 1) at a depth of the
+// camera (from -1 at the near plane to 1 at the far plane).
+fn unproject(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let position = globals.inv_proj_view_matrix * vec4<f32>(uv, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// Get the direction of the ray of a point of the screen (from -1 to 1).
+fn screen_ray_dir(uv: vec2<f32>) -> vec3<f32> {
+    return normalize(unproject(uv, 1.0) - unproject(uv, -1.0));
+}
 
 @compute
 @workgroup_size(8, 8)
 fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
-    var uv: vec2<f32> = (vec2<f32>(id.xy) / in_data.screen_size) * 2.0 - 1.0;
+    // The rays go through the center of the pixels, from the near
+    // plane of the camera.
+    var uv: vec2<f32> = ((vec2<f32>(id.xy) + 0.5) / globals.resolution) * 2.0 - 1.0;
     uv.y = 0.0 - uv.y;
 
-    let ray_pos: vec3<f32> = (in_data.inv_proj_view_matrix * vec4<f32>(uv, 2.0, 1.0) * in_data.near).xyz;
-    let ray_dir: vec3<f32> = (in_data.inv_proj_view_matrix * vec4<f32>(uv * (in_data.far - in_data.near), in_data.far + in_data.near, in_data.far - in_data.near)).xyz;
+    let ray_pos: vec3<f32> = unproject(uv, -1.0);
+    let ray_dir: vec3<f32> = screen_ray_dir(uv);
 
-    let ray_step: vec3<i32>   = vec3<i32>(sign(ray_dir));
-    let delta_dist: vec3<f32> = 1.0 / abs(ray_dir);
+    // The width of a pixel at a distance of 1, from the ray of
+    // the next pixel.
+    let pixel_size: f32 = length(screen_ray_dir(uv + vec2<f32>(2.0 / globals.resolution.x, 0.0)) - ray_dir);
 
-    var map_pos: vec3<i32>   = vec3<i32>(floor(ray_pos + 0.));
-    var side_dist: vec3<f32> = (sign(ray_dir) * (vec3<f32>(map_pos) - ray_pos) + (sign(ray_dir) * 0.5) + 0.5) * delta_dist;
+    // The random numbers of the shadow and ambient occlusion rays.
+    var seed: u32            = random_seed(id.xy, globals.frame);
+
+    var distance: f32        = 0.0;
+    var normal: vec3<f32>    = vec3<f32>(0.0);
     var color: vec3<f32>     = vec3<f32>(0.0, 0.0, 0.0);
-    var mask: vec3<i32>      = vec3<i32>(0, 0, 0);
-    
-    let x = octree_data[0];
-    for(var i: i32 = 0; i < MAX_RAY_STEPS; i++) {
-        if (
-            (map_pos.x ==  2 && map_pos.y ==  0 && map_pos.z == 0) ||
-            (map_pos.x == -2 && map_pos.y ==  0 && map_pos.z == 0) ||
-            (map_pos.x ==  0 && map_pos.y ==  2 && map_pos.z == 0) ||
-            (map_pos.x ==  0 && map_pos.y == -2 && map_pos.z == 0) ||
-            (map_pos.x ==  0 && map_pos.y ==  0 && map_pos.z == 0)
-        )
-        {
-            let d: f32 = length(vec3<f32>(mask) * (side_dist - delta_dist));
-            let dst: vec3<f32> = ray_pos + ray_dir * d;
-            
-            color = (dst - vec3<f32>(map_pos));
-
-            // if (color.z <= 0.000001) {
-            //     color = vec3<f32>(1.0, 0.0, 0.0);
-            // }
-
-            // if (color.x <= 0.000001) {
-            //     color = vec3<f32>(0.0, 1.0, 0.0);
-            // }
-            
-            textureStore(render_texture, vec2<i32>(id.xy), vec4<f32>(color, 1.0));
-            return;
+
+    // The light still going through the transparent voxels hit
+    // so far, and the last voxel hit (to only blend a block of
+    // the same transparent voxels once).
+    var throughput: f32      = 1.0;
+    var last_voxel: u32      = 0u;
+
+    // The first surface hit, that guides the denoiser (the sky
+    // has no normal).
+    var surface_position     = ray_pos + ray_dir * globals.far;
+    var surface_normal       = vec3<f32>(0.0);
+    var surface_material     = 0u;
+
+    for(var i: u32 = 0u; i < globals.shading.max_ray_steps && distance < globals.far; i++) {
+        // Sample a bit ahead, so a point on a face belongs to the
+        // cell behind it.
+        let voxel = vec3<i32>(floor(ray_pos + ray_dir * (distance + 1e-3)));
+
+        // The level of detail, fractional so the transitions are
+        // continuous: the cells are blended with their parent.
+        let lod = clamp(log2(max(distance * pixel_size / LOD_PIXELS, 1e-6)), 0.0, f32(LOD_LEVELS));
+        let level = min(u32(lod), LOD_LEVELS);
+        let blend = lod - f32(level);
+
+        // Skip the largest empty node of the octree that contains
+        // the voxel, without visiting its cells.
+        var cell_level = 0u;
+
+        for (var node = LOD_LEVELS; node >= max(level, 1u); node--) {
+            if (mip_at(voxel, node).w == 0.0) {
+                cell_level = node;
+                break;
+            }
         }
 
-        if (side_dist.x <= side_dist.y) {
-            if (side_dist.x <= side_dist.z) {
-                side_dist.x += delta_dist.x;
-                map_pos.x += ray_step.x;
-                mask = vec3<i32>(1, 0, 0);
-            } else {
-                side_dist.z += delta_dist.z;
-                map_pos.z += ray_step.z;
-                mask = vec3<i32>(0, 0, 1);
+        if (cell_level == 0u) {
+            cell_level = level;
+
+            var sample = sample_level(voxel, level);
+
+            if (level < LOD_LEVELS) {
+                sample = mix(sample, mip_at(voxel, level + 1u), blend);
             }
-        } else {
-            if (side_dist.y <= side_dist.z) {
-                side_dist.y += delta_dist.y;
-                map_pos.y += ray_step.y;
-                mask = vec3<i32>(0, 1, 0);
-            } else {
-                side_dist.z += delta_dist.z;
-                map_pos.z += ray_step.z;
-                mask = vec3<i32>(0, 0, 1);
+
+            // A voxel of the same transparent block as the last one
+            // isn't a new surface.
+            let value = select(0u, voxel_at(voxel), level == 0u);
+
+            if (sample.w >= LOD_THRESHOLD && (value == 0u || value != last_voxel)) {
+                var material = Material(sample.xyz, 1.0, vec3<f32>(0.0), 0.0, 0.0);
+
+                if (value != 0u) {
+                    material = material_at(value);
+                    material.albedo = sample.xyz;
+                }
+
+                if (throughput == 1.0) {
+                    surface_position = ray_pos + ray_dir * distance;
+                    surface_normal = normal;
+                    surface_material = value;
+                }
+
+                // The light of the empty voxel in front of the face, the
+                // sun only lights the voxels under the sky.
+                let light = light_at(vec3<i32>(floor(ray_pos + ray_dir * (distance - 1e-3))));
+
+                // The secondary rays start in front of the cell, a
+                // coarse cell is larger than a voxel.
+                let position = ray_pos + ray_dir * distance + normal * f32((1 << cell_level) - 1);
+                let shadow = sun_shadow(position, normal, &seed);
+                let occlusion = ambient_occlusion(position, normal, &seed);
+
+                let shaded = shade_material(material, normal, -ray_dir, normalize(globals.shading.sun_direction), SUN_RADIANCE * light.x * light.x * shadow)
+                           + material.albedo * (AMBIENT * light.x + BLOCK_LIGHT * light.y * light.y) * occlusion;
+
+                color += shaded * (1.0 - material.transparency) * throughput;
+                throughput *= material.transparency;
+
+                if (throughput <= 0.01) {
+                    write_gbuffer(id.xy, color, surface_position, surface_normal, surface_material);
+                    return;
+                }
             }
+
+            last_voxel = value;
+        } else {
+            last_voxel = 0u;
+        }
+
+        // Move to the face where the ray exits the cell.
+        let cell_size = f32(1 << cell_level);
+        let cell_min = vec3<f32>((voxel >> vec3<u32>(cell_level)) << vec3<u32>(cell_level));
+        let bounds = cell_min + select(vec3<f32>(0.0), vec3<f32>(cell_size), ray_dir > vec3<f32>(0.0));
+        let exits = select(vec3<f32>(1e30), (bounds - ray_pos) / ray_dir, ray_dir != vec3<f32>(0.0));
+
+        if (exits.x <= exits.y && exits.x <= exits.z) {
+            distance = exits.x;
+            normal = vec3<f32>(-sign(ray_dir.x), 0.0, 0.0);
+        } else if (exits.y <= exits.z) {
+            distance = exits.y;
+            normal = vec3<f32>(0.0, -sign(ray_dir.y), 0.0);
+        } else {
+            distance = exits.z;
+            normal = vec3<f32>(0.0, 0.0, -sign(ray_dir.z));
         }
     }
 
-    textureStore(render_texture, vec2<i32>(id.xy), vec4<f32>(color, 1.0));
-}
\ No newline at end of file
+    color += SKY_COLOR * throughput;
+
+    write_gbuffer(id.xy, color, surface_position, surface_normal, surface_material);
+}
@@ -29,10 +29,8 @@ impl Node {
 
         let mut childs = Box::new(self.subdivide());
 
-        for child in childs.as_mut() {
-            if let Some(child) = child {
-                child.add_point(point);
-            }
+        for child in childs.iter_mut().flatten() {
+            child.add_point(point);
         }
 
         self.childs = childs;
@@ -0,0 +1,78 @@
+use nalgebra::Vector3;
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    /// Create a new [Aabb].
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - The minimum corner.
+    /// * `max` - The maximum corner.
+    ///
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    /// Create a new [Aabb] from its center and its half size.
+    pub fn from_center(center: Vector3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self { min: center - half_extents, max: center + half_extents }
+    }
+
+    /// Get the center of the box.
+    pub fn center(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Get the half size of the box.
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (self.max - self.min) * 0.5
+    }
+
+    /// Check if a point is inside the box.
+    pub fn contains(&self, point: &Vector3<f32>) -> bool {
+        point.x >= self.min.x && point.y >= self.min.y && point.z >= self.min.z &&
+        point.x <= self.max.x && point.y <= self.max.y && point.z <= self.max.z
+    }
+
+    /// Check if two boxes overlap (touching boxes don't overlap).
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x < other.max.x && self.max.x > other.min.x &&
+        self.min.y < other.max.y && self.max.y > other.min.y &&
+        self.min.z < other.max.z && self.max.z > other.min.z
+    }
+
+    /// Get the box moved by `offset`.
+    pub fn translated(&self, offset: &Vector3<f32>) -> Aabb {
+        Aabb { min: self.min + offset, max: self.max + offset }
+    }
+
+    /// Get the smallest box that contains both boxes.
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb { min: self.min.inf(&other.min), max: self.max.sup(&other.max) }
+    }
+
+    /// Get the smallest sphere that contains the box.
+    pub fn bounding_sphere(&self) -> Sphere {
+        Sphere { center: self.center(), radius: self.half_extents().norm() }
+    }
+}
+
+/// A sphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+    pub center: Vector3<f32>,
+    pub radius: f32,
+}
+
+impl Sphere {
+    /// Create a new [Sphere].
+    pub fn new(center: Vector3<f32>, radius: f32) -> Self {
+        Self { center, radius }
+    }
+}
@@ -0,0 +1,347 @@
+use nalgebra::{Isometry3, Matrix4, Translation3, UnitQuaternion, Vector3};
+use winit::event::WindowEvent;
+
+use crate::engine::{
+    plugin::{Plugin, Resources, Systems},
+    renderer::RendererTrait,
+};
+
+/// The maximum pitch angle (in radians), slightly less than 90°
+/// to avoid the view flipping when looking straight up or down.
+pub(crate) const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.001;
+
+/// The projection of a [Camera].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// A perspective projection.
+    Perspective {
+        /// The vertical field of view **in degrees**.
+        fov : f32,
+        near: f32,
+        far : f32,
+    },
+    /// An orthographic projection.
+    Orthographic {
+        /// The height of the view volume in world units, the
+        /// width is computed from the aspect ratio.
+        height  : f32,
+        near    : f32,
+        far     : f32,
+    },
+}
+
+impl Projection {
+    /// Get the near plane distance.
+    pub fn near(&self) -> f32 {
+        match self {
+            Projection::Perspective { near, .. } | Projection::Orthographic { near, .. } => *near,
+        }
+    }
+
+    /// Get the far plane distance.
+    pub fn far(&self) -> f32 {
+        match self {
+            Projection::Perspective { far, .. } | Projection::Orthographic { far, .. } => *far,
+        }
+    }
+}
+
+/// A camera that use a left handed coordinate system: `+X` is
+/// the right, `+Y` is up and the camera look toward `+Z`.
+///
+/// The projection matrices map the depth in the `[-1, 1]` range,
+/// as expected by the raymarcher shaders.
+#[derive(Clone, Debug)]
+pub struct Camera {
+    /// The camera position in world space.
+    position    : Vector3<f32>,
+    /// The camera orientation in world space.
+    orientation : UnitQuaternion<f32>,
+    /// The camera projection.
+    projection  : Projection,
+    /// The width / height ratio of the viewport.
+    aspect      : f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::perspective(1.0, 1.0, 0.1, 1000.0, 45.0)
+    }
+}
+
+impl Camera {
+    /// Create a new [Camera] with a perspective projection.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`   - The screen width.
+    /// * `height`  - The screen height.
+    /// * `near`    - The camera near.
+    /// * `far`     - The camera far.
+    /// * `fov`     - The camera vertical fov **in degrees**
+    ///
+    pub fn perspective(width: f32, height: f32, near: f32, far: f32, fov: f32) -> Self {
+        Self::new(width, height, Projection::Perspective { fov, near, far })
+    }
+
+    /// Create a new [Camera] with an orthographic projection.
+    ///
+    /// # Arguments
+    ///
+    /// * `width`       - The screen width.
+    /// * `height`      - The screen height.
+    /// * `near`        - The camera near.
+    /// * `far`         - The camera far.
+    /// * `view_height` - The height of the view volume in world units.
+    ///
+    pub fn orthographic(width: f32, height: f32, near: f32, far: f32, view_height: f32) -> Self {
+        Self::new(width, height, Projection::Orthographic { height: view_height, near, far })
+    }
+
+    /// Create a new [Camera].
+    ///
+    /// # Arguments
+    ///
+    /// * `width`       - The screen width.
+    /// * `height`      - The screen height.
+    /// * `projection`  - The camera projection.
+    ///
+    pub fn new(width: f32, height: f32, projection: Projection) -> Self {
+        Self {
+            position    : Vector3::zeros(),
+            orientation : UnitQuaternion::identity(),
+            projection,
+            aspect      : aspect_ratio(width, height),
+        }
+    }
+
+    /// Update the aspect ratio of the projection, must be called
+    /// when the screen is resized (the [CameraPlugin] do it).
+    ///
+    /// # Arguments
+    ///
+    /// * `width`   - The new screen width.
+    /// * `height`  - The new screen height.
+    ///
+    pub fn resize(&mut self, width: f32, height: f32) {
+        if width > 0.0 && height > 0.0 {
+            self.aspect = aspect_ratio(width, height);
+        }
+    }
+
+    /// Get the camera projection.
+    pub fn projection(&self) -> &Projection {
+        &self.projection
+    }
+
+    /// Set the camera projection.
+    pub fn set_projection(&mut self, projection: Projection) {
+        self.projection = projection;
+    }
+
+    /// Get the aspect ratio (width / height).
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    /// Get the camera near.
+    pub fn near(&self) -> f32 {
+        self.projection.near()
+    }
+
+    /// Get the camera far.
+    pub fn far(&self) -> f32 {
+        self.projection.far()
+    }
+
+    /// Get the camera position.
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    /// Set the camera position.
+    ///
+    /// # Arguments
+    ///
+    /// * `position` - The new camera position.
+    ///
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.position = position;
+    }
+
+    /// Translate the camera in world space.
+    pub fn translate(&mut self, v: Vector3<f32>) {
+        self.position += v;
+    }
+
+    /// Translate the camera relative to its orientation (`+X`
+    /// move to the right, `+Z` move forward).
+    pub fn translate_local(&mut self, v: Vector3<f32>) {
+        self.position += self.orientation * v;
+    }
+
+    /// Get the camera orientation.
+    pub fn orientation(&self) -> UnitQuaternion<f32> {
+        self.orientation
+    }
+
+    /// Set the camera orientation.
+    pub fn set_orientation(&mut self, orientation: UnitQuaternion<f32>) {
+        self.orientation = orientation;
+    }
+
+    /// Get the direction the camera look at.
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation * Vector3::z()
+    }
+
+    /// Get the right direction of the camera.
+    pub fn right(&self) -> Vector3<f32> {
+        self.orientation * Vector3::x()
+    }
+
+    /// Get the up direction of the camera.
+    pub fn up(&self) -> Vector3<f32> {
+        self.orientation * Vector3::y()
+    }
+
+    /// Rotate the camera so it look at `target`.
+    ///
+    /// # Arguments
+    ///
+    /// * `target`  - The point to look at.
+    /// * `up`      - The world up direction (usually `+Y`).
+    ///
+    pub fn look_at(&mut self, target: Vector3<f32>, up: Vector3<f32>) {
+        let direction = target - self.position;
+
+        if direction.norm_squared() > f32::EPSILON {
+            self.orientation = UnitQuaternion::face_towards(&direction, &up);
+        }
+    }
+
+    /// Get the yaw angle (rotation around `+Y`) in radians, a
+    /// positive yaw turn the camera toward `+X`.
+    pub fn yaw(&self) -> f32 {
+        let forward = self.forward();
+        forward.x.atan2(forward.z)
+    }
+
+    /// Get the pitch angle in radians, a positive pitch make
+    /// the camera look up.
+    pub fn pitch(&self) -> f32 {
+        self.forward().y.clamp(-1.0, 1.0).asin()
+    }
+
+    /// Set the camera orientation from yaw and pitch angles
+    /// (without roll). The pitch is clamped to avoid flipping.
+    ///
+    /// # Arguments
+    ///
+    /// * `yaw`     - The yaw angle in radians.
+    /// * `pitch`   - The pitch angle in radians.
+    ///
+    pub fn set_yaw_pitch(&mut self, yaw: f32, pitch: f32) {
+        let pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+
+        self.orientation = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), yaw)
+                         * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -pitch);
+    }
+
+    /// Add angles to the current yaw and pitch.
+    pub fn rotate_yaw_pitch(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        self.set_yaw_pitch(self.yaw() + delta_yaw, self.pitch() + delta_pitch);
+    }
+
+    /// Compute the view matrix (world space to camera space).
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Isometry3::from_parts(Translation3::from(self.position), self.orientation)
+            .inverse()
+            .to_homogeneous()
+    }
+
+    /// Compute the projection matrix (camera space to clip space).
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        match self.projection {
+            Projection::Perspective { fov, near, far } => {
+                let h = 1.0 / (fov.to_radians() * 0.5).tan();
+                let w = h / self.aspect;
+
+                let mut m = Matrix4::zeros();
+                m[(0, 0)] = w;
+                m[(1, 1)] = h;
+                m[(2, 2)] = (far + near) / (far - near);
+                m[(2, 3)] = -(2.0 * far * near) / (far - near);
+                m[(3, 2)] = 1.0;
+                m
+            },
+
+            Projection::Orthographic { height, near, far } => {
+                let width = height * self.aspect;
+
+                let mut m = Matrix4::identity();
+                m[(0, 0)] = 2.0 / width;
+                m[(1, 1)] = 2.0 / height;
+                m[(2, 2)] = 2.0 / (far - near);
+                m[(2, 3)] = -(far + near) / (far - near);
+                m
+            },
+        }
+    }
+
+    /// Compute the projection view matrix (world space to clip space).
+    pub fn proj_view_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+
+    /// Compute the inverse of the projection view matrix (clip
+    /// space to world space), used to generate the rays.
+    pub fn inv_proj_view_matrix(&self) -> Matrix4<f32> {
+        // The matrix is invertible as long as the near and far
+        // planes are distinct and the fov/height is not zero.
+        self.proj_view_matrix()
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+    }
+}
+
+fn aspect_ratio(width: f32, height: f32) -> f32 {
+    if height > 0.0 { width / height } else { 1.0 }
+}
+
+/// A plugin that insert a [Camera] resource and keep its
+/// aspect ratio in sync with the window size.
+#[derive(Default)]
+pub struct CameraPlugin {
+    camera: Option<Camera>,
+}
+
+impl CameraPlugin {
+    /// Create a new [CameraPlugin].
+    ///
+    /// # Arguments
+    ///
+    /// * `camera` - The camera inserted as resource.
+    ///
+    pub fn new(camera: Camera) -> Self {
+        Self { camera: Some(camera) }
+    }
+}
+
+impl<R: RendererTrait + 'static> Plugin<R> for CameraPlugin {
+    fn build(&mut self, _systems: &mut Systems<R>, resources: &mut Resources) {
+        resources.insert(self.camera.take().unwrap_or_default());
+    }
+
+    fn on_window_event(&mut self, event: &WindowEvent, resources: &mut Resources) {
+        let size = match event {
+            WindowEvent::Resized(size) => *size,
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => **new_inner_size,
+            _ => return,
+        };
+
+        if let Some(camera) = resources.get_mut::<Camera>() {
+            camera.resize(size.width as f32, size.height as f32);
+        }
+    }
+}
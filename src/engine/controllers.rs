@@ -0,0 +1,405 @@
+use std::marker::PhantomData;
+
+use nalgebra::Vector3;
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::engine::{
+    camera::{Camera, MAX_PITCH},
+    input::Input,
+    plugin::{Plugin, Resources, Systems},
+    renderer::RendererTrait,
+    time::{Time, DEFAULT_FIXED_DELTA, MAX_FIXED_STEPS},
+    voxel::{
+        character::{CharacterController, CharacterSettings},
+        VoxelQuery,
+    },
+};
+
+/// The settings shared by the camera controllers.
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerSettings {
+    /// The move speed in world units per second.
+    pub speed           : f32,
+    /// The speed multiplier applied while `Left Control` is held.
+    pub fast_multiplier : f32,
+    /// The rotation in radians per pixel of mouse motion.
+    pub sensitivity     : f32,
+    /// The time in seconds needed to reach ~63% of the target
+    /// velocity/rotation, `0.0` disable the smoothing.
+    pub smoothing       : f32,
+    /// The button that must be held to rotate the camera with
+    /// the mouse, `None` to always rotate.
+    pub look_button     : Option<MouseButton>,
+}
+
+impl Default for ControllerSettings {
+    fn default() -> Self {
+        Self {
+            speed           : 5.0,
+            fast_multiplier : 4.0,
+            sensitivity     : 0.003,
+            smoothing       : 0.08,
+            look_button     : Some(MouseButton::Right),
+        }
+    }
+}
+
+impl ControllerSettings {
+    /// Get the interpolation factor used to smooth a value over
+    /// a frame of `dt` seconds.
+    fn smoothing_factor(&self, dt: f32) -> f32 {
+        if self.smoothing <= 0.0 { 1.0 } else { 1.0 - (-dt / self.smoothing).exp() }
+    }
+
+    /// Get the mouse motion in pixels used to rotate the camera.
+    fn look_delta(&self, input: &Input) -> (f32, f32) {
+        match self.look_button {
+            Some(button) if !input.is_button_down(button) => (0.0, 0.0),
+            _ => input.mouse_delta(),
+        }
+    }
+
+    /// Get the move speed, accelerated while `Left Control` is held.
+    fn current_speed(&self, input: &Input) -> f32 {
+        if input.is_key_down(VirtualKeyCode::LControl) { self.speed * self.fast_multiplier } else { self.speed }
+    }
+}
+
+/// The yaw and the pitch of a camera, smoothed toward the angles
+/// given by the mouse.
+#[derive(Clone, Copy, Debug, Default)]
+struct SmoothLook {
+    /// The current and the target `(yaw, pitch)`, read from the
+    /// camera at the first update.
+    angles: Option<((f32, f32), (f32, f32))>,
+}
+
+impl SmoothLook {
+    /// Rotate the target angles with the mouse motion, and the
+    /// camera toward them.
+    fn update(&mut self, camera: &mut Camera, settings: &ControllerSettings, input: &Input, dt: f32) {
+        let (current, target) = self.angles.get_or_insert_with(|| {
+            let angles = (camera.yaw(), camera.pitch());
+            (angles, angles)
+        });
+
+        let (dx, dy) = settings.look_delta(input);
+        target.0 += dx * settings.sensitivity;
+        target.1 = (target.1 - dy * settings.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+
+        let k = settings.smoothing_factor(dt);
+        current.0 += (target.0 - current.0) * k;
+        current.1 += (target.1 - current.1) * k;
+
+        camera.set_yaw_pitch(current.0, current.1);
+    }
+}
+
+/// Update a [Camera] from the user input each frame.
+pub trait CameraController {
+    /// Update the camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `camera`      - The camera to update.
+    /// * `input`       - The keyboard and mouse state.
+    /// * `dt`          - The frame delta time in seconds.
+    /// * `resources`   - The engine resources (to query the world...).
+    ///
+    fn update(&mut self, camera: &mut Camera, input: &Input, dt: f32, resources: &Resources);
+}
+
+/// Get the WASD direction, `x` is the right and `z` the forward.
+fn wasd(input: &Input) -> Vector3<f32> {
+    let axis = |positive, negative| {
+        (input.is_key_down(positive) as i32 - input.is_key_down(negative) as i32) as f32
+    };
+
+    Vector3::new(axis(VirtualKeyCode::D, VirtualKeyCode::A), 0.0, axis(VirtualKeyCode::W, VirtualKeyCode::S))
+}
+
+/// A free fly controller: `WASD` to move, `Space`/`Left Shift`
+/// to move up/down and the mouse to look around.
+#[derive(Clone, Debug, Default)]
+pub struct FlyController {
+    pub settings: ControllerSettings,
+    velocity    : Vector3<f32>,
+    look        : SmoothLook,
+}
+
+impl FlyController {
+    /// Create a new [FlyController].
+    pub fn new(settings: ControllerSettings) -> Self {
+        Self { settings, ..Default::default() }
+    }
+}
+
+impl CameraController for FlyController {
+    fn update(&mut self, camera: &mut Camera, input: &Input, dt: f32, _resources: &Resources) {
+        let k = self.settings.smoothing_factor(dt);
+
+        self.look.update(camera, &self.settings, input, dt);
+
+        let mut direction = wasd(input);
+        direction.y = (input.is_key_down(VirtualKeyCode::Space) as i32 - input.is_key_down(VirtualKeyCode::LShift) as i32) as f32;
+
+        let target = if direction.norm_squared() > 0.0 {
+            let world = camera.right() * direction.x + Vector3::y() * direction.y + camera.forward() * direction.z;
+            world.normalize() * self.settings.current_speed(input)
+        } else {
+            Vector3::zeros()
+        };
+
+        self.velocity += (target - self.velocity) * k;
+        camera.translate(self.velocity * dt);
+    }
+}
+
+/// A controller that orbit around a target: drag the mouse to
+/// rotate, scroll to zoom and `WASD` to move the target.
+#[derive(Clone, Debug)]
+pub struct OrbitController {
+    pub settings    : ControllerSettings,
+    /// The point the camera orbit around.
+    pub target      : Vector3<f32>,
+    /// The minimum distance between the camera and the target.
+    pub min_distance: f32,
+    /// The maximum distance between the camera and the target.
+    pub max_distance: f32,
+    /// The relative zoom applied for each scrolled line.
+    pub zoom_speed  : f32,
+
+    distance        : f32,
+    smooth_distance : f32,
+    yaw             : f32,
+    pitch           : f32,
+}
+
+impl Default for OrbitController {
+    fn default() -> Self {
+        Self::new(Vector3::zeros(), 10.0, ControllerSettings { look_button: Some(MouseButton::Left), ..Default::default() })
+    }
+}
+
+impl OrbitController {
+    /// Create a new [OrbitController].
+    ///
+    /// # Arguments
+    ///
+    /// * `target`      - The point to orbit around.
+    /// * `distance`    - The initial distance to the target.
+    /// * `settings`    - The controller settings.
+    ///
+    pub fn new(target: Vector3<f32>, distance: f32, settings: ControllerSettings) -> Self {
+        Self {
+            settings,
+            target,
+            min_distance    : 0.5,
+            max_distance    : 500.0,
+            zoom_speed      : 0.1,
+            distance,
+            smooth_distance : distance,
+            yaw             : 0.0,
+            pitch           : 0.3,
+        }
+    }
+
+    /// Get the target distance.
+    pub fn distance(&self) -> f32 {
+        self.distance
+    }
+
+    /// Set the target distance.
+    pub fn set_distance(&mut self, distance: f32) {
+        self.distance = distance.clamp(self.min_distance, self.max_distance);
+    }
+}
+
+impl CameraController for OrbitController {
+    fn update(&mut self, camera: &mut Camera, input: &Input, dt: f32, _resources: &Resources) {
+        let k = self.settings.smoothing_factor(dt);
+
+        let (dx, dy) = self.settings.look_delta(input);
+        self.yaw += dx * self.settings.sensitivity;
+        self.pitch = (self.pitch + dy * self.settings.sensitivity).clamp(-1.55, 1.55);
+
+        self.set_distance(self.distance * (1.0 - input.scroll_delta() * self.zoom_speed));
+        self.smooth_distance += (self.distance - self.smooth_distance) * k;
+
+        // Move the target on the horizontal plane.
+        let direction = wasd(input);
+        if direction.norm_squared() > 0.0 {
+            let forward = Vector3::new(self.yaw.sin(), 0.0, self.yaw.cos());
+            let right = Vector3::new(forward.z, 0.0, -forward.x);
+            let motion = (right * direction.x + forward * direction.z).normalize();
+
+            self.target += motion * self.settings.current_speed(input) * dt;
+        }
+
+        // The camera is placed behind the target, looking at it.
+        let offset = Vector3::new(
+            -self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        );
+
+        camera.set_position(self.target + offset * self.smooth_distance);
+        camera.look_at(self.target, Vector3::y());
+    }
+}
+
+/// A first person controller that walk on the voxels of the
+/// world resource `W`: `WASD` to walk, `Space` to jump and the
+/// mouse to look around. The body is moved with steps of
+/// [Time::fixed_delta] like the [CharacterPlugin](crate::engine::voxel::character::CharacterPlugin),
+/// and the camera follows its position interpolated between them.
+pub struct FirstPersonController<W: VoxelQuery + 'static> {
+    pub settings    : ControllerSettings,
+    /// The height of the eyes above the feet.
+    pub eye_height  : f32,
+    /// The size and the physics of the player body.
+    pub character   : CharacterSettings,
+
+    body            : CharacterController,
+    walk            : Vector3<f32>,
+    look            : SmoothLook,
+    /// The time not consumed by the steps of the body yet.
+    accumulator     : f32,
+    /// The position the camera was moved to at the last update.
+    eye             : Option<Vector3<f32>>,
+    world           : PhantomData<fn() -> W>,
+}
+
+impl<W: VoxelQuery + 'static> Default for FirstPersonController<W> {
+    fn default() -> Self {
+        Self::new(ControllerSettings { speed: 4.0, look_button: None, ..Default::default() })
+    }
+}
+
+impl<W: VoxelQuery + 'static> FirstPersonController<W> {
+    /// Create a new [FirstPersonController].
+    pub fn new(settings: ControllerSettings) -> Self {
+        let character = CharacterSettings::default();
+
+        Self {
+            settings,
+            eye_height  : 1.6,
+            character,
+            body        : CharacterController::new(character, Vector3::zeros()),
+            walk        : Vector3::zeros(),
+            look        : SmoothLook::default(),
+            accumulator : 0.0,
+            eye         : None,
+            world       : PhantomData,
+        }
+    }
+
+    /// Check if the player stand on a solid voxel.
+    pub fn is_on_ground(&self) -> bool {
+        self.body.is_on_ground()
+    }
+}
+
+impl<W: VoxelQuery + 'static> CameraController for FirstPersonController<W> {
+    fn update(&mut self, camera: &mut Camera, input: &Input, dt: f32, resources: &Resources) {
+        let k = self.settings.smoothing_factor(dt);
+
+        self.look.update(camera, &self.settings, input, dt);
+
+        // Walk on the horizontal plane, whatever the pitch is.
+        let yaw = camera.yaw();
+        let forward = Vector3::new(yaw.sin(), 0.0, yaw.cos());
+        let right = Vector3::new(forward.z, 0.0, -forward.x);
+        let direction = wasd(input);
+
+        let target = if direction.norm_squared() > 0.0 {
+            (right * direction.x + forward * direction.z).normalize() * self.settings.current_speed(input)
+        } else {
+            Vector3::zeros()
+        };
+
+        self.walk += (target - self.walk) * k;
+
+        self.body.settings = self.character;
+
+        let eye_offset = Vector3::new(0.0, self.eye_height, 0.0);
+
+        // The camera may have been moved by something else.
+        if self.eye != Some(camera.position()) {
+            self.body.set_position(camera.position() - eye_offset);
+            self.accumulator = 0.0;
+        }
+
+        self.body.set_walk_velocity(self.walk);
+
+        let feet = match resources.get::<W>() {
+            Some(world) => {
+                if input.is_key_pressed(VirtualKeyCode::Space) {
+                    self.body.jump();
+                }
+
+                let fixed_delta = resources.get::<Time>().map(|time| time.fixed_delta()).unwrap_or(DEFAULT_FIXED_DELTA);
+                self.accumulator = (self.accumulator + dt).min(fixed_delta * MAX_FIXED_STEPS as f32);
+
+                while self.accumulator >= fixed_delta {
+                    self.body.step(world, fixed_delta);
+                    self.accumulator -= fixed_delta;
+                }
+
+                self.body.interpolated_position(self.accumulator / fixed_delta)
+            },
+
+            // Without world there is nothing to walk on, so
+            // don't fall forever.
+            None => {
+                self.body.set_velocity(Vector3::zeros());
+                self.body.set_on_ground(true);
+                self.body.set_position(self.body.position() + self.walk * dt);
+                self.body.position()
+            },
+        };
+
+        let eye = feet + eye_offset;
+        camera.set_position(eye);
+        self.eye = Some(eye);
+    }
+}
+
+/// A plugin that update the [Camera] resource with a
+/// [CameraController] each frame. It need the [Input]
+/// resource (see [InputPlugin](crate::engine::input::InputPlugin)).
+pub struct CameraControllerPlugin<C: CameraController + 'static> {
+    controller: Option<C>,
+}
+
+impl<C: CameraController + 'static> CameraControllerPlugin<C> {
+    /// Create a new [CameraControllerPlugin].
+    ///
+    /// # Arguments
+    ///
+    /// * `controller` - The controller that update the camera.
+    ///
+    pub fn new(controller: C) -> Self {
+        Self { controller: Some(controller) }
+    }
+}
+
+impl<R: RendererTrait + 'static, C: CameraController + 'static> Plugin<R> for CameraControllerPlugin<C> {
+    fn build(&mut self, systems: &mut Systems<R>, _resources: &mut Resources) {
+        let Some(mut controller) = self.controller.take() else { return };
+
+        systems.add_update_system(move |resources| {
+            let dt = resources.get::<Time>().map(|time| time.delta()).unwrap_or(0.0);
+
+            // Take the camera out of the resources, so the controller
+            // can read the other resources while updating it.
+            let Some(mut camera) = resources.remove::<Camera>() else { return };
+
+            if let Some(input) = resources.get::<Input>() {
+                controller.update(&mut camera, input, dt, resources);
+            }
+
+            resources.insert(camera);
+        });
+    }
+}
@@ -0,0 +1,364 @@
+use nalgebra::{Matrix4, Vector3, Vector4};
+
+use crate::engine::{
+    bounds::{Aabb, Sphere},
+    camera::Camera,
+    logging,
+    renderer::{Buffer, BufferUsage, ComputePipeline, RendererTrait},
+};
+
+/// The size of the workgroups of the culling shader.
+const CULL_WORKGROUP_SIZE: u32 = 64;
+
+/// A plane, the points `p` on the plane verify `normal.dot(p) + d == 0`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+    pub normal  : Vector3<f32>,
+    pub d       : f32,
+}
+
+impl Plane {
+    /// Create a new [Plane] from the `(a, b, c, d)` coefficients
+    /// of its equation, the plane is normalized.
+    pub fn from_coefficients(coefficients: Vector4<f32>) -> Self {
+        let normal = coefficients.xyz();
+        let length = normal.norm();
+
+        if length > f32::EPSILON {
+            Self { normal: normal / length, d: coefficients.w / length }
+        } else {
+            Self { normal, d: coefficients.w }
+        }
+    }
+
+    /// Get the signed distance between the plane and a point,
+    /// positive when the point is on the side of the normal.
+    pub fn distance(&self, point: &Vector3<f32>) -> f32 {
+        self.normal.dot(point) + self.d
+    }
+}
+
+/// The six planes of a view volume, their normals point inside
+/// the volume.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// The left, right, bottom, top, near and far planes.
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    /// Extract the planes of a projection view matrix (Gribb &
+    /// Hartmann method). The matrix must map the depth in the
+    /// `[-1, 1]` range, like the [Camera] matrices.
+    ///
+    /// # Arguments
+    ///
+    /// * `proj_view` - The projection view matrix.
+    ///
+    pub fn from_matrix(proj_view: &Matrix4<f32>) -> Self {
+        let row = |i: usize| proj_view.row(i).transpose();
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [
+                Plane::from_coefficients(r3 + r0),
+                Plane::from_coefficients(r3 - r0),
+                Plane::from_coefficients(r3 + r1),
+                Plane::from_coefficients(r3 - r1),
+                Plane::from_coefficients(r3 + r2),
+                Plane::from_coefficients(r3 - r2),
+            ],
+        }
+    }
+
+    /// Extract the planes of the view volume of a [Camera].
+    pub fn from_camera(camera: &Camera) -> Self {
+        Self::from_matrix(&camera.proj_view_matrix())
+    }
+
+    /// Check if a point is inside the frustum.
+    pub fn contains_point(&self, point: &Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.distance(point) >= 0.0)
+    }
+
+    /// Check if a sphere is (at least partially) inside the frustum.
+    pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+        self.planes.iter().all(|plane| plane.distance(&sphere.center) >= -sphere.radius)
+    }
+
+    /// Check if a box is (at least partially) inside the frustum.
+    ///
+    /// The test is conservative: a box near a corner of the
+    /// frustum can be reported visible while it is outside.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box the farthest along the normal,
+            // if it is behind the plane the whole box is.
+            let corner = Vector3::new(
+                if plane.normal.x >= 0.0 { aabb.max.x } else { aabb.min.x },
+                if plane.normal.y >= 0.0 { aabb.max.y } else { aabb.min.y },
+                if plane.normal.z >= 0.0 { aabb.max.z } else { aabb.min.z },
+            );
+
+            plane.distance(&corner) >= 0.0
+        })
+    }
+
+    /// Filter a list of boxes, return the indices of the visible ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `bounds` - The boxes of the chunks or models to cull.
+    ///
+    pub fn cull(&self, bounds: &[Aabb]) -> Vec<usize> {
+        bounds.iter()
+            .enumerate()
+            .filter(|(_, aabb)| self.intersects_aabb(aabb))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Keep only the visible items of a list.
+    ///
+    /// # Arguments
+    ///
+    /// * `items`  - The items to cull.
+    /// * `bounds` - A function that give the box of an item.
+    ///
+    pub fn retain_visible<T, F: Fn(&T) -> Aabb>(&self, items: &mut Vec<T>, bounds: F) {
+        items.retain(|item| self.intersects_aabb(&bounds(item)));
+    }
+
+    /// Get the planes as `(a, b, c, d)` vectors, as used in shaders.
+    pub fn to_vectors(&self) -> [[f32; 4]; 6] {
+        self.planes.map(|plane| [plane.normal.x, plane.normal.y, plane.normal.z, plane.d])
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct CullParams {
+    planes      : [[f32; 4]; 6],
+    count       : u32,
+    group_size  : u32,
+    _padding    : [u32; 2],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuBounds {
+    min: [f32; 4],
+    max: [f32; 4],
+}
+
+/// The header of the visible list, reset before each cull: no
+/// workgroup on `x`, one on `y` and `z`, and no visible box.
+const VISIBLE_LIST_RESET: [u32; 4] = [0, 1, 1, 0];
+
+/// The size in bytes of the header of the visible list.
+pub const VISIBLE_LIST_HEADER_SIZE: u64 = 16;
+
+/// Cull a list of boxes with a compute shader.
+///
+/// The result is written in a storage buffer with the layout:
+///
+/// ```wgsl
+/// struct VisibleList {
+///     dispatch_x  : u32,
+///     dispatch_y  : u32,
+///     dispatch_z  : u32,
+///     count       : u32,
+///     indices     : array<u32>,
+/// };
+/// ```
+///
+/// The first three values are the arguments of an indirect dispatch
+/// with one workgroup per `group_size` visible boxes, so a following
+/// pass can process the visible list with
+/// [RendererTrait::dispatch_compute_pipeline_indirect] without reading
+/// it back.
+pub struct GpuCuller {
+    pipeline    : ComputePipeline,
+    params      : Buffer,
+    bounds      : Buffer,
+    visible     : Buffer,
+    capacity    : usize,
+    group_size  : u32,
+}
+
+impl GpuCuller {
+    /// Create a new [GpuCuller].
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer used to create the GPU resources.
+    /// * `capacity`    - The amount of boxes the buffers can hold, they grow if needed.
+    /// * `group_size`  - The workgroup size of the pass that consume the visible list.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, capacity: usize, group_size: u32) -> Self {
+        let shader = renderer.compile_shader(include_str!("../shaders/cull.wgsl"));
+        let pipeline = renderer.create_compute_pipeline(shader, None);
+        let params = renderer.create_buffer(std::mem::size_of::<CullParams>() as u64, BufferUsage::UNIFORM, true);
+
+        let capacity = capacity.max(1);
+        let (bounds, visible) = Self::create_list_buffers(renderer, capacity);
+
+        renderer.set_binding_data(pipeline, 0, &[params, bounds, visible]);
+
+        Self {
+            pipeline,
+            params,
+            bounds,
+            visible,
+            capacity,
+            group_size: group_size.max(1),
+        }
+    }
+
+    /// Get the buffer that contains the visible list, it can be
+    /// bound to the consumer pass and used for the indirect dispatch
+    /// (the arguments are at the offset `0`).
+    ///
+    /// The buffer change when the capacity grows, see [GpuCuller::cull].
+    pub fn visible_buffer(&self) -> Buffer {
+        self.visible
+    }
+
+    /// Record the culling pass of the boxes against a frustum.
+    ///
+    /// Return `true` if the buffers grew: the previous visible
+    /// buffer is destroyed, the new [GpuCuller::visible_buffer] must
+    /// be bound again to the consumer pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `frustum`     - The frustum.
+    /// * `bounds`      - The boxes to cull.
+    ///
+    pub fn cull<R: RendererTrait>(&mut self, renderer: &mut R, frustum: &Frustum, bounds: &[Aabb]) -> bool {
+        let reallocated = bounds.len() > self.capacity;
+
+        if reallocated {
+            let capacity = bounds.len().next_power_of_two();
+
+            log::debug!(target: logging::RENDERER, "Grow the GPU culling buffers from {} to {} boxes", self.capacity, capacity);
+
+            renderer.destroy_buffer(self.bounds);
+            renderer.destroy_buffer(self.visible);
+
+            (self.bounds, self.visible) = Self::create_list_buffers(renderer, capacity);
+            self.capacity = capacity;
+
+            renderer.set_binding_data(self.pipeline, 0, &[self.params, self.bounds, self.visible]);
+        }
+
+        let params = CullParams {
+            planes      : frustum.to_vectors(),
+            count       : bounds.len() as u32,
+            group_size  : self.group_size,
+            _padding    : [0; 2],
+        };
+
+        let gpu_bounds = bounds.iter()
+            .map(|aabb| GpuBounds {
+                min: [aabb.min.x, aabb.min.y, aabb.min.z, 0.0],
+                max: [aabb.max.x, aabb.max.y, aabb.max.z, 0.0],
+            })
+            .collect::<Vec<_>>();
+
+        renderer.update_buffer(self.params, &params, 0);
+        renderer.update_buffer(self.visible, &VISIBLE_LIST_RESET, 0);
+
+        if !gpu_bounds.is_empty() {
+            renderer.update_buffer_with_slice(self.bounds, &gpu_bounds, 0);
+        }
+
+        let workgroups = (bounds.len() as u32).div_ceil(CULL_WORKGROUP_SIZE).max(1);
+        renderer.dispatch_compute_pipeline(self.pipeline, (workgroups, 1, 1));
+
+        reallocated
+    }
+
+    fn create_list_buffers<R: RendererTrait>(renderer: &mut R, capacity: usize) -> (Buffer, Buffer) {
+        let bounds_size = (capacity * std::mem::size_of::<GpuBounds>()) as u64;
+        let visible_size = VISIBLE_LIST_HEADER_SIZE + (capacity * std::mem::size_of::<u32>()) as u64;
+
+        let bounds = renderer.create_buffer(bounds_size, BufferUsage::STORAGE, true);
+        let visible = renderer.create_buffer(visible_size, BufferUsage::INDIRECT, true);
+
+        (bounds, visible)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_plane(plane: &Plane, normal: Vector3<f32>, d: f32) {
+        assert!((plane.normal - normal).norm() < 1e-5, "{:?} != {:?}", plane.normal, normal);
+        assert!((plane.d - d).abs() < 1e-4, "{} != {}", plane.d, d);
+    }
+
+    #[test]
+    fn orthographic_planes() {
+        let frustum = Frustum::from_matrix(&Matrix4::new_orthographic(-2.0, 2.0, -1.0, 1.0, 0.5, 10.0));
+        let [left, right, bottom, top, near, far] = &frustum.planes;
+
+        // The camera looks toward `-Z`.
+        assert_plane(left, Vector3::x(), 2.0);
+        assert_plane(right, -Vector3::x(), 2.0);
+        assert_plane(bottom, Vector3::y(), 1.0);
+        assert_plane(top, -Vector3::y(), 1.0);
+        assert_plane(near, -Vector3::z(), -0.5);
+        assert_plane(far, Vector3::z(), 10.0);
+    }
+
+    #[test]
+    fn perspective_planes() {
+        let fov = std::f32::consts::FRAC_PI_2;
+        let frustum = Frustum::from_matrix(&Matrix4::new_perspective(1.0, fov, 0.1, 100.0));
+        let [left, right, bottom, top, near, far] = &frustum.planes;
+
+        // With a 90° field of view the side planes are at 45°.
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        assert_plane(left, Vector3::new(s, 0.0, -s), 0.0);
+        assert_plane(right, Vector3::new(-s, 0.0, -s), 0.0);
+        assert_plane(bottom, Vector3::new(0.0, s, -s), 0.0);
+        assert_plane(top, Vector3::new(0.0, -s, -s), 0.0);
+        assert_plane(near, -Vector3::z(), -0.1);
+        assert_plane(far, Vector3::z(), 100.0);
+
+        assert!(frustum.contains_point(&Vector3::new(0.0, 0.0, -1.0)));
+        assert!(frustum.contains_point(&Vector3::new(0.9, -0.9, -1.0)));
+        assert!(!frustum.contains_point(&Vector3::new(1.1, 0.0, -1.0)));
+        assert!(!frustum.contains_point(&Vector3::new(0.0, 0.0, 1.0)));
+        assert!(!frustum.contains_point(&Vector3::new(0.0, 0.0, -0.05)));
+        assert!(!frustum.contains_point(&Vector3::new(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn aabb_classification() {
+        let frustum = Frustum::from_matrix(&Matrix4::new_perspective(1.0, std::f32::consts::FRAC_PI_2, 0.1, 100.0));
+
+        let inside = Aabb::new(Vector3::new(-1.0, -1.0, -6.0), Vector3::new(1.0, 1.0, -4.0));
+        let intersecting = Aabb::new(Vector3::new(3.0, -1.0, -6.0), Vector3::new(6.0, 1.0, -4.0));
+        let beside = Aabb::new(Vector3::new(8.0, -1.0, -6.0), Vector3::new(10.0, 1.0, -4.0));
+        let behind = Aabb::new(Vector3::new(-1.0, -1.0, 1.0), Vector3::new(1.0, 1.0, 2.0));
+        let beyond = Aabb::new(Vector3::new(-1.0, -1.0, -120.0), Vector3::new(1.0, 1.0, -110.0));
+        let around = Aabb::new(Vector3::new(-200.0, -200.0, -200.0), Vector3::new(200.0, 200.0, 200.0));
+
+        assert!(frustum.intersects_aabb(&inside));
+        assert!(frustum.intersects_aabb(&intersecting));
+        assert!(frustum.intersects_aabb(&around));
+        assert!(!frustum.intersects_aabb(&beside));
+        assert!(!frustum.intersects_aabb(&behind));
+        assert!(!frustum.intersects_aabb(&beyond));
+
+        let bounds = [inside, beside, intersecting, behind, beyond, around];
+        assert_eq!(frustum.cull(&bounds), vec![0, 2, 5]);
+
+        assert!(frustum.intersects_sphere(&Sphere::new(Vector3::new(5.5, 0.0, -5.0), 1.0)));
+        assert!(!frustum.intersects_sphere(&Sphere::new(Vector3::new(9.0, 0.0, -5.0), 1.0)));
+    }
+}
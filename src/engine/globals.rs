@@ -0,0 +1,96 @@
+use nalgebra::{Matrix4, Vector2, Vector3};
+
+use crate::engine::{
+    camera::Camera,
+    shader_type::ShaderStruct,
+    time::Time,
+    voxel::shading::ShadingSettings,
+};
+
+/// The bind group reserved for the [Globals] uniform, the
+/// groups `0` to `2` are free for the pipelines.
+pub const GLOBALS_GROUP: u32 = 3;
+
+/// The binding of the [Globals] uniform in its group.
+pub const GLOBALS_BINDING: u32 = 0;
+
+crate::wgsl_struct! {
+    /// The data uploaded by the engine each frame, available to
+    /// all the compute shaders that include [globals_wgsl].
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Globals {
+        /// The camera projection view matrix (world to clip space).
+        pub proj_view_matrix    : Matrix4<f32>,
+        /// The inverse of the camera projection view matrix.
+        pub inv_proj_view_matrix: Matrix4<f32>,
+        /// The camera position in world space.
+        pub camera_position     : Vector3<f32>,
+        /// The time in seconds since the first frame.
+        pub time                : f32,
+        /// The size of the render texture in pixels.
+        pub resolution          : Vector2<f32>,
+        /// The time in seconds since the last frame.
+        pub delta_time          : f32,
+        /// The index of the frame (wraps around).
+        pub frame               : u32,
+        /// The camera near.
+        pub near                : f32,
+        /// The camera far.
+        pub far                 : f32,
+        /// The lighting and the quality of the secondary rays.
+        pub shading             : ShadingSettings,
+    }
+}
+
+impl Globals {
+    /// Create new [Globals].
+    ///
+    /// # Arguments
+    ///
+    /// * `camera`      - The camera (if any), otherwise the matrices are the identity.
+    /// * `shading`     - The shading settings (if any), otherwise the default ones.
+    /// * `time`        - The engine clock.
+    /// * `resolution`  - The size of the render texture in pixels.
+    ///
+    pub fn new(camera: Option<&Camera>, shading: Option<&ShadingSettings>, time: &Time, resolution: (u32, u32)) -> Self {
+        let mut globals = Self {
+            proj_view_matrix    : Matrix4::identity(),
+            inv_proj_view_matrix: Matrix4::identity(),
+            time                : time.elapsed(),
+            resolution          : Vector2::new(resolution.0 as f32, resolution.1 as f32),
+            delta_time          : time.delta(),
+            frame               : time.frame() as u32,
+            shading             : shading.copied().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if let Some(camera) = camera {
+            globals.proj_view_matrix = camera.proj_view_matrix();
+            globals.inv_proj_view_matrix = camera.inv_proj_view_matrix();
+            globals.camera_position = camera.position();
+            globals.near = camera.near();
+            globals.far = camera.far();
+        }
+
+        globals
+    }
+}
+
+/// Get the WGSL declaration of the [Globals] structure and of
+/// the `globals` uniform, to prepend to the source of a shader:
+///
+/// ```ignore
+/// let source = globals_wgsl() + include_str!("shaders/my_shader.wgsl");
+/// let shader = renderer.compile_shader(source);
+/// ```
+///
+/// The uniform is bound automatically to the pipelines that use it.
+pub fn globals_wgsl() -> String {
+    format!(
+        "{}\n{}\n@group({}) @binding({})\nvar<uniform> globals: Globals;\n\n",
+        ShadingSettings::wgsl_definition(),
+        Globals::wgsl_definition(),
+        GLOBALS_GROUP,
+        GLOBALS_BINDING,
+    )
+}
@@ -0,0 +1,201 @@
+use std::collections::HashSet;
+
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use crate::engine::{
+    plugin::{Plugin, Resources, Systems},
+    renderer::RendererTrait,
+};
+
+/// The keyboard and mouse state of the current frame.
+///
+/// The state is updated by the [InputPlugin], the events
+/// received between two frames are applied at the begin
+/// of the next frame so all the systems see the same state.
+#[derive(Default)]
+pub struct Input {
+    keys_down       : HashSet<VirtualKeyCode>,
+    keys_pressed    : HashSet<VirtualKeyCode>,
+    keys_released   : HashSet<VirtualKeyCode>,
+    buttons_down    : HashSet<MouseButton>,
+    buttons_pressed : HashSet<MouseButton>,
+    buttons_released: HashSet<MouseButton>,
+    cursor_position : (f32, f32),
+    mouse_delta     : (f32, f32),
+    scroll_delta    : f32,
+
+    pending: PendingInput,
+}
+
+/// The events received since the begin of the last frame.
+#[derive(Default)]
+struct PendingInput {
+    events         : Vec<PendingEvent>,
+    cursor_position: Option<(f32, f32)>,
+    mouse_delta    : (f32, f32),
+    scroll_delta   : f32,
+}
+
+/// A key or button event, kept in the order it was received.
+enum PendingEvent {
+    Key(VirtualKeyCode, ElementState),
+    Button(MouseButton, ElementState),
+}
+
+impl Input {
+    /// Check if a key is currently held down.
+    pub fn is_key_down(&self, key: VirtualKeyCode) -> bool {
+        self.keys_down.contains(&key)
+    }
+
+    /// Check if a key was pressed during this frame.
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys_pressed.contains(&key)
+    }
+
+    /// Check if a key was released during this frame.
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys_released.contains(&key)
+    }
+
+    /// Check if a mouse button is currently held down.
+    pub fn is_button_down(&self, button: MouseButton) -> bool {
+        self.buttons_down.contains(&button)
+    }
+
+    /// Check if a mouse button was pressed during this frame.
+    pub fn is_button_pressed(&self, button: MouseButton) -> bool {
+        self.buttons_pressed.contains(&button)
+    }
+
+    /// Check if a mouse button was released during this frame.
+    pub fn is_button_released(&self, button: MouseButton) -> bool {
+        self.buttons_released.contains(&button)
+    }
+
+    /// Get the cursor position in pixels, relative to the
+    /// top left corner of the window.
+    pub fn cursor_position(&self) -> (f32, f32) {
+        self.cursor_position
+    }
+
+    /// Get the amount of pixels the cursor moved during this frame.
+    pub fn mouse_delta(&self) -> (f32, f32) {
+        self.mouse_delta
+    }
+
+    /// Get the amount of lines scrolled during this frame.
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll_delta
+    }
+
+    /// Apply the pending events to the current state.
+    pub(crate) fn begin_frame(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+
+        self.keys_pressed.clear();
+        self.keys_released.clear();
+        self.buttons_pressed.clear();
+        self.buttons_released.clear();
+
+        // The events are applied in the order they were received, so a
+        // key released then pressed again during the frame stays down.
+        for event in pending.events {
+            match event {
+                PendingEvent::Key(key, ElementState::Pressed) => {
+                    // Ignore the key repeat.
+                    if self.keys_down.insert(key) {
+                        self.keys_pressed.insert(key);
+                    }
+                },
+                PendingEvent::Key(key, ElementState::Released) => {
+                    self.keys_down.remove(&key);
+                    self.keys_released.insert(key);
+                },
+                PendingEvent::Button(button, ElementState::Pressed) => {
+                    if self.buttons_down.insert(button) {
+                        self.buttons_pressed.insert(button);
+                    }
+                },
+                PendingEvent::Button(button, ElementState::Released) => {
+                    self.buttons_down.remove(&button);
+                    self.buttons_released.insert(button);
+                },
+            }
+        }
+
+        if let Some(position) = pending.cursor_position {
+            self.cursor_position = position;
+        }
+
+        self.mouse_delta  = pending.mouse_delta;
+        self.scroll_delta = pending.scroll_delta;
+    }
+
+    /// Record a window event.
+    pub(crate) fn handle_event(&mut self, event: &WindowEvent) {
+        let pending = &mut self.pending;
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { state, virtual_keycode: Some(key), .. },
+                ..
+            } => pending.events.push(PendingEvent::Key(*key, *state)),
+
+            WindowEvent::MouseInput { state, button, .. } => {
+                pending.events.push(PendingEvent::Button(*button, *state));
+            },
+
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = (position.x as f32, position.y as f32);
+                let previous = pending.cursor_position.unwrap_or(self.cursor_position);
+
+                pending.mouse_delta.0 += position.0 - previous.0;
+                pending.mouse_delta.1 += position.1 - previous.1;
+                pending.cursor_position = Some(position);
+            },
+
+            WindowEvent::MouseWheel { delta, .. } => {
+                pending.scroll_delta += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    // Approximate a line to 20 pixels.
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                };
+            },
+
+            WindowEvent::Focused(false) => {
+                // Release everything, otherwise the keys held when
+                // the window lost the focus will stay down forever. The
+                // events received before are dropped for the same reason.
+                *pending = PendingInput::default();
+
+                pending.events.extend(self.keys_down.iter().map(|key| PendingEvent::Key(*key, ElementState::Released)));
+                pending.events.extend(self.buttons_down.iter().map(|button| PendingEvent::Button(*button, ElementState::Released)));
+            },
+
+            _ => {},
+        }
+    }
+}
+
+/// A plugin that maintains the [Input] resource.
+#[derive(Default)]
+pub struct InputPlugin;
+
+impl<R: RendererTrait + 'static> Plugin<R> for InputPlugin {
+    fn build(&mut self, systems: &mut Systems<R>, resources: &mut Resources) {
+        resources.insert(Input::default());
+
+        systems.add_update_system(|resources| {
+            if let Some(input) = resources.get_mut::<Input>() {
+                input.begin_frame();
+            }
+        });
+    }
+
+    fn on_window_event(&mut self, event: &WindowEvent, resources: &mut Resources) {
+        if let Some(input) = resources.get_mut::<Input>() {
+            input.handle_event(event);
+        }
+    }
+}
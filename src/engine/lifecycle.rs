@@ -0,0 +1,46 @@
+/// The events that describe the life of the application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LifecycleEvent {
+    /// The event loop started, sent once before the first frame.
+    Init,
+    /// The application was suspended (mobile platforms).
+    Suspended,
+    /// The application was resumed (mobile platforms).
+    Resumed,
+    /// The window lost the focus.
+    FocusLost,
+    /// The window gained the focus.
+    FocusGained,
+    /// The user asked to close the window. The response to
+    /// this event decide if the application will exit.
+    ExitRequested,
+    /// The application is shutting down, sent once after the
+    /// last frame. The GPU resources are still alive.
+    Shutdown,
+}
+
+/// The response to a [LifecycleEvent::ExitRequested] event. The
+/// response is ignored for all other events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExitResponse {
+    /// Let the application exit.
+    #[default]
+    Allow,
+    /// Ask again on the next frame, useful to finish some
+    /// work (like saving the world) before exiting.
+    Delay,
+    /// Cancel the exit request.
+    Veto,
+}
+
+impl ExitResponse {
+    /// Combine two responses, the most restrictive one win
+    /// ([Veto](ExitResponse::Veto) > [Delay](ExitResponse::Delay) > [Allow](ExitResponse::Allow)).
+    pub fn merge(self, other: ExitResponse) -> ExitResponse {
+        match (self, other) {
+            (ExitResponse::Veto, _) | (_, ExitResponse::Veto) => ExitResponse::Veto,
+            (ExitResponse::Delay, _) | (_, ExitResponse::Delay) => ExitResponse::Delay,
+            _ => ExitResponse::Allow,
+        }
+    }
+}
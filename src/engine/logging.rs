@@ -0,0 +1,21 @@
+//! The log targets used by the engine subsystems.
+//!
+//! All the engine diagnostics go through the [log] facade, so the
+//! application choose the logger and can filter the messages by
+//! target, for example with `env_logger`:
+//!
+//! ```text
+//! RUST_LOG=engine=info,engine::renderer=debug
+//! ```
+
+/// The engine core (main loop, plugins, lifecycle).
+pub const ENGINE: &str = "engine";
+
+/// The window and its event loop.
+pub const WINDOW: &str = "engine::window";
+
+/// The renderer (resources creation, frames).
+pub const RENDERER: &str = "engine::renderer";
+
+/// The errors reported by wgpu (validation, out of memory).
+pub const WGPU: &str = "engine::wgpu";
@@ -1,45 +1,77 @@
 pub mod window;
 pub mod renderer;
 pub mod renderers;
+pub mod plugin;
+pub mod input;
+pub mod lifecycle;
+pub mod logging;
+pub mod profiler;
+pub mod camera;
+pub mod controllers;
+pub mod time;
+pub mod voxel;
+pub mod bounds;
+pub mod frustum;
+pub mod shader_type;
+pub mod globals;
 
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
-use crate::engine::{ renderer::RendererTrait, window::Window };
+use crate::engine::{
+    renderer::RendererTrait,
+    window::Window,
+    camera::Camera,
+    globals::Globals,
+    plugin::{Plugin, Plugins, Resources},
+    lifecycle::{LifecycleEvent, ExitResponse},
+    profiler::Profiler,
+    time::Time,
+    voxel::shading::ShadingSettings,
+};
 
-use self::renderers::wgpu_renderer::WGPURenderer;
 
 pub struct MRenderer {
 
 }
 
-#[derive(Default)]
-pub struct GRenderer<R: RendererTrait + Sized + 'static> {
-    internal: Option<R>
-}
+/// The callback called after the engine rendered a frame.
+type RenderCallback<R> = Box<dyn FnMut(&mut R) + 'static>;
 
-static mut GRENDERER: GRenderer<WGPURenderer> = GRenderer {
-    internal: None,
-};
+/// The callback notified of the lifecycle events of the application.
+type LifecycleCallback<R> = Box<dyn FnMut(LifecycleEvent, &mut R) -> ExitResponse + 'static>;
 
 pub struct Engine<R: RendererTrait + 'static> {
     window: Window,
     renderer: Arc<Mutex<R>>,
+    plugins: Arc<Mutex<Plugins<R>>>,
     update_callback: Option<Box<dyn FnMut() + 'static>>,
-    render_callback: Option<Box<dyn FnMut(&mut R) + 'static>>,
+    render_callback: Option<RenderCallback<R>>,
+    lifecycle_callback: Option<LifecycleCallback<R>>,
+}
+
+impl<R: RendererTrait + 'static> Default for Engine<R> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<R: RendererTrait + 'static> Engine<R> {
     pub fn new() -> Self {
-        let internal = unsafe { GRENDERER.internal.as_mut().unwrap() };
-
         let window = Window::new();
         let renderer = R::new(&window, window.size());
 
+        let mut plugins = Plugins::default();
+        plugins.resources_mut().insert(Profiler::default());
+        plugins.resources_mut().insert(Time::default());
+
         Self {
             window,
             renderer: Arc::new(Mutex::new(renderer)),
+            plugins: Arc::new(Mutex::new(plugins)),
             update_callback: None,
             render_callback: None,
+            lifecycle_callback: None,
         }
     }
 
@@ -55,6 +87,59 @@ impl<R: RendererTrait + 'static> Engine<R> {
         f(&renderer)
     }
 
+    /// Add a [Plugin] to the engine. The plugin is built
+    /// immediately, so the resources it insert are available
+    /// right after this call.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `plugin` - The plugin to add.
+    /// 
+    pub fn add_plugin<P: Plugin<R> + 'static>(&mut self, plugin: P) -> &mut Self {
+        self.plugins.lock().unwrap().add(plugin);
+        self
+    }
+
+    /// Add a system executed once when the engine starts, after
+    /// the startup systems of the plugins added before it.
+    pub fn add_startup_system<S: FnMut(&mut Resources, &mut R) + 'static>(&mut self, system: S) -> &mut Self {
+        self.plugins.lock().unwrap().systems_mut().add_startup_system(system);
+        self
+    }
+
+    /// Add a system executed at a fixed rate, before the update
+    /// systems, with access to the resources.
+    pub fn add_fixed_update_system<S: FnMut(&mut Resources) + 'static>(&mut self, system: S) -> &mut Self {
+        self.plugins.lock().unwrap().systems_mut().add_fixed_update_system(system);
+        self
+    }
+
+    /// Add a system executed each frame before the rendering,
+    /// with access to the resources.
+    pub fn add_update_system<S: FnMut(&mut Resources) + 'static>(&mut self, system: S) -> &mut Self {
+        self.plugins.lock().unwrap().systems_mut().add_update_system(system);
+        self
+    }
+
+    /// Add a system executed each frame during the rendering,
+    /// with access to the resources and the renderer.
+    pub fn add_render_system<S: FnMut(&mut Resources, &mut R) + 'static>(&mut self, system: S) -> &mut Self {
+        self.plugins.lock().unwrap().systems_mut().add_render_system(system);
+        self
+    }
+
+    pub fn with_resources_mut<T, F: FnMut(&mut Resources) -> T>(&mut self, mut f: F) -> T {
+        let mut plugins = self.plugins.lock().unwrap();
+
+        f(plugins.resources_mut())
+    }
+
+    pub fn with_resources_ref<T, F: Fn(&Resources) -> T>(&self, f: F) -> T {
+        let plugins = self.plugins.lock().unwrap();
+
+        f(plugins.resources())
+    }
+
     pub fn set_on_update_callback<C: FnMut() + 'static>(&mut self, callback: C) {
         self.update_callback = Some(Box::new(callback));
     }
@@ -63,21 +148,73 @@ impl<R: RendererTrait + 'static> Engine<R> {
         self.render_callback = Some(Box::new(callback));
     }
 
+    /// Set the callback called for each [LifecycleEvent]. The
+    /// returned [ExitResponse] is used to veto or delay the exit
+    /// when the event is [LifecycleEvent::ExitRequested].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `callback` - The callback.
+    /// 
+    pub fn set_on_lifecycle_callback<C: FnMut(LifecycleEvent, &mut R) -> ExitResponse + 'static>(&mut self, callback: C) {
+        self.lifecycle_callback = Some(Box::new(callback));
+    }
+
     pub fn run(self) {
         let renderer = self.renderer.clone();
+        let plugins = self.plugins.clone();
         let mut on_update_callback = self.update_callback.unwrap_or(Box::new(|| {}));
-        let mut on_render_callback = self.render_callback.unwrap_or(Box::new(|_| {}));
+        let mut on_render_callback: RenderCallback<R> = self.render_callback.unwrap_or(Box::new(|_| {}));
 
         let update_callback = move || {
             let mut renderer = renderer.lock().unwrap();
+            let mut plugins = plugins.lock().unwrap();
 
+            let frame_start = Instant::now();
+
+            if let Some(time) = plugins.resources_mut().get_mut::<Time>() {
+                time.tick();
+            }
+
+            while plugins.resources_mut().get_mut::<Time>().is_some_and(|time| time.next_fixed_step()) {
+                plugins.fixed_update();
+            }
+
+            plugins.update();
             on_update_callback.as_mut()();
+
+            let update_duration = frame_start.elapsed();
+
+            if let Some(time) = plugins.resources().get::<Time>() {
+                let camera = plugins.resources().get::<Camera>();
+                let shading = plugins.resources().get::<ShadingSettings>();
+                let globals = Globals::new(camera, shading, time, renderer.get_size());
+                renderer.update_globals(&globals);
+            }
+
             renderer.render_begin();
             
+            let render_start = Instant::now();
+
             renderer.render();
+            plugins.render(&mut renderer);
             on_render_callback.as_mut()(&mut renderer);
 
+            let render_duration = render_start.elapsed();
+
             renderer.render_end();
+
+            let gpu_timings = renderer.take_gpu_timings();
+
+            if let Some(profiler) = plugins.resources_mut().get_mut::<Profiler>() {
+                profiler.record_cpu("update", frame_start, update_duration);
+                profiler.record_cpu("render", render_start, render_duration);
+                profiler.record_cpu("frame", frame_start, frame_start.elapsed());
+
+                if let Some(gpu_timings) = &gpu_timings {
+                    profiler.record_gpu(gpu_timings);
+                }
+            }
         };
         
         let renderer = self.renderer.clone();
@@ -85,9 +222,48 @@ impl<R: RendererTrait + 'static> Engine<R> {
             renderer.lock().unwrap().resize(new_size);
 
             let (width, height) = new_size;
-            println!("The new window size is {}x{}", width, height);
+            log::debug!(target: logging::WINDOW, "The new window size is {}x{}", width, height);
+        };
+
+        let renderer = self.renderer.clone();
+        let plugins = self.plugins.clone();
+        let event_callback = move |event: &winit::event::WindowEvent| {
+            if !renderer.lock().unwrap().handle_window_event(event) {
+                plugins.lock().unwrap().window_event(event);
+            }
+        };
+
+        let renderer = self.renderer.clone();
+        let plugins = self.plugins.clone();
+        let mut on_lifecycle_callback: LifecycleCallback<R> = self.lifecycle_callback.unwrap_or(Box::new(|_, _| ExitResponse::Allow));
+
+        let lifecycle_callback = move |event: LifecycleEvent| {
+            log::debug!(target: logging::ENGINE, "Lifecycle event: {:?}", event);
+
+            let mut renderer = renderer.lock().unwrap();
+            let mut plugins = plugins.lock().unwrap();
+
+            if event == LifecycleEvent::Init {
+                plugins.startup(&mut renderer);
+            }
+
+            let response = on_lifecycle_callback.as_mut()(event, &mut renderer)
+                .merge(plugins.lifecycle_event(event));
+
+            if event == LifecycleEvent::ExitRequested && response != ExitResponse::Allow {
+                log::info!(target: logging::ENGINE, "Exit request answered with {:?}", response);
+            }
+
+            if event == LifecycleEvent::Shutdown {
+                // The plugins release their resources before the
+                // renderer release everything else.
+                plugins.shutdown(&mut renderer);
+                renderer.shutdown();
+            }
+
+            response
         };
 
-        self.window.run(update_callback, resize_callback);
+        self.window.run(update_callback, resize_callback, event_callback, lifecycle_callback);
     }
 }
\ No newline at end of file
@@ -0,0 +1,278 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use winit::event::WindowEvent;
+
+use crate::engine::{lifecycle::{ExitResponse, LifecycleEvent}, logging, renderer::RendererTrait};
+
+/// A system that only have access to the [Resources].
+pub type System = Box<dyn FnMut(&mut Resources) + 'static>;
+
+/// A system that have access to the [Resources] and the renderer.
+pub type RenderSystem<R> = Box<dyn FnMut(&mut Resources, &mut R) + 'static>;
+
+/// A type map that contains the resources owned by the
+/// engine and by the plugins. A resource is identified
+/// by its type, so only one resource of each type can be
+/// stored.
+#[derive(Default)]
+pub struct Resources {
+    map: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Resources {
+    /// Create a new empty [Resources].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Insert a resource. If a resource of the same type
+    /// already exist, it will be replaced and returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `resource` - The resource to insert.
+    ///
+    pub fn insert<T: 'static>(&mut self, resource: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(resource))
+            .and_then(|old| old.downcast::<T>().ok())
+            .map(|old| *old)
+    }
+
+    /// Remove a resource and return it.
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|old| old.downcast::<T>().ok())
+            .map(|old| *old)
+    }
+
+    /// Get a reference to a resource.
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast_ref::<T>())
+    }
+
+    /// Get a mutable reference to a resource.
+    pub fn get_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|resource| resource.downcast_mut::<T>())
+    }
+
+    /// Check if a resource of type `T` exist.
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+}
+
+/// Contains all the systems registered by the plugins,
+/// sorted by the stage where they are executed.
+pub struct Systems<R: RendererTrait + 'static> {
+    startup : Vec<RenderSystem<R>>,
+    fixed   : Vec<System>,
+    update  : Vec<System>,
+    render  : Vec<RenderSystem<R>>,
+    shutdown: Vec<RenderSystem<R>>,
+}
+
+impl<R: RendererTrait + 'static> Default for Systems<R> {
+    fn default() -> Self {
+        Self {
+            startup : Vec::new(),
+            fixed   : Vec::new(),
+            update  : Vec::new(),
+            render  : Vec::new(),
+            shutdown: Vec::new(),
+        }
+    }
+}
+
+impl<R: RendererTrait + 'static> Systems<R> {
+    /// Add a system that is executed once, before the
+    /// first frame.
+    pub fn add_startup_system<S: FnMut(&mut Resources, &mut R) + 'static>(&mut self, system: S) -> &mut Self {
+        self.startup.push(Box::new(system));
+        self
+    }
+
+    /// Add a system that is executed at a fixed rate (see
+    /// [Time::fixed_delta](crate::engine::time::Time::fixed_delta)),
+    /// zero or more times each frame before the update systems.
+    pub fn add_fixed_update_system<S: FnMut(&mut Resources) + 'static>(&mut self, system: S) -> &mut Self {
+        self.fixed.push(Box::new(system));
+        self
+    }
+
+    /// Add a system that is executed once each frame, before
+    /// the rendering.
+    pub fn add_update_system<S: FnMut(&mut Resources) + 'static>(&mut self, system: S) -> &mut Self {
+        self.update.push(Box::new(system));
+        self
+    }
+
+    /// Add a system that is executed once each frame, between
+    /// the begin and the end of the rendering.
+    pub fn add_render_system<S: FnMut(&mut Resources, &mut R) + 'static>(&mut self, system: S) -> &mut Self {
+        self.render.push(Box::new(system));
+        self
+    }
+
+    /// Add a system that is executed once, when the engine
+    /// is shutting down.
+    pub fn add_shutdown_system<S: FnMut(&mut Resources, &mut R) + 'static>(&mut self, system: S) -> &mut Self {
+        self.shutdown.push(Box::new(system));
+        self
+    }
+}
+
+/// A plugin extend the engine with some features by
+/// registering systems and resources.
+pub trait Plugin<R: RendererTrait + 'static> {
+    /// The name of the plugin.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Register the plugin systems and resources.
+    ///
+    /// # Arguments
+    ///
+    /// * `systems`   - Where to register the plugin systems.
+    /// * `resources` - Where to insert the plugin resources.
+    ///
+    fn build(&mut self, systems: &mut Systems<R>, resources: &mut Resources);
+
+    /// Called for each event received by the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `event`     - The window event.
+    /// * `resources` - The engine resources.
+    ///
+    fn on_window_event(&mut self, _event: &WindowEvent, _resources: &mut Resources) {}
+
+    /// Called for each [LifecycleEvent]. The returned response is
+    /// only used for [LifecycleEvent::ExitRequested], so a plugin
+    /// can veto or delay the exit.
+    ///
+    /// # Arguments
+    ///
+    /// * `event`     - The lifecycle event.
+    /// * `resources` - The engine resources.
+    ///
+    fn on_lifecycle_event(&mut self, _event: LifecycleEvent, _resources: &mut Resources) -> ExitResponse {
+        ExitResponse::Allow
+    }
+}
+
+/// Own the plugins, their systems and the resources, and
+/// execute them at the right stage.
+pub struct Plugins<R: RendererTrait + 'static> {
+    plugins  : Vec<Box<dyn Plugin<R>>>,
+    systems  : Systems<R>,
+    resources: Resources,
+}
+
+impl<R: RendererTrait + 'static> Default for Plugins<R> {
+    fn default() -> Self {
+        Self {
+            plugins  : Vec::new(),
+            systems  : Systems::default(),
+            resources: Resources::new(),
+        }
+    }
+}
+
+impl<R: RendererTrait + 'static> Plugins<R> {
+    /// Add a plugin and build it.
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin` - The plugin to add.
+    ///
+    pub fn add<P: Plugin<R> + 'static>(&mut self, mut plugin: P) {
+        log::debug!(target: logging::ENGINE, "Add plugin {}", plugin.name());
+
+        plugin.build(&mut self.systems, &mut self.resources);
+        self.plugins.push(Box::new(plugin));
+    }
+
+    /// Check if a plugin with the given name was added.
+    pub fn contains(&self, name: &str) -> bool {
+        self.plugins.iter().any(|plugin| plugin.name() == name)
+    }
+
+    /// Get the systems mutably, to register systems that
+    /// are not owned by a plugin.
+    pub fn systems_mut(&mut self) -> &mut Systems<R> {
+        &mut self.systems
+    }
+
+    /// Get the resources.
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Get the resources mutably.
+    pub fn resources_mut(&mut self) -> &mut Resources {
+        &mut self.resources
+    }
+
+    /// Execute the startup systems.
+    pub fn startup(&mut self, renderer: &mut R) {
+        for system in &mut self.systems.startup {
+            system(&mut self.resources, renderer);
+        }
+    }
+
+    /// Execute the fixed update systems once.
+    pub fn fixed_update(&mut self) {
+        for system in &mut self.systems.fixed {
+            system(&mut self.resources);
+        }
+    }
+
+    /// Execute the update systems.
+    pub fn update(&mut self) {
+        for system in &mut self.systems.update {
+            system(&mut self.resources);
+        }
+    }
+
+    /// Execute the render systems.
+    pub fn render(&mut self, renderer: &mut R) {
+        for system in &mut self.systems.render {
+            system(&mut self.resources, renderer);
+        }
+    }
+
+    /// Execute the shutdown systems, in the reverse order
+    /// of their registration.
+    pub fn shutdown(&mut self, renderer: &mut R) {
+        for system in self.systems.shutdown.iter_mut().rev() {
+            system(&mut self.resources, renderer);
+        }
+    }
+
+    /// Forward a lifecycle event to all the plugins and
+    /// return the most restrictive response.
+    pub fn lifecycle_event(&mut self, event: LifecycleEvent) -> ExitResponse {
+        let mut response = ExitResponse::Allow;
+
+        for plugin in &mut self.plugins {
+            response = response.merge(plugin.on_lifecycle_event(event, &mut self.resources));
+        }
+
+        response
+    }
+
+    /// Forward a window event to all the plugins.
+    pub fn window_event(&mut self, event: &WindowEvent) {
+        for plugin in &mut self.plugins {
+            plugin.on_window_event(event, &mut self.resources);
+        }
+    }
+}
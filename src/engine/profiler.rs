@@ -0,0 +1,347 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// The amount of samples kept for the statistics of each scope.
+const DEFAULT_WINDOW: usize = 240;
+
+/// The maximum amount of events kept for the trace export.
+const MAX_TRACE_EVENTS: usize = 100_000;
+
+/// The time spent by the GPU in a pass, measured with
+/// timestamp queries.
+#[derive(Clone, Debug)]
+pub struct GpuTiming {
+    /// The name of the pass.
+    pub label: String,
+    /// The start of the pass in milliseconds, relative to
+    /// the start of the first pass of the frame.
+    pub start: f32,
+    /// The duration of the pass in milliseconds.
+    pub duration: f32,
+}
+
+/// The GPU passes timings of a measured frame.
+#[derive(Clone, Debug)]
+pub struct GpuFrameTimings {
+    /// When the CPU started to record the frame. The results
+    /// are read back a few frames later, the passes are placed
+    /// relative to this instant in the trace.
+    pub frame_start: Instant,
+    /// The timings of the passes of the frame.
+    pub passes: Vec<GpuTiming>,
+}
+
+/// Where a scope was executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Timeline {
+    Cpu,
+    Gpu,
+}
+
+/// The statistics of a scope, in milliseconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScopeStats {
+    pub avg: f32,
+    pub min: f32,
+    pub max: f32,
+    pub p99: f32,
+    /// The last measured duration.
+    pub last: f32,
+}
+
+struct TraceEvent {
+    name: String,
+    timeline: Timeline,
+    /// Start in microseconds since the profiler creation.
+    start: f64,
+    /// Duration in microseconds.
+    duration: f64,
+}
+
+/// Record the duration of CPU scopes and GPU passes, and keep
+/// rolling statistics for each of them.
+///
+/// The engine record the `update`, `render` and `frame` CPU scopes,
+/// and one GPU scope per pass when the adapter support timestamp
+/// queries. The profiler is available as a resource of the engine.
+pub struct Profiler {
+    enabled : bool,
+    window  : usize,
+    origin  : Instant,
+    samples : HashMap<(Timeline, String), VecDeque<f32>>,
+    trace   : VecDeque<TraceEvent>,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW)
+    }
+}
+
+impl Profiler {
+    /// Create a new [Profiler].
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - The amount of samples used to compute the statistics.
+    ///
+    pub fn new(window: usize) -> Self {
+        Self {
+            enabled : true,
+            window  : window.max(1),
+            origin  : Instant::now(),
+            samples : HashMap::new(),
+            trace   : VecDeque::new(),
+        }
+    }
+
+    /// Enable or disable the profiler. A disabled profiler
+    /// ignore all the recorded scopes.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Check if the profiler is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Measure the time taken by `f` as a CPU scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the scope.
+    /// * `f`    - The code to measure.
+    ///
+    pub fn scope<T, F: FnOnce() -> T>(&mut self, name: &str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record_cpu(name, start, start.elapsed());
+        result
+    }
+
+    /// Record a CPU scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`     - The name of the scope.
+    /// * `start`    - When the scope started.
+    /// * `duration` - The duration of the scope.
+    ///
+    pub fn record_cpu(&mut self, name: &str, start: Instant, duration: Duration) {
+        if !self.enabled { return; }
+
+        let start = start.saturating_duration_since(self.origin).as_secs_f64() * 1_000_000.0;
+        let duration_us = duration.as_secs_f64() * 1_000_000.0;
+
+        self.push_sample(Timeline::Cpu, name, duration.as_secs_f32() * 1000.0);
+        self.push_event(TraceEvent { name: name.to_owned(), timeline: Timeline::Cpu, start, duration: duration_us });
+    }
+
+    /// Record the GPU passes timings of a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `timings` - The timings of the measured frame.
+    ///
+    pub fn record_gpu(&mut self, timings: &GpuFrameTimings) {
+        if !self.enabled { return; }
+
+        let frame_start = timings.frame_start.saturating_duration_since(self.origin).as_secs_f64() * 1_000_000.0;
+
+        for timing in &timings.passes {
+            self.push_sample(Timeline::Gpu, &timing.label, timing.duration);
+            self.push_event(TraceEvent {
+                name    : timing.label.clone(),
+                timeline: Timeline::Gpu,
+                start   : frame_start + timing.start as f64 * 1000.0,
+                duration: timing.duration as f64 * 1000.0,
+            });
+        }
+    }
+
+    /// Get the statistics of a scope.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeline` - Where the scope was executed.
+    /// * `name`     - The name of the scope.
+    ///
+    pub fn stats(&self, timeline: Timeline, name: &str) -> Option<ScopeStats> {
+        self.samples
+            .get(&(timeline, name.to_owned()))
+            .map(compute_stats)
+    }
+
+    /// Get the statistics of all the scopes, sorted by timeline
+    /// and name.
+    pub fn all_stats(&self) -> Vec<(Timeline, String, ScopeStats)> {
+        let mut stats = self.samples
+            .iter()
+            .map(|((timeline, name), samples)| (*timeline, name.clone(), compute_stats(samples)))
+            .collect::<Vec<_>>();
+
+        stats.sort_by(|a, b| (a.0 as u8, &a.1).cmp(&(b.0 as u8, &b.1)));
+        stats
+    }
+
+    /// Remove all the samples and the trace events.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.trace.clear();
+    }
+
+    /// Export the recorded events in the Chrome trace event format,
+    /// that can be opened with `chrome://tracing` or Perfetto.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where to write the JSON document.
+    ///
+    pub fn export_chrome_trace(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "{{\"traceEvents\":[")?;
+
+        writeln!(writer, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":0,\"args\":{{\"name\":\"CPU\"}}}},")?;
+        write!(writer, "{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":0,\"tid\":1,\"args\":{{\"name\":\"GPU\"}}}}")?;
+
+        for event in &self.trace {
+            let tid = match event.timeline {
+                Timeline::Cpu => 0,
+                Timeline::Gpu => 1,
+            };
+
+            write!(
+                writer,
+                ",\n{{\"name\":\"{}\",\"cat\":\"{:?}\",\"ph\":\"X\",\"pid\":0,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}",
+                escape_json(&event.name),
+                event.timeline,
+                tid,
+                event.start,
+                event.duration,
+            )?;
+        }
+
+        writeln!(writer, "\n],\"displayTimeUnit\":\"ms\"}}")
+    }
+
+    fn push_sample(&mut self, timeline: Timeline, name: &str, value: f32) {
+        let window = self.window;
+        let samples = self.samples
+            .entry((timeline, name.to_owned()))
+            .or_insert_with(|| VecDeque::with_capacity(window));
+
+        if samples.len() == window {
+            samples.pop_front();
+        }
+
+        samples.push_back(value);
+    }
+
+    fn push_event(&mut self, event: TraceEvent) {
+        if self.trace.len() == MAX_TRACE_EVENTS {
+            self.trace.pop_front();
+        }
+
+        self.trace.push_back(event);
+    }
+}
+
+fn compute_stats(samples: &VecDeque<f32>) -> ScopeStats {
+    if samples.is_empty() {
+        return ScopeStats::default();
+    }
+
+    let mut sorted = samples.iter().copied().collect::<Vec<_>>();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let p99_index = ((sorted.len() as f32 * 0.99).ceil() as usize).clamp(1, sorted.len()) - 1;
+
+    ScopeStats {
+        avg : sorted.iter().sum::<f32>() / sorted.len() as f32,
+        min : sorted[0],
+        max : sorted[sorted.len() - 1],
+        p99 : sorted[p99_index],
+        last: *samples.back().unwrap(),
+    }
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        match c {
+            '"'  => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_of_the_window() {
+        let mut profiler = Profiler::new(100);
+
+        // The first samples leave the window.
+        for value in [1000, 1000].into_iter().chain(1..=100) {
+            profiler.record_cpu("scope", Instant::now(), Duration::from_millis(value));
+        }
+
+        let stats = profiler.stats(Timeline::Cpu, "scope").unwrap();
+        assert!((stats.avg - 50.5).abs() < 1e-3);
+        assert!((stats.min - 1.0).abs() < 1e-3);
+        assert!((stats.max - 100.0).abs() < 1e-3);
+        assert!((stats.p99 - 99.0).abs() < 1e-3);
+        assert!((stats.last - 100.0).abs() < 1e-3);
+
+        assert!(profiler.stats(Timeline::Gpu, "scope").is_none());
+    }
+
+    #[test]
+    fn disabled_profiler_ignores_scopes() {
+        let mut profiler = Profiler::default();
+        profiler.set_enabled(false);
+        profiler.record_cpu("scope", Instant::now(), Duration::from_millis(1));
+
+        assert!(profiler.all_stats().is_empty());
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let mut profiler = Profiler::default();
+        let frame_start = profiler.origin + Duration::from_millis(10);
+
+        profiler.record_cpu("update \"main\"", frame_start, Duration::from_micros(1500));
+        profiler.record_gpu(&GpuFrameTimings {
+            frame_start,
+            passes: vec![GpuTiming { label: "Render Pass".to_owned(), start: 0.5, duration: 2.0 }],
+        });
+
+        let mut output = Vec::new();
+        profiler.export_chrome_trace(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        // The header, the two thread names, the two events and the footer.
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "{\"traceEvents\":[");
+        assert_eq!(lines[3], "{\"name\":\"update \\\"main\\\"\",\"cat\":\"Cpu\",\"ph\":\"X\",\"pid\":0,\"tid\":0,\"ts\":10000.000,\"dur\":1500.000},");
+        assert_eq!(lines[4], "{\"name\":\"Render Pass\",\"cat\":\"Gpu\",\"ph\":\"X\",\"pid\":0,\"tid\":1,\"ts\":10500.000,\"dur\":2000.000}");
+        assert_eq!(lines[5], "],\"displayTimeUnit\":\"ms\"}");
+    }
+
+    #[test]
+    fn json_escape() {
+        assert_eq!(escape_json("plain"), "plain");
+        assert_eq!(escape_json("a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(escape_json("line\nbreak\t"), "line\\u000abreak\\u0009");
+        assert_eq!(escape_json("é"), "é");
+    }
+}
@@ -1,7 +1,9 @@
-use std::marker::PhantomData;
+use std::borrow::Cow;
 
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
+use crate::engine::{globals::Globals, profiler::GpuFrameTimings};
+
 /// Represent a shader.
 #[derive(Clone, Copy)]
 pub struct Shader {
@@ -14,6 +16,12 @@ pub struct ComputePipeline {
     pub(crate) id: usize,
 }
 
+/// Represent a render pipeline, made of a vertex and a fragment shader.
+#[derive(Clone, Copy)]
+pub struct RenderPipeline {
+    pub(crate) id: usize,
+}
+
 /// Represent a compute buffer.
 #[derive(Clone, Copy)]
 pub struct Buffer {
@@ -24,6 +32,58 @@ pub enum BufferUsage {
     /// Use the buffer as an uniform buffer (can be used in shader as binding)
     UNIFORM = 1,
     STORAGE = 2,
+    /// Use the buffer as a storage buffer that can also contains
+    /// the arguments of an indirect dispatch.
+    INDIRECT = 4,
+    /// Use the buffer as a vertex buffer of a [RenderPipeline].
+    VERTEX = 8,
+    /// Use the buffer as an index buffer (`u32` indices).
+    INDEX = 16,
+}
+
+/// The format of a vertex attribute, the attributes of a vertex are
+/// tightly packed and their locations follow their order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexAttribute {
+    Float32,
+    Float32x2,
+    Float32x3,
+    Float32x4,
+    Uint32,
+}
+
+impl VertexAttribute {
+    /// Get the size of the attribute in bytes.
+    pub fn size(&self) -> u64 {
+        match self {
+            VertexAttribute::Float32    => 4,
+            VertexAttribute::Float32x2  => 8,
+            VertexAttribute::Float32x3  => 12,
+            VertexAttribute::Float32x4  => 16,
+            VertexAttribute::Uint32     => 4,
+        }
+    }
+}
+
+/// An indexed draw of a [RenderPipeline].
+#[derive(Clone, Copy)]
+pub struct DrawIndexed {
+    pub vertex_buffer   : Buffer,
+    pub index_buffer    : Buffer,
+    pub index_count     : u32,
+}
+
+/// The data that can be copied into a buffer: any [bytemuck::Pod]
+/// type, or a structure declared with [wgsl_struct](crate::wgsl_struct).
+pub trait BufferData {
+    /// Get the bytes to copy into the buffer.
+    fn as_bytes(&self) -> Cow<'_, [u8]>;
+}
+
+impl<T: bytemuck::Pod> BufferData for T {
+    fn as_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Borrowed(bytemuck::bytes_of(self))
+    }
 }
 
 pub trait RendererTrait {
@@ -52,9 +112,47 @@ pub trait RendererTrait {
     /// 
     fn resize(&mut self, new_size: (u32, u32));
 
+    /// Release all the GPU resources. This method wait for the
+    /// GPU to be idle, then release the pipelines, the buffers,
+    /// the shaders and the textures (in this order).
+    /// 
+    /// The renderer must not be used after this call.
+    fn shutdown(&mut self);
+
+    /// Handle an event received by the window, before it is
+    /// forwarded to the plugins. Return `true` if the event was
+    /// consumed (by a UI for example), it is then not forwarded.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `event` - The window event.
+    /// 
+    fn handle_window_event(&mut self, _event: &winit::event::WindowEvent) -> bool {
+        false
+    }
+
+    /// Take the GPU time of the passes of the last measured
+    /// frame. Return `None` if the GPU timings are not
+    /// available (yet).
+    fn take_gpu_timings(&mut self) -> Option<GpuFrameTimings>;
+
+    /// Upload the [Globals] of the frame, they are bound to the
+    /// group [GLOBALS_GROUP](crate::engine::globals::GLOBALS_GROUP) of
+    /// the pipelines that declare the `globals` uniform.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `globals` - The globals of the frame.
+    /// 
+    fn update_globals(&mut self, globals: &Globals);
+
     /// Get the renderer size.
     fn get_size(&self) -> (u32, u32);
 
+    /// Get the maximum size in bytes of a storage buffer binding,
+    /// it depends on the adapter.
+    fn max_storage_buffer_size(&self) -> u64;
+
     /// Compile a shader from source.
     /// 
     /// # Arguments
@@ -70,6 +168,17 @@ pub trait RendererTrait {
     /// * `entry_point` - The name of the entry point of the compute shader (by default is `"cs_main"`).
     fn create_compute_pipeline(&mut self, shader: Shader, entry_point: Option<&'static str>) -> ComputePipeline;
 
+    /// Create a render pipeline that draws triangles into the render
+    /// texture, with a depth test. The entry points of the shader are
+    /// `vs_main` and `fs_main`, and the clip space depth is in `[0, 1]`.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `shader`              - The shader.
+    /// * `vertex_attributes`   - The attributes of a vertex, at the locations `0`, `1`...
+    /// 
+    fn create_render_pipeline(&mut self, shader: Shader, vertex_attributes: &[VertexAttribute]) -> RenderPipeline;
+
     /// Create a buffer.
     /// 
     /// # Arguments
@@ -82,22 +191,48 @@ pub trait RendererTrait {
 
     /// Create a buffer and initialize it with some data.
     /// 
+    /// The size of the data is checked against the shader bindings
+    /// the buffer is bound to (see [RendererTrait::set_binding_data]).
+    /// 
     /// # Arguments
     /// 
     /// * `data`        - The data to put into the buffer.
     /// * `usage`       - The usage(s) of the buffer.
     /// * `read_only`   - `true` if the buffer is read only otherwise `false`
-    fn create_buffer_with_data<T: bytemuck::Pod>(&mut self, data: &T, usage: BufferUsage, read_only: bool) -> Buffer;
+    fn create_buffer_with_data<T: BufferData>(&mut self, data: &T, usage: BufferUsage, read_only: bool) -> Buffer;
+
+    /// Create a buffer and initialize it with a slice of data.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `data`        - The data to put into the buffer.
+    /// * `usage`       - The usage(s) of the buffer.
+    /// * `read_only`   - `true` if the buffer is read only otherwise `false`
+    fn create_buffer_with_slice<T: bytemuck::Pod>(&mut self, data: &[T], usage: BufferUsage, read_only: bool) -> Buffer;
 
     /// Update the buffer data.
     /// 
+    /// When the whole buffer is written (`offset` is `0`), the size of
+    /// the data is checked against the shader bindings the buffer is
+    /// bound to, a mismatch is reported as an error.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `buffer`  - The buffer to update.
+    /// * `data`    - The data to copy from.
+    /// * `offset`  - The start index at where the data must be copied.
+    /// 
+    fn update_buffer<T: BufferData>(&self, buffer: Buffer, data: &T, offset: u64);
+
+    /// Update the buffer data from a slice.
+    /// 
     /// # Arguments
     /// 
     /// * `buffer`  - The buffer to update.
     /// * `data`    - The data to copy from.
     /// * `offset`  - The start index at where the data must be copied.
     /// 
-    fn update_buffer<T: bytemuck::Pod>(&self, buffer: Buffer, data: &T, offset: u64);
+    fn update_buffer_with_slice<T: bytemuck::Pod>(&self, buffer: Buffer, data: &[T], offset: u64);
 
     /// Destory a buffer.
     /// 
@@ -107,7 +242,24 @@ pub trait RendererTrait {
     /// 
     fn destroy_buffer(&mut self, buffer: Buffer);
 
-    /// Dispatch a compute pipeline.
+    /// Read the data of a buffer back to the CPU, block until the
+    /// GPU finished all the submitted work.
+    ///
+    /// The passes recorded during the current frame are submitted
+    /// at [RendererTrait::render_end], so their results must be read
+    /// after it (e.g. in the update of the next frame).
+    ///
+    /// # Arguments
+    ///
+    /// * `buffer`  - The buffer to read.
+    /// * `offset`  - The offset of the data in the buffer.
+    /// * `size`    - The size of the data to read.
+    ///
+    fn read_buffer(&mut self, buffer: Buffer, offset: u64, size: u64) -> Vec<u8>;
+
+    /// Dispatch a compute pipeline on the render texture, bound to
+    /// the group 0. Enough workgroups are dispatched to cover all the
+    /// pixels, the shader must ignore the ones outside of the texture.
     /// 
     /// # Arguments
     /// 
@@ -115,5 +267,53 @@ pub trait RendererTrait {
     /// * `workgroups` - The amount of worker for each group.
     fn dispatch_post_process_compute_pipeline(&mut self, pipeline: ComputePipeline, workgroups: (u32, u32, u32));
 
+    /// Dispatch a compute pipeline. Unlike [RendererTrait::dispatch_post_process_compute_pipeline]
+    /// the render texture is not bound and the amount of workgroups is used as is.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `pipeline`    - The pipeline to dispatch.
+    /// * `workgroups`  - The amount of workgroups on each axis.
+    fn dispatch_compute_pipeline(&mut self, pipeline: ComputePipeline, workgroups: (u32, u32, u32));
+
+    /// Dispatch a compute pipeline, the amount of workgroups is
+    /// read from a buffer (three `u32`) filled by a previous pass.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `pipeline`    - The pipeline to dispatch.
+    /// * `buffer`      - The buffer that contains the amount of workgroups (created with [BufferUsage::INDIRECT]).
+    /// * `offset`      - The offset of the arguments in the buffer.
+    fn dispatch_compute_pipeline_indirect(&mut self, pipeline: ComputePipeline, buffer: Buffer, offset: u64);
+
+    /// Bind buffers to a group of a pipeline, the buffer `i` is
+    /// bound to the binding `i`. The size of the data of each buffer
+    /// is checked against the type declared by the shader, and the
+    /// buffers of the bindings the entry point doesn't use are ignored.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `pipeline`    - The pipeline.
+    /// * `group`       - The bind group index.
+    /// * `data`        - The buffers to bind.
     fn set_binding_data(&mut self, pipeline: ComputePipeline, group: u32, data: &[Buffer]);
+
+    /// Bind buffers to a group of a render pipeline, see
+    /// [RendererTrait::set_binding_data].
+    /// 
+    /// # Arguments
+    /// 
+    /// * `pipeline`    - The pipeline.
+    /// * `group`       - The bind group index.
+    /// * `data`        - The buffers to bind.
+    fn set_render_binding_data(&mut self, pipeline: RenderPipeline, group: u32, data: &[Buffer]);
+
+    /// Record a render pass that draws into the render texture (and
+    /// the depth buffer, both cleared at [RendererTrait::render]).
+    /// 
+    /// # Arguments
+    /// 
+    /// * `pipeline`    - The pipeline.
+    /// * `draws`       - The draws of the pass.
+    fn draw_indexed(&mut self, pipeline: RenderPipeline, draws: &[DrawIndexed]);
 }
\ No newline at end of file
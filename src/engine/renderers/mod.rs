@@ -1 +1,6 @@
-pub mod wgpu_renderer;
\ No newline at end of file
+pub mod wgpu_renderer;
+pub(crate) mod wgpu_timer;
+pub(crate) mod wgpu_reflection;
+
+#[cfg(feature = "debug-ui")]
+pub mod wgpu_debug_ui;
\ No newline at end of file
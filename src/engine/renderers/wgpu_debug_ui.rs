@@ -0,0 +1,390 @@
+use std::time::Instant;
+
+use nalgebra::Vector3;
+use winit::event::{ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use crate::engine::{
+    camera::{Camera, Projection},
+    plugin::{Plugin, Resources, Systems},
+    profiler::{Profiler, Timeline},
+    voxel::shading::ShadingSettings,
+};
+
+use super::wgpu_renderer::WGPURenderer;
+
+/// The key that show/hide the debug UI.
+const TOGGLE_KEY: VirtualKeyCode = VirtualKeyCode::F3;
+
+/// An immediate mode UI (egui) drawn on top of the surface,
+/// after the blit of the render texture.
+///
+/// A new UI frame begin with each rendered frame, so the
+/// panels can be added from the render callback with
+/// [WGPURenderer::debug_panel].
+pub struct DebugUi {
+    context     : egui::Context,
+    renderer    : egui_wgpu::Renderer,
+    input       : egui::RawInput,
+    pointer     : Option<egui::Pos2>,
+    modifiers   : egui::Modifiers,
+    scale_factor: f32,
+    start       : Instant,
+    last_frame  : Instant,
+    frame_time  : f32,
+    visible     : bool,
+}
+
+impl DebugUi {
+    /// Create a new [DebugUi].
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - The device used to create the UI resources.
+    /// * `format` - The surface texture format.
+    ///
+    pub(crate) fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self {
+            context     : egui::Context::default(),
+            renderer    : egui_wgpu::Renderer::new(device, format, None, 1),
+            input       : egui::RawInput::default(),
+            pointer     : None,
+            modifiers   : egui::Modifiers::default(),
+            scale_factor: 1.0,
+            start       : Instant::now(),
+            last_frame  : Instant::now(),
+            frame_time  : 0.0,
+            visible     : true,
+        }
+    }
+
+    /// Get the egui context of the current frame.
+    pub fn context(&self) -> &egui::Context {
+        &self.context
+    }
+
+    /// Check if the UI is visible.
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Show or hide the UI (it can also be toggled with `F3`).
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Get the amount of frames per second, averaged over the last frames.
+    pub fn fps(&self) -> f32 {
+        if self.frame_time > 0.0 { 1.0 / self.frame_time } else { 0.0 }
+    }
+
+    /// Check if the UI is currently using the mouse (hovering a
+    /// window, dragging a slider...).
+    pub fn wants_pointer_input(&self) -> bool {
+        self.visible && self.context.wants_pointer_input()
+    }
+
+    /// Check if the UI is currently using the keyboard (a text
+    /// field has the focus).
+    pub fn wants_keyboard_input(&self) -> bool {
+        self.visible && self.context.wants_keyboard_input()
+    }
+
+    /// Translate a window event into egui input. Return `true` if
+    /// the UI uses the event, it must then be ignored by the game.
+    ///
+    /// The releases are never consumed, so a key or a button
+    /// pressed outside of the UI doesn't stay down.
+    pub(crate) fn handle_event(&mut self, event: &WindowEvent) -> bool {
+        let consumed = match event {
+            WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode, .. }, .. } => {
+                *virtual_keycode != Some(TOGGLE_KEY) && self.wants_keyboard_input()
+            },
+            WindowEvent::ReceivedCharacter(_) => self.wants_keyboard_input(),
+            WindowEvent::MouseInput { state: ElementState::Pressed, .. } | WindowEvent::MouseWheel { .. } => {
+                self.wants_pointer_input()
+            },
+            _ => false,
+        };
+
+        match event {
+            WindowEvent::KeyboardInput {
+                input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(TOGGLE_KEY), .. },
+                ..
+            } => self.visible = !self.visible,
+
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor as f32;
+            },
+
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = egui::Modifiers {
+                    alt     : state.alt(),
+                    ctrl    : state.ctrl(),
+                    shift   : state.shift(),
+                    mac_cmd : cfg!(target_os = "macos") && state.logo(),
+                    command : if cfg!(target_os = "macos") { state.logo() } else { state.ctrl() },
+                };
+            },
+
+            WindowEvent::CursorMoved { position, .. } => {
+                let pos = egui::pos2(
+                    position.x as f32 / self.scale_factor,
+                    position.y as f32 / self.scale_factor,
+                );
+
+                self.pointer = Some(pos);
+                self.input.events.push(egui::Event::PointerMoved(pos));
+            },
+
+            WindowEvent::CursorLeft { .. } => {
+                self.pointer = None;
+                self.input.events.push(egui::Event::PointerGone);
+            },
+
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left   => egui::PointerButton::Primary,
+                    MouseButton::Right  => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return consumed,
+                };
+
+                if let Some(pos) = self.pointer {
+                    self.input.events.push(egui::Event::PointerButton {
+                        pos,
+                        button,
+                        pressed  : *state == ElementState::Pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+            },
+
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    // Approximate a line to 50 points.
+                    MouseScrollDelta::LineDelta(x, y) => egui::vec2(*x, *y) * 50.0,
+                    MouseScrollDelta::PixelDelta(delta) => egui::vec2(delta.x as f32, delta.y as f32) / self.scale_factor,
+                };
+
+                self.input.events.push(egui::Event::Scroll(delta));
+            },
+
+            WindowEvent::ReceivedCharacter(c) if !c.is_control() => {
+                self.input.events.push(egui::Event::Text(c.to_string()));
+            },
+
+            _ => {},
+        }
+
+        consumed
+    }
+
+    /// Begin a new UI frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - The surface size in pixels.
+    ///
+    pub(crate) fn begin_frame(&mut self, size: (u32, u32)) {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_frame).as_secs_f32();
+        self.last_frame = now;
+
+        // Smooth the frame time, otherwise the readout is unreadable.
+        self.frame_time = if self.frame_time == 0.0 { delta } else { self.frame_time * 0.95 + delta * 0.05 };
+
+        let mut input = std::mem::take(&mut self.input);
+        input.screen_rect = Some(egui::Rect::from_min_size(
+            egui::Pos2::ZERO,
+            egui::vec2(size.0 as f32, size.1 as f32) / self.scale_factor,
+        ));
+        input.pixels_per_point = Some(self.scale_factor);
+        input.time = Some(self.start.elapsed().as_secs_f64());
+        input.modifiers = self.modifiers;
+
+        self.context.begin_frame(input);
+
+        if self.visible {
+            let fps = self.fps();
+            let frame_time = self.frame_time * 1000.0;
+
+            egui::Window::new("Stats")
+                .resizable(false)
+                .show(&self.context, |ui| {
+                    ui.label(format!("{:.0} FPS ({:.2} ms)", fps, frame_time));
+                });
+        }
+    }
+
+    /// End the UI frame and record the commands that draw it
+    /// on top of the surface.
+    ///
+    /// # Arguments
+    ///
+    /// * `device`  - The device.
+    /// * `queue`   - The queue.
+    /// * `encoder` - The encoder of the frame.
+    /// * `view`    - The surface texture view.
+    /// * `size`    - The surface size in pixels.
+    ///
+    /// Return the command buffers that must be submitted
+    /// before the encoder.
+    pub(crate) fn draw(
+        &mut self,
+        device  : &wgpu::Device,
+        queue   : &wgpu::Queue,
+        encoder : &mut wgpu::CommandEncoder,
+        view    : &wgpu::TextureView,
+        size    : (u32, u32),
+    ) -> Vec<wgpu::CommandBuffer> {
+        let output = self.context.end_frame();
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+
+        let mut command_buffers = Vec::new();
+
+        if self.visible {
+            let primitives = self.context.tessellate(output.shapes);
+            let screen = egui_wgpu::renderer::ScreenDescriptor {
+                size_in_pixels  : [size.0, size.1],
+                pixels_per_point: self.scale_factor,
+            };
+
+            command_buffers = self.renderer.update_buffers(device, queue, encoder, &primitives, &screen);
+
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Debug UI Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+
+            self.renderer.render(&mut pass, &primitives, &screen);
+        }
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+
+        command_buffers
+    }
+}
+
+/// A plugin that show the [Profiler] statistics in the debug UI,
+/// and the sliders that edit the [Camera] and the [ShadingSettings]
+/// resources.
+#[derive(Default)]
+pub struct DebugUiPlugin;
+
+impl Plugin<WGPURenderer> for DebugUiPlugin {
+    fn build(&mut self, systems: &mut Systems<WGPURenderer>, _resources: &mut Resources) {
+        systems.add_render_system(|resources, renderer| {
+            // The resources are removed while the panel borrows them.
+            let mut camera = resources.remove::<Camera>();
+            let mut shading = resources.remove::<ShadingSettings>();
+
+            if camera.is_some() || shading.is_some() {
+                renderer.debug_panel("Settings", |ui| {
+                    if let Some(camera) = camera.as_mut() {
+                        camera_settings(ui, camera);
+                    }
+
+                    if let Some(shading) = shading.as_mut() {
+                        shading_settings(ui, shading);
+                    }
+                });
+            }
+
+            if let Some(camera) = camera {
+                resources.insert(camera);
+            }
+
+            if let Some(shading) = shading {
+                resources.insert(shading);
+            }
+        });
+
+        systems.add_render_system(|resources, renderer| {
+            let Some(profiler) = resources.get::<Profiler>() else { return };
+
+            renderer.debug_panel("Profiler", |ui| {
+                egui::Grid::new("profiler_stats").striped(true).show(ui, |ui| {
+                    ui.label("Scope");
+                    ui.label("avg");
+                    ui.label("min");
+                    ui.label("max");
+                    ui.label("p99");
+                    ui.end_row();
+
+                    for (timeline, name, stats) in profiler.all_stats() {
+                        let timeline = match timeline {
+                            Timeline::Cpu => "CPU",
+                            Timeline::Gpu => "GPU",
+                        };
+
+                        ui.label(format!("[{}] {}", timeline, name));
+                        ui.label(format!("{:.2}", stats.avg));
+                        ui.label(format!("{:.2}", stats.min));
+                        ui.label(format!("{:.2}", stats.max));
+                        ui.label(format!("{:.2}", stats.p99));
+                        ui.end_row();
+                    }
+                });
+            });
+        });
+    }
+}
+
+/// Add the sliders of the camera field of view.
+fn camera_settings(ui: &mut egui::Ui, camera: &mut Camera) {
+    let Projection::Perspective { fov, near, far } = *camera.projection() else { return };
+    let mut fov = fov;
+
+    ui.heading("Camera");
+
+    if ui.add(egui::Slider::new(&mut fov, 20.0..=120.0).text("FOV (°)")).changed() {
+        camera.set_projection(Projection::Perspective { fov, near, far });
+    }
+}
+
+/// Add the sliders of the secondary rays and of the sun.
+fn shading_settings(ui: &mut egui::Ui, shading: &mut ShadingSettings) {
+    ui.heading("Rays");
+    ui.add(egui::Slider::new(&mut shading.shadow_rays, 0..=16).text("Shadow rays"));
+    ui.add(egui::Slider::new(&mut shading.shadow_distance, 0.0..=512.0).text("Shadow distance"));
+    ui.add(egui::Slider::new(&mut shading.ao_rays, 0..=32).text("AO rays"));
+    ui.add(egui::Slider::new(&mut shading.ao_distance, 0.0..=16.0).text("AO distance"));
+    ui.add(egui::Slider::new(&mut shading.max_ray_steps, 16..=1024).text("Camera ray steps"));
+    ui.add(egui::Slider::new(&mut shading.max_trace_steps, 16..=1024).text("Shading ray steps"));
+
+    // The sun direction is edited as angles, the vector is
+    // normalized by the shaders.
+    let direction = shading.sun_direction.normalize();
+    let mut azimuth = direction.z.atan2(direction.x).to_degrees();
+    let mut elevation = direction.y.clamp(-1.0, 1.0).asin().to_degrees();
+
+    ui.heading("Light");
+
+    let azimuth_changed = ui.add(egui::Slider::new(&mut azimuth, -180.0..=180.0).text("Sun azimuth (°)")).changed();
+    let elevation_changed = ui.add(egui::Slider::new(&mut elevation, -90.0..=90.0).text("Sun elevation (°)")).changed();
+
+    if azimuth_changed || elevation_changed {
+        let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+
+        shading.sun_direction = Vector3::new(
+            elevation.cos() * azimuth.cos(),
+            elevation.sin(),
+            elevation.cos() * azimuth.sin(),
+        );
+    }
+
+    ui.add(egui::Slider::new(&mut shading.shadow_softness, 0.0..=0.2).text("Sun radius (rad)"));
+}
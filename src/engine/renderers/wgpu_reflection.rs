@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::engine::logging;
+
+/// The layout of a buffer binding declared in a shader.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct BindingLayout {
+    /// The name of the variable in the shader.
+    pub name    : String,
+    /// The size of the fixed part of the type.
+    pub size    : u64,
+    /// The stride of the elements of the runtime sized array that
+    /// end the type (if any).
+    pub stride  : Option<u64>,
+}
+
+impl BindingLayout {
+    /// Check if data of `size` bytes match the binding type.
+    pub fn accepts(&self, size: u64) -> bool {
+        match self.stride {
+            Some(stride) => size >= self.size && (size - self.size).is_multiple_of(stride),
+            None => size == self.size,
+        }
+    }
+}
+
+impl fmt::Display for BindingLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.stride {
+            Some(stride) => write!(f, "`{}` ({} + N * {} bytes)", self.name, self.size, stride),
+            None => write!(f, "`{}` ({} bytes)", self.name, self.size),
+        }
+    }
+}
+
+/// The buffer bindings of a shader, indexed by `(group, binding)`.
+pub(crate) type Bindings = HashMap<(u32, u32), BindingLayout>;
+
+/// Find the uniform and storage buffers used by each entry point of
+/// a WGSL shader, indexed by the name of the entry point. The
+/// bindings declared but not used by an entry point aren't in the
+/// layout of its pipeline, so they are left out.
+///
+/// A shader that can't be parsed has no bindings, the error is
+/// reported by wgpu when the shader module is created.
+pub(crate) fn reflect_bindings(source: &str) -> HashMap<String, Bindings> {
+    let module = match naga::front::wgsl::parse_str(source) {
+        Ok(module) => module,
+        Err(error) => {
+            log::debug!(target: logging::RENDERER, "Can't reflect the shader bindings: {}", error);
+            return HashMap::new();
+        },
+    };
+
+    module.entry_points
+        .iter()
+        .map(|entry_point| {
+            let mut used = HashSet::new();
+            find_used_globals(&module, &entry_point.function, &mut used, &mut HashSet::new());
+
+            let bindings = used.into_iter()
+                .map(|handle| &module.global_variables[handle])
+                .filter(|variable| matches!(variable.space, naga::AddressSpace::Uniform | naga::AddressSpace::Storage { .. }))
+                .filter_map(|variable| {
+                    let binding = variable.binding.as_ref()?;
+                    let (size, stride) = type_layout(&module, variable.ty);
+
+                    let layout = BindingLayout {
+                        name: variable.name.clone().unwrap_or_default(),
+                        size,
+                        stride,
+                    };
+
+                    Some(((binding.group, binding.binding), layout))
+                })
+                .collect();
+
+            (entry_point.name.clone(), bindings)
+        })
+        .collect()
+}
+
+/// Find the global variables used by a function and by the
+/// functions it calls.
+fn find_used_globals(
+    module: &naga::Module,
+    function: &naga::Function,
+    used: &mut HashSet<naga::Handle<naga::GlobalVariable>>,
+    visited: &mut HashSet<naga::Handle<naga::Function>>,
+) {
+    for (_, expression) in function.expressions.iter() {
+        if let naga::Expression::GlobalVariable(handle) = expression {
+            used.insert(*handle);
+        }
+    }
+
+    let mut calls = Vec::new();
+    find_calls(&function.body, &mut calls);
+
+    for call in calls {
+        if visited.insert(call) {
+            find_used_globals(module, &module.functions[call], used, visited);
+        }
+    }
+}
+
+/// Find the functions called by the statements of a block.
+fn find_calls(block: &naga::Block, calls: &mut Vec<naga::Handle<naga::Function>>) {
+    for statement in block.iter() {
+        match statement {
+            naga::Statement::Call { function, .. } => calls.push(*function),
+            naga::Statement::Block(block) => find_calls(block, calls),
+            naga::Statement::If { accept, reject, .. } => {
+                find_calls(accept, calls);
+                find_calls(reject, calls);
+            },
+            naga::Statement::Switch { cases, .. } => {
+                for case in cases {
+                    find_calls(&case.body, calls);
+                }
+            },
+            naga::Statement::Loop { body, continuing, .. } => {
+                find_calls(body, calls);
+                find_calls(continuing, calls);
+            },
+            _ => {},
+        }
+    }
+}
+
+/// Get the size of the fixed part of a type and the stride of its
+/// runtime sized array.
+fn type_layout(module: &naga::Module, ty: naga::Handle<naga::Type>) -> (u64, Option<u64>) {
+    let inner = &module.types[ty].inner;
+
+    match inner {
+        naga::TypeInner::Array { size: naga::ArraySize::Dynamic, stride, .. } => (0, Some(*stride as u64)),
+
+        naga::TypeInner::Struct { members, span } => {
+            let runtime_array = members.last().and_then(|member| match module.types[member.ty].inner {
+                naga::TypeInner::Array { size: naga::ArraySize::Dynamic, stride, .. } => Some((member.offset, stride)),
+                _ => None,
+            });
+
+            match runtime_array {
+                Some((offset, stride)) => (offset as u64, Some(stride as u64)),
+                None => (*span as u64, None),
+            }
+        },
+
+        _ => (inner.size(&module.constants) as u64, None),
+    }
+}
@@ -1,17 +1,94 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+
 use raw_window_handle::{HasRawWindowHandle, HasRawDisplayHandle};
 use wgpu::util::DeviceExt;
-use crate::engine::renderer::{RendererTrait, Shader, ComputePipeline, BufferUsage, Buffer};
+use crate::engine::{
+    globals::{Globals, GLOBALS_BINDING, GLOBALS_GROUP},
+    logging,
+    shader_type::ShaderType,
+    profiler::GpuFrameTimings,
+    renderer::{RendererTrait, Shader, ComputePipeline, RenderPipeline, BufferUsage, Buffer, BufferData, DrawIndexed, VertexAttribute},
+};
+
+use super::wgpu_reflection::{self, BindingLayout, Bindings};
+use super::wgpu_timer::GpuTimer;
+
+#[cfg(feature = "debug-ui")]
+use super::wgpu_debug_ui::DebugUi;
+
+struct InternalShader {
+    module: wgpu::ShaderModule,
+    /// The buffers used by each entry point of the shader.
+    bindings: HashMap<String, Bindings>,
+}
+
+impl InternalShader {
+    /// Get the buffers used by some entry points.
+    fn entry_point_bindings(&self, entry_points: &[&str]) -> Bindings {
+        entry_points.iter()
+            .filter_map(|entry_point| self.bindings.get(*entry_point))
+            .flat_map(|bindings| bindings.iter().map(|(key, binding)| (*key, binding.clone())))
+            .collect()
+    }
+}
 
 struct InternalComputePipeline {
     pipeline: wgpu::ComputePipeline,
     bind_groups: Vec<(usize, wgpu::BindGroup)>,
+    bindings: Bindings,
+    /// `true` if its group 0 is the render texture, bound by
+    /// [WGPURenderer::dispatch_post_process_compute_pipeline].
+    post_process: bool,
 }
 
 impl InternalComputePipeline {
-    pub fn new(pipeline: wgpu::ComputePipeline) -> Self {
+    pub fn new(pipeline: wgpu::ComputePipeline, bindings: Bindings) -> Self {
         Self {
             pipeline,
             bind_groups: Vec::new(),
+            bindings,
+            post_process: false,
+        }
+    }
+}
+
+struct InternalRenderPipeline {
+    pipeline: wgpu::RenderPipeline,
+    bind_groups: Vec<(usize, wgpu::BindGroup)>,
+    bindings: Bindings,
+}
+
+struct InternalBuffer {
+    buffer: wgpu::Buffer,
+    /// The size of the last data written to the whole buffer.
+    data_size: Cell<Option<u64>>,
+    /// The shader bindings the buffer is bound to.
+    bindings: Vec<BindingLayout>,
+}
+
+impl InternalBuffer {
+    pub fn new(buffer: wgpu::Buffer, data_size: Option<u64>) -> Self {
+        Self {
+            buffer,
+            data_size: Cell::new(data_size),
+            bindings: Vec::new(),
+        }
+    }
+
+    /// Record the size of the data written to the buffer and
+    /// check it against the bindings.
+    fn set_data_size(&self, id: usize, size: u64) {
+        self.data_size.set(Some(size));
+        self.check_data_size(id);
+    }
+
+    /// Report the bindings that don't match the size of the data.
+    fn check_data_size(&self, id: usize) {
+        let Some(size) = self.data_size.get() else { return };
+
+        for binding in self.bindings.iter().filter(|binding| !binding.accepts(size)) {
+            log::error!(target: logging::RENDERER, "Buffer {} holds {} bytes of data but it is bound to {}", id, size, binding);
         }
     }
 }
@@ -28,13 +105,232 @@ pub struct WGPURenderer {
     main_texture_view: Option<wgpu::TextureView>,
 
     render_texture: wgpu::Texture,
+    /// The depth buffer of the render pipelines.
+    depth_texture: wgpu::Texture,
 
     blit_pipeline: wgpu::RenderPipeline,
     blit_bind_group: wgpu::BindGroup,
 
-    shaders : Vec<wgpu::ShaderModule>,
+    shaders : Vec<InternalShader>,
     compute_pipelines: Vec<InternalComputePipeline>,
-    buffers : Vec<wgpu::Buffer>,
+    render_pipelines: Vec<InternalRenderPipeline>,
+    buffers : Vec<InternalBuffer>,
+
+    /// The uniform buffer of the [Globals].
+    globals_buffer: wgpu::Buffer,
+
+    /// Measure the GPU time of each pass, `None` if the
+    /// adapter don't support timestamp queries.
+    gpu_timer: Option<GpuTimer>,
+
+    #[cfg(feature = "debug-ui")]
+    debug_ui: DebugUi,
+}
+
+#[cfg(feature = "debug-ui")]
+impl WGPURenderer {
+    /// Get the debug UI.
+    pub fn debug_ui(&mut self) -> &mut DebugUi {
+        &mut self.debug_ui
+    }
+
+    /// Show a panel in the debug UI for the current frame. Must
+    /// be called each frame (from the render callback for example)
+    /// while the panel should be visible.
+    ///
+    /// # Arguments
+    ///
+    /// * `title`        - The title of the panel, used as identifier.
+    /// * `add_contents` - Add the widgets of the panel.
+    ///
+    pub fn debug_panel<F: FnOnce(&mut egui::Ui)>(&mut self, title: &str, add_contents: F) {
+        if !self.debug_ui.is_visible() { return; }
+
+        egui::Window::new(title).show(self.debug_ui.context(), add_contents);
+    }
+}
+
+/// Convert a [BufferUsage] to the wgpu buffer usages.
+fn buffer_usages(usage: BufferUsage, read_only: bool) -> wgpu::BufferUsages {
+    let mut usage = match usage {
+        BufferUsage::UNIFORM    => wgpu::BufferUsages::UNIFORM,
+        BufferUsage::STORAGE    => wgpu::BufferUsages::STORAGE,
+        BufferUsage::INDIRECT   => wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+        BufferUsage::VERTEX     => wgpu::BufferUsages::VERTEX,
+        BufferUsage::INDEX      => wgpu::BufferUsages::INDEX,
+    };
+
+    if read_only {
+        usage |= wgpu::BufferUsages::COPY_DST;
+    }
+
+    // All the buffers can be read back (see RendererTrait::read_buffer).
+    usage | wgpu::BufferUsages::COPY_SRC
+}
+
+/// The format of the depth buffer.
+const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+fn create_depth_texture(device: &wgpu::Device, size: winit::dpi::PhysicalSize<u32>) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label           : Some("DepthTexture"),
+        size            : wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 },
+        mip_level_count : 1,
+        sample_count    : 1,
+        dimension       : wgpu::TextureDimension::D2,
+        format          : DEPTH_FORMAT,
+        usage           : wgpu::TextureUsages::RENDER_ATTACHMENT,
+    })
+}
+
+/// Create the bind group of the blit pipeline, that samples the
+/// render texture.
+fn create_blit_bind_group(device: &wgpu::Device, blit_pipeline: &wgpu::RenderPipeline, render_texture: &wgpu::Texture) -> wgpu::BindGroup {
+    let render_texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("RenderTextureSampler"),
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::Repeat,
+        address_mode_w: wgpu::AddressMode::Repeat,
+        mag_filter: wgpu::FilterMode::Nearest,
+        min_filter: wgpu::FilterMode::Nearest,
+        mipmap_filter: wgpu::FilterMode::Nearest,
+        ..Default::default()
+    });
+
+    let render_texture_view = render_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Blit Bind Group"),
+        layout: &blit_pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&render_texture_view),
+            },
+
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&render_texture_sampler),
+            },
+        ]
+    })
+}
+
+fn vertex_format(attribute: VertexAttribute) -> wgpu::VertexFormat {
+    match attribute {
+        VertexAttribute::Float32    => wgpu::VertexFormat::Float32,
+        VertexAttribute::Float32x2  => wgpu::VertexFormat::Float32x2,
+        VertexAttribute::Float32x3  => wgpu::VertexFormat::Float32x3,
+        VertexAttribute::Float32x4  => wgpu::VertexFormat::Float32x4,
+        VertexAttribute::Uint32     => wgpu::VertexFormat::Uint32,
+    }
+}
+
+/// Create the bind group of buffers bound to a group of a pipeline,
+/// the buffers remember the bindings to check the size of their data.
+fn create_buffers_bind_group(
+    device: &wgpu::Device,
+    buffers: &mut [InternalBuffer],
+    bindings: &Bindings,
+    layout: &wgpu::BindGroupLayout,
+    group: u32,
+    data: &[Buffer],
+) -> wgpu::BindGroup {
+    if group == GLOBALS_GROUP {
+        log::warn!(target: logging::RENDERER, "The bind group {} is reserved for the globals", GLOBALS_GROUP);
+    }
+
+    for (index, buffer) in data.iter().enumerate() {
+        let Some(binding) = bindings.get(&(group, index as u32)) else { continue };
+        let internal = &mut buffers[buffer.id];
+
+        if !internal.bindings.contains(binding) {
+            internal.bindings.push(binding.clone());
+            internal.check_data_size(buffer.id);
+        }
+    }
+
+    // The buffers of the bindings the pipeline doesn't use aren't
+    // in its layout.
+    let entries = data.iter().enumerate()
+        .filter(|(index, _)| bindings.contains_key(&(group, *index as u32)))
+        .map(|(index, buff)| {
+            wgpu::BindGroupEntry {
+                binding: index as u32,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffers[buff.id].buffer,
+                    offset: 0,
+                    size: None,
+                }),
+            }
+        });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout,
+        entries: &entries.collect::<Vec<_>>(),
+    })
+}
+
+/// Replace the bind group of a group (or add it).
+fn set_bind_group(bind_groups: &mut Vec<(usize, wgpu::BindGroup)>, group: u32, bind_group: wgpu::BindGroup) {
+    let bind_group = (group as usize, bind_group);
+
+    if let Some(index) = bind_groups.iter().position(|(id, _)| *id == (group as usize)) {
+        bind_groups[index] = bind_group;
+    } else {
+        bind_groups.push(bind_group);
+    }
+}
+
+impl WGPURenderer {
+    /// Create the bind group of the globals for a pipeline.
+    fn create_globals_bind_group(&self, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Globals Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: GLOBALS_BINDING,
+                    resource: self.globals_buffer.as_entire_binding(),
+                }
+            ]
+        })
+    }
+
+    /// Record a compute pass that dispatch a pipeline with all
+    /// its bind groups.
+    fn record_compute_pass(&mut self, pipeline: ComputePipeline, dispatch: ComputeDispatch) {
+        let encoder = self.main_encoder.as_mut().unwrap();
+        let timestamp = self.gpu_timer.as_mut().and_then(|timer| timer.begin_pass(encoder, format!("Compute Pipeline {}", pipeline.id)));
+
+        let pipeline = &self.compute_pipelines[pipeline.id];
+
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+
+            for (group, bind_group) in &pipeline.bind_groups {
+                pass.set_bind_group(*group as u32, bind_group, &[]);
+            }
+
+            pass.set_pipeline(&pipeline.pipeline);
+
+            match dispatch {
+                ComputeDispatch::Direct(x, y, z) => pass.dispatch_workgroups(x, y, z),
+                ComputeDispatch::Indirect(buffer, offset) => pass.dispatch_workgroups_indirect(&self.buffers[buffer.id].buffer, offset),
+            }
+        }
+
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.end_pass(encoder, timestamp);
+        }
+    }
+}
+
+/// How the amount of workgroups of a compute pass is given.
+enum ComputeDispatch {
+    Direct(u32, u32, u32),
+    Indirect(Buffer, u64),
 }
 
 impl RendererTrait for WGPURenderer {
@@ -42,23 +338,45 @@ impl RendererTrait for WGPURenderer {
         let size = winit::dpi::PhysicalSize::new(size.0, size.1);
         let instance = wgpu::Instance::new(wgpu::Backends::all());
         let surface = unsafe { instance.create_surface(surface) };
-        let adapter = pollster::block_on(instance.request_adapter(
+        let request_adapter = |force_fallback_adapter| pollster::block_on(instance.request_adapter(
             &wgpu::RequestAdapterOptions {
                 power_preference        : wgpu::PowerPreference::default(),
                 compatible_surface      : Some(&surface),
-                force_fallback_adapter  : false,
+                force_fallback_adapter,
             },
-        )).unwrap();
+        ));
+
+        // Without GPU (e.g. a CI or a virtual machine) the software
+        // adapter is used, if the platform has one.
+        let adapter = request_adapter(false)
+            .or_else(|| {
+                log::warn!(target: logging::RENDERER, "No hardware adapter available, trying the fallback adapter");
+                request_adapter(true)
+            })
+            .expect("No graphics adapter available");
+
+        let info = adapter.get_info();
+        log::info!(target: logging::RENDERER, "Using adapter {} ({:?}, {:?})", info.name, info.device_type, info.backend);
 
         let (device, queue) = pollster::block_on(adapter.request_device(
             &wgpu::DeviceDescriptor {
-                features: wgpu::Features::empty(),
-                limits  : wgpu::Limits::default(),
+                // Only used by the profiler when available.
+                features: adapter.features() & wgpu::Features::TIMESTAMP_QUERY,
+                // The fallback adapters may not reach the default
+                // limits, the largest supported ones are requested.
+                limits  : adapter.limits(),
                 label   : None,
             },
             None,
         )).unwrap();
 
+        device.on_uncaptured_error(|error| {
+            match error {
+                wgpu::Error::OutOfMemory { .. } => log::error!(target: logging::WGPU, "Out of memory: {}", error),
+                wgpu::Error::Validation { description, .. } => log::error!(target: logging::WGPU, "Validation error: {}", description),
+            }
+        });
+
         let config = wgpu::SurfaceConfiguration {
             usage       : wgpu::TextureUsages::RENDER_ATTACHMENT,
             format      : surface.get_supported_formats(&adapter)[0],
@@ -70,6 +388,19 @@ impl RendererTrait for WGPURenderer {
 
         surface.configure(&device, &config);
 
+        let gpu_timer = GpuTimer::new(&device, &queue);
+
+        let globals_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Globals Buffer"),
+            size: Globals::SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        if gpu_timer.is_none() {
+            log::info!(target: logging::RENDERER, "Timestamp queries are not supported, the GPU passes will not be profiled");
+        }
+
         let render_texture = device.create_texture(&wgpu::TextureDescriptor {
             label           : Some("RenderTexture"),
             size            : wgpu::Extent3d { width: size.width, height: size.height, depth_or_array_layers: 1 },
@@ -80,16 +411,7 @@ impl RendererTrait for WGPURenderer {
             usage           : wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
         });
 
-        let render_texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            label: Some("RenderTextureSampler"),
-            address_mode_u: wgpu::AddressMode::Repeat,
-            address_mode_v: wgpu::AddressMode::Repeat,
-            address_mode_w: wgpu::AddressMode::Repeat,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
-            ..Default::default()
-        });
+        let depth_texture = create_depth_texture(&device, size);
 
         let blit_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Blit Shader"),
@@ -119,23 +441,7 @@ impl RendererTrait for WGPURenderer {
             multiview: None,
         });
 
-        let render_texture_view = render_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-        let blit_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("Blit Bind Group"),
-            layout: &blit_pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&render_texture_view),
-                },
-
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&render_texture_sampler),
-                },
-            ]
-        });
+        let blit_bind_group = create_blit_bind_group(&device, &blit_pipeline, &render_texture);
 
         Self {
             surface,
@@ -149,20 +455,38 @@ impl RendererTrait for WGPURenderer {
             main_encoder: None,
 
             render_texture,
+            depth_texture,
 
             blit_pipeline,
             blit_bind_group,
 
             shaders: Vec::new(),
             compute_pipelines: Vec::new(),
+            render_pipelines: Vec::new(),
             buffers: Vec::new(),
+
+            globals_buffer,
+
+            gpu_timer,
+
+            #[cfg(feature = "debug-ui")]
+            debug_ui: DebugUi::new(&device, config.format),
         }
     }
 
+    fn update_globals(&mut self, globals: &Globals) {
+        self.queue.write_buffer(&self.globals_buffer, 0, &globals.to_bytes());
+    }
+
     fn get_size(&self) -> (u32, u32) {
         (self.config.width, self.config.height)
     }
 
+    fn max_storage_buffer_size(&self) -> u64 {
+        let limits = self.device.limits();
+        (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size)
+    }
+
     fn render_begin(&mut self) {
         let output = self.surface.get_current_texture().unwrap();
         let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
@@ -171,6 +495,13 @@ impl RendererTrait for WGPURenderer {
             label: Some("Render Encoder"),
         });
 
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.begin_frame(&self.device);
+        }
+
+        #[cfg(feature = "debug-ui")]
+        self.debug_ui.begin_frame((self.config.width, self.config.height));
+
         self.main_surface_texture = Some(output);
         self.main_texture_view = Some(view);
         self.main_encoder = Some(encoder);
@@ -178,9 +509,11 @@ impl RendererTrait for WGPURenderer {
 
     fn render(&mut self) {
         let encoder = self.main_encoder.as_mut().unwrap();
+        let timestamp = self.gpu_timer.as_mut().and_then(|timer| timer.begin_pass(encoder, "Render Pass"));
         
         {
             let render_texture_view = self.render_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let depth_texture_view = self.depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
              
             let mut _render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -199,15 +532,26 @@ impl RendererTrait for WGPURenderer {
                         },
                     })
                 ],
-                depth_stencil_attachment: None,
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
             });
         }
-        
+
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.end_pass(encoder, timestamp);
+        }
     }
 
     fn render_end(&mut self) {
         let mut encoder = self.main_encoder.take().unwrap();
         let view = self.main_surface_texture.as_ref().unwrap().texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let timestamp = self.gpu_timer.as_mut().and_then(|timer| timer.begin_pass(&mut encoder, "Post Process Pass"));
 
         {
             let mut post_process_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
@@ -228,10 +572,36 @@ impl RendererTrait for WGPURenderer {
             post_process_pass.draw(0..4, 0..1);
         }
 
+        // The UI is drawn after the end of the measured pass, it
+        // isn't part of the frame.
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.end_pass(&mut encoder, timestamp);
+        }
+
+        #[cfg(feature = "debug-ui")]
+        let ui_command_buffers = self.debug_ui.draw(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &view,
+            (self.config.width, self.config.height),
+        );
+
+        #[cfg(not(feature = "debug-ui"))]
+        let ui_command_buffers = Vec::new();
+
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.resolve(&mut encoder);
+        }
+
         let output = self.main_surface_texture.take().unwrap();
         
-        self.queue.submit(std::iter::once(encoder.finish()));
+        self.queue.submit(ui_command_buffers.into_iter().chain(std::iter::once(encoder.finish())));
         output.present();
+
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.after_submit();
+        }
     }
 
     fn resize(&mut self, new_size: (u32, u32)) {
@@ -253,18 +623,79 @@ impl RendererTrait for WGPURenderer {
                 format          : wgpu::TextureFormat::Rgba8Unorm,
                 usage           : wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
             });
+
+            self.depth_texture.destroy();
+            self.depth_texture = create_depth_texture(&self.device, new_size);
+
+            // The bind groups of the old render texture are created
+            // again with the new one.
+            self.blit_bind_group = create_blit_bind_group(&self.device, &self.blit_pipeline, &self.render_texture);
+
+            for pipeline in self.compute_pipelines.iter_mut().filter(|pipeline| pipeline.post_process) {
+                pipeline.bind_groups.retain(|(group, _)| *group != 0);
+            }
+        }
+    }
+
+    fn shutdown(&mut self) {
+        log::info!(target: logging::RENDERER, "Shutting down the renderer");
+
+        // Drop the frame that is currently being recorded (if
+        // any) without submitting it.
+        self.main_encoder = None;
+        self.main_texture_view = None;
+        self.main_surface_texture = None;
+
+        // Wait until all the submitted work is done...
+        self.device.poll(wgpu::Maintain::Wait);
+
+        self.gpu_timer = None;
+
+        // The pipelines and their bind groups first because
+        // the bind groups reference the buffers and textures.
+        self.compute_pipelines.clear();
+        self.render_pipelines.clear();
+
+        for buffer in self.buffers.drain(..) {
+            buffer.buffer.destroy();
         }
+
+        self.globals_buffer.destroy();
+
+        self.shaders.clear();
+        self.render_texture.destroy();
+        self.depth_texture.destroy();
+    }
+
+    fn handle_window_event(&mut self, _event: &winit::event::WindowEvent) -> bool {
+        #[cfg(feature = "debug-ui")]
+        let consumed = self.debug_ui.handle_event(_event);
+
+        #[cfg(not(feature = "debug-ui"))]
+        let consumed = false;
+
+        consumed
+    }
+
+    fn take_gpu_timings(&mut self) -> Option<GpuFrameTimings> {
+        self.gpu_timer
+            .as_mut()
+            .and_then(|timer| timer.take_results())
     }
 
     fn compile_shader(&mut self, source: impl Into<String>) -> Shader {
+        let source = source.into();
+        let bindings = wgpu_reflection::reflect_bindings(&source);
 
         let module = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(source.into().into()),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
         });
 
         let id = self.shaders.len();
-        self.shaders.push(module);
+        self.shaders.push(InternalShader { module, bindings });
+
+        log::debug!(target: logging::RENDERER, "Compile shader {}", id);
 
         Shader { id }
     }
@@ -273,28 +704,107 @@ impl RendererTrait for WGPURenderer {
         let pipeline = self.device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
             label       : None,
             layout      : None,
-            module      : &self.shaders[shader.id],
+            module      : &self.shaders[shader.id].module,
             entry_point : entry_point.unwrap_or("cs_main"),
         });
 
+        let bindings = self.shaders[shader.id].entry_point_bindings(&[entry_point.unwrap_or("cs_main")]);
+        let mut internal = InternalComputePipeline::new(pipeline, bindings);
+
+        // Bind the globals if the shader use them.
+        if internal.bindings.contains_key(&(GLOBALS_GROUP, GLOBALS_BINDING)) {
+            let globals_bind_group = self.create_globals_bind_group(&internal.pipeline.get_bind_group_layout(GLOBALS_GROUP));
+            internal.bind_groups.push((GLOBALS_GROUP as usize, globals_bind_group));
+        }
+
         let id = self.compute_pipelines.len();
-        self.compute_pipelines.push(InternalComputePipeline::new(pipeline));
+        self.compute_pipelines.push(internal);
 
         ComputePipeline { id }
     }
 
-    fn dispatch_post_process_compute_pipeline(&mut self, pipeline: ComputePipeline, workgroups: (u32, u32, u32)) {
-        let encoder = self.main_encoder.as_mut().unwrap();
+    fn create_render_pipeline(&mut self, shader: Shader, vertex_attributes: &[VertexAttribute]) -> RenderPipeline {
+        let mut offset = 0;
+        let attributes = vertex_attributes.iter().enumerate()
+            .map(|(location, attribute)| {
+                let attribute_offset = offset;
+                offset += attribute.size();
 
-        let pipeline = &mut self.compute_pipelines[pipeline.id];
+                wgpu::VertexAttribute {
+                    format          : vertex_format(*attribute),
+                    offset          : attribute_offset,
+                    shader_location : location as u32,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let module = &self.shaders[shader.id].module;
+
+        let pipeline = self.device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: None,
+            vertex: wgpu::VertexState {
+                module,
+                entry_point : "vs_main",
+                buffers     : &[wgpu::VertexBufferLayout {
+                    array_stride: offset,
+                    step_mode   : wgpu::VertexStepMode::Vertex,
+                    attributes  : &attributes,
+                }],
+            },
+            primitive: wgpu::PrimitiveState {
+                topology    : wgpu::PrimitiveTopology::TriangleList,
+                front_face  : wgpu::FrontFace::Ccw,
+                cull_mode   : Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format              : DEPTH_FORMAT,
+                depth_write_enabled : true,
+                depth_compare       : wgpu::CompareFunction::Less,
+                stencil             : wgpu::StencilState::default(),
+                bias                : wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module,
+                entry_point : "fs_main",
+                targets     : &[Some(wgpu::ColorTargetState {
+                    format      : wgpu::TextureFormat::Rgba8Unorm,
+                    blend       : Some(wgpu::BlendState::REPLACE),
+                    write_mask  : wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        let mut internal = InternalRenderPipeline {
+            pipeline,
+            bind_groups: Vec::new(),
+            bindings: self.shaders[shader.id].entry_point_bindings(&["vs_main", "fs_main"]),
+        };
+
+        if internal.bindings.contains_key(&(GLOBALS_GROUP, GLOBALS_BINDING)) {
+            let globals_bind_group = self.create_globals_bind_group(&internal.pipeline.get_bind_group_layout(GLOBALS_GROUP));
+            internal.bind_groups.push((GLOBALS_GROUP as usize, globals_bind_group));
+        }
 
-        let postprocess_bing_group = pipeline.bind_groups.iter()
+        let id = self.render_pipelines.len();
+        self.render_pipelines.push(internal);
+
+        RenderPipeline { id }
+    }
+
+    fn dispatch_post_process_compute_pipeline(&mut self, pipeline: ComputePipeline, workgroups: (u32, u32, u32)) {
+        let internal = &mut self.compute_pipelines[pipeline.id];
+
+        let postprocess_bing_group = internal.bind_groups.iter()
             .find(|(group_id, _)| *group_id == 0);
 
         if postprocess_bing_group.is_none() {
             let postprocess_bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: None,
-                layout: &pipeline.pipeline.get_bind_group_layout(0),
+                layout: &internal.pipeline.get_bind_group_layout(0),
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
@@ -305,34 +815,18 @@ impl RendererTrait for WGPURenderer {
                 ]
             });
 
-            pipeline.bind_groups.push((0, postprocess_bind_group));
+            internal.bind_groups.push((0, postprocess_bind_group));
+            internal.post_process = true;
         }
 
-        {
-            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
-
-            for (group, bind_group) in &pipeline.bind_groups {
-                let group = *group;
-
-                pass.set_bind_group(group as u32, bind_group, &[]);
-            }
-
-            pass.set_pipeline(&pipeline.pipeline);
-
-            let (x, y, z) = workgroups;
-            pass.dispatch_workgroups(self.config.width / x, self.config.height / y, z);
-        }
+        // Enough workgroups to cover the pixels of the edges.
+        let (x, y, z) = workgroups;
+        let (width, height) = (self.config.width.div_ceil(x.max(1)), self.config.height.div_ceil(y.max(1)));
+        self.record_compute_pass(pipeline, ComputeDispatch::Direct(width, height, z));
     }
 
     fn create_buffer(&mut self, size: u64, usage: BufferUsage, read_only: bool) -> Buffer {
-        let mut usage = match usage {
-            BufferUsage::UNIFORM => wgpu::BufferUsages::UNIFORM,
-            BufferUsage::STORAGE => wgpu::BufferUsages::STORAGE,
-        };
-
-        if read_only {
-            usage = usage | wgpu::BufferUsages::COPY_DST;
-        }
+        let usage = buffer_usages(usage, read_only);
 
         let id = self.buffers.len();
 
@@ -343,69 +837,179 @@ impl RendererTrait for WGPURenderer {
             mapped_at_creation: false,
         });
 
-        self.buffers.push(buffer);
+        self.buffers.push(InternalBuffer::new(buffer, None));
 
         Buffer { id }
     }
 
-    fn create_buffer_with_data<T: bytemuck::Pod>(&mut self, data: &T, usage: BufferUsage, read_only: bool) -> Buffer {
-        let mut usage = match usage {
-            BufferUsage::UNIFORM => wgpu::BufferUsages::UNIFORM,
-            BufferUsage::STORAGE => wgpu::BufferUsages::STORAGE,
-        };
+    fn create_buffer_with_data<T: BufferData>(&mut self, data: &T, usage: BufferUsage, read_only: bool) -> Buffer {
+        let usage = buffer_usages(usage, read_only);
+        let data = data.as_bytes();
 
-        if read_only {
-            usage = usage | wgpu::BufferUsages::COPY_DST;
-        }
+        let id = self.buffers.len();
+
+        let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: &data,
+            usage,
+        });
+
+        self.buffers.push(InternalBuffer::new(buffer, Some(data.len() as u64)));
 
+        Buffer { id }
+    }
+
+    fn create_buffer_with_slice<T: bytemuck::Pod>(&mut self, data: &[T], usage: BufferUsage, read_only: bool) -> Buffer {
+        let usage = buffer_usages(usage, read_only);
         let id = self.buffers.len();
 
+        let data: &[u8] = bytemuck::cast_slice(data);
+
         let buffer = self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            contents: bytemuck::bytes_of(data),
+            contents: data,
             usage,
         });
 
-        self.buffers.push(buffer);
+        self.buffers.push(InternalBuffer::new(buffer, Some(data.len() as u64)));
 
         Buffer { id }
     }
 
-    fn update_buffer<T: bytemuck::Pod>(&self, buffer: Buffer, data: &T, offset: u64) {
-        let data = bytemuck::bytes_of(data);
-        self.queue.write_buffer(&self.buffers[buffer.id], offset, data);
+    fn update_buffer_with_slice<T: bytemuck::Pod>(&self, buffer: Buffer, data: &[T], offset: u64) {
+        let data: &[u8] = bytemuck::cast_slice(data);
+        let internal = &self.buffers[buffer.id];
+
+        if offset == 0 {
+            internal.set_data_size(buffer.id, data.len() as u64);
+        }
+
+        self.queue.write_buffer(&internal.buffer, offset, data);
+    }
+
+    fn dispatch_compute_pipeline(&mut self, pipeline: ComputePipeline, workgroups: (u32, u32, u32)) {
+        let (x, y, z) = workgroups;
+        self.record_compute_pass(pipeline, ComputeDispatch::Direct(x, y, z));
+    }
+
+    fn dispatch_compute_pipeline_indirect(&mut self, pipeline: ComputePipeline, buffer: Buffer, offset: u64) {
+        self.record_compute_pass(pipeline, ComputeDispatch::Indirect(buffer, offset));
+    }
+
+    fn update_buffer<T: BufferData>(&self, buffer: Buffer, data: &T, offset: u64) {
+        let data = data.as_bytes();
+        let internal = &self.buffers[buffer.id];
+
+        if offset == 0 {
+            internal.set_data_size(buffer.id, data.len() as u64);
+        }
+
+        self.queue.write_buffer(&internal.buffer, offset, &data);
     }
 
     fn destroy_buffer(&mut self, buffer: Buffer) {
-        self.buffers[buffer.id].destroy();
+        self.buffers[buffer.id].buffer.destroy();
+    }
+
+    fn read_buffer(&mut self, buffer: Buffer, offset: u64, size: u64) -> Vec<u8> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Read Back Buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Read Back Encoder"),
+        });
+
+        encoder.copy_buffer_to_buffer(&self.buffers[buffer.id].buffer, offset, &staging, 0, size);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let data = match receiver.recv() {
+            Ok(Ok(())) => slice.get_mapped_range().to_vec(),
+            _ => {
+                log::error!(target: logging::RENDERER, "Failed to read the buffer {}", buffer.id);
+                vec![0; size as usize]
+            },
+        };
+
+        staging.unmap();
+        staging.destroy();
+
+        data
     }
 
     fn set_binding_data(&mut self, pipeline: ComputePipeline, group: u32, data: &[Buffer]) {
         let pipeline = &mut self.compute_pipelines[pipeline.id];
+        let layout = pipeline.pipeline.get_bind_group_layout(group);
+        let bind_group = create_buffers_bind_group(&self.device, &mut self.buffers, &pipeline.bindings, &layout, group, data);
 
-        let entries = data.iter().enumerate().map(|(index, buff)| {
-            wgpu::BindGroupEntry {
-                binding: index as u32,
-                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                    buffer: &self.buffers[buff.id],
-                    offset: 0,
-                    size: None,
+        set_bind_group(&mut pipeline.bind_groups, group, bind_group);
+    }
+
+    fn set_render_binding_data(&mut self, pipeline: RenderPipeline, group: u32, data: &[Buffer]) {
+        let pipeline = &mut self.render_pipelines[pipeline.id];
+        let layout = pipeline.pipeline.get_bind_group_layout(group);
+        let bind_group = create_buffers_bind_group(&self.device, &mut self.buffers, &pipeline.bindings, &layout, group, data);
+
+        set_bind_group(&mut pipeline.bind_groups, group, bind_group);
+    }
+
+    fn draw_indexed(&mut self, pipeline: RenderPipeline, draws: &[DrawIndexed]) {
+        let encoder = self.main_encoder.as_mut().unwrap();
+        let timestamp = self.gpu_timer.as_mut().and_then(|timer| timer.begin_pass(encoder, format!("Render Pipeline {}", pipeline.id)));
+
+        let pipeline = &self.render_pipelines[pipeline.id];
+
+        {
+            let render_texture_view = self.render_texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let depth_texture_view = self.depth_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Draw Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &render_texture_view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &depth_texture_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    }),
+                    stencil_ops: None,
                 }),
-            }
-        });
+            });
 
-        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &pipeline.pipeline.get_bind_group_layout(group),
-            entries: &entries.collect::<Vec<_>>(),
-        });
+            pass.set_pipeline(&pipeline.pipeline);
+
+            for (group, bind_group) in &pipeline.bind_groups {
+                pass.set_bind_group(*group as u32, bind_group, &[]);
+            }
 
-        let bind_group = (group as usize, bind_group);
+            for draw in draws.iter().filter(|draw| draw.index_count > 0) {
+                pass.set_vertex_buffer(0, self.buffers[draw.vertex_buffer.id].buffer.slice(..));
+                pass.set_index_buffer(self.buffers[draw.index_buffer.id].buffer.slice(..), wgpu::IndexFormat::Uint32);
+                pass.draw_indexed(0..draw.index_count, 0, 0..1);
+            }
+        }
 
-        if pipeline.bind_groups.iter_mut().find(|(id, _)| *id == (group as usize)).is_some() {
-            pipeline.bind_groups[group as usize]= bind_group;
-        } else {
-            pipeline.bind_groups.push(bind_group);
+        if let Some(timer) = self.gpu_timer.as_mut() {
+            timer.end_pass(encoder, timestamp);
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,195 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::engine::{logging, profiler::{GpuFrameTimings, GpuTiming}};
+
+/// The maximum amount of passes measured in a frame.
+const MAX_PASSES: u32 = 64;
+
+/// Measure the GPU time of the passes with timestamp queries.
+///
+/// The results are read back without blocking: while the results
+/// of a frame are not mapped yet, the next frames are not measured.
+pub(crate) struct GpuTimer {
+    query_set       : wgpu::QuerySet,
+    resolve_buffer  : wgpu::Buffer,
+    read_buffer     : wgpu::Buffer,
+    /// The amount of nanoseconds per timestamp tick.
+    period          : f32,
+
+    /// `true` if the current frame is measured.
+    recording       : bool,
+    labels          : Vec<String>,
+    frame_start     : Instant,
+
+    /// `true` while the read buffer is waiting to be mapped.
+    in_flight       : bool,
+    /// The result of the mapping, set by the `map_async` callback.
+    map_result      : Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>,
+    in_flight_labels: Vec<String>,
+    in_flight_start : Instant,
+
+    results         : Option<GpuFrameTimings>,
+}
+
+impl GpuTimer {
+    /// Create a new [GpuTimer], return `None` if the device
+    /// don't support timestamp queries.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+
+        let size = (MAX_PASSES * 2) as u64 * std::mem::size_of::<u64>() as u64;
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label   : Some("GpuTimer QuerySet"),
+            ty      : wgpu::QueryType::Timestamp,
+            count   : MAX_PASSES * 2,
+        });
+
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label   : Some("GpuTimer Resolve Buffer"),
+            size,
+            usage   : wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let read_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label   : Some("GpuTimer Read Buffer"),
+            size,
+            usage   : wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            query_set,
+            resolve_buffer,
+            read_buffer,
+            period          : queue.get_timestamp_period(),
+            recording       : false,
+            labels          : Vec::new(),
+            frame_start     : Instant::now(),
+            in_flight       : false,
+            map_result      : Arc::new(Mutex::new(None)),
+            in_flight_labels: Vec::new(),
+            in_flight_start : Instant::now(),
+            results         : None,
+        })
+    }
+
+    /// Read the results of the previous measured frame (if they
+    /// are available) and decide if this frame will be measured.
+    pub fn begin_frame(&mut self, device: &wgpu::Device) {
+        if self.in_flight {
+            device.poll(wgpu::Maintain::Poll);
+
+            let map_result = self.map_result.lock().unwrap().take();
+
+            match map_result {
+                Some(Ok(())) => {
+                    self.read_results();
+                    self.in_flight = false;
+                },
+                Some(Err(error)) => {
+                    log::warn!(target: logging::RENDERER, "Failed to read the GPU timings: {}", error);
+
+                    self.read_buffer.unmap();
+                    self.in_flight_labels.clear();
+                    self.in_flight = false;
+                },
+                None => {},
+            }
+        }
+
+        self.recording = !self.in_flight;
+        self.labels.clear();
+        self.frame_start = Instant::now();
+    }
+
+    /// Write the timestamp of the begin of a pass. Return the
+    /// index of the pass, or `None` if it isn't measured.
+    pub fn begin_pass(&mut self, encoder: &mut wgpu::CommandEncoder, label: impl Into<String>) -> Option<u32> {
+        if !self.recording || self.labels.len() as u32 >= MAX_PASSES {
+            return None;
+        }
+
+        let index = self.labels.len() as u32;
+        encoder.write_timestamp(&self.query_set, index * 2);
+        self.labels.push(label.into());
+
+        Some(index)
+    }
+
+    /// Write the timestamp of the end of a pass.
+    pub fn end_pass(&mut self, encoder: &mut wgpu::CommandEncoder, index: Option<u32>) {
+        if let Some(index) = index {
+            encoder.write_timestamp(&self.query_set, index * 2 + 1);
+        }
+    }
+
+    /// Copy the timestamps of the frame into the read buffer,
+    /// must be called before the encoder is submitted.
+    pub fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if !self.recording || self.labels.is_empty() {
+            return;
+        }
+
+        let count = self.labels.len() as u32 * 2;
+        let size = count as u64 * std::mem::size_of::<u64>() as u64;
+
+        encoder.resolve_query_set(&self.query_set, 0..count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.read_buffer, 0, size);
+    }
+
+    /// Start to map the read buffer, must be called after the
+    /// encoder is submitted.
+    pub fn after_submit(&mut self) {
+        if !self.recording || self.labels.is_empty() {
+            return;
+        }
+
+        let map_result = self.map_result.clone();
+
+        self.read_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            *map_result.lock().unwrap() = Some(result);
+        });
+
+        self.in_flight = true;
+        self.in_flight_labels = std::mem::take(&mut self.labels);
+        self.in_flight_start = self.frame_start;
+    }
+
+    /// Take the last available results.
+    pub fn take_results(&mut self) -> Option<GpuFrameTimings> {
+        self.results.take()
+    }
+
+    fn read_results(&mut self) {
+        {
+            let data = self.read_buffer.slice(..).get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            let origin = timestamps[0];
+            let to_ms = |ticks: u64| ticks as f32 * self.period / 1_000_000.0;
+
+            let passes = self.in_flight_labels
+                .drain(..)
+                .enumerate()
+                .map(|(index, label)| {
+                    let begin = timestamps[index * 2];
+                    let end = timestamps[index * 2 + 1];
+
+                    GpuTiming {
+                        label,
+                        start   : to_ms(begin.saturating_sub(origin)),
+                        duration: to_ms(end.saturating_sub(begin)),
+                    }
+                })
+                .collect();
+
+            self.results = Some(GpuFrameTimings { frame_start: self.in_flight_start, passes });
+        }
+
+        self.read_buffer.unmap();
+    }
+}
@@ -0,0 +1,353 @@
+use nalgebra::{Matrix2, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+
+/// A type that can be stored in a uniform or storage buffer,
+/// laid out following the WGSL memory layout rules (alignment
+/// of `vec3`, alignment and size of structures, array stride).
+///
+/// Structures are declared with the [wgsl_struct](crate::wgsl_struct)
+/// macro, that compute the offset of each field and insert the
+/// padding, so the Rust declaration can match the WGSL one field
+/// by field.
+///
+/// The uniform address space has stricter rules (array stride
+/// and structure alignment must be a multiple of 16), a shader
+/// that break them is rejected when it is compiled.
+pub trait ShaderType {
+    /// The alignment in bytes (`AlignOf` in the WGSL specification).
+    const ALIGN: usize;
+    /// The size in bytes (`SizeOf` in the WGSL specification).
+    const SIZE: usize;
+
+    /// Get the name of the type in WGSL.
+    fn wgsl_name() -> String;
+
+    /// Write the value in `out`, that is exactly [ShaderType::SIZE]
+    /// bytes long. The padding bytes are left untouched.
+    fn write_bytes(&self, out: &mut [u8]);
+
+    /// Get the value as it must be stored in a GPU buffer.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; Self::SIZE];
+        self.write_bytes(&mut bytes);
+        bytes
+    }
+}
+
+/// A structure declared with the [wgsl_struct](crate::wgsl_struct) macro.
+pub trait ShaderStruct: ShaderType {
+    /// Get the WGSL declaration of the structure, so it can be
+    /// included in a shader instead of being written by hand.
+    fn wgsl_definition() -> String;
+}
+
+/// Round `size` up to a multiple of `align`.
+pub const fn round_up(align: usize, size: usize) -> usize {
+    size.div_ceil(align) * align
+}
+
+/// Get the maximum of two values, usable in constants.
+pub const fn max(a: usize, b: usize) -> usize {
+    if a > b { a } else { b }
+}
+
+/// A scalar that can be used in vectors.
+pub trait ShaderScalar: ShaderType + bytemuck::Pod + nalgebra::Scalar {}
+
+macro_rules! impl_scalar {
+    ($ty:ty, $name:literal) => {
+        impl ShaderType for $ty {
+            const ALIGN: usize = 4;
+            const SIZE: usize = 4;
+
+            fn wgsl_name() -> String {
+                $name.to_owned()
+            }
+
+            fn write_bytes(&self, out: &mut [u8]) {
+                out.copy_from_slice(bytemuck::bytes_of(self));
+            }
+        }
+
+        impl ShaderScalar for $ty {}
+    };
+}
+
+impl_scalar!(f32, "f32");
+impl_scalar!(i32, "i32");
+impl_scalar!(u32, "u32");
+
+macro_rules! impl_vector {
+    ($ty:ident, $len:literal, $align:literal) => {
+        impl<T: ShaderScalar> ShaderType for $ty<T> {
+            const ALIGN: usize = $align;
+            const SIZE: usize = $len * 4;
+
+            fn wgsl_name() -> String {
+                format!("vec{}<{}>", $len, T::wgsl_name())
+            }
+
+            fn write_bytes(&self, out: &mut [u8]) {
+                out.copy_from_slice(bytemuck::cast_slice(self.as_slice()));
+            }
+        }
+    };
+}
+
+impl_vector!(Vector2, 2, 8);
+impl_vector!(Vector3, 3, 16);
+impl_vector!(Vector4, 4, 16);
+
+macro_rules! impl_matrix {
+    ($ty:ident, $columns:literal, $align:literal) => {
+        impl ShaderType for $ty<f32> {
+            // A matrix is stored as an array of column vectors.
+            const ALIGN: usize = $align;
+            const SIZE: usize = $columns * $align;
+
+            fn wgsl_name() -> String {
+                format!("mat{0}x{0}<f32>", $columns)
+            }
+
+            fn write_bytes(&self, out: &mut [u8]) {
+                for (column, chunk) in self.column_iter().zip(out.chunks_exact_mut($align)) {
+                    chunk[..$columns * 4].copy_from_slice(bytemuck::cast_slice(column.as_slice()));
+                }
+            }
+        }
+    };
+}
+
+impl_matrix!(Matrix2, 2, 8);
+impl_matrix!(Matrix3, 3, 16);
+impl_matrix!(Matrix4, 4, 16);
+
+impl<T: ShaderType, const N: usize> ShaderType for [T; N] {
+    const ALIGN: usize = T::ALIGN;
+    const SIZE: usize = N * round_up(T::ALIGN, T::SIZE);
+
+    fn wgsl_name() -> String {
+        format!("array<{}, {}>", T::wgsl_name(), N)
+    }
+
+    fn write_bytes(&self, out: &mut [u8]) {
+        let stride = round_up(T::ALIGN, T::SIZE);
+
+        for (element, chunk) in self.iter().zip(out.chunks_exact_mut(stride)) {
+            element.write_bytes(&mut chunk[..T::SIZE]);
+        }
+    }
+}
+
+/// Declare a structure laid out following the WGSL rules. The
+/// structure implements [ShaderType], [ShaderStruct] and
+/// [BufferData](crate::engine::renderer::BufferData), so it can be
+/// given to [RendererTrait::create_buffer_with_data](crate::engine::renderer::RendererTrait::create_buffer_with_data)
+/// and [RendererTrait::update_buffer](crate::engine::renderer::RendererTrait::update_buffer).
+///
+/// The fields must be [ShaderType]s and declared in the same
+/// order as in the shader, the padding is inserted automatically.
+///
+/// ```ignore
+/// wgsl_struct! {
+///     #[derive(Clone, Copy, Default)]
+///     pub struct InData {
+///         pub inv_proj_view_matrix: Matrix4<f32>,
+///         pub screen_size         : Vector2<f32>,
+///         pub time                : f32,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! wgsl_struct {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_meta:meta])*
+                $field_vis:vis $field:ident : $ty:ty
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                $field_vis $field : $ty,
+            )+
+        }
+
+        impl $crate::engine::shader_type::ShaderType for $name {
+            const ALIGN: usize = {
+                let mut align = 1;
+                $(
+                    align = $crate::engine::shader_type::max(align, <$ty as $crate::engine::shader_type::ShaderType>::ALIGN);
+                )+
+                align
+            };
+
+            const SIZE: usize = {
+                let mut offset = 0;
+                $(
+                    offset = $crate::engine::shader_type::round_up(<$ty as $crate::engine::shader_type::ShaderType>::ALIGN, offset)
+                           + <$ty as $crate::engine::shader_type::ShaderType>::SIZE;
+                )+
+                $crate::engine::shader_type::round_up(<Self as $crate::engine::shader_type::ShaderType>::ALIGN, offset)
+            };
+
+            fn wgsl_name() -> String {
+                stringify!($name).to_owned()
+            }
+
+            fn write_bytes(&self, out: &mut [u8]) {
+                let mut offset = 0;
+                $(
+                    offset = $crate::engine::shader_type::round_up(<$ty as $crate::engine::shader_type::ShaderType>::ALIGN, offset);
+                    let end = offset + <$ty as $crate::engine::shader_type::ShaderType>::SIZE;
+                    $crate::engine::shader_type::ShaderType::write_bytes(&self.$field, &mut out[offset..end]);
+                    offset = end;
+                )+
+                let _ = offset;
+            }
+        }
+
+        impl $crate::engine::shader_type::ShaderStruct for $name {
+            fn wgsl_definition() -> String {
+                let mut definition = format!("struct {} {{\n", stringify!($name));
+                $(
+                    definition.push_str(&format!(
+                        "    {} : {},\n",
+                        stringify!($field),
+                        <$ty as $crate::engine::shader_type::ShaderType>::wgsl_name(),
+                    ));
+                )+
+                definition.push_str("};\n");
+                definition
+            }
+        }
+
+        impl $crate::engine::renderer::BufferData for $name {
+            fn as_bytes(&self) -> std::borrow::Cow<'_, [u8]> {
+                std::borrow::Cow::Owned($crate::engine::shader_type::ShaderType::to_bytes(self))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::wgsl_struct! {
+        #[derive(Clone, Copy, Default)]
+        struct Packed {
+            position: Vector3<f32>,
+            radius  : f32,
+        }
+    }
+
+    crate::wgsl_struct! {
+        #[derive(Clone, Copy, Default)]
+        struct Vec3Array {
+            values  : [Vector3<f32>; 3],
+            tail    : f32,
+        }
+    }
+
+    crate::wgsl_struct! {
+        #[derive(Clone, Copy, Default)]
+        struct Inner {
+            a       : Vector2<f32>,
+            b       : f32,
+        }
+    }
+
+    crate::wgsl_struct! {
+        #[derive(Clone, Copy, Default)]
+        struct Outer {
+            x       : f32,
+            inner   : Inner,
+            v       : Vector3<f32>,
+            y       : u32,
+            rotation: Matrix3<f32>,
+        }
+    }
+
+    /// Get the offsets of the members and the size of a structure,
+    /// as laid out by naga.
+    fn naga_layout(source: &str, name: &str) -> (Vec<u32>, u32) {
+        let module = naga::front::wgsl::parse_str(source).unwrap();
+
+        let layout = module.types.iter()
+            .find_map(|(_, ty)| match &ty.inner {
+                naga::TypeInner::Struct { members, span } if ty.name.as_deref() == Some(name) => {
+                    Some((members.iter().map(|member| member.offset).collect(), *span))
+                },
+                _ => None,
+            });
+
+        layout.unwrap()
+    }
+
+    fn f32_at(bytes: &[u8], offset: usize) -> f32 {
+        f32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn scalar_after_vec3() {
+        assert_eq!((Packed::ALIGN, Packed::SIZE), (16, 16));
+        assert_eq!(naga_layout(&Packed::wgsl_definition(), "Packed"), (vec![0, 12], 16));
+
+        let bytes = Packed { position: Vector3::new(1.0, 2.0, 3.0), radius: 4.0 }.to_bytes();
+        assert_eq!(bytes.len(), 16);
+        assert_eq!([0, 4, 8, 12].map(|offset| f32_at(&bytes, offset)), [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn vec3_array_stride() {
+        assert_eq!(<[Vector3<f32>; 3]>::SIZE, 48);
+        assert_eq!(<[Vector3<f32>; 3]>::wgsl_name(), "array<vec3<f32>, 3>");
+        assert_eq!((Vec3Array::ALIGN, Vec3Array::SIZE), (16, 64));
+        assert_eq!(naga_layout(&Vec3Array::wgsl_definition(), "Vec3Array"), (vec![0, 48], 64));
+
+        let values = [Vector3::new(1.0, 2.0, 3.0), Vector3::new(4.0, 5.0, 6.0), Vector3::new(7.0, 8.0, 9.0)];
+        let bytes = Vec3Array { values, tail: 10.0 }.to_bytes();
+
+        assert_eq!(bytes.len(), 64);
+        assert_eq!([16, 20, 24].map(|offset| f32_at(&bytes, offset)), [4.0, 5.0, 6.0]);
+        assert_eq!(f32_at(&bytes, 32), 7.0);
+        assert_eq!(f32_at(&bytes, 48), 10.0);
+
+        // The padding after each element is left to zero.
+        assert_eq!(f32_at(&bytes, 12), 0.0);
+    }
+
+    #[test]
+    fn nested_structures() {
+        assert_eq!((Inner::ALIGN, Inner::SIZE), (8, 16));
+        assert_eq!((Outer::ALIGN, Outer::SIZE), (16, 96));
+
+        let source = Inner::wgsl_definition() + &Outer::wgsl_definition();
+        assert_eq!(naga_layout(&source, "Inner"), (vec![0, 8], 16));
+        assert_eq!(naga_layout(&source, "Outer"), (vec![0, 8, 32, 44, 48], 96));
+
+        let outer = Outer {
+            x       : 1.0,
+            inner   : Inner { a: Vector2::new(2.0, 3.0), b: 4.0 },
+            v       : Vector3::new(5.0, 6.0, 7.0),
+            y       : 8,
+            rotation: Matrix3::new(
+                9.0, 12.0, 15.0,
+                10.0, 13.0, 16.0,
+                11.0, 14.0, 17.0,
+            ),
+        };
+
+        let bytes = outer.to_bytes();
+        assert_eq!(bytes.len(), 96);
+        assert_eq!([0, 8, 12, 16, 32, 36, 40].map(|offset| f32_at(&bytes, offset)), [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(u32::from_le_bytes(bytes[44..48].try_into().unwrap()), 8);
+
+        // The columns of the matrix are 16 bytes apart.
+        assert_eq!([48, 52, 56, 64, 80].map(|offset| f32_at(&bytes, offset)), [9.0, 10.0, 11.0, 12.0, 15.0]);
+    }
+}
@@ -0,0 +1,100 @@
+use std::time::Instant;
+
+/// The default duration of a fixed update step (60 steps per second).
+pub(crate) const DEFAULT_FIXED_DELTA: f32 = 1.0 / 60.0;
+
+/// The maximum amount of fixed update steps in a frame, the time
+/// beyond is dropped so a slow frame doesn't make the next ones
+/// slower and slower.
+pub(crate) const MAX_FIXED_STEPS: u32 = 8;
+
+/// The engine clock, available as a resource. It is updated
+/// once per frame, before the update systems.
+pub struct Time {
+    start       : Instant,
+    last        : Option<Instant>,
+    elapsed     : f32,
+    delta       : f32,
+    frame       : u64,
+    fixed_delta : f32,
+    /// The time not consumed by the fixed update steps yet.
+    accumulator : f32,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            start       : Instant::now(),
+            last        : None,
+            elapsed     : 0.0,
+            delta       : 0.0,
+            frame       : 0,
+            fixed_delta : DEFAULT_FIXED_DELTA,
+            accumulator : 0.0,
+        }
+    }
+}
+
+impl Time {
+    /// Get the time in seconds since the first frame.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+
+    /// Get the time in seconds between the last frame and
+    /// the current one.
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    /// Get the index of the current frame.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Get the duration in seconds of a fixed update step.
+    pub fn fixed_delta(&self) -> f32 {
+        self.fixed_delta
+    }
+
+    /// Set the duration in seconds of a fixed update step.
+    pub fn set_fixed_delta(&mut self, fixed_delta: f32) {
+        self.fixed_delta = fixed_delta.max(1e-4);
+    }
+
+    /// Get how far the current frame is between the last fixed
+    /// update step and the next one (from `0` to `1`), to
+    /// interpolate what the fixed update systems move.
+    pub fn fixed_alpha(&self) -> f32 {
+        (self.accumulator / self.fixed_delta).clamp(0.0, 1.0)
+    }
+
+    /// Consume the time of a fixed update step, return `false`
+    /// if the frame has no step left.
+    pub(crate) fn next_fixed_step(&mut self) -> bool {
+        if self.accumulator >= self.fixed_delta {
+            self.accumulator -= self.fixed_delta;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Advance the clock to a new frame.
+    pub(crate) fn tick(&mut self) {
+        let now = Instant::now();
+
+        match self.last {
+            Some(last) => {
+                self.delta = now.duration_since(last).as_secs_f32();
+                self.frame += 1;
+            },
+            None => self.start = now,
+        }
+
+        self.elapsed = now.duration_since(self.start).as_secs_f32();
+        self.last = Some(now);
+
+        self.accumulator = (self.accumulator + self.delta).min(self.fixed_delta * MAX_FIXED_STEPS as f32);
+    }
+}
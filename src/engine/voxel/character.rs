@@ -0,0 +1,333 @@
+use std::marker::PhantomData;
+
+use nalgebra::Vector3;
+
+use crate::engine::{
+    bounds::Aabb,
+    plugin::{Plugin, Resources, Systems},
+    renderer::RendererTrait,
+    time::Time,
+};
+
+use super::{collision::move_aabb, world::VoxelWorld, VoxelQuery};
+
+/// The size and the physics of a [CharacterController].
+#[derive(Clone, Copy, Debug)]
+pub struct CharacterSettings {
+    /// The half width of the body.
+    pub radius          : f32,
+    /// The height of the body.
+    pub height          : f32,
+    /// The height of the highest ledge the character walks up.
+    pub step_height     : f32,
+    /// The gravity acceleration in world units per second².
+    pub gravity         : f32,
+    /// The initial vertical speed of a jump.
+    pub jump_speed      : f32,
+    /// The maximum falling speed.
+    pub max_fall_speed  : f32,
+}
+
+impl Default for CharacterSettings {
+    fn default() -> Self {
+        Self {
+            radius          : 0.3,
+            height          : 1.8,
+            step_height     : 1.0,
+            gravity         : 20.0,
+            jump_speed      : 7.0,
+            max_fall_speed  : 50.0,
+        }
+    }
+}
+
+/// A kinematic character that walks on the voxels: it falls with
+/// the gravity, slides along the walls, steps up the ledges and
+/// jumps from the ground.
+///
+/// The horizontal velocity is given by [CharacterController::set_walk_velocity]
+/// and the character is moved by [CharacterController::step].
+#[derive(Clone, Debug)]
+pub struct CharacterController {
+    pub settings        : CharacterSettings,
+
+    /// The position of the feet (the bottom center of the body).
+    position            : Vector3<f32>,
+    previous_position   : Vector3<f32>,
+    velocity            : Vector3<f32>,
+    walk_velocity       : Vector3<f32>,
+    jump_requested      : bool,
+    on_ground           : bool,
+}
+
+impl CharacterController {
+    /// Create a new [CharacterController].
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The size and the physics of the character.
+    /// * `position` - The position of its feet.
+    ///
+    pub fn new(settings: CharacterSettings, position: Vector3<f32>) -> Self {
+        Self {
+            settings,
+            position,
+            previous_position   : position,
+            velocity            : Vector3::zeros(),
+            walk_velocity       : Vector3::zeros(),
+            jump_requested      : false,
+            on_ground           : false,
+        }
+    }
+
+    /// Get the position of the feet.
+    pub fn position(&self) -> Vector3<f32> {
+        self.position
+    }
+
+    /// Move the character without collision (teleport).
+    pub fn set_position(&mut self, position: Vector3<f32>) {
+        self.position = position;
+        self.previous_position = position;
+    }
+
+    /// Get the position of the feet between the last two steps,
+    /// `alpha` goes from the previous step (`0`) to the last one
+    /// (`1`), see [Time::fixed_alpha].
+    pub fn interpolated_position(&self, alpha: f32) -> Vector3<f32> {
+        self.previous_position.lerp(&self.position, alpha)
+    }
+
+    /// Get the velocity.
+    pub fn velocity(&self) -> Vector3<f32> {
+        self.velocity
+    }
+
+    /// Set the velocity, e.g. to push the character.
+    pub fn set_velocity(&mut self, velocity: Vector3<f32>) {
+        self.velocity = velocity;
+    }
+
+    /// Set the horizontal velocity, the vertical component is ignored.
+    pub fn set_walk_velocity(&mut self, velocity: Vector3<f32>) {
+        self.walk_velocity = Vector3::new(velocity.x, 0.0, velocity.z);
+    }
+
+    /// Jump at the next step, if the character is on the ground.
+    pub fn jump(&mut self) {
+        self.jump_requested = true;
+    }
+
+    /// Check if the character stands on a solid voxel.
+    pub fn is_on_ground(&self) -> bool {
+        self.on_ground
+    }
+
+    /// Set if the character stands on the ground, e.g. when it is
+    /// moved without a world. [CharacterController::step] updates it.
+    pub fn set_on_ground(&mut self, on_ground: bool) {
+        self.on_ground = on_ground;
+    }
+
+    /// Get the box of the body.
+    pub fn aabb(&self) -> Aabb {
+        let s = &self.settings;
+
+        Aabb::new(
+            self.position - Vector3::new(s.radius, 0.0, s.radius),
+            self.position + Vector3::new(s.radius, s.height, s.radius),
+        )
+    }
+
+    /// Move the character during `dt` seconds. The character
+    /// doesn't move while the voxel of its feet isn't loaded, so it
+    /// doesn't fall through the chunks being streamed.
+    ///
+    /// # Arguments
+    ///
+    /// * `world`   - The voxels the character walks on.
+    /// * `dt`      - The duration of the step in seconds.
+    ///
+    pub fn step(&mut self, world: &impl VoxelQuery, dt: f32) {
+        let s = self.settings;
+
+        self.previous_position = self.position;
+
+        let feet = self.position.map(|v| v.floor() as i32);
+
+        if !world.is_loaded(feet.x, feet.y, feet.z) {
+            return;
+        }
+
+        self.velocity.x = self.walk_velocity.x;
+        self.velocity.z = self.walk_velocity.z;
+
+        if std::mem::take(&mut self.jump_requested) && self.on_ground {
+            self.velocity.y = s.jump_speed;
+        }
+
+        self.velocity.y = (self.velocity.y - s.gravity * dt).max(-s.max_fall_speed);
+
+        let step_height = if self.on_ground { s.step_height } else { 0.0 };
+        let collision = move_aabb(world, &self.aabb(), self.velocity * dt, step_height);
+
+        self.position += collision.motion;
+        self.on_ground = collision.on_ground;
+
+        for (axis, blocked) in collision.blocked.into_iter().enumerate() {
+            if blocked {
+                self.velocity[axis] = 0.0;
+            }
+        }
+    }
+}
+
+/// A plugin that insert a [CharacterController] resource and move
+/// it in the fixed update, on the voxels of the world resource `W`
+/// (the [VoxelWorld] by default).
+pub struct CharacterPlugin<W: VoxelQuery + 'static = VoxelWorld> {
+    character   : Option<CharacterController>,
+    world       : PhantomData<fn() -> W>,
+}
+
+impl<W: VoxelQuery + 'static> CharacterPlugin<W> {
+    /// Create a new [CharacterPlugin].
+    ///
+    /// # Arguments
+    ///
+    /// * `character` - The character to move.
+    ///
+    pub fn new(character: CharacterController) -> Self {
+        Self {
+            character   : Some(character),
+            world       : PhantomData,
+        }
+    }
+}
+
+impl<R: RendererTrait + 'static, W: VoxelQuery + 'static> Plugin<R> for CharacterPlugin<W> {
+    fn build(&mut self, systems: &mut Systems<R>, resources: &mut Resources) {
+        if let Some(character) = self.character.take() {
+            resources.insert(character);
+        }
+
+        systems.add_fixed_update_system(|resources| {
+            let dt = resources.get::<Time>().map(|time| time.fixed_delta()).unwrap_or(0.0);
+
+            let Some(mut character) = resources.remove::<CharacterController>() else { return };
+
+            // The character doesn't move until its world exists (e.g.
+            // while the chunks around it are loading).
+            if let Some(world) = resources.get::<W>() {
+                character.step(world, dt);
+            }
+
+            resources.insert(character);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::chunk::{Chunk, ChunkPos};
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// A floor whose top is at `y = 0` in loaded chunks, with a
+    /// ledge of `ledge` voxels from `x = 3`.
+    fn world(ledge: i32) -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+
+        for y in -1..=4 {
+            for x in -1..=0 {
+                for z in -1..=0 {
+                    world.insert_chunk(ChunkPos::new(x, y, z), Chunk::new());
+                }
+            }
+        }
+
+        for z in -16..16 {
+            for x in -16..16 {
+                world.set_voxel(x, -1, z, 1);
+
+                for y in 0..ledge {
+                    if x >= 3 {
+                        world.set_voxel(x, y, z, 1);
+                    }
+                }
+            }
+        }
+
+        world
+    }
+
+    fn run(character: &mut CharacterController, world: &VoxelWorld, steps: usize) {
+        for _ in 0..steps {
+            character.step(world, DT);
+        }
+    }
+
+    #[test]
+    fn falls_on_the_ground() {
+        let world = world(0);
+        let mut character = CharacterController::new(CharacterSettings::default(), Vector3::new(0.5, 3.0, 0.5));
+
+        run(&mut character, &world, 120);
+
+        assert!(character.is_on_ground());
+        assert!(character.position().y >= 0.0 && character.position().y < 0.01);
+        assert_eq!(character.velocity().y, 0.0);
+
+        // The character doesn't move while its chunk isn't loaded.
+        let mut character = CharacterController::new(CharacterSettings::default(), Vector3::new(100.5, 3.0, 0.5));
+        run(&mut character, &world, 10);
+        assert_eq!(character.position(), Vector3::new(100.5, 3.0, 0.5));
+    }
+
+    #[test]
+    fn walks_up_a_ledge() {
+        for (ledge, expected) in [(1, 1.0), (2, 0.0)] {
+            let world = world(ledge);
+            let mut character = CharacterController::new(CharacterSettings::default(), Vector3::new(0.5, 0.0, 0.5));
+
+            run(&mut character, &world, 10);
+            character.set_walk_velocity(Vector3::new(4.0, 0.0, 0.0));
+            run(&mut character, &world, 60);
+
+            let position = character.position();
+            assert!((position.y - expected).abs() < 0.01, "{} voxels: {:?}", ledge, position);
+            assert!(character.is_on_ground());
+
+            if ledge > 1 {
+                assert!(position.x + character.settings.radius <= 3.0);
+            } else {
+                assert!(position.x > 3.0);
+            }
+        }
+    }
+
+    #[test]
+    fn no_tunnelling() {
+        let world = world(2);
+        let settings = CharacterSettings { max_fall_speed: 1000.0, ..Default::default() };
+        let mut character = CharacterController::new(settings, Vector3::new(0.5, 60.0, 0.5));
+
+        // Steps of a second, the motion of a step is larger than the
+        // floor and the body.
+        for _ in 0..5 {
+            character.step(&world, 1.0);
+        }
+
+        assert!(character.is_on_ground());
+        assert!(character.position().y >= 0.0 && character.position().y < 0.01);
+
+        // Against the ledge, too high to step up.
+        character.set_walk_velocity(Vector3::new(500.0, 0.0, 0.0));
+        character.step(&world, 1.0);
+
+        let position = character.position();
+        assert!(position.x + settings.radius <= 3.0 && position.x + settings.radius > 2.99);
+        assert!(position.y < 0.01);
+    }
+}
@@ -0,0 +1,265 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use nalgebra::Vector3;
+
+use crate::engine::bounds::Aabb;
+
+use super::{Voxel, AIR};
+
+/// The amount of voxels on each side of a chunk.
+pub const CHUNK_SIZE: i32 = 16;
+
+/// The amount of voxels in a chunk.
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// The coordinates of a chunk, the chunk `(x, y, z)` contains
+/// the voxels from `(x, y, z) * CHUNK_SIZE` to
+/// `(x + 1, y + 1, z + 1) * CHUNK_SIZE` (excluded).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    /// Create a new [ChunkPos].
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Get the chunk that contains a voxel.
+    pub fn from_voxel(x: i32, y: i32, z: i32) -> Self {
+        Self {
+            x: x.div_euclid(CHUNK_SIZE),
+            y: y.div_euclid(CHUNK_SIZE),
+            z: z.div_euclid(CHUNK_SIZE),
+        }
+    }
+
+    /// Get the chunk that contains a point in world space.
+    pub fn from_world(position: &Vector3<f32>) -> Self {
+        Self::from_voxel(
+            position.x.floor() as i32,
+            position.y.floor() as i32,
+            position.z.floor() as i32,
+        )
+    }
+
+    /// Get the coordinates of the first voxel of the chunk.
+    pub fn min_voxel(&self) -> Vector3<i32> {
+        Vector3::new(self.x, self.y, self.z) * CHUNK_SIZE
+    }
+
+    /// Get the bounding box of the chunk in world space.
+    pub fn bounds(&self) -> Aabb {
+        let min = self.min_voxel().cast::<f32>();
+        Aabb::new(min, min + Vector3::repeat(CHUNK_SIZE as f32))
+    }
+
+    /// Get the neighbor chunk at `offset`.
+    pub fn offset(&self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    /// Get the squared distance (in chunks) to another chunk.
+    pub fn distance_squared(&self, other: &ChunkPos) -> i32 {
+        let (dx, dy, dz) = (self.x - other.x, self.y - other.y, self.z - other.z);
+        dx * dx + dy * dy + dz * dz
+    }
+}
+
+/// Split world voxel coordinates into the chunk and the local
+/// coordinates of the voxel in the chunk.
+pub fn split_voxel(x: i32, y: i32, z: i32) -> (ChunkPos, (usize, usize, usize)) {
+    let local = (
+        x.rem_euclid(CHUNK_SIZE) as usize,
+        y.rem_euclid(CHUNK_SIZE) as usize,
+        z.rem_euclid(CHUNK_SIZE) as usize,
+    );
+
+    (ChunkPos::from_voxel(x, y, z), local)
+}
+
+/// Identify the content of a chunk: the revisions are different
+/// for two chunks created separately, and change each time a voxel
+/// of the chunk changes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Revision {
+    chunk   : u64,
+    changes : u64,
+}
+
+fn next_chunk_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// A cube of [CHUNK_SIZE]³ voxels.
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    voxels  : Box<[Voxel]>,
+    /// The light of each voxel (see [light](super::light)), it
+    /// isn't saved.
+    light   : Box<[u8]>,
+    /// `true` if the light of the chunk was computed.
+    lit     : bool,
+    /// The amount of voxels that are not [AIR].
+    solid   : usize,
+    /// `true` if the chunk changed since it was uploaded to the GPU.
+    dirty   : bool,
+    /// `true` if the chunk changed since it was saved.
+    modified: bool,
+    revision: Revision,
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Chunk {
+    /// Create a new [Chunk] filled with [AIR].
+    pub fn new() -> Self {
+        Self::filled(AIR)
+    }
+
+    /// Create a new [Chunk] filled with a voxel.
+    pub fn filled(voxel: Voxel) -> Self {
+        Self {
+            voxels  : vec![voxel; CHUNK_VOLUME].into_boxed_slice(),
+            light   : vec![0; CHUNK_VOLUME].into_boxed_slice(),
+            lit     : false,
+            solid   : if voxel == AIR { 0 } else { CHUNK_VOLUME },
+            dirty   : true,
+            modified: false,
+            revision: Revision { chunk: next_chunk_id(), changes: 0 },
+        }
+    }
+
+    /// Create a new [Chunk] from its voxels, ordered by `x`, then
+    /// `z`, then `y` (see [Chunk::index]). Return `None` if there
+    /// isn't exactly [CHUNK_VOLUME] voxels.
+    pub fn from_voxels(voxels: Vec<Voxel>) -> Option<Self> {
+        if voxels.len() != CHUNK_VOLUME {
+            return None;
+        }
+
+        let solid = voxels.iter().filter(|voxel| **voxel != AIR).count();
+
+        Some(Self {
+            voxels  : voxels.into_boxed_slice(),
+            light   : vec![0; CHUNK_VOLUME].into_boxed_slice(),
+            lit     : false,
+            solid,
+            dirty   : true,
+            modified: false,
+            revision: Revision { chunk: next_chunk_id(), changes: 0 },
+        })
+    }
+
+    /// Get the index of a voxel in [Chunk::voxels].
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        let size = CHUNK_SIZE as usize;
+        x + z * size + y * size * size
+    }
+
+    /// Get the voxel at local coordinates.
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Voxel {
+        self.voxels[Self::index(x, y, z)]
+    }
+
+    /// Set the voxel at local coordinates, return the previous one.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, voxel: Voxel) -> Voxel {
+        let previous = std::mem::replace(&mut self.voxels[Self::index(x, y, z)], voxel);
+
+        if previous != voxel {
+            match (previous == AIR, voxel == AIR) {
+                (true, false) => self.solid += 1,
+                (false, true) => self.solid -= 1,
+                _ => {},
+            }
+
+            self.dirty = true;
+            self.modified = true;
+            self.revision.changes += 1;
+        }
+
+        previous
+    }
+
+    /// Get all the voxels of the chunk.
+    pub fn voxels(&self) -> &[Voxel] {
+        &self.voxels
+    }
+
+    /// Check if the chunk contains only [AIR].
+    pub fn is_empty(&self) -> bool {
+        self.solid == 0
+    }
+
+    /// Get the packed light of a voxel at local coordinates (see
+    /// [light](super::light)).
+    pub fn light(&self, x: usize, y: usize, z: usize) -> u8 {
+        self.light[Self::index(x, y, z)]
+    }
+
+    /// Set the packed light of a voxel at local coordinates, the
+    /// chunk must be uploaded again if it changes.
+    pub fn set_light(&mut self, x: usize, y: usize, z: usize, light: u8) {
+        let current = &mut self.light[Self::index(x, y, z)];
+
+        if *current != light {
+            *current = light;
+            self.dirty = true;
+        }
+    }
+
+    /// Get the packed light of all the voxels, in the order of
+    /// [Chunk::voxels].
+    pub fn lights(&self) -> &[u8] {
+        &self.light
+    }
+
+    /// Set the light of all the voxels to `0`.
+    pub fn clear_light(&mut self) {
+        self.light.fill(0);
+        self.dirty = true;
+    }
+
+    /// Check if the light of the chunk was computed.
+    pub fn is_lit(&self) -> bool {
+        self.lit
+    }
+
+    /// Mark the light of the chunk as (not) computed.
+    pub fn set_lit(&mut self, lit: bool) {
+        self.lit = lit;
+    }
+
+    /// Get the revision of the content of the chunk.
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    /// Check if the chunk must be uploaded to the GPU.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Mark the chunk as (not) needing an upload to the GPU.
+    pub fn set_dirty(&mut self, dirty: bool) {
+        self.dirty = dirty;
+    }
+
+    /// Check if the chunk changed since it was loaded or saved.
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Mark the chunk as (not) needing to be saved.
+    pub fn set_modified(&mut self, modified: bool) {
+        self.modified = modified;
+    }
+}
@@ -0,0 +1,264 @@
+//! Move boxes through the voxels: the motion is swept along each
+//! axis and stops against the faces of the solid voxels, so the
+//! boxes slide along the walls and never tunnel through them.
+
+use nalgebra::Vector3;
+
+use crate::engine::bounds::Aabb;
+
+use super::VoxelQuery;
+
+/// The gap kept between a box and the voxels it stops against, so
+/// the box doesn't overlap them due to rounding.
+const SKIN: f32 = 1e-3;
+
+/// The distance below a box where a solid voxel is ground.
+const GROUND_PROBE: f32 = 0.05;
+
+/// The result of [move_aabb].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Collision {
+    /// The box at its new position.
+    pub aabb    : Aabb,
+    /// The motion that was applied.
+    pub motion  : Vector3<f32>,
+    /// The axes on which the motion was stopped by a voxel.
+    pub blocked : [bool; 3],
+    /// `true` if the box stands on a solid voxel after the motion.
+    pub on_ground: bool,
+    /// `true` if the box stepped up a ledge.
+    pub stepped : bool,
+}
+
+/// The voxels overlapped by a range of coordinates, the faces that
+/// are only touched are excluded.
+fn overlapped(min: f32, max: f32) -> std::ops::RangeInclusive<i32> {
+    (min + SKIN).floor() as i32..=(max - SKIN).floor() as i32
+}
+
+/// Check if a layer of voxels (the voxels at `layer` on `axis`)
+/// contains a solid voxel in the cross section of a box.
+fn layer_is_solid(world: &impl VoxelQuery, aabb: &Aabb, axis: usize, layer: i32) -> bool {
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+    overlapped(aabb.min[u], aabb.max[u]).any(|a| {
+        overlapped(aabb.min[v], aabb.max[v]).any(|b| {
+            let mut voxel = [0; 3];
+            voxel[axis] = layer;
+            voxel[u] = a;
+            voxel[v] = b;
+            world.is_solid(voxel[0], voxel[1], voxel[2])
+        })
+    })
+}
+
+/// Sweep a box along an axis and return how far it can move
+/// before touching a solid voxel, between `0` and `distance`.
+///
+/// # Arguments
+///
+/// * `world`       - The voxels.
+/// * `aabb`        - The box, it must not overlap solid voxels.
+/// * `axis`        - The axis of the motion.
+/// * `distance`    - The signed distance to move.
+///
+pub fn sweep_axis(world: &impl VoxelQuery, aabb: &Aabb, axis: usize, distance: f32) -> f32 {
+    if distance > 0.0 {
+        let end = aabb.max[axis] + distance;
+
+        // The layers in front of the box, from the nearest one.
+        for layer in (aabb.max[axis] - SKIN).ceil() as i32..=end.ceil() as i32 - 1 {
+            if layer_is_solid(world, aabb, axis, layer) {
+                return (layer as f32 - SKIN - aabb.max[axis]).clamp(0.0, distance);
+            }
+        }
+    } else if distance < 0.0 {
+        let end = aabb.min[axis] + distance;
+
+        for layer in (end.floor() as i32..=(aabb.min[axis] + SKIN).floor() as i32 - 1).rev() {
+            if layer_is_solid(world, aabb, axis, layer) {
+                return (layer as f32 + 1.0 + SKIN - aabb.min[axis]).clamp(distance, 0.0);
+            }
+        }
+    }
+
+    distance
+}
+
+/// Check if a box stands on a solid voxel.
+pub fn is_on_ground(world: &impl VoxelQuery, aabb: &Aabb) -> bool {
+    sweep_axis(world, aabb, 1, -GROUND_PROBE) > -GROUND_PROBE
+}
+
+/// Move a box along each axis (vertical first), a blocked axis
+/// doesn't stop the motion on the other ones.
+fn slide(world: &impl VoxelQuery, aabb: &Aabb, motion: Vector3<f32>) -> (Aabb, Vector3<f32>, [bool; 3]) {
+    let mut aabb = *aabb;
+    let mut moved = Vector3::zeros();
+    let mut blocked = [false; 3];
+
+    for axis in [1, 0, 2] {
+        let distance = sweep_axis(world, &aabb, axis, motion[axis]);
+        let mut offset = Vector3::zeros();
+        offset[axis] = distance;
+
+        aabb = aabb.translated(&offset);
+        moved[axis] = distance;
+        blocked[axis] = distance != motion[axis];
+    }
+
+    (aabb, moved, blocked)
+}
+
+/// Move a box through the voxels. The box slides along the voxels
+/// that block it and, when it stands on the ground, steps up the
+/// ledges up to `step_height`.
+///
+/// # Arguments
+///
+/// * `world`       - The voxels.
+/// * `aabb`        - The box, it must not overlap solid voxels.
+/// * `motion`      - The motion of the box.
+/// * `step_height` - The height of the highest ledge the box can step up, `0` to disable.
+///
+pub fn move_aabb(world: &impl VoxelQuery, aabb: &Aabb, motion: Vector3<f32>, step_height: f32) -> Collision {
+    let (mut result, mut moved, mut blocked) = slide(world, aabb, motion);
+    let mut stepped = false;
+
+    let horizontal = |v: &Vector3<f32>| v.x * v.x + v.z * v.z;
+
+    if step_height > 0.0 && (blocked[0] || blocked[2]) && motion.y <= 0.0 && is_on_ground(world, aabb) {
+        // Move up, then forward, then back down on the ledge.
+        let up = sweep_axis(world, aabb, 1, step_height);
+        let raised = aabb.translated(&Vector3::new(0.0, up, 0.0));
+        let (forward, forward_moved, forward_blocked) = slide(world, &raised, Vector3::new(motion.x, 0.0, motion.z));
+        let down = sweep_axis(world, &forward, 1, motion.y - up);
+
+        if horizontal(&forward_moved) > horizontal(&moved) + SKIN * SKIN {
+            result = forward.translated(&Vector3::new(0.0, down, 0.0));
+            moved = result.min - aabb.min;
+            blocked = [forward_blocked[0], down != motion.y - up, forward_blocked[2]];
+            stepped = true;
+        }
+    }
+
+    Collision {
+        aabb    : result,
+        motion  : moved,
+        blocked,
+        on_ground: motion.y <= 0.0 && is_on_ground(world, &result),
+        stepped,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::world::VoxelWorld;
+
+    /// A floor whose top is at `y = 0`, from `-8` to `8`.
+    fn floor() -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+
+        for z in -8..=8 {
+            for x in -8..=8 {
+                world.set_voxel(x, -1, z, 1);
+            }
+        }
+
+        world
+    }
+
+    /// Add a wall of `height` voxels at `x`.
+    fn wall(world: &mut VoxelWorld, x: i32, height: i32) {
+        for y in 0..height {
+            for z in -8..=8 {
+                world.set_voxel(x, y, z, 1);
+            }
+        }
+    }
+
+    /// A body whose feet are at `(x, y, z)`.
+    fn body(x: f32, y: f32, z: f32) -> Aabb {
+        Aabb::new(Vector3::new(x - 0.3, y, z - 0.3), Vector3::new(x + 0.3, y + 1.8, z + 0.3))
+    }
+
+    #[test]
+    fn grounding() {
+        let world = floor();
+
+        assert!(is_on_ground(&world, &body(0.5, 0.0, 0.5)));
+        assert!(is_on_ground(&world, &body(0.5, 0.01, 0.5)));
+        assert!(!is_on_ground(&world, &body(0.5, 0.2, 0.5)));
+        assert!(!is_on_ground(&world, &body(20.0, 0.0, 0.5)));
+
+        let collision = move_aabb(&world, &body(0.5, 0.5, 0.5), Vector3::new(0.0, -1.0, 0.0), 0.0);
+        assert!(collision.on_ground);
+        assert_eq!(collision.blocked, [false, true, false]);
+        assert!(collision.aabb.min.y >= 0.0 && collision.aabb.min.y < 0.01);
+
+        // Moving up is never on the ground.
+        let collision = move_aabb(&world, &body(0.5, 0.0, 0.5), Vector3::new(0.0, 0.5, 0.0), 0.0);
+        assert!(!collision.on_ground);
+        assert_eq!(collision.motion, Vector3::new(0.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn slide_along_wall() {
+        let mut world = floor();
+        wall(&mut world, 3, 4);
+
+        let collision = move_aabb(&world, &body(2.5, 0.0, 0.5), Vector3::new(1.0, 0.0, 1.0), 1.0);
+
+        assert_eq!(collision.blocked, [true, false, false]);
+        assert!(!collision.stepped);
+        assert!(collision.aabb.max.x <= 3.0 && collision.aabb.max.x > 2.99);
+        assert_eq!(collision.motion.z, 1.0);
+    }
+
+    #[test]
+    fn step_up_ledges() {
+        let mut world = floor();
+        wall(&mut world, 3, 1);
+
+        let motion = Vector3::new(1.0, -0.01, 0.0);
+        let collision = move_aabb(&world, &body(2.5, 0.0, 0.5), motion, 1.0);
+
+        assert!(collision.stepped);
+        assert!(collision.on_ground);
+        assert!((collision.aabb.min.y - 1.0).abs() < 0.01);
+        assert_eq!(collision.motion.x, 1.0);
+
+        // Not without a step height, or in the air.
+        assert!(!move_aabb(&world, &body(2.5, 0.0, 0.5), motion, 0.0).stepped);
+        assert!(!move_aabb(&world, &body(2.5, 0.5, 0.5), motion, 1.0).stepped);
+
+        // A ledge of two voxels is a wall.
+        wall(&mut world, 3, 2);
+        let collision = move_aabb(&world, &body(2.5, 0.0, 0.5), motion, 1.0);
+
+        assert!(!collision.stepped);
+        assert!(collision.blocked[0]);
+        assert!(collision.aabb.min.y < 0.01);
+    }
+
+    #[test]
+    fn no_tunnelling() {
+        let mut world = floor();
+        wall(&mut world, 5, 3);
+
+        // Through a wall of one voxel.
+        let collision = move_aabb(&world, &body(0.5, 0.0, 0.5), Vector3::new(1000.0, 0.0, 0.0), 0.0);
+        assert!(collision.blocked[0]);
+        assert!(collision.aabb.max.x <= 5.0 && collision.aabb.max.x > 4.99);
+
+        let collision = move_aabb(&world, &body(8.5, 0.0, 0.5), Vector3::new(-1000.0, 0.0, 0.0), 0.0);
+        assert!(collision.blocked[0]);
+        assert!(collision.aabb.min.x >= 6.0 && collision.aabb.min.x < 6.01);
+
+        // Through a floor of one voxel.
+        let collision = move_aabb(&world, &body(0.5, 500.0, 0.5), Vector3::new(0.0, -1000.0, 0.0), 0.0);
+        assert!(collision.on_ground);
+        assert!(collision.aabb.min.y >= 0.0 && collision.aabb.min.y < 0.01);
+    }
+}
@@ -0,0 +1,402 @@
+//! The temporal reprojection and the spatial denoising of the noisy
+//! lighting of the voxel raymarchers (the shadow and ambient
+//! occlusion rays of [shading_wgsl](super::shading::shading_wgsl)).
+//!
+//! The raymarch pass writes its color, with the depth, the normal
+//! and the material of the first surface of each pixel, into a
+//! G-buffer (see [gbuffer_wgsl]). The [Denoiser] then runs the
+//! compute passes:
+//!
+//! * the motion vectors, from the previous and the current camera matrices.
+//! * the reprojection of the history of the last frames, clamped to
+//!   the colors around the pixel so a stale history doesn't ghost.
+//! * a few iterations of an à-trous filter that only blurs the
+//!   neighbors on the same surface (normal, depth and material).
+//! * the resolve into the render texture, before the blit.
+
+use nalgebra::{Matrix4, Vector2, Vector3};
+
+use crate::engine::{
+    camera::Camera,
+    globals::globals_wgsl,
+    logging,
+    renderer::{Buffer, BufferUsage, ComputePipeline, RendererTrait},
+    shader_type::{ShaderStruct, ShaderType},
+};
+
+/// The workgroup size of `denoise.wgsl` and `denoise_resolve.wgsl`
+/// on each axis.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The size of a pixel of the G-buffer (a `GBufferPixel`) in bytes.
+const GBUFFER_PIXEL_SIZE: u64 = 32;
+
+/// The maximum amount of iterations of the à-trous filter, the
+/// last one blurs the neighbors `2^(n - 1)` pixels away.
+pub const MAX_ATROUS_ITERATIONS: u32 = 5;
+
+/// The settings of a [Denoiser].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DenoiseSettings {
+    /// The maximum amount of frames averaged by the history, `1`
+    /// disables the temporal reprojection.
+    pub max_history         : u32,
+    /// The amount of standard deviations of the colors around a
+    /// pixel its history is clamped to, a larger value ghosts more
+    /// but keeps more of the history.
+    pub clamp_sigma         : f32,
+    /// The amount of iterations of the à-trous filter (up to
+    /// [MAX_ATROUS_ITERATIONS]), `0` disables the spatial filter.
+    pub atrous_iterations   : u32,
+    /// The exponent of the similarity of the normals of two pixels,
+    /// a larger value blurs less across the edges.
+    pub normal_power        : f32,
+    /// The difference of depth (relative to the depth of the pixel)
+    /// under which two pixels are on the same surface.
+    pub depth_sigma         : f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        Self {
+            max_history         : 32,
+            clamp_sigma         : 1.5,
+            atrous_iterations   : 3,
+            normal_power        : 64.0,
+            depth_sigma         : 0.1,
+        }
+    }
+}
+
+impl DenoiseSettings {
+    /// Get the settings that show the noisy image as is.
+    pub fn disabled() -> Self {
+        Self {
+            max_history         : 1,
+            atrous_iterations   : 0,
+            ..Self::default()
+        }
+    }
+}
+
+crate::wgsl_struct! {
+    #[derive(Clone, Copy, Debug, Default)]
+    struct DenoiseParams {
+        inv_proj_view           : Matrix4<f32>,
+        previous_proj_view      : Matrix4<f32>,
+        camera_position         : Vector3<f32>,
+        max_history             : u32,
+        previous_camera_position: Vector3<f32>,
+        clamp_sigma             : f32,
+        size                    : Vector2<u32>,
+        normal_power            : f32,
+        depth_sigma             : f32,
+    }
+}
+
+crate::wgsl_struct! {
+    #[derive(Clone, Copy, Debug, Default)]
+    struct AtrousPass {
+        step                    : u32,
+    }
+}
+
+/// The buffers of a [Denoiser], of the size of the render texture.
+struct DenoiseTargets {
+    gbuffer     : Buffer,
+    /// The G-buffer of the last frame.
+    previous    : Buffer,
+    motion      : Buffer,
+    /// The color of the last frame (rgb) and the amount of frames it
+    /// averages (a).
+    history     : Buffer,
+    /// The output of the temporal pass.
+    temporal    : Buffer,
+    /// The ping-pong outputs of the à-trous iterations.
+    filtered    : [Buffer; 2],
+}
+
+impl DenoiseTargets {
+    fn new<R: RendererTrait>(renderer: &mut R, size: (u32, u32)) -> Self {
+        let pixels = size.0.max(1) as u64 * size.1.max(1) as u64;
+        let color_size = pixels * std::mem::size_of::<[f32; 4]>() as u64;
+
+        log::debug!(target: logging::RENDERER, "Allocate the denoising buffers of {}x{} pixels", size.0, size.1);
+
+        Self {
+            gbuffer     : renderer.create_buffer(pixels * GBUFFER_PIXEL_SIZE, BufferUsage::STORAGE, false),
+            previous    : renderer.create_buffer(pixels * GBUFFER_PIXEL_SIZE, BufferUsage::STORAGE, false),
+            motion      : renderer.create_buffer(pixels * std::mem::size_of::<[f32; 2]>() as u64, BufferUsage::STORAGE, false),
+            history     : renderer.create_buffer(color_size, BufferUsage::STORAGE, false),
+            temporal    : renderer.create_buffer(color_size, BufferUsage::STORAGE, false),
+            filtered    : [
+                renderer.create_buffer(color_size, BufferUsage::STORAGE, false),
+                renderer.create_buffer(color_size, BufferUsage::STORAGE, false),
+            ],
+        }
+    }
+
+    fn destroy<R: RendererTrait>(&self, renderer: &mut R) {
+        for buffer in [self.gbuffer, self.previous, self.motion, self.history, self.temporal, self.filtered[0], self.filtered[1]] {
+            renderer.destroy_buffer(buffer);
+        }
+    }
+
+    /// Get the buffers of the group `1` of `denoise.wgsl`.
+    fn frame_buffers(&self) -> [Buffer; 5] {
+        [self.gbuffer, self.previous, self.motion, self.history, self.temporal]
+    }
+}
+
+/// Denoise the G-buffer written by raymarch pipelines (the sources)
+/// into the render texture.
+///
+/// The sources are added with [Denoiser::add_source], then
+/// [Denoiser::render] is called each frame (in a render system) to
+/// dispatch them and the denoising passes.
+pub struct Denoiser {
+    motion      : ComputePipeline,
+    temporal    : ComputePipeline,
+    /// A pipeline per iteration of the à-trous filter, each one
+    /// with its input, its output and its step.
+    atrous      : Vec<ComputePipeline>,
+    resolve     : ComputePipeline,
+    history     : ComputePipeline,
+    sources     : Vec<ComputePipeline>,
+    params      : Buffer,
+    steps       : Vec<Buffer>,
+    targets     : DenoiseTargets,
+    /// The size of the targets in pixels.
+    size        : (u32, u32),
+    settings    : DenoiseSettings,
+    /// The amount of iterations the resolve pass reads the output of.
+    resolved    : u32,
+    /// The camera matrix and position of the last frame.
+    previous    : Option<(Matrix4<f32>, Vector3<f32>)>,
+}
+
+impl Denoiser {
+    /// Create a new [Denoiser], with buffers of the size of the
+    /// renderer.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer used to create the GPU resources.
+    /// * `settings`    - The denoising settings.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, settings: DenoiseSettings) -> Self {
+        let source = globals_wgsl() + &DenoiseParams::wgsl_definition() + &AtrousPass::wgsl_definition() + &gbuffer_wgsl(1)
+                   + include_str!("../../shaders/denoise.wgsl");
+        let shader = renderer.compile_shader(source);
+
+        let motion = renderer.create_compute_pipeline(shader, Some("motion_main"));
+        let temporal = renderer.create_compute_pipeline(shader, Some("temporal_main"));
+        let atrous = (0..MAX_ATROUS_ITERATIONS).map(|_| renderer.create_compute_pipeline(shader, Some("atrous_main"))).collect();
+        let history = renderer.create_compute_pipeline(shader, Some("history_main"));
+
+        let source = DenoiseParams::wgsl_definition() + include_str!("../../shaders/denoise_resolve.wgsl");
+        let shader = renderer.compile_shader(source);
+        let resolve = renderer.create_compute_pipeline(shader, None);
+
+        let params = renderer.create_buffer(DenoiseParams::SIZE as u64, BufferUsage::UNIFORM, true);
+        let steps = (0..MAX_ATROUS_ITERATIONS)
+            .map(|iteration| renderer.create_buffer_with_data(&AtrousPass { step: 1 << iteration }, BufferUsage::UNIFORM, false))
+            .collect();
+
+        let size = renderer.get_size();
+        let targets = DenoiseTargets::new(renderer, size);
+
+        let denoiser = Self {
+            motion,
+            temporal,
+            atrous,
+            resolve,
+            history,
+            sources     : Vec::new(),
+            params,
+            steps,
+            targets,
+            size,
+            settings,
+            resolved    : settings.atrous_iterations.min(MAX_ATROUS_ITERATIONS),
+            previous    : None,
+        };
+
+        denoiser.bind_targets(renderer);
+        denoiser.bind_resolve(renderer);
+        denoiser
+    }
+
+    /// Add a raymarch pipeline that writes the G-buffer, declared
+    /// by [gbuffer_wgsl] at the group `0`. The sources are dispatched
+    /// over the pixels by [Denoiser::render], in the order they are
+    /// added, before the denoising passes.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `pipeline`    - The raymarch pipeline.
+    ///
+    pub fn add_source<R: RendererTrait>(&mut self, renderer: &mut R, pipeline: ComputePipeline) {
+        renderer.set_binding_data(pipeline, 0, &[self.targets.gbuffer]);
+        self.sources.push(pipeline);
+    }
+
+    /// Get the denoising settings.
+    pub fn settings(&self) -> DenoiseSettings {
+        self.settings
+    }
+
+    /// Set the denoising settings.
+    pub fn set_settings(&mut self, settings: DenoiseSettings) {
+        self.settings = settings;
+    }
+
+    /// Dispatch the sources and the denoising passes of a frame, the
+    /// result is written into the render texture. The history is
+    /// dropped when the renderer is resized.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `camera`      - The camera (if any), the one of the [Globals](crate::engine::globals::Globals).
+    ///
+    pub fn render<R: RendererTrait>(&mut self, renderer: &mut R, camera: Option<&Camera>) {
+        let size = renderer.get_size();
+
+        if size != self.size {
+            self.targets.destroy(renderer);
+            self.targets = DenoiseTargets::new(renderer, size);
+            self.size = size;
+
+            for source in &self.sources {
+                renderer.set_binding_data(*source, 0, &[self.targets.gbuffer]);
+            }
+
+            self.bind_targets(renderer);
+            self.bind_resolve(renderer);
+        }
+
+        let s = self.settings;
+        let iterations = s.atrous_iterations.min(MAX_ATROUS_ITERATIONS);
+
+        if iterations != self.resolved {
+            self.resolved = iterations;
+            self.bind_resolve(renderer);
+        }
+
+        // The motion of the first frame is the one of a still camera.
+        let (proj_view, inv_proj_view, camera_position) = camera
+            .map(|camera| (camera.proj_view_matrix(), camera.inv_proj_view_matrix(), camera.position()))
+            .unwrap_or((Matrix4::identity(), Matrix4::identity(), Vector3::zeros()));
+        let view = (proj_view, camera_position);
+        let (previous_proj_view, previous_camera_position) = self.previous.replace(view).unwrap_or(view);
+
+        let params = DenoiseParams {
+            inv_proj_view,
+            previous_proj_view,
+            camera_position,
+            max_history             : s.max_history.max(1),
+            previous_camera_position,
+            clamp_sigma             : s.clamp_sigma,
+            size                    : Vector2::new(size.0, size.1),
+            normal_power            : s.normal_power,
+            depth_sigma             : s.depth_sigma,
+        };
+
+        renderer.update_buffer(self.params, &params, 0);
+
+        let workgroups = (size.0.div_ceil(WORKGROUP_SIZE), size.1.div_ceil(WORKGROUP_SIZE), 1);
+
+        for source in &self.sources {
+            renderer.dispatch_compute_pipeline(*source, workgroups);
+        }
+
+        renderer.dispatch_compute_pipeline(self.motion, workgroups);
+        renderer.dispatch_compute_pipeline(self.temporal, workgroups);
+
+        for pipeline in &self.atrous[..iterations as usize] {
+            renderer.dispatch_compute_pipeline(*pipeline, workgroups);
+        }
+
+        // The renderer binds the render texture again when it is
+        // resized, and covers the pixels of the edges (the shader
+        // ignores the ones beyond `params.size`).
+        renderer.dispatch_post_process_compute_pipeline(self.resolve, (WORKGROUP_SIZE, WORKGROUP_SIZE, 1));
+
+        // The G-buffer and the color of this frame are the history of
+        // the next one.
+        renderer.dispatch_compute_pipeline(self.history, workgroups);
+    }
+
+    /// Bind the targets to the passes, the iteration `i` of the
+    /// à-trous filter reads the output of the iteration `i - 1`.
+    fn bind_targets<R: RendererTrait>(&self, renderer: &mut R) {
+        let t = &self.targets;
+
+        for pipeline in [self.motion, self.temporal, self.history] {
+            renderer.set_binding_data(pipeline, 0, &[self.params]);
+            renderer.set_binding_data(pipeline, 1, &t.frame_buffers());
+        }
+
+        for (iteration, pipeline) in self.atrous.iter().enumerate() {
+            let input = if iteration == 0 { t.temporal } else { t.filtered[(iteration - 1) % 2] };
+
+            renderer.set_binding_data(*pipeline, 0, &[self.params]);
+            renderer.set_binding_data(*pipeline, 1, &t.frame_buffers());
+            renderer.set_binding_data(*pipeline, 2, &[input, t.filtered[iteration % 2], self.steps[iteration]]);
+        }
+    }
+
+    /// Bind the output of the last pass to the resolve pass.
+    fn bind_resolve<R: RendererTrait>(&self, renderer: &mut R) {
+        let t = &self.targets;
+        let input = match self.resolved {
+            0 => t.temporal,
+            iterations => t.filtered[(iterations as usize - 1) % 2],
+        };
+
+        renderer.set_binding_data(self.resolve, 1, &[self.params, input]);
+    }
+}
+
+/// Get the WGSL declarations of the G-buffer denoised by a
+/// [Denoiser], to prepend to the source of a raymarch shader after
+/// [globals_wgsl]. It declares the binding `0` of `group` and the
+/// function:
+///
+/// * `write_gbuffer(pixel: vec2<u32>, color: vec3<f32>, position: vec3<f32>, normal: vec3<f32>, material: u32)`
+///   that write the color of a pixel with the world position, the
+///   normal and the material of its first surface. The normal of
+///   the pixels of the sky is `0`.
+///
+/// # Arguments
+///
+/// * `group` - The bind group of the G-buffer.
+///
+pub fn gbuffer_wgsl(group: u32) -> String {
+    format!(
+        r#"struct GBufferPixel {{
+    color   : vec3<f32>,
+    depth   : f32,
+    normal  : vec3<f32>,
+    material: u32,
+}};
+
+@group({group}) @binding(0)
+var<storage, read_write> gbuffer: array<GBufferPixel>;
+
+fn write_gbuffer(pixel: vec2<u32>, color: vec3<f32>, position: vec3<f32>, normal: vec3<f32>, material: u32) {{
+    let size = vec2<u32>(globals.resolution);
+
+    if (any(pixel >= size)) {{
+        return;
+    }}
+
+    // The depth is the distance from the camera.
+    gbuffer[pixel.x + pixel.y * size.x] = GBufferPixel(color, distance(globals.camera_position, position), normal, material);
+}}
+
+"#,
+    )
+}
@@ -0,0 +1,677 @@
+//! Edit the voxels of a [VoxelWorld] with brushes and CSG
+//! operations, with an undo/redo history.
+//!
+//! The edits go through [Chunk::set], so only the chunks they touch
+//! are marked dirty (uploaded again to the GPU), modified (saved) and
+//! meshed again.
+
+use std::collections::{HashMap, VecDeque};
+
+use nalgebra::Vector3;
+
+use super::{
+    chunk::{split_voxel, Chunk, ChunkPos, CHUNK_SIZE},
+    world::VoxelWorld,
+    Voxel, AIR,
+};
+
+/// The default maximum amount of edits that can be undone.
+const DEFAULT_MAX_HISTORY: usize = 256;
+
+/// The shape of a [Brush], a voxel is in the shape if its center is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrushShape {
+    /// The voxels from `min` to `max` (included).
+    Box { min: Vector3<i32>, max: Vector3<i32> },
+    Sphere { center: Vector3<f32>, radius: f32 },
+    /// A vertical cylinder, `center` is the center of its base.
+    Cylinder { center: Vector3<f32>, radius: f32, height: f32 },
+}
+
+impl BrushShape {
+    /// Get the voxels that can be in the shape, from the first to
+    /// the last (included).
+    pub fn bounds(&self) -> (Vector3<i32>, Vector3<i32>) {
+        let (min, max) = match *self {
+            BrushShape::Box { min, max } => return (min.inf(&max), min.sup(&max)),
+            BrushShape::Sphere { center, radius } => (center.add_scalar(-radius), center.add_scalar(radius)),
+            BrushShape::Cylinder { center, radius, height } => (
+                center - Vector3::new(radius, 0.0, radius),
+                center + Vector3::new(radius, height, radius),
+            ),
+        };
+
+        (min.map(|v| v.floor() as i32), max.map(|v| v.ceil() as i32))
+    }
+
+    /// Check if a voxel is in the shape.
+    pub fn contains(&self, x: i32, y: i32, z: i32) -> bool {
+        let p = Vector3::new(x as f32, y as f32, z as f32).add_scalar(0.5);
+
+        match *self {
+            BrushShape::Box { min, max } => {
+                let (min, max) = (min.inf(&max), min.sup(&max));
+                (min.x..=max.x).contains(&x) && (min.y..=max.y).contains(&y) && (min.z..=max.z).contains(&z)
+            }
+            BrushShape::Sphere { center, radius } => (p - center).norm_squared() <= radius * radius,
+            BrushShape::Cylinder { center, radius, height } => {
+                let (dx, dz) = (p.x - center.x, p.z - center.z);
+                dx * dx + dz * dz <= radius * radius && p.y >= center.y && p.y <= center.y + height
+            }
+        }
+    }
+}
+
+/// What a [Brush] does to the voxels in its shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrushMode {
+    /// Fill the shape with a voxel.
+    Add(Voxel),
+    /// Clear the voxels of the shape.
+    Remove,
+    /// Replace the solid voxels of the shape, the air isn't changed.
+    Paint(Voxel),
+}
+
+/// A shape applied to the voxels with a mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    pub shape   : BrushShape,
+    pub mode    : BrushMode,
+}
+
+impl Brush {
+    /// Create a new [Brush].
+    ///
+    /// # Arguments
+    ///
+    /// * `shape`   - The voxels changed by the brush.
+    /// * `mode`    - How they are changed.
+    ///
+    pub fn new(shape: BrushShape, mode: BrushMode) -> Self {
+        Self { shape, mode }
+    }
+
+    /// Get the new voxel of a voxel in the shape.
+    fn apply(&self, voxel: Voxel) -> Voxel {
+        match self.mode {
+            BrushMode::Add(new) => new,
+            BrushMode::Remove => AIR,
+            BrushMode::Paint(new) if voxel != AIR => new,
+            BrushMode::Paint(_) => voxel,
+        }
+    }
+}
+
+/// A boolean operation between voxels, the solid voxels are the
+/// inside of the volumes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsgOperation {
+    /// The voxels of both volumes, the second one wins where they
+    /// overlap.
+    Union,
+    /// The voxels of the first volume outside of the second one.
+    Subtract,
+    /// The voxels of the first volume inside of the second one.
+    Intersect,
+}
+
+impl CsgOperation {
+    /// Combine two voxels.
+    pub fn combine(&self, a: Voxel, b: Voxel) -> Voxel {
+        match self {
+            CsgOperation::Union => if b != AIR { b } else { a },
+            CsgOperation::Subtract => if b != AIR { AIR } else { a },
+            CsgOperation::Intersect => if b != AIR { a } else { AIR },
+        }
+    }
+}
+
+/// A box of voxels outside of the world, placed at world coordinates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxelVolume {
+    min     : Vector3<i32>,
+    size    : Vector3<i32>,
+    voxels  : Vec<Voxel>,
+}
+
+impl VoxelVolume {
+    /// Create a new [VoxelVolume] filled with [AIR].
+    ///
+    /// # Arguments
+    ///
+    /// * `min`     - The world coordinates of its first voxel.
+    /// * `size`    - The amount of voxels on each axis.
+    ///
+    pub fn new(min: Vector3<i32>, size: Vector3<i32>) -> Self {
+        let size = size.map(|v| v.max(0));
+
+        Self {
+            min,
+            size,
+            voxels: vec![AIR; (size.x * size.y * size.z) as usize],
+        }
+    }
+
+    /// Create a [VoxelVolume] from the voxels of a brush in an
+    /// empty space, [BrushMode::Add] fills the shape.
+    pub fn from_brush(brush: &Brush) -> Self {
+        let (min, max) = brush.shape.bounds();
+        let mut volume = Self::new(min, max - min + Vector3::repeat(1));
+
+        volume.fill(|x, y, z| if brush.shape.contains(x, y, z) { brush.apply(AIR) } else { AIR });
+        volume
+    }
+
+    /// Copy the voxels of a world, from `min` to `max` (included).
+    pub fn from_world(world: &VoxelWorld, min: Vector3<i32>, max: Vector3<i32>) -> Self {
+        let mut volume = Self::new(min, max - min + Vector3::repeat(1));
+
+        volume.fill(|x, y, z| world.get_voxel(x, y, z));
+        volume
+    }
+
+    /// Get the world coordinates of the first voxel.
+    pub fn min(&self) -> Vector3<i32> {
+        self.min
+    }
+
+    /// Get the amount of voxels on each axis.
+    pub fn size(&self) -> Vector3<i32> {
+        self.size
+    }
+
+    /// Move the volume.
+    pub fn translate(&mut self, offset: Vector3<i32>) {
+        self.min += offset;
+    }
+
+    fn index(&self, x: i32, y: i32, z: i32) -> Option<usize> {
+        let l = Vector3::new(x, y, z) - self.min;
+
+        if l.iter().zip(self.size.iter()).all(|(l, size)| (0..*size).contains(l)) {
+            Some((l.x + (l.z + l.y * self.size.z) * self.size.x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Get a voxel at world coordinates, [AIR] outside of the volume.
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Voxel {
+        self.index(x, y, z).map(|index| self.voxels[index]).unwrap_or(AIR)
+    }
+
+    /// Set a voxel at world coordinates, ignored outside of the
+    /// volume. Return the previous voxel.
+    pub fn set(&mut self, x: i32, y: i32, z: i32, voxel: Voxel) -> Voxel {
+        match self.index(x, y, z) {
+            Some(index) => std::mem::replace(&mut self.voxels[index], voxel),
+            None => AIR,
+        }
+    }
+
+    /// Set all the voxels from their world coordinates.
+    fn fill(&mut self, mut f: impl FnMut(i32, i32, i32) -> Voxel) {
+        let mut index = 0;
+
+        for y in 0..self.size.y {
+            for z in 0..self.size.z {
+                for x in 0..self.size.x {
+                    self.voxels[index] = f(self.min.x + x, self.min.y + y, self.min.z + z);
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    /// Combine two volumes. The result cover both volumes for
+    /// [CsgOperation::Union], and the first one otherwise.
+    pub fn combine(&self, other: &VoxelVolume, operation: CsgOperation) -> VoxelVolume {
+        let (min, max) = match operation {
+            CsgOperation::Union => (self.min.inf(&other.min), (self.min + self.size).sup(&(other.min + other.size))),
+            _ => (self.min, self.min + self.size),
+        };
+
+        let mut volume = Self::new(min, max - min);
+        volume.fill(|x, y, z| operation.combine(self.get(x, y, z), other.get(x, y, z)));
+        volume
+    }
+}
+
+/// A voxel changed by an edit, in a chunk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct VoxelChange {
+    /// The index of the voxel in the chunk, see [Chunk::index].
+    index   : u16,
+    before  : Voxel,
+    after   : Voxel,
+}
+
+/// The voxels changed by an edit, grouped by chunk.
+#[derive(Clone, Debug, Default)]
+pub struct EditDelta {
+    chunks: Vec<(ChunkPos, Vec<VoxelChange>)>,
+}
+
+impl EditDelta {
+    /// Check if the edit changed nothing.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Get the amount of voxels changed.
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|(_, changes)| changes.len()).sum()
+    }
+
+    /// Get the chunks changed by the edit.
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.iter().map(|(pos, _)| *pos)
+    }
+
+    /// Set the voxels before (`undo`) or after the edit.
+    fn apply(&self, world: &mut VoxelWorld, undo: bool) {
+        let size = CHUNK_SIZE as usize;
+
+        for (pos, changes) in &self.chunks {
+            let min = pos.min_voxel();
+
+            for change in changes {
+                let index = change.index as usize;
+                let (x, z, y) = (index % size, index / size % size, index / (size * size));
+                let voxel = if undo { change.before } else { change.after };
+
+                world.set_voxel(min.x + x as i32, min.y + y as i32, min.z + z as i32, voxel);
+            }
+        }
+    }
+}
+
+/// Change the voxels of a world while recording an [EditDelta],
+/// given to [VoxelEditor::edit].
+pub struct EditContext<'a> {
+    world   : &'a mut VoxelWorld,
+    changes : HashMap<ChunkPos, Vec<VoxelChange>>,
+}
+
+impl EditContext<'_> {
+    /// Get the world being edited.
+    pub fn world(&self) -> &VoxelWorld {
+        self.world
+    }
+
+    /// Set a voxel, return the previous one.
+    pub fn set_voxel(&mut self, x: i32, y: i32, z: i32, voxel: Voxel) -> Voxel {
+        let before = self.world.set_voxel(x, y, z, voxel);
+
+        if before != voxel {
+            let (pos, (lx, ly, lz)) = split_voxel(x, y, z);
+            let index = Chunk::index(lx, ly, lz) as u16;
+
+            self.changes.entry(pos).or_default().push(VoxelChange { index, before, after: voxel });
+        }
+
+        before
+    }
+
+    /// Clear a voxel, return the previous one.
+    pub fn clear_voxel(&mut self, x: i32, y: i32, z: i32) -> Voxel {
+        self.set_voxel(x, y, z, AIR)
+    }
+
+    /// Apply a brush.
+    pub fn apply_brush(&mut self, brush: &Brush) {
+        let (min, max) = brush.shape.bounds();
+
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    if brush.shape.contains(x, y, z) {
+                        let voxel = brush.apply(self.world.get_voxel(x, y, z));
+                        self.set_voxel(x, y, z, voxel);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Combine the world (first operand) with a volume, in the box
+    /// of the volume: the world outside of it is never changed.
+    pub fn apply_volume(&mut self, volume: &VoxelVolume, operation: CsgOperation) {
+        let (min, max) = (volume.min, volume.min + volume.size);
+
+        for y in min.y..max.y {
+            for z in min.z..max.z {
+                for x in min.x..max.x {
+                    let voxel = operation.combine(self.world.get_voxel(x, y, z), volume.get(x, y, z));
+                    self.set_voxel(x, y, z, voxel);
+                }
+            }
+        }
+    }
+
+    /// Build the delta, a voxel changed several times keeps its
+    /// first and last values.
+    fn finish(self) -> EditDelta {
+        let mut chunks = self.changes.into_iter()
+            .map(|(pos, mut changes)| {
+                changes.sort_by_key(|change| change.index);
+
+                let mut merged: Vec<VoxelChange> = Vec::with_capacity(changes.len());
+
+                for change in changes {
+                    match merged.last_mut() {
+                        Some(last) if last.index == change.index => last.after = change.after,
+                        _ => merged.push(change),
+                    }
+                }
+
+                merged.retain(|change| change.before != change.after);
+                (pos, merged)
+            })
+            .filter(|(_, changes)| !changes.is_empty())
+            .collect::<Vec<_>>();
+
+        chunks.sort_by_key(|(pos, _)| (pos.x, pos.y, pos.z));
+
+        EditDelta { chunks }
+    }
+}
+
+/// Edit a [VoxelWorld] and keep the history of the edits to undo
+/// and redo them. The history only keeps the changed voxels.
+///
+/// The chunks of an edit must still be loaded when it is undone or
+/// redone, otherwise they are created.
+pub struct VoxelEditor {
+    undo_stack  : VecDeque<EditDelta>,
+    redo_stack  : Vec<EditDelta>,
+    max_history : usize,
+}
+
+impl Default for VoxelEditor {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_HISTORY)
+    }
+}
+
+impl VoxelEditor {
+    /// Create a new [VoxelEditor].
+    ///
+    /// # Arguments
+    ///
+    /// * `max_history` - The maximum amount of edits that can be undone.
+    ///
+    pub fn new(max_history: usize) -> Self {
+        Self {
+            undo_stack  : VecDeque::new(),
+            redo_stack  : Vec::new(),
+            max_history,
+        }
+    }
+
+    /// Make an edit made of several changes (e.g. a brush stroke),
+    /// undone at once. Return the amount of voxels changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `world`   - The world to edit.
+    /// * `f`       - The function that change the voxels.
+    ///
+    pub fn edit(&mut self, world: &mut VoxelWorld, f: impl FnOnce(&mut EditContext)) -> usize {
+        let mut context = EditContext { world, changes: HashMap::new() };
+        f(&mut context);
+
+        let delta = context.finish();
+        let changed = delta.len();
+
+        if !delta.is_empty() {
+            self.redo_stack.clear();
+            self.undo_stack.push_back(delta);
+
+            while self.undo_stack.len() > self.max_history {
+                self.undo_stack.pop_front();
+            }
+        }
+
+        changed
+    }
+
+    /// Set a voxel, return `true` if it changed.
+    pub fn set_voxel(&mut self, world: &mut VoxelWorld, x: i32, y: i32, z: i32, voxel: Voxel) -> bool {
+        self.edit(world, |context| { context.set_voxel(x, y, z, voxel); }) > 0
+    }
+
+    /// Clear a voxel, return `true` if it changed.
+    pub fn clear_voxel(&mut self, world: &mut VoxelWorld, x: i32, y: i32, z: i32) -> bool {
+        self.set_voxel(world, x, y, z, AIR)
+    }
+
+    /// Apply a brush, return the amount of voxels changed.
+    pub fn apply_brush(&mut self, world: &mut VoxelWorld, brush: &Brush) -> usize {
+        self.edit(world, |context| context.apply_brush(brush))
+    }
+
+    /// Combine the world with a volume (see [EditContext::apply_volume]),
+    /// return the amount of voxels changed.
+    pub fn apply_volume(&mut self, world: &mut VoxelWorld, volume: &VoxelVolume, operation: CsgOperation) -> usize {
+        self.edit(world, |context| context.apply_volume(volume, operation))
+    }
+
+    /// Undo the last edit, return `false` if there is nothing to undo.
+    pub fn undo(&mut self, world: &mut VoxelWorld) -> bool {
+        let Some(delta) = self.undo_stack.pop_back() else { return false };
+
+        delta.apply(world, true);
+        self.redo_stack.push(delta);
+        true
+    }
+
+    /// Redo the last undone edit, return `false` if there is nothing
+    /// to redo.
+    pub fn redo(&mut self, world: &mut VoxelWorld) -> bool {
+        let Some(delta) = self.redo_stack.pop() else { return false };
+
+        delta.apply(world, false);
+        self.undo_stack.push_back(delta);
+        true
+    }
+
+    /// Check if an edit can be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Check if an edit can be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Get the last edit that can be undone.
+    pub fn last_edit(&self) -> Option<&EditDelta> {
+        self.undo_stack.back()
+    }
+
+    /// Forget all the edits.
+    pub fn clear_history(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The voxels of the box used by the tests, across several chunks.
+    fn snapshot(world: &VoxelWorld) -> Vec<Voxel> {
+        VoxelVolume::from_world(world, Vector3::repeat(-20), Vector3::repeat(20)).voxels
+    }
+
+    /// A floor with a few voxels of another material.
+    fn test_world() -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+
+        for z in -20..=20 {
+            for x in -20..=20 {
+                world.set_voxel(x, -1, z, 1);
+                world.set_voxel(x, -2, z, if (x + z) % 3 == 0 { 2 } else { 1 });
+            }
+        }
+
+        world
+    }
+
+    #[test]
+    fn undo_redo_round_trip() {
+        let mut world = test_world();
+        let mut editor = VoxelEditor::default();
+        let mut snapshots = vec![snapshot(&world)];
+
+        let brushes = [
+            Brush::new(BrushShape::Sphere { center: Vector3::new(0.0, 0.0, 0.0), radius: 6.0 }, BrushMode::Add(3)),
+            Brush::new(BrushShape::Box { min: Vector3::new(-3, -2, -3), max: Vector3::new(10, 2, 4) }, BrushMode::Remove),
+            Brush::new(BrushShape::Cylinder { center: Vector3::new(-5.0, -3.0, 2.0), radius: 4.0, height: 6.0 }, BrushMode::Paint(4)),
+        ];
+
+        for brush in &brushes {
+            assert!(editor.apply_brush(&mut world, brush) > 0);
+            snapshots.push(snapshot(&world));
+        }
+
+        assert!(editor.set_voxel(&mut world, 15, 15, 15, 5));
+        assert!(!editor.set_voxel(&mut world, 15, 15, 15, 5));
+        snapshots.push(snapshot(&world));
+
+        for expected in snapshots.iter().rev().skip(1) {
+            assert!(editor.undo(&mut world));
+            assert!(snapshot(&world) == *expected);
+        }
+
+        assert!(!editor.undo(&mut world));
+
+        for expected in snapshots.iter().skip(1) {
+            assert!(editor.redo(&mut world));
+            assert!(snapshot(&world) == *expected);
+        }
+
+        assert!(!editor.redo(&mut world));
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut world = VoxelWorld::new();
+        let mut editor = VoxelEditor::default();
+
+        editor.set_voxel(&mut world, 0, 0, 0, 1);
+        editor.set_voxel(&mut world, 1, 0, 0, 1);
+        assert!(editor.undo(&mut world));
+        assert!(editor.can_redo());
+
+        // An edit that changes nothing keeps the redo stack.
+        editor.set_voxel(&mut world, 0, 0, 0, 1);
+        assert!(editor.can_redo());
+
+        editor.set_voxel(&mut world, 2, 0, 0, 1);
+        assert!(!editor.can_redo());
+        assert!(!editor.redo(&mut world));
+        assert_eq!(world.get_voxel(1, 0, 0), AIR);
+
+        assert!(editor.undo(&mut world));
+        assert!(editor.undo(&mut world));
+        assert!(!editor.can_undo());
+        assert!(snapshot(&world).iter().all(|voxel| *voxel == AIR));
+    }
+
+    #[test]
+    fn limited_history() {
+        let mut world = VoxelWorld::new();
+        let mut editor = VoxelEditor::new(2);
+
+        for x in 0..4 {
+            editor.set_voxel(&mut world, x, 0, 0, 1);
+        }
+
+        assert!(editor.undo(&mut world));
+        assert!(editor.undo(&mut world));
+        assert!(!editor.undo(&mut world));
+        assert_eq!((0..4).map(|x| world.get_voxel(x, 0, 0)).collect::<Vec<_>>(), [1, 1, AIR, AIR]);
+    }
+
+    #[test]
+    fn merged_delta() {
+        let mut world = VoxelWorld::new();
+        let mut editor = VoxelEditor::default();
+
+        let changed = editor.edit(&mut world, |context| {
+            context.set_voxel(0, 0, 0, 1);
+            context.set_voxel(0, 0, 0, 2);
+            context.set_voxel(1, 0, 0, 1);
+            context.clear_voxel(1, 0, 0);
+            context.set_voxel(-1, 0, 0, 3);
+        });
+
+        // The voxel set back to air isn't in the delta.
+        assert_eq!(changed, 2);
+
+        let delta = editor.last_edit().unwrap();
+        assert_eq!(delta.chunks().collect::<Vec<_>>(), [ChunkPos::new(-1, 0, 0), ChunkPos::new(0, 0, 0)]);
+
+        editor.undo(&mut world);
+        assert_eq!(world.get_voxel(0, 0, 0), AIR);
+        assert_eq!(world.get_voxel(-1, 0, 0), AIR);
+    }
+
+    #[test]
+    fn csg_operations() {
+        let a = VoxelVolume::from_brush(&Brush::new(
+            BrushShape::Box { min: Vector3::new(0, 0, 0), max: Vector3::new(3, 3, 3) },
+            BrushMode::Add(1),
+        ));
+        let b = VoxelVolume::from_brush(&Brush::new(
+            BrushShape::Box { min: Vector3::new(2, 0, 0), max: Vector3::new(5, 1, 3) },
+            BrushMode::Add(2),
+        ));
+
+        let count = |volume: &VoxelVolume, voxel: Voxel| volume.voxels.iter().filter(|v| **v == voxel).count();
+
+        // 64 voxels in `a`, 32 in `b`, 16 in both.
+        let union = a.combine(&b, CsgOperation::Union);
+        assert_eq!((union.min(), union.size()), (Vector3::new(0, 0, 0), Vector3::new(6, 4, 4)));
+        assert_eq!((count(&union, 1), count(&union, 2)), (48, 32));
+        assert_eq!((union.get(2, 0, 0), union.get(2, 3, 0), union.get(5, 3, 0)), (2, 1, AIR));
+
+        let subtract = a.combine(&b, CsgOperation::Subtract);
+        assert_eq!((subtract.min(), subtract.size()), (a.min(), a.size()));
+        assert_eq!((count(&subtract, 1), count(&subtract, 2)), (48, 0));
+        assert_eq!((subtract.get(2, 0, 0), subtract.get(2, 2, 0)), (AIR, 1));
+
+        let intersect = a.combine(&b, CsgOperation::Intersect);
+        assert_eq!((intersect.min(), intersect.size()), (a.min(), a.size()));
+        assert_eq!((count(&intersect, 1), count(&intersect, 2)), (16, 0));
+        assert_eq!((intersect.get(3, 1, 3), intersect.get(1, 1, 1)), (1, AIR));
+
+        // The same operations on the world, only in the box of `b`:
+        // the intersection with a full box changes nothing.
+        for (operation, expected) in [(CsgOperation::Union, 32), (CsgOperation::Subtract, 16), (CsgOperation::Intersect, 0)] {
+            let mut world = VoxelWorld::new();
+            let mut editor = VoxelEditor::default();
+
+            editor.apply_volume(&mut world, &a, CsgOperation::Union);
+            assert_eq!(editor.apply_volume(&mut world, &b, operation), expected, "{:?}", operation);
+
+            for y in -1..=6 {
+                for z in -1..=6 {
+                    for x in -1..=6 {
+                        let voxel = match b.index(x, y, z) {
+                            Some(_) => operation.combine(a.get(x, y, z), b.get(x, y, z)),
+                            None => a.get(x, y, z),
+                        };
+
+                        assert_eq!(world.get_voxel(x, y, z), voxel);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::collections::HashMap;
+
+use nalgebra::Vector3;
+
+use crate::engine::{
+    logging,
+    renderer::{Buffer, BufferUsage, RendererTrait},
+};
+
+use super::{
+    chunk::{ChunkPos, CHUNK_SIZE, CHUNK_VOLUME},
+    light::{pack_light, MAX_LIGHT},
+    lod::{build_mips, LOD_LEVELS, MIP_WORDS},
+    material::MaterialPalette,
+    streaming::StreamingSettings,
+    world::VoxelWorld,
+};
+
+/// The value of the indirection table for the chunks that
+/// aren't uploaded (not loaded or empty).
+pub const EMPTY_SLOT: u32 = u32::MAX;
+
+/// The amount of `u32` used by a chunk on the GPU, the voxels
+/// are packed two by two.
+const SLOT_WORDS: usize = CHUNK_VOLUME / 2;
+
+/// The amount of `u32` used by the light of a chunk on the GPU,
+/// the light of the voxels is packed four by four.
+const LIGHT_WORDS: usize = CHUNK_VOLUME / 4;
+
+crate::wgsl_struct! {
+    #[derive(Clone, Copy, Debug, Default)]
+    struct ChunkTable {
+        origin      : Vector3<i32>,
+        chunk_size  : i32,
+        size        : Vector3<i32>,
+        slot_count  : u32,
+    }
+}
+
+/// Store the chunks of a [VoxelWorld] in GPU memory, so the
+/// compute shaders (the raymarcher) can read them.
+///
+/// The chunks are stored in fixed size slots of a storage buffer,
+/// and an indirection table give the slot of each chunk in a box
+/// around the streaming center. Only the chunks that changed are
+/// uploaded, a few per frame. The levels of detail of each chunk
+/// (see [lod](super::lod)) and its light (see [light](super::light))
+/// are stored in the same slot of other buffers. The light of the
+/// chunks that aren't lit yet is the full sky light, and the empty
+/// chunks fully under the sky aren't stored.
+///
+/// The buffers are bound with [GpuChunkStore::buffers], and the
+/// shaders include [chunks_wgsl] to read the voxels.
+pub struct GpuChunkStore {
+    params      : Buffer,
+    table       : Buffer,
+    pool        : Buffer,
+    mips        : Buffer,
+    light       : Buffer,
+    /// The size of the table (in chunks).
+    size        : Vector3<i32>,
+    /// The chunk at the minimum corner of the table.
+    origin      : Option<ChunkPos>,
+    slots       : HashMap<ChunkPos, u32>,
+    free_slots  : Vec<u32>,
+    table_dirty : bool,
+    /// The revision of the palette the levels of detail were built with.
+    palette_revision: Option<u64>,
+    /// The maximum amount of chunks uploaded per frame.
+    uploads_per_frame: usize,
+    /// Incremented each time the stored voxels change.
+    revision    : u64,
+}
+
+impl GpuChunkStore {
+    /// Create a new [GpuChunkStore] large enough for all the chunks
+    /// loaded by a [ChunkStreamer](super::streaming::ChunkStreamer).
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`            - The renderer used to create the buffers.
+    /// * `settings`            - The settings of the streaming.
+    /// * `uploads_per_frame`   - The maximum amount of chunks uploaded per frame.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, settings: &StreamingSettings, uploads_per_frame: usize) -> Self {
+        let margin = settings.unload_margin.max(0);
+        let horizontal = (settings.radius + margin) * 2 + 1;
+        let vertical = (settings.vertical_radius + margin) * 2 + 1;
+        let size = Vector3::new(horizontal, vertical, horizontal);
+
+        let slot_count = (size.x * size.y * size.z) as usize;
+        let slot_bytes = (SLOT_WORDS * std::mem::size_of::<u32>()) as u64;
+
+        let params = renderer.create_buffer_with_data(&ChunkTable::default(), BufferUsage::UNIFORM, true);
+        let table = renderer.create_buffer_with_slice(&vec![EMPTY_SLOT; slot_count], BufferUsage::STORAGE, true);
+        let pool = renderer.create_buffer(slot_count as u64 * slot_bytes, BufferUsage::STORAGE, true);
+        let mips = renderer.create_buffer((slot_count * MIP_WORDS * std::mem::size_of::<u32>()) as u64, BufferUsage::STORAGE, true);
+        let light = renderer.create_buffer((slot_count * LIGHT_WORDS * std::mem::size_of::<u32>()) as u64, BufferUsage::STORAGE, true);
+
+        log::debug!(target: logging::RENDERER, "Allocate {} chunk slots ({} MiB)", slot_count, (slot_count as u64 * slot_bytes) >> 20);
+
+        Self {
+            params,
+            table,
+            pool,
+            mips,
+            light,
+            size,
+            origin      : None,
+            slots       : HashMap::new(),
+            free_slots  : (0..slot_count as u32).rev().collect(),
+            table_dirty : true,
+            palette_revision: None,
+            uploads_per_frame: uploads_per_frame.max(1),
+            revision    : 0,
+        }
+    }
+
+    /// Get the buffers to bind (in this order) to the group used
+    /// by [chunks_wgsl]: the table parameters, the indirection
+    /// table, the voxels, the levels of detail and the light.
+    pub fn buffers(&self) -> [Buffer; 5] {
+        [self.params, self.table, self.pool, self.mips, self.light]
+    }
+
+    /// Get the amount of chunks stored on the GPU.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Check if no chunk is stored on the GPU.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Get the revision of the stored voxels, it changes each time
+    /// a chunk is uploaded or released (e.g. to restart an
+    /// accumulation over the frames).
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Upload the chunks that changed (nearest first) and update
+    /// the indirection table. All the chunks are uploaded again
+    /// when the palette changes, as their levels of detail depend
+    /// on it.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `world`       - The world to upload.
+    /// * `palette`     - The materials of the voxels.
+    /// * `center`      - The chunk at the center of the table.
+    ///
+    pub fn sync<R: RendererTrait>(&mut self, renderer: &mut R, world: &mut VoxelWorld, palette: &MaterialPalette, center: ChunkPos) {
+        let half = (self.size - Vector3::repeat(1)) / 2;
+        let origin = center.offset(-half.x, -half.y, -half.z);
+
+        if self.origin != Some(origin) {
+            self.origin = Some(origin);
+            self.table_dirty = true;
+        }
+
+        // Release the slots of the chunks that were unloaded or
+        // that are outside the table.
+        let released = self.slots.keys()
+            .filter(|pos| !world.contains_chunk(pos) || !self.in_table(pos))
+            .copied()
+            .collect::<Vec<_>>();
+
+        for pos in released {
+            self.release(&pos);
+
+            // Upload it again if it come back in the table.
+            if let Some(chunk) = world.chunk_mut(&pos) {
+                chunk.set_dirty(true);
+            }
+        }
+
+        if self.palette_revision != Some(palette.revision()) {
+            self.palette_revision = Some(palette.revision());
+
+            for pos in self.slots.keys() {
+                if let Some(chunk) = world.chunk_mut(pos) {
+                    chunk.set_dirty(true);
+                }
+            }
+        }
+
+        let mut dirty = world.chunks()
+            .filter(|(pos, chunk)| chunk.is_dirty() && self.in_table(pos))
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+
+        dirty.sort_by_key(|pos| pos.distance_squared(&center));
+        dirty.truncate(self.uploads_per_frame);
+
+        let mut packed = vec![0u32; SLOT_WORDS];
+        let mut mips = vec![0u32; MIP_WORDS];
+        let mut light = vec![0u32; LIGHT_WORDS];
+        let full_sky = pack_light(MAX_LIGHT, 0);
+
+        for pos in dirty {
+            let Some(chunk) = world.chunk_mut(&pos) else { continue };
+            chunk.set_dirty(false);
+
+            let under_sky = !chunk.is_lit() || chunk.lights().iter().all(|light| *light == full_sky);
+
+            if chunk.is_empty() && under_sky {
+                self.release(&pos);
+                continue;
+            }
+
+            let slot = match self.slots.get(&pos) {
+                Some(slot) => *slot,
+                None => match self.free_slots.pop() {
+                    Some(slot) => {
+                        self.slots.insert(pos, slot);
+                        self.table_dirty = true;
+                        slot
+                    },
+                    None => {
+                        log::warn!(target: logging::RENDERER, "No free GPU slot for the chunk {:?}", pos);
+                        chunk.set_dirty(true);
+                        break;
+                    },
+                },
+            };
+
+            for (word, voxels) in packed.iter_mut().zip(chunk.voxels().chunks_exact(2)) {
+                *word = voxels[0] as u32 | ((voxels[1] as u32) << 16);
+            }
+
+            let offset = slot as u64 * (SLOT_WORDS * std::mem::size_of::<u32>()) as u64;
+            renderer.update_buffer_with_slice(self.pool, &packed, offset);
+
+            build_mips(chunk, palette, &mut mips);
+
+            let offset = slot as u64 * (MIP_WORDS * std::mem::size_of::<u32>()) as u64;
+            renderer.update_buffer_with_slice(self.mips, &mips, offset);
+
+            if chunk.is_lit() {
+                for (word, lights) in light.iter_mut().zip(chunk.lights().chunks_exact(4)) {
+                    *word = u32::from_le_bytes([lights[0], lights[1], lights[2], lights[3]]);
+                }
+            } else {
+                light.fill(u32::from_le_bytes([full_sky; 4]));
+            }
+
+            let offset = slot as u64 * (LIGHT_WORDS * std::mem::size_of::<u32>()) as u64;
+            renderer.update_buffer_with_slice(self.light, &light, offset);
+
+            self.revision += 1;
+        }
+
+        if self.table_dirty {
+            self.upload_table(renderer, origin);
+            self.table_dirty = false;
+            self.revision += 1;
+        }
+    }
+
+    fn in_table(&self, pos: &ChunkPos) -> bool {
+        let Some(origin) = self.origin else { return false };
+        let local = Vector3::new(pos.x - origin.x, pos.y - origin.y, pos.z - origin.z);
+
+        local.x >= 0 && local.y >= 0 && local.z >= 0 &&
+        local.x < self.size.x && local.y < self.size.y && local.z < self.size.z
+    }
+
+    fn release(&mut self, pos: &ChunkPos) {
+        if let Some(slot) = self.slots.remove(pos) {
+            self.free_slots.push(slot);
+            self.table_dirty = true;
+        }
+    }
+
+    fn upload_table<R: RendererTrait>(&self, renderer: &mut R, origin: ChunkPos) {
+        let mut table = vec![EMPTY_SLOT; (self.size.x * self.size.y * self.size.z) as usize];
+
+        for (pos, slot) in &self.slots {
+            let (x, y, z) = (pos.x - origin.x, pos.y - origin.y, pos.z - origin.z);
+            table[(x + z * self.size.x + y * self.size.x * self.size.z) as usize] = *slot;
+        }
+
+        let params = ChunkTable {
+            origin      : Vector3::new(origin.x, origin.y, origin.z),
+            chunk_size  : CHUNK_SIZE,
+            size        : self.size,
+            slot_count  : (self.slots.len() + self.free_slots.len()) as u32,
+        };
+
+        renderer.update_buffer(self.params, &params, 0);
+        renderer.update_buffer_with_slice(self.table, &table, 0);
+    }
+}
+
+/// Get the WGSL declarations used to read the voxels stored by a
+/// [GpuChunkStore], to prepend to the source of a shader. It
+/// declares the bindings `0` to `4` of `group` and the functions:
+///
+/// * `voxel_at(voxel: vec3<i32>) -> u32` that return the voxel at
+///   world coordinates (`0` is air).
+/// * `mip_at(voxel: vec3<i32>, level: u32) -> vec4<f32>` that return
+///   the cell of the level of detail `level` (`1` to `LOD_LEVELS`)
+///   that contains a voxel: the average albedo in `xyz` and the
+///   occupancy in `w` (`0` if the chunk isn't uploaded).
+/// * `light_at(voxel: vec3<i32>) -> vec2<f32>` that return the sky
+///   light in `x` and the block light in `y` of a voxel, from `0`
+///   to `1` (the full sky light if the chunk isn't uploaded).
+///
+/// # Arguments
+///
+/// * `group` - The bind group of the [GpuChunkStore::buffers].
+///
+pub fn chunks_wgsl(group: u32) -> String {
+    format!(
+        r#"{definition}
+@group({group}) @binding(0)
+var<uniform> chunk_table: ChunkTable;
+
+@group({group}) @binding(1)
+var<storage, read> chunk_slots: array<u32>;
+
+@group({group}) @binding(2)
+var<storage, read> chunk_voxels: array<u32>;
+
+@group({group}) @binding(3)
+var<storage, read> chunk_mips: array<u32>;
+
+@group({group}) @binding(4)
+var<storage, read> chunk_light: array<u32>;
+
+let EMPTY_SLOT: u32 = {empty}u;
+let LOD_LEVELS: u32 = {lod_levels}u;
+
+fn chunk_slot(chunk: vec3<i32>) -> u32 {{
+    let local = chunk - chunk_table.origin;
+
+    if (any(local < vec3<i32>(0)) || any(local >= chunk_table.size)) {{
+        return EMPTY_SLOT;
+    }}
+
+    return chunk_slots[local.x + local.z * chunk_table.size.x + local.y * chunk_table.size.x * chunk_table.size.z];
+}}
+
+fn voxel_chunk(voxel: vec3<i32>) -> vec3<i32> {{
+    let size = chunk_table.chunk_size;
+
+    // Floor division, the voxels with negative coordinates
+    // belong to the chunks with negative coordinates.
+    return (voxel - select(vec3<i32>(0), vec3<i32>(size - 1), voxel < vec3<i32>(0))) / size;
+}}
+
+fn voxel_at(voxel: vec3<i32>) -> u32 {{
+    let size = chunk_table.chunk_size;
+    let chunk = voxel_chunk(voxel);
+    let slot = chunk_slot(chunk);
+
+    if (slot == EMPTY_SLOT) {{
+        return 0u;
+    }}
+
+    let local = voxel - chunk * size;
+    let index = u32(local.x + local.z * size + local.y * size * size);
+    let word = chunk_voxels[slot * {slot_words}u + index / 2u];
+
+    return (word >> ((index & 1u) * 16u)) & 0xffffu;
+}}
+
+fn mip_at(voxel: vec3<i32>, level: u32) -> vec4<f32> {{
+    let size = chunk_table.chunk_size;
+    let chunk = voxel_chunk(voxel);
+    let slot = chunk_slot(chunk);
+
+    if (slot == EMPTY_SLOT) {{
+        return vec4<f32>(0.0);
+    }}
+
+    // The levels are stored one after the other, from the level 1.
+    var offset = 0;
+    for (var current = 1u; current < level; current++) {{
+        let cells = size >> current;
+        offset += cells * cells * cells;
+    }}
+
+    let cells = size >> level;
+    let cell = (voxel - chunk * size) >> vec3<u32>(level);
+    let index = u32(offset + cell.x + cell.z * cells + cell.y * cells * cells);
+
+    return unpack4x8unorm(chunk_mips[slot * {mip_words}u + index]);
+}}
+
+fn light_at(voxel: vec3<i32>) -> vec2<f32> {{
+    let size = chunk_table.chunk_size;
+    let chunk = voxel_chunk(voxel);
+    let slot = chunk_slot(chunk);
+
+    if (slot == EMPTY_SLOT) {{
+        return vec2<f32>(1.0, 0.0);
+    }}
+
+    let local = voxel - chunk * size;
+    let index = u32(local.x + local.z * size + local.y * size * size);
+    let light = (chunk_light[slot * {light_words}u + index / 4u] >> ((index & 3u) * 8u)) & 0xffu;
+
+    return vec2<f32>(f32(light >> 4u), f32(light & 0xfu)) / {max_light}.0;
+}}
+
+"#,
+        definition = <ChunkTable as crate::engine::shader_type::ShaderStruct>::wgsl_definition(),
+        group = group,
+        empty = EMPTY_SLOT,
+        slot_words = SLOT_WORDS,
+        lod_levels = LOD_LEVELS,
+        mip_words = MIP_WORDS,
+        light_words = LIGHT_WORDS,
+        max_light = MAX_LIGHT,
+    )
+}
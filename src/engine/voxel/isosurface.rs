@@ -0,0 +1,553 @@
+//! Smooth surfaces extracted from a density field, with the marching
+//! cubes or the dual contouring.
+//!
+//! The density is sampled at the center of the voxels: the sample
+//! `(x, y, z)` is at `(x + 0.5, y + 0.5, z + 0.5)` in world space. A
+//! chunk owns the cells (and the edges) that start at its samples, so
+//! the meshes of neighbor chunks share their border vertices exactly
+//! and stitch without cracks.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use nalgebra::{Matrix3, Vector3};
+
+use super::{
+    chunk::{ChunkPos, CHUNK_SIZE},
+    mesh::{ChunkMesh, MeshVertex},
+    Voxel,
+};
+
+/// The maximum amount of smoothing passes, the blurred samples must
+/// stay in the neighbor chunks.
+pub const MAX_SMOOTHING: u32 = 4;
+
+/// The singular values of the quadratic error below which its
+/// direction is ignored by the dual contouring.
+const QEF_EPSILON: f32 = 0.1;
+
+/// A scalar field sampled at the center of the voxels, the surface
+/// is where the density is `0` and the density is positive inside.
+pub trait DensityField {
+    /// Get the density at the center of a voxel.
+    fn density(&self, x: i32, y: i32, z: i32) -> f32;
+
+    /// Get the material of the surface near a voxel, the voxel is
+    /// inside the surface.
+    fn material(&self, x: i32, y: i32, z: i32) -> Voxel;
+}
+
+/// The algorithm used to extract the surface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum IsosurfaceMethod {
+    /// One vertex per crossed edge, the surface is smooth but the
+    /// sharp features are rounded.
+    #[default]
+    MarchingCubes,
+    /// One vertex per crossed cell, placed with the normals of the
+    /// crossings so the sharp features (edges, corners) are kept.
+    DualContouring,
+}
+
+/// The settings of the surface extraction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct IsosurfaceSettings {
+    pub method      : IsosurfaceMethod,
+    /// The amount of box blur passes applied to the density, up to
+    /// [MAX_SMOOTHING]. Smooth the fields made of steps (voxels).
+    pub smoothing   : u32,
+}
+
+/// The border of samples around the chunk: the cells of the dual
+/// contouring start at `-1` and the gradients need one more sample.
+const BORDER: i32 = 2;
+
+/// The density sampled around a chunk.
+struct DensitySamples {
+    origin  : Vector3<i32>,
+    size    : i32,
+    values  : Vec<f32>,
+}
+
+impl DensitySamples {
+    fn new(field: &impl DensityField, pos: ChunkPos, smoothing: u32) -> Self {
+        let smoothing = smoothing.min(MAX_SMOOTHING) as i32;
+        let padding = BORDER + smoothing;
+        let origin = pos.min_voxel() - Vector3::repeat(padding);
+        let size = CHUNK_SIZE + 2 * padding;
+        let mut values = Vec::with_capacity((size * size * size) as usize);
+
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    values.push(field.density(origin.x + x, origin.y + y, origin.z + z));
+                }
+            }
+        }
+
+        let mut samples = Self { origin, size, values };
+
+        // The samples on the border aren't blurred, they are only
+        // read by the samples outside of the chunk border.
+        for _ in 0..smoothing {
+            for axis in 0..3 {
+                samples.blur(axis);
+            }
+        }
+
+        samples
+    }
+
+    fn index(&self, p: Vector3<i32>) -> usize {
+        let l = p - self.origin;
+        (l.x + (l.y + l.z * self.size) * self.size) as usize
+    }
+
+    /// Get the density at world coordinates.
+    fn get(&self, p: Vector3<i32>) -> f32 {
+        self.values[self.index(p)]
+    }
+
+    /// Get the gradient of the density at world coordinates.
+    fn gradient(&self, p: Vector3<i32>) -> Vector3<f32> {
+        Vector3::from_fn(|axis, _| {
+            let e = Vector3::from_fn(|i, _| (i == axis) as i32);
+            (self.get(p + e) - self.get(p - e)) * 0.5
+        })
+    }
+
+    /// Blur the samples along an axis with a `[1, 2, 1]` kernel.
+    fn blur(&mut self, axis: usize) {
+        let source = self.values.clone();
+        let stride = [1, self.size, self.size * self.size][axis] as usize;
+
+        for z in 0..self.size {
+            for y in 0..self.size {
+                for x in 0..self.size {
+                    let l = [x, y, z];
+
+                    if l[axis] == 0 || l[axis] == self.size - 1 {
+                        continue;
+                    }
+
+                    let index = (x + (y + z * self.size) * self.size) as usize;
+                    self.values[index] = (source[index - stride] + 2.0 * source[index] + source[index + stride]) * 0.25;
+                }
+            }
+        }
+    }
+}
+
+/// The offset of a corner of a cell, the bit `i` of the corner
+/// index is the offset on the axis `i`.
+fn corner_offset(corner: usize) -> Vector3<i32> {
+    Vector3::new((corner & 1) as i32, (corner >> 1 & 1) as i32, (corner >> 2 & 1) as i32)
+}
+
+/// The edges of a cell `(corner, axis)`, the edge starts at the
+/// corner and goes toward `+axis`.
+fn cell_edges() -> [(usize, usize); 12] {
+    let mut edges = [(0, 0); 12];
+    let mut count = 0;
+
+    for axis in 0..3 {
+        for corner in 0..8 {
+            if corner & (1 << axis) == 0 {
+                edges[count] = (corner, axis);
+                count += 1;
+            }
+        }
+    }
+
+    edges
+}
+
+/// The triangles (as edges of the cell) of the 256 configurations
+/// of a cell, built once from the faces of the cell.
+///
+/// The crossings of each face are linked by segments, then the
+/// segments are linked into polygons. The ambiguous faces always
+/// separate their inside corners, as a face is shared by two cells
+/// this keeps the surface closed.
+fn triangle_table() -> &'static [Vec<[u8; 3]>] {
+    static TABLE: OnceLock<Vec<Vec<[u8; 3]>>> = OnceLock::new();
+
+    TABLE.get_or_init(|| {
+        let edges = cell_edges();
+        let edge_between = |a: usize, b: usize| {
+            let (corner, axis) = (a.min(b), (a ^ b).trailing_zeros() as usize);
+            edges.iter().position(|edge| *edge == (corner, axis)).unwrap()
+        };
+
+        (0..256usize).map(|config| {
+            let inside = |corner: usize| config & (1 << corner) != 0;
+            let mut segments = Vec::new();
+
+            for axis in 0..3 {
+                let (u, v) = (1 << ((axis + 1) % 3), 1 << ((axis + 2) % 3));
+
+                for side in [0, 1 << axis] {
+                    let corners = [side, side | u, side | u | v, side | v];
+                    let crossings = (0..4)
+                        .filter(|k| inside(corners[*k]) != inside(corners[(k + 1) % 4]))
+                        .map(|k| edge_between(corners[k], corners[(k + 1) % 4]))
+                        .collect::<Vec<_>>();
+
+                    match crossings[..] {
+                        [a, b] => segments.push((a, b)),
+                        [e0, e1, e2, e3] if inside(corners[0]) => segments.extend([(e3, e0), (e1, e2)]),
+                        [e0, e1, e2, e3] => segments.extend([(e0, e1), (e2, e3)]),
+                        _ => {}
+                    }
+                }
+            }
+
+            let mut triangles = Vec::new();
+
+            while let Some((first, mut current)) = segments.pop() {
+                let mut polygon = vec![first];
+
+                while current != first {
+                    polygon.push(current);
+
+                    let next = segments.iter().position(|(a, b)| *a == current || *b == current).unwrap();
+                    let (a, b) = segments.swap_remove(next);
+                    current = if a == current { b } else { a };
+                }
+
+                for i in 1..polygon.len() - 1 {
+                    triangles.push([polygon[0] as u8, polygon[i] as u8, polygon[i + 1] as u8]);
+                }
+            }
+
+            triangles
+        }).collect()
+    })
+}
+
+/// Get the outward normal from the gradient of the density,
+/// `fallback` if the gradient is null.
+fn surface_normal(gradient: Vector3<f32>, fallback: Vector3<f32>) -> Vector3<f32> {
+    (-gradient).try_normalize(1e-6).unwrap_or(fallback)
+}
+
+/// Push a triangle, the front face (counter clockwise on screen) is
+/// on the side of `normal`.
+fn push_triangle(mesh: &mut ChunkMesh, [a, b, c]: [u32; 3], normal: Vector3<f32>) {
+    let p = |i: u32| Vector3::from(mesh.vertices[i as usize].position);
+    let (pa, pb, pc) = (p(a), p(b), p(c));
+
+    // With the left-handed camera, the cross product of a front
+    // face points away from the viewer.
+    if (pb - pa).cross(&(pc - pa)).dot(&normal) <= 0.0 {
+        mesh.indices.extend_from_slice(&[a, b, c]);
+    } else {
+        mesh.indices.extend_from_slice(&[a, c, b]);
+    }
+}
+
+/// The crossing of the surface on the edge of a cell.
+struct Crossing {
+    position: Vector3<f32>,
+    normal  : Vector3<f32>,
+    /// The sample of the edge inside the surface.
+    inside  : Vector3<i32>,
+}
+
+/// Find where the surface crosses the edge that starts at the
+/// sample `start` and goes toward `+axis`, if it does.
+fn edge_crossing(samples: &DensitySamples, start: Vector3<i32>, axis: usize) -> Option<Crossing> {
+    let e = Vector3::from_fn(|i, _| (i == axis) as i32);
+    let end = start + e;
+    let (d0, d1) = (samples.get(start), samples.get(end));
+
+    if (d0 > 0.0) == (d1 > 0.0) {
+        return None;
+    }
+
+    let t = d0 / (d0 - d1);
+    let mut position = start.cast::<f32>().add_scalar(0.5);
+    position[axis] += t;
+
+    let gradient = samples.gradient(start).lerp(&samples.gradient(end), t);
+    let outside = if d0 > 0.0 { e } else { -e };
+
+    Some(Crossing {
+        position,
+        normal: surface_normal(gradient, outside.cast()),
+        inside: if d0 > 0.0 { start } else { end },
+    })
+}
+
+/// Extract the surface of a chunk from a density field.
+///
+/// # Arguments
+///
+/// * `field`       - The density field, sampled around the chunk.
+/// * `pos`         - The chunk.
+/// * `settings`    - The extraction settings.
+///
+pub fn extract_isosurface(field: &impl DensityField, pos: ChunkPos, settings: &IsosurfaceSettings) -> ChunkMesh {
+    let samples = DensitySamples::new(field, pos, settings.smoothing);
+
+    let inside = samples.values.iter().filter(|d| **d > 0.0).count();
+
+    if inside == 0 || inside == samples.values.len() {
+        return ChunkMesh::default();
+    }
+
+    match settings.method {
+        IsosurfaceMethod::MarchingCubes => marching_cubes(field, pos, &samples),
+        IsosurfaceMethod::DualContouring => dual_contouring(field, pos, &samples),
+    }
+}
+
+fn marching_cubes(field: &impl DensityField, pos: ChunkPos, samples: &DensitySamples) -> ChunkMesh {
+    let mut mesh = ChunkMesh::default();
+    let table = triangle_table();
+    let edges = cell_edges();
+    let origin = pos.min_voxel();
+
+    // The vertices of the edges, shared by the cells around them.
+    let mut edge_vertices: HashMap<(Vector3<i32>, usize), u32> = HashMap::new();
+
+    for z in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let cell = origin + Vector3::new(x, y, z);
+
+                let config = (0..8)
+                    .filter(|corner| samples.get(cell + corner_offset(*corner)) > 0.0)
+                    .fold(0, |config, corner| config | (1 << corner));
+
+                for triangle in &table[config] {
+                    let indices = triangle.map(|edge| {
+                        let (corner, axis) = edges[edge as usize];
+                        let start = cell + corner_offset(corner);
+
+                        *edge_vertices.entry((start, axis)).or_insert_with(|| {
+                            let crossing = edge_crossing(samples, start, axis).unwrap();
+                            let inside = crossing.inside;
+
+                            mesh.vertices.push(MeshVertex {
+                                position: crossing.position.into(),
+                                normal  : crossing.normal.into(),
+                                material: field.material(inside.x, inside.y, inside.z) as u32,
+                                ao      : 1.0,
+                            });
+
+                            mesh.vertices.len() as u32 - 1
+                        })
+                    });
+
+                    let normal = indices.iter()
+                        .map(|i| Vector3::from(mesh.vertices[*i as usize].normal))
+                        .sum::<Vector3<f32>>();
+
+                    push_triangle(&mut mesh, indices, normal);
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+/// Find the point that minimizes the distance to the planes of the
+/// crossings, relative to their mass point so the directions that
+/// aren't constrained (flat surfaces) stay at the mass point.
+fn solve_qef(crossings: &[Crossing]) -> Vector3<f32> {
+    let mass = crossings.iter().map(|c| c.position).sum::<Vector3<f32>>() / crossings.len() as f32;
+    let mut ata = Matrix3::zeros();
+    let mut atb = Vector3::zeros();
+
+    for crossing in crossings {
+        let n = crossing.normal;
+        ata += n * n.transpose();
+        atb += n * n.dot(&(crossing.position - mass));
+    }
+
+    match ata.svd(true, true).pseudo_inverse(QEF_EPSILON) {
+        Ok(inverse) => mass + inverse * atb,
+        Err(_) => mass,
+    }
+}
+
+fn dual_contouring(field: &impl DensityField, pos: ChunkPos, samples: &DensitySamples) -> ChunkMesh {
+    let mut mesh = ChunkMesh::default();
+    let edges = cell_edges();
+    let origin = pos.min_voxel();
+
+    // The vertices of the cells, the cells from -1 are shared with
+    // the previous chunks.
+    let mut cell_vertices: HashMap<Vector3<i32>, u32> = HashMap::new();
+
+    let mut cell_vertex = |mesh: &mut ChunkMesh, cell: Vector3<i32>| {
+        *cell_vertices.entry(cell).or_insert_with(|| {
+            let crossings = edges.iter()
+                .filter_map(|(corner, axis)| edge_crossing(samples, cell + corner_offset(*corner), *axis))
+                .collect::<Vec<_>>();
+
+            // Keep the vertex in its cell, between the samples.
+            let min = cell.cast::<f32>().add_scalar(0.5);
+            let position = solve_qef(&crossings).zip_map(&min, |p, min| p.clamp(min, min + 1.0));
+            let normal = crossings.iter().map(|c| c.normal).sum::<Vector3<f32>>();
+            let inside = crossings[0].inside;
+
+            mesh.vertices.push(MeshVertex {
+                position: position.into(),
+                normal  : normal.try_normalize(1e-6).unwrap_or(crossings[0].normal).into(),
+                material: field.material(inside.x, inside.y, inside.z) as u32,
+                ao      : 1.0,
+            });
+
+            mesh.vertices.len() as u32 - 1
+        })
+    };
+
+    for z in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let start = origin + Vector3::new(x, y, z);
+
+                for axis in 0..3 {
+                    let Some(crossing) = edge_crossing(samples, start, axis) else { continue };
+
+                    // The four cells around the edge, from -u -v to
+                    // +u +v.
+                    let u = Vector3::from_fn(|i, _| (i == (axis + 1) % 3) as i32);
+                    let v = Vector3::from_fn(|i, _| (i == (axis + 2) % 3) as i32);
+                    let [a, b, c, d] = [start - u - v, start - v, start, start - u].map(|cell| cell_vertex(&mut mesh, cell));
+
+                    // The quads are oriented by the sign of the edge, not by
+                    // their shape that can fold at the sharp features (as
+                    // in `mesh_chunk`, the front faces of +axis are reversed).
+                    if crossing.inside == start {
+                        mesh.indices.extend_from_slice(&[a, c, b, a, d, c]);
+                    } else {
+                        mesh.indices.extend_from_slice(&[a, b, c, a, c, d]);
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// A sphere of material `1`.
+    struct Sphere {
+        center: Vector3<f32>,
+        radius: f32,
+    }
+
+    impl DensityField for Sphere {
+        fn density(&self, x: i32, y: i32, z: i32) -> f32 {
+            self.radius - (Vector3::new(x, y, z).cast::<f32>().add_scalar(0.5) - self.center).norm()
+        }
+
+        fn material(&self, _x: i32, _y: i32, _z: i32) -> Voxel {
+            1
+        }
+    }
+
+    const METHODS: [IsosurfaceMethod; 2] = [IsosurfaceMethod::MarchingCubes, IsosurfaceMethod::DualContouring];
+
+    /// Check that the mesh is closed and consistently wound: once the
+    /// vertices at the same position are welded, each edge is used
+    /// once in each direction. Return the signed volume.
+    fn assert_closed(mesh: &ChunkMesh) -> f32 {
+        let mut welded = HashMap::new();
+        let ids = mesh.vertices
+            .iter()
+            .map(|vertex| {
+                let key = vertex.position.map(f32::to_bits);
+                let len = welded.len();
+                *welded.entry(key).or_insert(len)
+            })
+            .collect::<Vec<_>>();
+
+        let mut edges = HashSet::new();
+        let mut volume = 0.0;
+
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| triangle[i] as usize);
+            let [ia, ib, ic] = [ids[a], ids[b], ids[c]];
+            assert!(ia != ib && ib != ic && ic != ia, "degenerate triangle");
+
+            for edge in [(ia, ib), (ib, ic), (ic, ia)] {
+                assert!(edges.insert(edge), "edge {:?} used twice in the same direction", edge);
+            }
+
+            let p = |i: usize| Vector3::from(mesh.vertices[i].position);
+            volume += p(a).dot(&p(b).cross(&p(c))) / 6.0;
+        }
+
+        for &(a, b) in &edges {
+            assert!(edges.contains(&(b, a)), "open edge {:?}", (a, b));
+        }
+
+        volume
+    }
+
+    #[test]
+    fn closed_sphere() {
+        let sphere = Sphere { center: Vector3::new(8.0, 7.5, 8.5), radius: 5.0 };
+        let expected = 4.0 / 3.0 * std::f32::consts::PI * sphere.radius.powi(3);
+
+        for method in METHODS {
+            for smoothing in [0, 2] {
+                let settings = IsosurfaceSettings { method, smoothing };
+                let mesh = extract_isosurface(&sphere, ChunkPos::new(0, 0, 0), &settings);
+                assert!(!mesh.is_empty());
+
+                // The front faces are counter clockwise with the
+                // left-handed camera, so the volume is negative.
+                let volume = -assert_closed(&mesh);
+                assert!((volume - expected).abs() < expected * 0.15, "{:?}: {} instead of {}", settings, volume, expected);
+
+                for vertex in &mesh.vertices {
+                    let outward = Vector3::from(vertex.position) - sphere.center;
+                    assert!(Vector3::from(vertex.normal).dot(&outward) > 0.0);
+                    assert_eq!(vertex.material, 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn empty_and_full_chunks() {
+        let sphere = Sphere { center: Vector3::new(8.0, 8.0, 8.0), radius: 100.0 };
+        let settings = IsosurfaceSettings::default();
+
+        assert!(extract_isosurface(&sphere, ChunkPos::new(0, 0, 0), &settings).is_empty());
+        assert!(extract_isosurface(&sphere, ChunkPos::new(20, 0, 0), &settings).is_empty());
+    }
+
+    #[test]
+    fn stitched_chunks() {
+        // A sphere across the border of two chunks and two more below
+        // them, each mesh is open but the meshes stitch.
+        let sphere = Sphere { center: Vector3::new(16.0, 16.5, 7.5), radius: 6.0 };
+        let chunks = [(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0)].map(|(x, y, z)| ChunkPos::new(x, y, z));
+
+        for method in METHODS {
+            for smoothing in [0, 2] {
+                let settings = IsosurfaceSettings { method, smoothing };
+                let mut mesh = ChunkMesh::default();
+
+                for pos in chunks {
+                    mesh.append(&extract_isosurface(&sphere, pos, &settings));
+                }
+
+                assert!(-assert_closed(&mesh) > 0.0);
+            }
+        }
+    }
+}
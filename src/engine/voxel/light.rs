@@ -0,0 +1,516 @@
+//! The light of the voxels, computed by flood fill.
+//!
+//! Each voxel has two light levels from `0` to [MAX_LIGHT], packed
+//! in a byte (see [pack_light]): the sky light, that comes down from
+//! the top of the loaded chunks, and the block light, emitted by the
+//! emissive materials. A level decreases by one from a voxel to the
+//! next one, except for the full sky light that goes down through
+//! the air without decreasing. The opaque voxels stop the light.
+//!
+//! The light is updated incrementally: when a voxel changes, the
+//! light that depended on it is removed by a first flood fill, then
+//! the light around it is propagated again by a second one.
+
+use std::collections::VecDeque;
+
+use nalgebra::Vector3;
+
+use super::{
+    chunk::{split_voxel, ChunkPos, CHUNK_SIZE},
+    material::MaterialPalette,
+    world::VoxelWorld,
+    Voxel, AIR,
+};
+
+/// The maximum light level.
+pub const MAX_LIGHT: u8 = 15;
+
+/// The default amount of chunks lit per frame.
+const DEFAULT_CHUNKS_PER_FRAME: usize = 8;
+
+/// The neighbors of a voxel, the one below is [DOWN].
+const DIRECTIONS: [[i32; 3]; 6] = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+
+/// The index of the voxel below in [DIRECTIONS].
+const DOWN: usize = 3;
+
+/// Pack the light levels of a voxel, the sky light in the high bits
+/// and the block light in the low bits.
+pub fn pack_light(sky: u8, block: u8) -> u8 {
+    (sky.min(MAX_LIGHT) << 4) | block.min(MAX_LIGHT)
+}
+
+/// Get the sky light of a packed light.
+pub fn sky_light(light: u8) -> u8 {
+    light >> 4
+}
+
+/// Get the block light of a packed light.
+pub fn block_light(light: u8) -> u8 {
+    light & 0x0f
+}
+
+/// Get the level of the light emitted by a voxel, from the brightest
+/// channel of the emission of its material.
+pub fn emission_level(palette: &MaterialPalette, voxel: Voxel) -> u8 {
+    if voxel == AIR {
+        return 0;
+    }
+
+    (palette.get(voxel).emission.max() * MAX_LIGHT as f32).ceil().clamp(0.0, MAX_LIGHT as f32) as u8
+}
+
+/// Check if a voxel stops the light, the transparent materials let
+/// it go through.
+pub fn is_opaque(palette: &MaterialPalette, voxel: Voxel) -> bool {
+    voxel != AIR && palette.get(voxel).transparency <= 0.0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Channel {
+    Sky,
+    Block,
+}
+
+impl Channel {
+    fn get(self, light: u8) -> u8 {
+        match self {
+            Channel::Sky    => sky_light(light),
+            Channel::Block  => block_light(light),
+        }
+    }
+
+    fn with(self, light: u8, level: u8) -> u8 {
+        match self {
+            Channel::Sky    => pack_light(level, block_light(light)),
+            Channel::Block  => pack_light(sky_light(light), level),
+        }
+    }
+}
+
+/// The flood fills, over the chunks that are lit.
+struct Propagation<'a> {
+    world       : &'a mut VoxelWorld,
+    palette     : &'a MaterialPalette,
+    additions   : VecDeque<(Vector3<i32>, Channel)>,
+    /// The voxels whose light was removed, with their previous level.
+    removals    : VecDeque<(Vector3<i32>, u8, Channel)>,
+}
+
+impl<'a> Propagation<'a> {
+    fn new(world: &'a mut VoxelWorld, palette: &'a MaterialPalette) -> Self {
+        Self {
+            world,
+            palette,
+            additions   : VecDeque::new(),
+            removals    : VecDeque::new(),
+        }
+    }
+
+    /// Get a voxel and its light, `None` if its chunk isn't lit.
+    fn get(&self, pos: Vector3<i32>) -> Option<(Voxel, u8)> {
+        let (chunk, (x, y, z)) = split_voxel(pos.x, pos.y, pos.z);
+
+        self.world
+            .chunk(&chunk)
+            .filter(|chunk| chunk.is_lit())
+            .map(|chunk| (chunk.get(x, y, z), chunk.light(x, y, z)))
+    }
+
+    fn set(&mut self, pos: Vector3<i32>, light: u8) {
+        let (chunk, (x, y, z)) = split_voxel(pos.x, pos.y, pos.z);
+
+        if let Some(chunk) = self.world.chunk_mut(&chunk) {
+            chunk.set_light(x, y, z, light);
+        }
+    }
+
+    /// Get the level of the light produced by a voxel itself: the
+    /// sky light under the loaded chunks and the emitted light.
+    fn source_level(&self, pos: Vector3<i32>, voxel: Voxel, channel: Channel) -> u8 {
+        match channel {
+            Channel::Sky => {
+                let open = !self.world.contains_chunk(&ChunkPos::from_voxel(pos.x, pos.y + 1, pos.z));
+                if open && !is_opaque(self.palette, voxel) { MAX_LIGHT } else { 0 }
+            },
+            Channel::Block => emission_level(self.palette, voxel),
+        }
+    }
+
+    /// Remove the light that came from the voxels of the removal
+    /// queue, and queue the voxels around that still have light
+    /// to propagate it again.
+    fn remove(&mut self) {
+        while let Some((pos, level, channel)) = self.removals.pop_front() {
+            for (index, direction) in DIRECTIONS.iter().enumerate() {
+                let neighbor = pos + Vector3::from(*direction);
+                let Some((voxel, light)) = self.get(neighbor) else { continue };
+                let current = channel.get(light);
+
+                if current == 0 {
+                    continue;
+                }
+
+                let full_sky = channel == Channel::Sky && index == DOWN && level == MAX_LIGHT && current == MAX_LIGHT;
+
+                if current < level || full_sky {
+                    let source = self.source_level(neighbor, voxel, channel);
+
+                    self.set(neighbor, channel.with(light, source));
+                    self.removals.push_back((neighbor, current, channel));
+
+                    if source > 0 {
+                        self.additions.push_back((neighbor, channel));
+                    }
+                } else {
+                    self.additions.push_back((neighbor, channel));
+                }
+            }
+        }
+    }
+
+    /// Propagate the light of the voxels of the addition queue.
+    fn add(&mut self) {
+        while let Some((pos, channel)) = self.additions.pop_front() {
+            let Some((_, light)) = self.get(pos) else { continue };
+            let level = channel.get(light);
+
+            if level <= 1 {
+                continue;
+            }
+
+            for (index, direction) in DIRECTIONS.iter().enumerate() {
+                let neighbor = pos + Vector3::from(*direction);
+                let Some((voxel, light)) = self.get(neighbor) else { continue };
+
+                if is_opaque(self.palette, voxel) {
+                    continue;
+                }
+
+                let full_sky = channel == Channel::Sky && index == DOWN && level == MAX_LIGHT && voxel == AIR;
+                let next = if full_sky { MAX_LIGHT } else { level - 1 };
+
+                if next > channel.get(light) {
+                    self.set(neighbor, channel.with(light, next));
+                    self.additions.push_back((neighbor, channel));
+                }
+            }
+        }
+    }
+
+    /// Update the light around a voxel that changed.
+    fn relight_voxel(&mut self, pos: Vector3<i32>) {
+        let Some((voxel, light)) = self.get(pos) else { return };
+
+        self.set(pos, 0);
+
+        for channel in [Channel::Sky, Channel::Block] {
+            if channel.get(light) > 0 {
+                self.removals.push_back((pos, channel.get(light), channel));
+            }
+        }
+
+        self.remove();
+
+        let sky = self.source_level(pos, voxel, Channel::Sky);
+        let block = self.source_level(pos, voxel, Channel::Block);
+
+        self.set(pos, pack_light(sky, block));
+        self.additions.extend([(pos, Channel::Sky), (pos, Channel::Block)]);
+
+        // The light around can now go through the voxel.
+        if !is_opaque(self.palette, voxel) {
+            for direction in DIRECTIONS {
+                let neighbor = pos + Vector3::from(direction);
+                self.additions.extend([(neighbor, Channel::Sky), (neighbor, Channel::Block)]);
+            }
+        }
+
+        self.add();
+    }
+
+    /// Compute the light of a chunk, and update the light of the
+    /// lit chunks around it.
+    fn light_chunk(&mut self, pos: ChunkPos) {
+        let Some(chunk) = self.world.chunk_mut(&pos) else { return };
+
+        chunk.clear_light();
+        chunk.set_lit(true);
+
+        let min = pos.min_voxel();
+        let size = CHUNK_SIZE;
+
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let voxel_pos = min + Vector3::new(x, y, z);
+                    let Some((voxel, _)) = self.get(voxel_pos) else { continue };
+
+                    // Only the top of the chunk can be under the sky.
+                    let sky = if y == size - 1 { self.source_level(voxel_pos, voxel, Channel::Sky) } else { 0 };
+                    let block = self.source_level(voxel_pos, voxel, Channel::Block);
+
+                    if sky > 0 || block > 0 {
+                        self.set(voxel_pos, pack_light(sky, block));
+                        self.additions.extend([(voxel_pos, Channel::Sky), (voxel_pos, Channel::Block)]);
+                    }
+                }
+            }
+        }
+
+        // The top of the chunk below was under the sky until this
+        // chunk was loaded, remove this sky light.
+        for z in 0..size {
+            for x in 0..size {
+                let below = min + Vector3::new(x, -1, z);
+                let Some((_, light)) = self.get(below) else { continue };
+
+                if sky_light(light) > 0 {
+                    self.set(below, Channel::Sky.with(light, 0));
+                    self.removals.push_back((below, sky_light(light), Channel::Sky));
+                }
+            }
+        }
+
+        self.remove();
+
+        // Let the light of the chunks around come in.
+        for direction in DIRECTIONS {
+            let axis = direction.iter().position(|v| *v != 0).unwrap_or(0);
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+            for a in 0..size {
+                for b in 0..size {
+                    let mut local = Vector3::zeros();
+                    local[axis] = if direction[axis] > 0 { size } else { -1 };
+                    local[u] = a;
+                    local[v] = b;
+
+                    let neighbor = min + local;
+                    self.additions.extend([(neighbor, Channel::Sky), (neighbor, Channel::Block)]);
+                }
+            }
+        }
+
+        self.add();
+    }
+}
+
+/// Compute the light of the voxels of a [VoxelWorld]: the new chunks
+/// are lit a few per frame (nearest first), and the light around
+/// the voxels changed with [VoxelWorld::set_voxel] is updated (the
+/// changes must be tracked, see [VoxelWorld::set_change_tracking]).
+/// All the chunks are lit again when the [MaterialPalette] changes.
+///
+/// The light of the chunks is uploaded with their voxels by the
+/// [GpuChunkStore](super::gpu::GpuChunkStore).
+pub struct VoxelLighting {
+    chunks_per_frame    : usize,
+    palette_revision    : Option<u64>,
+}
+
+impl Default for VoxelLighting {
+    fn default() -> Self {
+        Self::new(DEFAULT_CHUNKS_PER_FRAME)
+    }
+}
+
+impl VoxelLighting {
+    /// Create a new [VoxelLighting].
+    ///
+    /// # Arguments
+    ///
+    /// * `chunks_per_frame` - The maximum amount of chunks lit per frame.
+    ///
+    pub fn new(chunks_per_frame: usize) -> Self {
+        Self {
+            chunks_per_frame    : chunks_per_frame.max(1),
+            palette_revision    : None,
+        }
+    }
+
+    /// Update the light of the voxels that changed and light the
+    /// new chunks.
+    ///
+    /// # Arguments
+    ///
+    /// * `world`   - The world to light.
+    /// * `palette` - The materials, for their emission and transparency.
+    /// * `center`  - The chunk around which the chunks are lit first.
+    ///
+    pub fn update(&mut self, world: &mut VoxelWorld, palette: &MaterialPalette, center: ChunkPos) {
+        if self.palette_revision != Some(palette.revision()) {
+            self.palette_revision = Some(palette.revision());
+
+            for (_, chunk) in world.chunks_mut() {
+                chunk.set_lit(false);
+            }
+        }
+
+        let changes = world.take_changes();
+
+        // The columns nearest to the center first, from the top so
+        // the sky light goes down through the loaded chunks.
+        let mut pending = world.chunks()
+            .filter(|(_, chunk)| !chunk.is_lit())
+            .map(|(pos, _)| *pos)
+            .collect::<Vec<_>>();
+
+        pending.sort_by_key(|pos| {
+            let (dx, dz) = (pos.x - center.x, pos.z - center.z);
+            (dx * dx + dz * dz, -pos.y)
+        });
+        pending.truncate(self.chunks_per_frame);
+
+        let mut propagation = Propagation::new(world, palette);
+
+        for pos in changes {
+            propagation.relight_voxel(pos);
+        }
+
+        for pos in pending {
+            propagation.light_chunk(pos);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::{chunk::Chunk, material::Material};
+
+    const LIGHT: Voxel = 2;
+    const GLASS: Voxel = 3;
+
+    fn palette() -> MaterialPalette {
+        let mut palette = MaterialPalette::default();
+        palette.set(LIGHT, Material::new(Vector3::repeat(1.0)).with_emission(Vector3::repeat(1.0)));
+        palette.set(GLASS, Material::new(Vector3::repeat(1.0)).with_transparency(0.5));
+        palette
+    }
+
+    /// The 8 chunks around the origin, from `-16` to `15`, with a
+    /// roof at `y = 10` that has a hole above the origin.
+    fn world(edit: impl Fn(&mut VoxelWorld)) -> VoxelWorld {
+        let mut world = VoxelWorld::new();
+
+        for i in 0..8 {
+            world.insert_chunk(ChunkPos::new(-(i & 1), -(i >> 1 & 1), -(i >> 2 & 1)), Chunk::new());
+        }
+
+        for z in -16..16 {
+            for x in -16..16 {
+                if (x, z) != (0, 0) {
+                    world.set_voxel(x, 10, z, 1);
+                }
+            }
+        }
+
+        edit(&mut world);
+        VoxelLighting::new(8).update(&mut world, &palette(), ChunkPos::new(0, 0, 0));
+        world
+    }
+
+    fn light(world: &VoxelWorld, x: i32, y: i32, z: i32) -> u8 {
+        let (pos, (lx, ly, lz)) = split_voxel(x, y, z);
+        world.chunk(&pos).unwrap().light(lx, ly, lz)
+    }
+
+    #[test]
+    fn packing() {
+        let light = pack_light(12, 3);
+        assert_eq!((sky_light(light), block_light(light)), (12, 3));
+        assert_eq!(pack_light(40, 20), 0xff);
+    }
+
+    #[test]
+    fn sky_light_shaft() {
+        let world = world(|_| {});
+
+        assert!(world.chunks().all(|(_, chunk)| chunk.is_lit()));
+
+        // Above the roof and down the shaft without decreasing.
+        assert_eq!(sky_light(light(&world, 5, 12, -7)), MAX_LIGHT);
+        assert_eq!(sky_light(light(&world, 5, 10, -7)), 0);
+
+        for y in -16..=10 {
+            assert_eq!(sky_light(light(&world, 0, y, 0)), MAX_LIGHT);
+        }
+
+        // And decreasing by one per voxel away from the shaft.
+        for d in 1..=15 {
+            let expected = MAX_LIGHT - d as u8;
+
+            assert_eq!(sky_light(light(&world, d, 0, 0)), expected);
+            assert_eq!(sky_light(light(&world, 0, -12, -d)), expected);
+        }
+
+        assert_eq!(sky_light(light(&world, 3, 5, -4)), 8);
+    }
+
+    #[test]
+    fn block_light_attenuation() {
+        let world = world(|world| {
+            world.set_voxel(-8, -8, -8, LIGHT);
+            world.set_voxel(-8, -8, -5, 1);
+        });
+
+        for k in 0..=15 {
+            assert_eq!(block_light(light(&world, -8 + k, -8, -8)), MAX_LIGHT - k as u8);
+            assert_eq!(block_light(light(&world, -8, -8 + k, -8)), MAX_LIGHT - k as u8);
+        }
+
+        assert_eq!(block_light(light(&world, -6, -5, -7)), 9);
+
+        // The opaque voxel is dark, the light goes around it (two
+        // voxels farther).
+        assert_eq!(block_light(light(&world, -8, -8, -6)), 13);
+        assert_eq!(block_light(light(&world, -8, -8, -5)), 0);
+        assert_eq!(block_light(light(&world, -8, -8, -4)), 9);
+    }
+
+    #[test]
+    fn incremental_matches_flood_fill() {
+        let edits: [(i32, i32, i32, Voxel); 7] = [
+            // Remove a light.
+            (-8, -8, -8, AIR),
+            // Block the shaft, with glass then an opaque voxel.
+            (0, 5, 0, GLASS),
+            (0, -4, 0, 1),
+            // Open a new shaft, add lights.
+            (5, 10, 5, AIR),
+            (6, -12, -6, LIGHT),
+            (1, -4, 0, LIGHT),
+            // Block the light next to the new one.
+            (7, -12, -6, 1),
+        ];
+
+        let mut world = world(|world| {
+            world.set_voxel(-8, -8, -8, LIGHT);
+            world.set_voxel(-2, 0, 3, LIGHT);
+        });
+
+        let palette = palette();
+        let mut lighting = VoxelLighting::new(8);
+        lighting.update(&mut world, &palette, ChunkPos::new(0, 0, 0));
+        world.set_change_tracking(true);
+
+        for (count, (x, y, z, voxel)) in edits.into_iter().enumerate() {
+            world.set_voxel(x, y, z, voxel);
+            lighting.update(&mut world, &palette, ChunkPos::new(0, 0, 0));
+
+            let expected = self::world(|world| {
+                world.set_voxel(-8, -8, -8, LIGHT);
+                world.set_voxel(-2, 0, 3, LIGHT);
+
+                for (x, y, z, voxel) in edits.into_iter().take(count + 1) {
+                    world.set_voxel(x, y, z, voxel);
+                }
+            });
+
+            for (pos, chunk) in expected.chunks() {
+                assert!(world.chunk(pos).unwrap().lights() == chunk.lights(), "edit {} in {:?}", count, pos);
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! The level of detail of the chunks, stored as the interior nodes
+//! of the octree of each chunk.
+//!
+//! A chunk is an octree of depth [LOD_LEVELS]: the level `0` is the
+//! voxels, and each cell of the level `n` aggregates the `2x2x2`
+//! cells of the level `n - 1` below it, up to the level [LOD_LEVELS]
+//! that is the whole chunk. A cell stores the average albedo of
+//! its solid voxels and the fraction of its voxels that are solid,
+//! so a distant cell can be drawn without visiting its voxels.
+
+use nalgebra::Vector3;
+
+use super::{
+    chunk::{Chunk, CHUNK_SIZE},
+    material::MaterialPalette,
+    AIR,
+};
+
+/// The amount of levels above the voxels (`log2(CHUNK_SIZE)`).
+pub const LOD_LEVELS: u32 = CHUNK_SIZE.trailing_zeros();
+
+/// The amount of `u32` used by the levels of a chunk, a cell is a
+/// `u32` (see [pack_cell]).
+pub const MIP_WORDS: usize = mip_offset(LOD_LEVELS + 1);
+
+/// Get the size of a level in cells on each axis.
+pub const fn mip_size(level: u32) -> usize {
+    (CHUNK_SIZE as usize) >> level
+}
+
+/// Get the offset (in cells) of a level in the levels of a chunk,
+/// the levels are stored from the level `1` to [LOD_LEVELS] and
+/// their cells are ordered like the voxels (see [Chunk::index]).
+pub const fn mip_offset(level: u32) -> usize {
+    let mut offset = 0;
+    let mut current = 1;
+
+    while current < level {
+        let size = mip_size(current);
+        offset += size * size * size;
+        current += 1;
+    }
+
+    offset
+}
+
+/// Pack a cell: the average albedo (linear, 8 bits per channel) in
+/// the low bytes and the occupancy in the high byte. The occupancy
+/// is rounded up, so a cell with a solid voxel is never empty.
+///
+/// # Arguments
+///
+/// * `albedo`      - The average albedo of the solid voxels.
+/// * `occupancy`   - The fraction of solid voxels, from `0` to `1`.
+///
+pub fn pack_cell(albedo: Vector3<f32>, occupancy: f32) -> u32 {
+    let [r, g, b] = [albedo.x, albedo.y, albedo.z].map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u32);
+    let a = (occupancy.clamp(0.0, 1.0) * 255.0).ceil() as u32;
+
+    r | (g << 8) | (b << 16) | (a << 24)
+}
+
+/// Unpack a cell packed by [pack_cell], return the albedo and
+/// the occupancy.
+pub fn unpack_cell(cell: u32) -> (Vector3<f32>, f32) {
+    let channel = |shift: u32| ((cell >> shift) & 0xff) as f32 / 255.0;
+
+    (Vector3::new(channel(0), channel(8), channel(16)), channel(24))
+}
+
+/// Compute the levels of a chunk.
+///
+/// # Arguments
+///
+/// * `chunk`   - The chunk.
+/// * `palette` - The materials, for the albedo of the voxels.
+/// * `out`     - Where the cells are written, [MIP_WORDS] long.
+///
+pub fn build_mips(chunk: &Chunk, palette: &MaterialPalette, out: &mut [u32]) {
+    // The amount of solid voxels and the sum of their albedo of
+    // each cell of the current level, from the voxels.
+    let mut size = CHUNK_SIZE as usize;
+    let mut cells = chunk.voxels().iter()
+        .map(|voxel| match *voxel {
+            AIR => (0, Vector3::zeros()),
+            voxel => (1, palette.get(voxel).albedo),
+        })
+        .collect::<Vec<(u32, Vector3<f32>)>>();
+
+    for level in 1..=LOD_LEVELS {
+        let parent_size = size / 2;
+        let mut parents = vec![(0, Vector3::zeros()); parent_size * parent_size * parent_size];
+
+        for y in 0..size {
+            for z in 0..size {
+                for x in 0..size {
+                    let (count, albedo) = cells[x + z * size + y * size * size];
+                    let parent = &mut parents[x / 2 + z / 2 * parent_size + y / 2 * parent_size * parent_size];
+
+                    parent.0 += count;
+                    parent.1 += albedo;
+                }
+            }
+        }
+
+        let volume = (1u32 << (level * 3)) as f32;
+        let offset = mip_offset(level);
+
+        for (cell, (count, albedo)) in out[offset..offset + parents.len()].iter_mut().zip(&parents) {
+            *cell = match count {
+                0 => 0,
+                count => pack_cell(albedo / *count as f32, *count as f32 / volume),
+            };
+        }
+
+        size = parent_size;
+        cells = parents;
+    }
+}
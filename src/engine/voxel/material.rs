@@ -0,0 +1,315 @@
+use std::ops::Range;
+
+use nalgebra::Vector3;
+
+use crate::engine::{
+    logging,
+    renderer::{Buffer, BufferUsage, RendererTrait},
+    shader_type::{ShaderStruct, ShaderType},
+};
+
+use super::Voxel;
+
+/// The maximum amount of materials, one for each [Voxel] value.
+pub const MAX_MATERIALS: usize = Voxel::MAX as usize + 1;
+
+/// The default amount of materials of a [MaterialPalette].
+pub const DEFAULT_MATERIALS: usize = 256;
+
+crate::wgsl_struct! {
+    /// The physically based properties of a voxel material, the
+    /// [Voxel] value is the index of its material in the
+    /// [MaterialPalette].
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct Material {
+        /// The base color, in linear space.
+        pub albedo      : Vector3<f32>,
+        /// The roughness, from `0` (mirror) to `1` (diffuse).
+        pub roughness   : f32,
+        /// The emitted light, in linear space (can exceed `1`).
+        pub emission    : Vector3<f32>,
+        /// The metalness, from `0` (dielectric) to `1` (metal).
+        pub metalness   : f32,
+        /// The amount of light going through, from `0` (opaque) to
+        /// `1` (invisible).
+        pub transparency: f32,
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self::new(Vector3::repeat(0.8))
+    }
+}
+
+impl Material {
+    /// Create a new opaque dielectric [Material].
+    ///
+    /// # Arguments
+    ///
+    /// * `albedo` - The base color, in linear space.
+    ///
+    pub fn new(albedo: Vector3<f32>) -> Self {
+        Self {
+            albedo,
+            roughness   : 1.0,
+            emission    : Vector3::zeros(),
+            metalness   : 0.0,
+            transparency: 0.0,
+        }
+    }
+
+    /// Create a new opaque dielectric [Material] from a sRGB color
+    /// (e.g. a color picked in a paint program).
+    pub fn from_srgb(r: u8, g: u8, b: u8) -> Self {
+        Self::new(Vector3::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b)))
+    }
+
+    /// Set the roughness.
+    pub fn with_roughness(mut self, roughness: f32) -> Self {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the emitted light.
+    pub fn with_emission(mut self, emission: Vector3<f32>) -> Self {
+        self.emission = emission.map(|v| v.max(0.0));
+        self
+    }
+
+    /// Set the metalness.
+    pub fn with_metalness(mut self, metalness: f32) -> Self {
+        self.metalness = metalness.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the transparency.
+    pub fn with_transparency(mut self, transparency: f32) -> Self {
+        self.transparency = transparency.clamp(0.0, 1.0);
+        self
+    }
+}
+
+/// Convert a sRGB channel to linear space.
+pub fn srgb_to_linear(channel: u8) -> f32 {
+    let c = channel as f32 / 255.0;
+
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// The materials of the voxels, indexed by the [Voxel] values. It
+/// is available as a resource (see [VoxelWorldPlugin](super::plugin::VoxelWorldPlugin))
+/// and can be edited at any time, the [GpuMaterialPalette] upload
+/// the materials that changed.
+///
+/// The material `0` is the one of the air, it is never drawn.
+#[derive(Clone, Debug)]
+pub struct MaterialPalette {
+    materials   : Vec<Material>,
+    /// The materials changed since the last upload.
+    dirty       : Option<Range<usize>>,
+    revision    : u64,
+}
+
+impl Default for MaterialPalette {
+    fn default() -> Self {
+        Self::new(DEFAULT_MATERIALS)
+    }
+}
+
+impl MaterialPalette {
+    /// Create a new [MaterialPalette] filled with the default [Material].
+    ///
+    /// # Arguments
+    ///
+    /// * `len` - The amount of materials, at most [MAX_MATERIALS].
+    ///
+    pub fn new(len: usize) -> Self {
+        let len = len.clamp(1, MAX_MATERIALS);
+
+        Self {
+            materials   : vec![Material::default(); len],
+            dirty       : Some(0..len),
+            revision    : 0,
+        }
+    }
+
+    /// Get the amount of materials.
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    /// Check if the palette has no material (never, the air
+    /// material always exists).
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// Get the material of a voxel, the voxels beyond the palette
+    /// use the last material (like on the GPU).
+    pub fn get(&self, voxel: Voxel) -> &Material {
+        &self.materials[(voxel as usize).min(self.materials.len() - 1)]
+    }
+
+    /// Set the material of a voxel. Return `false` if the voxel is
+    /// beyond the palette.
+    ///
+    /// # Arguments
+    ///
+    /// * `voxel`       - The voxel value.
+    /// * `material`    - Its new material.
+    ///
+    pub fn set(&mut self, voxel: Voxel, material: Material) -> bool {
+        let index = voxel as usize;
+
+        let Some(current) = self.materials.get_mut(index) else { return false };
+
+        if *current != material {
+            *current = material;
+            self.dirty = Some(match self.dirty.take() {
+                Some(dirty) => dirty.start.min(index)..dirty.end.max(index + 1),
+                None => index..index + 1,
+            });
+            self.revision += 1;
+        }
+
+        true
+    }
+
+    /// Get a counter incremented each time a material changes.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Iterate over the materials, by voxel value.
+    pub fn iter(&self) -> impl Iterator<Item = (Voxel, &Material)> {
+        self.materials.iter().enumerate().map(|(index, material)| (index as Voxel, material))
+    }
+
+    /// Take the range of the materials changed since the last call.
+    fn take_dirty(&mut self) -> Option<Range<usize>> {
+        self.dirty.take()
+    }
+}
+
+/// Store a [MaterialPalette] in GPU memory, so the compute shaders
+/// (the raymarcher) can shade the voxels with it.
+///
+/// The buffer is bound with [GpuMaterialPalette::buffers], and the
+/// shaders include [materials_wgsl] to read the materials.
+pub struct GpuMaterialPalette {
+    buffer      : Buffer,
+    len         : usize,
+}
+
+impl GpuMaterialPalette {
+    /// Create a new [GpuMaterialPalette] large enough for a palette.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer used to create the buffer.
+    /// * `palette`     - The palette, its length is the capacity of the buffer.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, palette: &MaterialPalette) -> Self {
+        let len = palette.len();
+        let buffer = renderer.create_buffer((len * Material::SIZE) as u64, BufferUsage::STORAGE, true);
+
+        log::debug!(target: logging::RENDERER, "Allocate {} materials", len);
+
+        Self { buffer, len }
+    }
+
+    /// Get the buffers to bind to the group used by [materials_wgsl].
+    pub fn buffers(&self) -> [Buffer; 1] {
+        [self.buffer]
+    }
+
+    /// Get the amount of materials stored on the GPU.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check if no material is stored on the GPU.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Upload the materials that changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `palette`     - The palette to upload.
+    ///
+    pub fn sync<R: RendererTrait>(&mut self, renderer: &mut R, palette: &mut MaterialPalette) {
+        let Some(dirty) = palette.take_dirty() else { return };
+        let dirty = dirty.start..dirty.end.min(self.len);
+
+        if dirty.is_empty() {
+            return;
+        }
+
+        let mut bytes = vec![0u8; dirty.len() * Material::SIZE];
+
+        for (material, out) in palette.materials[dirty.clone()].iter().zip(bytes.chunks_exact_mut(Material::SIZE)) {
+            material.write_bytes(out);
+        }
+
+        renderer.update_buffer_with_slice(self.buffer, &bytes, (dirty.start * Material::SIZE) as u64);
+    }
+}
+
+/// Get the WGSL declarations used to read the materials stored by a
+/// [GpuMaterialPalette], to prepend to the source of a shader. It
+/// declares the binding `0` of `group` and the functions:
+///
+/// * `material_at(voxel: u32) -> Material` that return the material
+///   of a voxel value (the last one beyond the palette).
+/// * `shade_material(material: Material, normal: vec3<f32>, view: vec3<f32>, light: vec3<f32>, radiance: vec3<f32>) -> vec3<f32>`
+///   that return the light reflected toward the viewer (`view` and
+///   `light` point away from the surface) plus the emission, with a
+///   Cook-Torrance (GGX) specular and a Lambert diffuse.
+///
+/// # Arguments
+///
+/// * `group` - The bind group of the [GpuMaterialPalette::buffers].
+///
+pub fn materials_wgsl(group: u32) -> String {
+    format!(
+        r#"{definition}
+@group({group}) @binding(0)
+var<storage, read> materials: array<Material>;
+
+fn material_at(voxel: u32) -> Material {{
+    return materials[min(voxel, arrayLength(&materials) - 1u)];
+}}
+
+fn shade_material(material: Material, normal: vec3<f32>, view: vec3<f32>, light: vec3<f32>, radiance: vec3<f32>) -> vec3<f32> {{
+    let PI = 3.14159265;
+    let n_dot_l = max(dot(normal, light), 0.0);
+    let n_dot_v = max(dot(normal, view), 1e-4);
+    let half_vector = normalize(view + light);
+    let n_dot_h = max(dot(normal, half_vector), 0.0);
+    let v_dot_h = max(dot(view, half_vector), 0.0);
+
+    let alpha = max(material.roughness * material.roughness, 1e-3);
+    let alpha2 = alpha * alpha;
+
+    // GGX distribution, Smith-Schlick visibility and Schlick fresnel.
+    let d = alpha2 / (PI * pow(n_dot_h * n_dot_h * (alpha2 - 1.0) + 1.0, 2.0));
+    let k = alpha * 0.5;
+    let visibility = 1.0 / ((n_dot_l * (1.0 - k) + k) * (n_dot_v * (1.0 - k) + k) * 4.0);
+    let f0 = mix(vec3<f32>(0.04), material.albedo, material.metalness);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - v_dot_h, 5.0);
+
+    let specular = fresnel * d * visibility;
+    let diffuse = (1.0 - fresnel) * (1.0 - material.metalness) * material.albedo / PI;
+
+    return (diffuse + specular) * radiance * n_dot_l + material.emission;
+}}
+
+"#,
+        definition = Material::wgsl_definition(),
+        group = group,
+    )
+}
@@ -0,0 +1,462 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::engine::renderer::VertexAttribute;
+
+use super::{
+    chunk::{Chunk, ChunkPos, CHUNK_SIZE},
+    world::VoxelWorld,
+    Voxel, AIR,
+};
+
+/// A vertex of a chunk mesh.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshVertex {
+    /// The position in world space.
+    pub position: [f32; 3],
+    pub normal  : [f32; 3],
+    /// The voxel of the face, the index of its material.
+    pub material: u32,
+    /// The ambient occlusion, from `0` (occluded) to `1`.
+    pub ao      : f32,
+}
+
+impl MeshVertex {
+    /// The attributes of the vertex, to create a render pipeline.
+    pub const ATTRIBUTES: [VertexAttribute; 4] = [
+        VertexAttribute::Float32x3,
+        VertexAttribute::Float32x3,
+        VertexAttribute::Uint32,
+        VertexAttribute::Float32,
+    ];
+}
+
+/// The triangles of a chunk, the front faces are counter clockwise
+/// on screen.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<MeshVertex>,
+    pub indices : Vec<u32>,
+}
+
+impl ChunkMesh {
+    /// Check if the mesh has no triangle.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Append the triangles of another mesh, to merge the meshes of
+    /// several chunks.
+    pub fn append(&mut self, other: &ChunkMesh) {
+        let first = self.vertices.len() as u32;
+
+        self.vertices.extend_from_slice(&other.vertices);
+        self.indices.extend(other.indices.iter().map(|index| first + index));
+    }
+
+    /// Write the mesh as a Wavefront OBJ. The faces are grouped by
+    /// material (`usemtl voxel_<material>`) and, as expected by the
+    /// OBJ readers, counter clockwise when seen from the front in a
+    /// right-handed space.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Where the mesh is written.
+    ///
+    pub fn write_obj(&self, mut writer: impl Write) -> io::Result<()> {
+        for vertex in &self.vertices {
+            let [x, y, z] = vertex.position;
+            writeln!(writer, "v {} {} {}", x, y, z)?;
+        }
+
+        for vertex in &self.vertices {
+            let [x, y, z] = vertex.normal;
+            writeln!(writer, "vn {} {} {}", x, y, z)?;
+        }
+
+        let mut material = None;
+
+        for triangle in self.indices.chunks_exact(3) {
+            let provoking = self.vertices[triangle[0] as usize].material;
+
+            if material != Some(provoking) {
+                writeln!(writer, "usemtl voxel_{}", provoking)?;
+                material = Some(provoking);
+            }
+
+            // The OBJ indices start at 1, and the front faces of the
+            // left-handed camera are reversed.
+            let [a, b, c] = [triangle[0] + 1, triangle[2] + 1, triangle[1] + 1];
+            writeln!(writer, "f {0}//{0} {1}//{1} {2}//{2}", a, b, c)?;
+        }
+
+        writer.flush()
+    }
+
+    /// Write the mesh into an OBJ file, see [ChunkMesh::write_obj].
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the file.
+    ///
+    pub fn save_obj(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_obj(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// The size of the voxels copied for meshing: the chunk and a
+/// border of one voxel from its neighbors.
+const PADDED: i32 = CHUNK_SIZE + 2;
+
+/// The voxels of a chunk and of the borders of its neighbors.
+struct PaddedVoxels {
+    voxels: Vec<Voxel>,
+}
+
+impl PaddedVoxels {
+    fn new(world: &VoxelWorld, pos: ChunkPos, chunk: &Chunk) -> Self {
+        let mut voxels = vec![AIR; (PADDED * PADDED * PADDED) as usize];
+
+        // The 27 chunks around (and including) the meshed chunk.
+        let mut neighbors = [None; 27];
+
+        for (index, neighbor) in neighbors.iter_mut().enumerate() {
+            let (dx, dy, dz) = (index as i32 % 3 - 1, index as i32 / 3 % 3 - 1, index as i32 / 9 - 1);
+
+            *neighbor = if (dx, dy, dz) == (0, 0, 0) {
+                Some(chunk)
+            } else {
+                world.chunk(&pos.offset(dx, dy, dz))
+            };
+        }
+
+        // Which neighbor contain a padded coordinate and where.
+        let split = |v: i32| match v {
+            -1          => (0, CHUNK_SIZE - 1),
+            CHUNK_SIZE  => (2, 0),
+            _           => (1, v),
+        };
+
+        for y in -1..=CHUNK_SIZE {
+            for z in -1..=CHUNK_SIZE {
+                for x in -1..=CHUNK_SIZE {
+                    let ((cx, lx), (cy, ly), (cz, lz)) = (split(x), split(y), split(z));
+
+                    if let Some(neighbor) = neighbors[(cx + cy * 3 + cz * 9) as usize] {
+                        voxels[Self::index(x, y, z)] = neighbor.get(lx as usize, ly as usize, lz as usize);
+                    }
+                }
+            }
+        }
+
+        Self { voxels }
+    }
+
+    fn index(x: i32, y: i32, z: i32) -> usize {
+        ((x + 1) + (z + 1) * PADDED + (y + 1) * PADDED * PADDED) as usize
+    }
+
+    /// Get a voxel, the coordinates are from `-1` to [CHUNK_SIZE].
+    fn get(&self, p: [i32; 3]) -> Voxel {
+        self.voxels[Self::index(p[0], p[1], p[2])]
+    }
+
+    fn is_solid(&self, p: [i32; 3]) -> bool {
+        self.get(p) != AIR
+    }
+}
+
+/// A visible face of a voxel, the faces are merged when they
+/// are equal.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Face {
+    material: Voxel,
+    /// The occlusion level (`0` to `3`) of the corners, in the
+    /// order `(-u, -v)`, `(+u, -v)`, `(+u, +v)`, `(-u, +v)`.
+    ao      : [u8; 4],
+}
+
+/// Get the occlusion level of a corner of a face from the voxels
+/// around it, in front of the face.
+fn corner_ao(side1: bool, side2: bool, corner: bool) -> u8 {
+    if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as u8 + side2 as u8 + corner as u8)
+    }
+}
+
+/// Build the mesh of a loaded chunk with a greedy meshing: the
+/// coplanar faces of the same material (and ambient occlusion) are
+/// merged into rectangles. The faces against the neighbor chunks
+/// are culled, the neighbors that aren't loaded are air.
+///
+/// # Arguments
+///
+/// * `world`   - The world.
+/// * `pos`     - The chunk to mesh.
+///
+pub fn mesh_chunk(world: &VoxelWorld, pos: ChunkPos) -> ChunkMesh {
+    let mut mesh = ChunkMesh::default();
+
+    let Some(chunk) = world.chunk(&pos) else { return mesh };
+
+    if chunk.is_empty() {
+        return mesh;
+    }
+
+    let voxels = PaddedVoxels::new(world, pos, chunk);
+    let origin = pos.min_voxel();
+    let size = CHUNK_SIZE as usize;
+    let mut mask: Vec<Option<Face>> = vec![None; size * size];
+
+    for axis in 0..3 {
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+
+        for direction in [-1, 1] {
+            for slice in 0..CHUNK_SIZE {
+                // Find the visible faces of the slice.
+                for j in 0..CHUNK_SIZE {
+                    for i in 0..CHUNK_SIZE {
+                        let mut cell = [0; 3];
+                        cell[axis] = slice;
+                        cell[u] = i;
+                        cell[v] = j;
+
+                        let material = voxels.get(cell);
+                        let mut front = cell;
+                        front[axis] += direction;
+
+                        mask[(i + j * CHUNK_SIZE) as usize] = if material == AIR || voxels.is_solid(front) {
+                            None
+                        } else {
+                            let sample = |du: i32, dv: i32| {
+                                let mut p = front;
+                                p[u] += du;
+                                p[v] += dv;
+                                voxels.is_solid(p)
+                            };
+
+                            let ao = [(-1, -1), (1, -1), (1, 1), (-1, 1)]
+                                .map(|(du, dv)| corner_ao(sample(du, 0), sample(0, dv), sample(du, dv)));
+
+                            Some(Face { material, ao })
+                        };
+                    }
+                }
+
+                // Merge the faces into rectangles.
+                for j in 0..size {
+                    let mut i = 0;
+
+                    while i < size {
+                        let Some(face) = mask[i + j * size] else {
+                            i += 1;
+                            continue;
+                        };
+
+                        let mut width = 1;
+
+                        while i + width < size && mask[i + width + j * size] == Some(face) {
+                            width += 1;
+                        }
+
+                        let mut height = 1;
+
+                        while j + height < size && (i..i + width).all(|k| mask[k + (j + height) * size] == Some(face)) {
+                            height += 1;
+                        }
+
+                        for row in j..j + height {
+                            mask[i + row * size..i + width + row * size].fill(None);
+                        }
+
+                        let mut base = [0.0; 3];
+                        base[axis] = (origin[axis] + slice + (direction > 0) as i32) as f32;
+                        base[u] = (origin[u] + i as i32) as f32;
+                        base[v] = (origin[v] + j as i32) as f32;
+
+                        let mut normal = [0.0; 3];
+                        normal[axis] = direction as f32;
+
+                        add_quad(&mut mesh, base, (u, width as f32), (v, height as f32), normal, direction > 0, face);
+
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+fn add_quad(mesh: &mut ChunkMesh, base: [f32; 3], (u, width): (usize, f32), (v, height): (usize, f32), normal: [f32; 3], positive: bool, face: Face) {
+    let first = mesh.vertices.len() as u32;
+
+    for (corner, (du, dv)) in [(0.0, 0.0), (width, 0.0), (width, height), (0.0, height)].into_iter().enumerate() {
+        let mut position = base;
+        position[u] += du;
+        position[v] += dv;
+
+        mesh.vertices.push(MeshVertex {
+            position,
+            normal,
+            material: face.material as u32,
+            ao      : face.ao[corner] as f32 / 3.0,
+        });
+    }
+
+    // Split the quad along the diagonal with the least occlusion
+    // difference, so the interpolated occlusion is symmetric.
+    let [a0, a1, a2, a3] = face.ao;
+    let triangles = if a0 as u32 + a2 as u32 >= a1 as u32 + a3 as u32 {
+        [[0, 1, 2], [0, 2, 3]]
+    } else {
+        [[0, 1, 3], [1, 2, 3]]
+    };
+
+    // The corners go from +u to +v, so they are counter clockwise
+    // on screen when seen from -axis (with a left-handed camera).
+    for [a, b, c] in triangles {
+        if positive {
+            mesh.indices.extend_from_slice(&[first + a, first + c, first + b]);
+        } else {
+            mesh.indices.extend_from_slice(&[first + a, first + b, first + c]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh_voxels(voxels: &[(i32, i32, i32, Voxel)]) -> ChunkMesh {
+        let mut world = VoxelWorld::new();
+
+        for &(x, y, z, voxel) in voxels {
+            world.set_voxel(x, y, z, voxel);
+        }
+
+        mesh_chunk(&world, ChunkPos::new(0, 0, 0))
+    }
+
+    fn quads(mesh: &ChunkMesh) -> usize {
+        assert_eq!(mesh.vertices.len() % 4, 0);
+        assert_eq!(mesh.indices.len(), mesh.vertices.len() / 4 * 6);
+
+        mesh.vertices.len() / 4
+    }
+
+    fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+    }
+
+    fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+    }
+
+    fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    #[test]
+    fn quad_count() {
+        let single = mesh_voxels(&[(3, 4, 5, 1)]);
+        assert_eq!(quads(&single), 6);
+        assert!(single.vertices.iter().all(|vertex| vertex.ao == 1.0 && vertex.material == 1));
+
+        // The faces of a slab are merged into one quad per side.
+        let slab = (0..4)
+            .flat_map(|x| (0..3).map(move |z| (x, 2, z, 1)))
+            .collect::<Vec<_>>();
+        assert_eq!(quads(&mesh_voxels(&slab)), 6);
+
+        // The faces of two materials aren't merged, the faces
+        // between the voxels are culled.
+        assert_eq!(quads(&mesh_voxels(&[(0, 0, 0, 1), (1, 0, 0, 1)])), 6);
+        assert_eq!(quads(&mesh_voxels(&[(0, 0, 0, 1), (1, 0, 0, 2)])), 10);
+
+        assert!(mesh_voxels(&[]).is_empty());
+    }
+
+    #[test]
+    fn ambient_occlusion() {
+        assert_eq!(corner_ao(false, false, false), 3);
+        assert_eq!(corner_ao(true, false, false), 2);
+        assert_eq!(corner_ao(false, false, true), 2);
+        assert_eq!(corner_ao(true, false, true), 1);
+        assert_eq!(corner_ao(true, true, false), 0);
+
+        // A voxel next to the voxel above the top face darken the
+        // two corners of the top face on its side.
+        let mesh = mesh_voxels(&[(1, 0, 1, 1), (2, 1, 1, 1)]);
+        let top = mesh.vertices
+            .iter()
+            .filter(|vertex| vertex.normal == [0.0, 1.0, 0.0] && vertex.position[1] == 1.0)
+            .collect::<Vec<_>>();
+
+        assert_eq!(top.len(), 4);
+
+        for vertex in top {
+            let expected = if vertex.position[0] == 2.0 { 2.0 / 3.0 } else { 1.0 };
+            assert!((vertex.ao - expected).abs() < 1e-6, "{:?}", vertex);
+        }
+    }
+
+    #[test]
+    fn winding() {
+        let mesh = mesh_voxels(&[(0, 0, 0, 1), (1, 0, 0, 2), (1, 1, 0, 1), (5, 5, 5, 3)]);
+
+        // Counter clockwise on screen with the left-handed camera, so
+        // clockwise around the normal in the right-handed world.
+        for triangle in mesh.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize]);
+
+            assert_eq!(a.normal, b.normal);
+            assert_eq!(a.normal, c.normal);
+
+            let area = cross(sub(b.position, a.position), sub(c.position, a.position));
+            assert!(dot(area, a.normal) < 0.0);
+        }
+    }
+
+    #[test]
+    fn obj_export() {
+        let mesh = mesh_voxels(&[(0, 0, 0, 1), (1, 0, 0, 2)]);
+
+        let mut output = Vec::new();
+        mesh.write_obj(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let count = |prefix: &str| output.lines().filter(|line| line.starts_with(prefix)).count();
+
+        assert_eq!(count("v "), 40);
+        assert_eq!(count("vn "), 40);
+        assert_eq!(count("f "), 20);
+        assert!(count("usemtl voxel_1") >= 1);
+        assert!(count("usemtl voxel_2") >= 1);
+        assert_eq!(output.lines().count(), 40 + 40 + 20 + count("usemtl "));
+
+        // The faces are counter clockwise around the normal.
+        let positions = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("v "))
+            .map(|line| {
+                let mut values = line.split(' ').map(|value| value.parse::<f32>().unwrap());
+                [values.next().unwrap(), values.next().unwrap(), values.next().unwrap()]
+            })
+            .collect::<Vec<_>>();
+
+        for face in output.lines().filter_map(|line| line.strip_prefix("f ")) {
+            let [a, b, c] = face
+                .split(' ')
+                .map(|corner| corner.split("//").next().unwrap().parse::<usize>().unwrap() - 1)
+                .collect::<Vec<_>>()[..] else { panic!("{}", face) };
+
+            let area = cross(sub(positions[b], positions[a]), sub(positions[c], positions[a]));
+            assert!(dot(area, mesh.vertices[a].normal) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::engine::{
+    bounds::Aabb,
+    frustum::Frustum,
+    globals::globals_wgsl,
+    logging,
+    renderer::{Buffer, BufferUsage, DrawIndexed, RenderPipeline, RendererTrait},
+};
+
+use super::{
+    chunk::ChunkPos,
+    isosurface::{extract_isosurface, IsosurfaceSettings},
+    mesh::{mesh_chunk, ChunkMesh, MeshVertex},
+    world::VoxelWorld,
+};
+
+/// The minimum amount of vertices of a mesh buffer.
+const MIN_VERTEX_CAPACITY: usize = 1024;
+
+/// How the chunks are turned into triangles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ChunkMesher {
+    /// The faces of the voxels, see [mesh_chunk].
+    #[default]
+    Greedy,
+    /// A smooth surface extracted from the voxels, see [extract_isosurface].
+    Isosurface(IsosurfaceSettings),
+}
+
+impl ChunkMesher {
+    /// Build the mesh of a chunk of a world.
+    pub fn mesh(&self, world: &VoxelWorld, pos: ChunkPos) -> ChunkMesh {
+        match self {
+            ChunkMesher::Greedy => mesh_chunk(world, pos),
+            ChunkMesher::Isosurface(settings) => extract_isosurface(world, pos, settings),
+        }
+    }
+}
+
+/// The buffers of a chunk mesh, reused by the other chunks when
+/// the chunk is unloaded.
+struct MeshBuffers {
+    vertex_buffer   : Buffer,
+    vertex_capacity : usize,
+    index_buffer    : Buffer,
+    index_capacity  : usize,
+}
+
+struct GpuMesh {
+    /// `None` if the mesh has no triangle.
+    buffers     : Option<MeshBuffers>,
+    index_count : u32,
+}
+
+/// Draw the chunks of a [VoxelWorld] with triangles, for the
+/// hardware where the raymarching is too slow.
+///
+/// The chunks are meshed with a [ChunkMesher] when they (or one of
+/// their neighbors) change, a few per frame, and drawn into the
+/// render texture with a depth test. The changes are tracked by the
+/// world, see [VoxelWorld::set_chunk_change_tracking].
+pub struct ChunkMeshRenderer {
+    pipeline        : RenderPipeline,
+    meshes          : HashMap<ChunkPos, GpuMesh>,
+    free_buffers    : Vec<MeshBuffers>,
+    mesher          : ChunkMesher,
+    /// The maximum amount of chunks meshed per frame.
+    meshes_per_frame: usize,
+    /// The chunks to mesh (again).
+    pending         : HashSet<ChunkPos>,
+    /// `true` if all the loaded chunks must be meshed again.
+    remesh_all      : bool,
+}
+
+impl ChunkMeshRenderer {
+    /// Create a new [ChunkMeshRenderer].
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`            - The renderer used to create the pipeline.
+    /// * `meshes_per_frame`    - The maximum amount of chunks meshed per frame.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, meshes_per_frame: usize) -> Self {
+        let shader = renderer.compile_shader(globals_wgsl() + include_str!("../../shaders/mesh.wgsl"));
+        let pipeline = renderer.create_render_pipeline(shader, &MeshVertex::ATTRIBUTES);
+
+        Self {
+            pipeline,
+            meshes          : HashMap::new(),
+            free_buffers    : Vec::new(),
+            mesher          : ChunkMesher::default(),
+            meshes_per_frame: meshes_per_frame.max(1),
+            pending         : HashSet::new(),
+            remesh_all      : true,
+        }
+    }
+
+    /// Set how the chunks are meshed, the chunks are meshed again.
+    pub fn set_mesher(&mut self, mesher: ChunkMesher) {
+        self.remesh_all |= self.mesher != mesher;
+        self.mesher = mesher;
+    }
+
+    /// Get how the chunks are meshed.
+    pub fn mesher(&self) -> ChunkMesher {
+        self.mesher
+    }
+
+    /// Get the render pipeline, to bind more data to it.
+    pub fn pipeline(&self) -> RenderPipeline {
+        self.pipeline
+    }
+
+    /// Get the amount of meshed chunks.
+    pub fn len(&self) -> usize {
+        self.meshes.len()
+    }
+
+    /// Check if no chunk is meshed.
+    pub fn is_empty(&self) -> bool {
+        self.meshes.is_empty()
+    }
+
+    /// Release the meshes of the unloaded chunks and mesh the
+    /// chunks that changed (nearest first). The tracking of the
+    /// chunk changes of the world is enabled at the first update.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `world`       - The world to mesh.
+    /// * `center`      - The chunk around which the chunks are meshed first.
+    ///
+    pub fn update<R: RendererTrait>(&mut self, renderer: &mut R, world: &mut VoxelWorld, center: ChunkPos) {
+        if std::mem::take(&mut self.remesh_all) {
+            world.set_chunk_change_tracking(true);
+            world.take_chunk_changes();
+
+            self.pending = world.chunks().map(|(pos, _)| *pos).collect();
+            self.release_unloaded(world, self.meshes.keys().copied().collect());
+        }
+
+        let changes = world.take_chunk_changes();
+
+        // The mesh of a chunk depends on the voxels of its neighbors.
+        for pos in &changes {
+            for dz in -1..=1 {
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        self.pending.insert(pos.offset(dx, dy, dz));
+                    }
+                }
+            }
+        }
+
+        self.release_unloaded(world, changes);
+        self.pending.retain(|pos| world.contains_chunk(pos));
+
+        let mut stale = self.pending.iter().copied().collect::<Vec<_>>();
+
+        stale.sort_by_key(|pos| pos.distance_squared(&center));
+        stale.truncate(self.meshes_per_frame);
+
+        for pos in stale {
+            self.pending.remove(&pos);
+
+            let mesh = self.mesher.mesh(world, pos);
+            let previous = self.meshes.remove(&pos).and_then(|mesh| mesh.buffers);
+
+            let buffers = if mesh.is_empty() {
+                self.free_buffers.extend(previous);
+                None
+            } else {
+                let buffers = self.acquire_buffers(renderer, previous, mesh.vertices.len(), mesh.indices.len());
+                renderer.update_buffer_with_slice(buffers.vertex_buffer, &mesh.vertices, 0);
+                renderer.update_buffer_with_slice(buffers.index_buffer, &mesh.indices, 0);
+                Some(buffers)
+            };
+
+            self.meshes.insert(pos, GpuMesh {
+                buffers,
+                index_count: mesh.indices.len() as u32,
+            });
+        }
+    }
+
+    /// Draw the meshes of the chunks in the frustum (all the chunks
+    /// without frustum).
+    pub fn draw<R: RendererTrait>(&self, renderer: &mut R, frustum: Option<&Frustum>) {
+        let draws = self.meshes.iter()
+            .filter(|(pos, _)| frustum.is_none_or(|frustum| {
+                // The smooth surfaces overflow the chunk of half a voxel.
+                let bounds = pos.bounds();
+                frustum.intersects_aabb(&Aabb::new(bounds.min.add_scalar(-1.0), bounds.max.add_scalar(1.0)))
+            }))
+            .filter_map(|(_, mesh)| {
+                mesh.buffers.as_ref().map(|buffers| DrawIndexed {
+                    vertex_buffer   : buffers.vertex_buffer,
+                    index_buffer    : buffers.index_buffer,
+                    index_count     : mesh.index_count,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        renderer.draw_indexed(self.pipeline, &draws);
+    }
+
+    /// Release the meshes of the chunks of a list that aren't loaded.
+    fn release_unloaded(&mut self, world: &VoxelWorld, chunks: Vec<ChunkPos>) {
+        for pos in chunks.into_iter().filter(|pos| !world.contains_chunk(pos)) {
+            if let Some(buffers) = self.meshes.remove(&pos).and_then(|mesh| mesh.buffers) {
+                self.free_buffers.push(buffers);
+            }
+        }
+    }
+
+    /// Get buffers large enough for a mesh, the previous buffers of
+    /// the chunk or free buffers are reused when possible.
+    fn acquire_buffers<R: RendererTrait>(&mut self, renderer: &mut R, previous: Option<MeshBuffers>, vertices: usize, indices: usize) -> MeshBuffers {
+        let fits = |buffers: &MeshBuffers| buffers.vertex_capacity >= vertices && buffers.index_capacity >= indices;
+
+        if let Some(buffers) = previous {
+            if fits(&buffers) {
+                return buffers;
+            }
+
+            self.free_buffers.push(buffers);
+        }
+
+        if let Some(index) = self.free_buffers.iter().position(fits) {
+            return self.free_buffers.swap_remove(index);
+        }
+
+        // Replace the smallest free buffers rather than growing
+        // the amount of buffers forever.
+        if let Some(index) = (0..self.free_buffers.len()).min_by_key(|index| self.free_buffers[*index].vertex_capacity) {
+            let buffers = self.free_buffers.swap_remove(index);
+            renderer.destroy_buffer(buffers.vertex_buffer);
+            renderer.destroy_buffer(buffers.index_buffer);
+        }
+
+        let vertex_capacity = vertices.next_power_of_two().max(MIN_VERTEX_CAPACITY);
+        let index_capacity = indices.next_power_of_two().max(MIN_VERTEX_CAPACITY * 3 / 2);
+
+        log::trace!(target: logging::RENDERER, "Create mesh buffers for {} vertices and {} indices", vertex_capacity, index_capacity);
+
+        MeshBuffers {
+            vertex_buffer: renderer.create_buffer((vertex_capacity * std::mem::size_of::<MeshVertex>()) as u64, BufferUsage::VERTEX, true),
+            vertex_capacity,
+            index_buffer: renderer.create_buffer((index_capacity * std::mem::size_of::<u32>()) as u64, BufferUsage::INDEX, true),
+            index_capacity,
+        }
+    }
+}
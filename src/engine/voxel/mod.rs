@@ -0,0 +1,48 @@
+pub mod chunk;
+pub mod world;
+pub mod material;
+pub mod edit;
+pub mod raycast;
+pub mod collision;
+pub mod character;
+pub mod streaming;
+pub mod region;
+pub mod terrain;
+pub mod vox;
+pub mod mesh;
+pub mod isosurface;
+pub mod mesh_renderer;
+pub mod lod;
+pub mod light;
+pub mod shading;
+pub mod path_trace;
+pub mod denoise;
+pub mod gpu;
+pub mod plugin;
+
+/// A voxel, the index of its material in the palette.
+pub type Voxel = u16;
+
+/// The empty voxel.
+pub const AIR: Voxel = 0;
+
+/// Give access to the solidity of the voxels, used by the
+/// CPU side queries (collisions, picking...).
+pub trait VoxelQuery {
+    /// Check if the voxel at the given coordinates is solid. The
+    /// voxel `(x, y, z)` fill the cube from `(x, y, z)` to
+    /// `(x + 1, y + 1, z + 1)`.
+    fn is_solid(&self, x: i32, y: i32, z: i32) -> bool;
+
+    /// Check if a whole chunk is empty, so its voxels can be skipped.
+    /// By default the voxels of all the chunks are queried.
+    fn is_chunk_empty(&self, _pos: chunk::ChunkPos) -> bool {
+        false
+    }
+
+    /// Check if the voxel at the given coordinates is known (e.g.
+    /// its chunk is loaded), the unknown voxels are empty.
+    fn is_loaded(&self, _x: i32, _y: i32, _z: i32) -> bool {
+        true
+    }
+}
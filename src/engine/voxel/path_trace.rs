@@ -0,0 +1,279 @@
+//! A progressive path tracer over the voxels, for the offline
+//! renders and the screenshots.
+//!
+//! Each frame traces a few paths per pixel (with several bounces,
+//! the emissive voxels, the sky and the sun) and adds them to the
+//! average radiance of the pixel, stored in linear HDR. The average
+//! converges while the camera and the voxels don't move, and starts
+//! again when they do. The average is then tone mapped into the
+//! render texture.
+
+use nalgebra::{Matrix4, Vector2, Vector3};
+
+use crate::engine::{
+    camera::Camera,
+    globals::globals_wgsl,
+    logging,
+    renderer::{Buffer, BufferUsage, ComputePipeline, RendererTrait},
+    shader_type::{ShaderStruct, ShaderType},
+};
+
+use super::{
+    gpu::{chunks_wgsl, GpuChunkStore},
+    material::{materials_wgsl, GpuMaterialPalette},
+    shading::shading_wgsl,
+};
+
+/// The workgroup size of `path_trace.wgsl` and `path_resolve.wgsl`
+/// on each axis.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// The settings of a [PathTracer]. The direction of the sun and the
+/// softness of its shadows are the ones of the
+/// [ShadingSettings](super::shading::ShadingSettings).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PathTraceSettings {
+    /// The maximum amount of bounces of a path.
+    pub max_bounces         : u32,
+    /// The amount of paths traced per pixel and per frame, a few
+    /// on a software adapter.
+    pub samples_per_frame   : u32,
+    /// The amount of paths per pixel after which the image is
+    /// converged and the tracing stops.
+    pub max_samples         : u32,
+    /// The radiance of the sky at the zenith, in linear space.
+    pub sky_color           : Vector3<f32>,
+    /// The radiance of the sun, in linear space.
+    pub sun_radiance        : Vector3<f32>,
+    /// The factor applied to the radiance before the tone mapping.
+    pub exposure            : f32,
+}
+
+impl Default for PathTraceSettings {
+    fn default() -> Self {
+        Self {
+            max_bounces         : 4,
+            samples_per_frame   : 1,
+            max_samples         : 4096,
+            sky_color           : Vector3::new(0.5, 0.7, 1.0),
+            sun_radiance        : Vector3::new(3.0, 2.9, 2.7),
+            exposure            : 1.0,
+        }
+    }
+}
+
+crate::wgsl_struct! {
+    #[derive(Clone, Copy, Debug, Default)]
+    struct PathTraceParams {
+        sky_color           : Vector3<f32>,
+        sample_count        : u32,
+        sun_radiance        : Vector3<f32>,
+        samples_per_frame   : u32,
+        size                : Vector2<u32>,
+        max_bounces         : u32,
+        exposure            : f32,
+        output_size         : Vector2<u32>,
+    }
+}
+
+/// Render the voxels stored by a [GpuChunkStore] with a progressive
+/// path tracer, into the render texture.
+///
+/// The chunks and the materials are bound with [PathTracer::bind_world],
+/// then [PathTracer::render] is called each frame (in a render system).
+pub struct PathTracer {
+    trace               : ComputePipeline,
+    resolve             : ComputePipeline,
+    params              : Buffer,
+    accumulation        : Buffer,
+    /// The size of the render texture and of the accumulation in
+    /// pixels, the accumulation is smaller if a buffer of the size of
+    /// the render texture doesn't fit in the adapter limits.
+    size                : (u32, u32),
+    accumulation_size   : (u32, u32),
+    settings            : PathTraceSettings,
+    /// The amount of paths per pixel in the accumulation.
+    samples             : u32,
+    /// The camera matrix and the revision of the voxels the
+    /// accumulation was traced with.
+    view                : Option<(Matrix4<f32>, u64)>,
+}
+
+impl PathTracer {
+    /// Create a new [PathTracer], with an accumulation of the size
+    /// of the renderer.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer used to create the GPU resources.
+    /// * `settings`    - The path tracing settings.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, settings: PathTraceSettings) -> Self {
+        let source = globals_wgsl() + &PathTraceParams::wgsl_definition() + &chunks_wgsl(1) + &materials_wgsl(2) + &shading_wgsl()
+                   + include_str!("../../shaders/path_trace.wgsl");
+        let shader = renderer.compile_shader(source);
+        let trace = renderer.create_compute_pipeline(shader, None);
+
+        let source = PathTraceParams::wgsl_definition() + include_str!("../../shaders/path_resolve.wgsl");
+        let shader = renderer.compile_shader(source);
+        let resolve = renderer.create_compute_pipeline(shader, None);
+
+        let size = renderer.get_size();
+        let accumulation_size = fit_accumulation(size, renderer.max_storage_buffer_size());
+        let params = renderer.create_buffer(PathTraceParams::SIZE as u64, BufferUsage::UNIFORM, true);
+        let accumulation = create_accumulation(renderer, accumulation_size);
+
+        renderer.set_binding_data(trace, 0, &[params, accumulation]);
+        renderer.set_binding_data(resolve, 1, &[params, accumulation]);
+
+        Self {
+            trace,
+            resolve,
+            params,
+            accumulation,
+            size,
+            accumulation_size,
+            settings,
+            samples             : 0,
+            view                : None,
+        }
+    }
+
+    /// Bind the voxels and the materials to trace.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `chunks`      - The chunks.
+    /// * `materials`   - The materials of the voxels.
+    ///
+    pub fn bind_world<R: RendererTrait>(&self, renderer: &mut R, chunks: &GpuChunkStore, materials: &GpuMaterialPalette) {
+        renderer.set_binding_data(self.trace, 1, &chunks.buffers());
+        renderer.set_binding_data(self.trace, 2, &materials.buffers());
+    }
+
+    /// Get the path tracing settings.
+    pub fn settings(&self) -> PathTraceSettings {
+        self.settings
+    }
+
+    /// Set the path tracing settings, the accumulation starts again
+    /// unless only the exposure changed.
+    pub fn set_settings(&mut self, settings: PathTraceSettings) {
+        let traced = PathTraceSettings { exposure: settings.exposure, ..self.settings };
+
+        if settings != traced {
+            self.reset();
+        }
+
+        self.settings = settings;
+    }
+
+    /// Get the amount of paths per pixel accumulated so far.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    /// Check if the image is converged, no more path is traced until
+    /// the accumulation starts again.
+    pub fn is_converged(&self) -> bool {
+        self.samples >= self.settings.max_samples
+    }
+
+    /// Start the accumulation again, e.g. when the lighting changed.
+    pub fn reset(&mut self) {
+        self.samples = 0;
+    }
+
+    /// Trace the paths of a frame and tone map the accumulation into
+    /// the render texture. The accumulation starts again when the
+    /// camera moves, when the voxels change or when the renderer is
+    /// resized.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`        - The renderer.
+    /// * `camera`          - The camera (if any), the view is only reset when it moves.
+    /// * `voxels_revision` - The [GpuChunkStore::revision] of the chunks.
+    ///
+    pub fn render<R: RendererTrait>(&mut self, renderer: &mut R, camera: Option<&Camera>, voxels_revision: u64) {
+        let size = renderer.get_size();
+
+        if size != self.size {
+            self.size = size;
+            self.accumulation_size = fit_accumulation(size, renderer.max_storage_buffer_size());
+
+            renderer.destroy_buffer(self.accumulation);
+            self.accumulation = create_accumulation(renderer, self.accumulation_size);
+
+            renderer.set_binding_data(self.trace, 0, &[self.params, self.accumulation]);
+            renderer.set_binding_data(self.resolve, 1, &[self.params, self.accumulation]);
+            self.reset();
+        }
+
+        let view = (camera.map(Camera::proj_view_matrix).unwrap_or_else(Matrix4::identity), voxels_revision);
+
+        if self.view != Some(view) {
+            self.view = Some(view);
+            self.reset();
+        }
+
+        let s = &self.settings;
+        let params = PathTraceParams {
+            sky_color           : s.sky_color,
+            sample_count        : self.samples,
+            sun_radiance        : s.sun_radiance,
+            samples_per_frame   : s.samples_per_frame.max(1),
+            size                : Vector2::new(self.accumulation_size.0, self.accumulation_size.1),
+            max_bounces         : s.max_bounces,
+            exposure            : s.exposure,
+            output_size         : Vector2::new(size.0, size.1),
+        };
+
+        renderer.update_buffer(self.params, &params, 0);
+
+        if !self.is_converged() {
+            let (width, height) = self.accumulation_size;
+            let workgroups = (width.div_ceil(WORKGROUP_SIZE), height.div_ceil(WORKGROUP_SIZE), 1);
+            renderer.dispatch_compute_pipeline(self.trace, workgroups);
+
+            self.samples += params.samples_per_frame;
+        }
+
+        renderer.dispatch_post_process_compute_pipeline(self.resolve, (WORKGROUP_SIZE, WORKGROUP_SIZE, 1));
+    }
+}
+
+/// Get the largest accumulation with the aspect ratio of the render
+/// texture that fits in a storage buffer of `max_bytes`.
+fn fit_accumulation(size: (u32, u32), max_bytes: u64) -> (u32, u32) {
+    let pixel_bytes = std::mem::size_of::<[f32; 4]>() as u64;
+    let pixels = size.0.max(1) as u64 * size.1.max(1) as u64;
+
+    if pixels * pixel_bytes <= max_bytes {
+        return size;
+    }
+
+    let scale = ((max_bytes / pixel_bytes) as f64 / pixels as f64).sqrt();
+    let fitted = (
+        ((size.0 as f64 * scale) as u32).max(1),
+        ((size.1 as f64 * scale) as u32).max(1),
+    );
+
+    log::warn!(
+        target: logging::RENDERER,
+        "A path tracing accumulation of {}x{} pixels doesn't fit in the adapter limits, it is traced at {}x{}",
+        size.0, size.1, fitted.0, fitted.1,
+    );
+
+    fitted
+}
+
+/// Create the accumulation buffer of an image, a `vec4<f32>` per pixel.
+fn create_accumulation<R: RendererTrait>(renderer: &mut R, size: (u32, u32)) -> Buffer {
+    let pixels = size.0.max(1) as u64 * size.1.max(1) as u64;
+
+    log::debug!(target: logging::RENDERER, "Allocate a path tracing accumulation of {}x{} pixels", size.0, size.1);
+
+    renderer.create_buffer(pixels * std::mem::size_of::<[f32; 4]>() as u64, BufferUsage::STORAGE, false)
+}
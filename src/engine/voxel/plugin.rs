@@ -0,0 +1,328 @@
+use std::sync::Arc;
+
+use crate::engine::{
+    camera::Camera,
+    frustum::Frustum,
+    plugin::{Plugin, Resources, Systems},
+    renderer::RendererTrait,
+};
+
+use super::{
+    chunk::ChunkPos,
+    edit::VoxelEditor,
+    gpu::GpuChunkStore,
+    light::VoxelLighting,
+    material::{GpuMaterialPalette, MaterialPalette},
+    mesh_renderer::{ChunkMeshRenderer, ChunkMesher},
+    path_trace::{PathTraceSettings, PathTracer},
+    shading::ShadingSettings,
+    streaming::{ChunkSource, ChunkStreamer, StreamingSettings},
+    world::VoxelWorld,
+};
+
+/// The default amount of chunks uploaded to the GPU per frame.
+const DEFAULT_UPLOADS_PER_FRAME: usize = 16;
+
+/// The default amount of chunks meshed per frame.
+const DEFAULT_MESHES_PER_FRAME: usize = 8;
+
+/// The default amount of chunks lit per frame.
+const DEFAULT_LIT_PER_FRAME: usize = 8;
+
+/// A plugin that insert a [VoxelWorld] (and a [VoxelEditor] to edit
+/// it), stream its chunks around the [Camera] with a [ChunkStreamer]
+/// and keep a [GpuChunkStore] in sync with it. The [MaterialPalette]
+/// of the voxels is inserted too, and kept in sync with a
+/// [GpuMaterialPalette].
+///
+/// The [GpuChunkStore] and [GpuMaterialPalette] resources are
+/// available after the startup, their buffers can be bound in a
+/// startup system added after this plugin.
+pub struct VoxelWorldPlugin {
+    source              : Arc<dyn ChunkSource>,
+    settings            : StreamingSettings,
+    palette             : Option<MaterialPalette>,
+    uploads_per_frame   : usize,
+}
+
+impl VoxelWorldPlugin {
+    /// Create a new [VoxelWorldPlugin].
+    ///
+    /// # Arguments
+    ///
+    /// * `source`      - Where the chunks are loaded from and saved to.
+    /// * `settings`    - The streaming settings.
+    ///
+    pub fn new(source: impl ChunkSource + 'static, settings: StreamingSettings) -> Self {
+        Self {
+            source              : Arc::new(source),
+            settings,
+            palette             : None,
+            uploads_per_frame   : DEFAULT_UPLOADS_PER_FRAME,
+        }
+    }
+
+    /// Set the materials of the voxels, the GPU palette has the
+    /// length of this one ([MaterialPalette::default] by default).
+    pub fn with_palette(mut self, palette: MaterialPalette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Set the maximum amount of chunks uploaded to the GPU per frame.
+    pub fn with_uploads_per_frame(mut self, uploads_per_frame: usize) -> Self {
+        self.uploads_per_frame = uploads_per_frame;
+        self
+    }
+}
+
+/// Get the chunk around which the world is streamed.
+fn streaming_center(resources: &Resources) -> ChunkPos {
+    resources
+        .get::<Camera>()
+        .map(|camera| ChunkPos::from_world(&camera.position()))
+        .unwrap_or_default()
+}
+
+impl<R: RendererTrait + 'static> Plugin<R> for VoxelWorldPlugin {
+    fn build(&mut self, systems: &mut Systems<R>, resources: &mut Resources) {
+        let settings = self.settings;
+        let uploads_per_frame = self.uploads_per_frame;
+
+        resources.insert(VoxelWorld::new());
+        resources.insert(VoxelEditor::default());
+        resources.insert(ChunkStreamer::new(self.source.clone(), settings));
+        resources.insert(self.palette.take().unwrap_or_default());
+
+        systems.add_startup_system(move |resources, renderer| {
+            resources.insert(GpuChunkStore::new(renderer, &settings, uploads_per_frame));
+
+            if let Some(palette) = resources.get::<MaterialPalette>() {
+                let materials = GpuMaterialPalette::new(renderer, palette);
+                resources.insert(materials);
+            }
+        });
+
+        systems.add_update_system(|resources| {
+            let center = streaming_center(resources);
+
+            let Some(mut streamer) = resources.remove::<ChunkStreamer>() else { return };
+
+            if let Some(world) = resources.get_mut::<VoxelWorld>() {
+                streamer.update(world, center);
+            }
+
+            resources.insert(streamer);
+        });
+
+        systems.add_render_system(|resources, renderer| {
+            let center = streaming_center(resources);
+
+            let Some(mut store) = resources.remove::<GpuChunkStore>() else { return };
+            let mut palette = resources.remove::<MaterialPalette>().unwrap_or_default();
+
+            if let Some(world) = resources.get_mut::<VoxelWorld>() {
+                store.sync(renderer, world, &palette, center);
+            }
+
+            if let Some(materials) = resources.get_mut::<GpuMaterialPalette>() {
+                materials.sync(renderer, &mut palette);
+            }
+
+            resources.insert(store);
+            resources.insert(palette);
+        });
+
+        systems.add_shutdown_system(|resources, _renderer| {
+            let Some(mut streamer) = resources.remove::<ChunkStreamer>() else { return };
+
+            if let Some(world) = resources.get_mut::<VoxelWorld>() {
+                streamer.save_all(world);
+            }
+
+            // Dropping the streamer wait for the workers.
+            drop(streamer);
+        });
+    }
+}
+
+/// A plugin that draws the chunks of the [VoxelWorld] (inserted by
+/// a [VoxelWorldPlugin] added before it) with a [ChunkMeshRenderer],
+/// culled with the frustum of the [Camera].
+///
+/// The [ChunkMeshRenderer] resource is available after the startup.
+pub struct VoxelMeshPlugin {
+    mesher          : ChunkMesher,
+    meshes_per_frame: usize,
+}
+
+impl VoxelMeshPlugin {
+    /// Create a new [VoxelMeshPlugin].
+    pub fn new() -> Self {
+        Self {
+            mesher          : ChunkMesher::default(),
+            meshes_per_frame: DEFAULT_MESHES_PER_FRAME,
+        }
+    }
+
+    /// Set how the chunks are meshed.
+    pub fn with_mesher(mut self, mesher: ChunkMesher) -> Self {
+        self.mesher = mesher;
+        self
+    }
+
+    /// Set the maximum amount of chunks meshed per frame.
+    pub fn with_meshes_per_frame(mut self, meshes_per_frame: usize) -> Self {
+        self.meshes_per_frame = meshes_per_frame;
+        self
+    }
+}
+
+impl Default for VoxelMeshPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: RendererTrait + 'static> Plugin<R> for VoxelMeshPlugin {
+    fn build(&mut self, systems: &mut Systems<R>, _resources: &mut Resources) {
+        let mesher = self.mesher;
+        let meshes_per_frame = self.meshes_per_frame;
+
+        systems.add_startup_system(move |resources, renderer| {
+            let mut meshes = ChunkMeshRenderer::new(renderer, meshes_per_frame);
+            meshes.set_mesher(mesher);
+            resources.insert(meshes);
+        });
+
+        systems.add_render_system(|resources, renderer| {
+            let center = streaming_center(resources);
+            let frustum = resources.get::<Camera>().map(Frustum::from_camera);
+
+            let Some(mut meshes) = resources.remove::<ChunkMeshRenderer>() else { return };
+
+            if let Some(world) = resources.get_mut::<VoxelWorld>() {
+                meshes.update(renderer, world, center);
+            }
+
+            meshes.draw(renderer, frustum.as_ref());
+
+            resources.insert(meshes);
+        });
+    }
+}
+
+/// A plugin that computes the light of the voxels of the [VoxelWorld]
+/// (inserted by a [VoxelWorldPlugin] added before it) with a
+/// [VoxelLighting] resource, around the [Camera]. The light is
+/// uploaded with the chunks by the [GpuChunkStore].
+pub struct VoxelLightPlugin {
+    chunks_per_frame: usize,
+}
+
+impl VoxelLightPlugin {
+    /// Create a new [VoxelLightPlugin].
+    pub fn new() -> Self {
+        Self { chunks_per_frame: DEFAULT_LIT_PER_FRAME }
+    }
+
+    /// Set the maximum amount of chunks lit per frame.
+    pub fn with_chunks_per_frame(mut self, chunks_per_frame: usize) -> Self {
+        self.chunks_per_frame = chunks_per_frame;
+        self
+    }
+}
+
+impl Default for VoxelLightPlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: RendererTrait + 'static> Plugin<R> for VoxelLightPlugin {
+    fn build(&mut self, systems: &mut Systems<R>, resources: &mut Resources) {
+        resources.insert(VoxelLighting::new(self.chunks_per_frame));
+
+        // The light around the edited voxels is updated.
+        if let Some(world) = resources.get_mut::<VoxelWorld>() {
+            world.set_change_tracking(true);
+        }
+
+        systems.add_update_system(|resources| {
+            let center = streaming_center(resources);
+
+            let Some(mut lighting) = resources.remove::<VoxelLighting>() else { return };
+            let palette = resources.remove::<MaterialPalette>().unwrap_or_default();
+
+            if let Some(world) = resources.get_mut::<VoxelWorld>() {
+                lighting.update(world, &palette, center);
+            }
+
+            resources.insert(lighting);
+            resources.insert(palette);
+        });
+    }
+}
+
+/// A plugin that renders the [VoxelWorld] (inserted by a
+/// [VoxelWorldPlugin] added before it) with a progressive
+/// [PathTracer], from the [Camera]. The [PathTracer] resource is
+/// available after the startup, to change its settings.
+pub struct VoxelPathTracePlugin {
+    settings: PathTraceSettings,
+}
+
+impl VoxelPathTracePlugin {
+    /// Create a new [VoxelPathTracePlugin].
+    pub fn new() -> Self {
+        Self { settings: PathTraceSettings::default() }
+    }
+
+    /// Set the path tracing settings.
+    pub fn with_settings(mut self, settings: PathTraceSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Default for VoxelPathTracePlugin {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: RendererTrait + 'static> Plugin<R> for VoxelPathTracePlugin {
+    fn build(&mut self, systems: &mut Systems<R>, _resources: &mut Resources) {
+        let settings = self.settings;
+
+        systems.add_startup_system(move |resources, renderer| {
+            let tracer = PathTracer::new(renderer, settings);
+
+            if let (Some(store), Some(materials)) = (resources.get::<GpuChunkStore>(), resources.get::<GpuMaterialPalette>()) {
+                tracer.bind_world(renderer, store, materials);
+            }
+
+            resources.insert(tracer);
+        });
+
+        // The sun of the shading settings lights the paths too.
+        let mut shading = None;
+
+        systems.add_render_system(move |resources, renderer| {
+            let revision = resources.get::<GpuChunkStore>().map(GpuChunkStore::revision).unwrap_or_default();
+
+            let Some(mut tracer) = resources.remove::<PathTracer>() else { return };
+
+            let current = resources.get::<ShadingSettings>().copied();
+
+            if current != shading {
+                shading = current;
+                tracer.reset();
+            }
+
+            tracer.render(renderer, resources.get::<Camera>(), revision);
+
+            resources.insert(tracer);
+        });
+    }
+}
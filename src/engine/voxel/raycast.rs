@@ -0,0 +1,217 @@
+use nalgebra::{Vector3, Vector4};
+
+use crate::engine::camera::Camera;
+
+use super::{chunk::ChunkPos, VoxelQuery};
+
+/// The maximum amount of voxels crossed by a raycast, the rays that
+/// go farther (e.g. far from the world origin) stop before their
+/// maximum distance.
+pub const MAX_RAYCAST_VOXELS: u32 = 1 << 16;
+
+/// A half line in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin      : Vector3<f32>,
+    /// The direction of the ray, normalized.
+    pub direction   : Vector3<f32>,
+}
+
+impl Ray {
+    /// Create a new [Ray].
+    ///
+    /// # Arguments
+    ///
+    /// * `origin`      - The start of the ray.
+    /// * `direction`   - The direction of the ray, normalized by the constructor.
+    ///
+    pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Self {
+        Self {
+            origin,
+            direction: direction.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::z),
+        }
+    }
+
+    /// Create the ray that goes through a pixel of the screen, from
+    /// the near plane of the camera (like the rays of `test.wgsl`).
+    ///
+    /// # Arguments
+    ///
+    /// * `camera`  - The camera.
+    /// * `pixel`   - The pixel, from the top left corner of the screen (e.g. the cursor position).
+    /// * `size`    - The size of the screen in pixels.
+    ///
+    pub fn from_screen(camera: &Camera, pixel: (f32, f32), size: (f32, f32)) -> Self {
+        let x = pixel.0 / size.0.max(1.0) * 2.0 - 1.0;
+        let y = 1.0 - pixel.1 / size.1.max(1.0) * 2.0;
+
+        let inv_proj_view = camera.inv_proj_view_matrix();
+        let unproject = |z: f32| {
+            let p = inv_proj_view * Vector4::new(x, y, z, 1.0);
+            p.xyz() / p.w
+        };
+
+        // The depth of the camera goes from -1 (near) to 1 (far).
+        let (near, far) = (unproject(-1.0), unproject(1.0));
+
+        Self::new(near, far - near)
+    }
+
+    /// Get the point at a distance along the ray.
+    pub fn at(&self, distance: f32) -> Vector3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+/// The voxel hit by a ray.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+    /// The coordinates of the solid voxel.
+    pub voxel       : Vector3<i32>,
+    /// The normal of the face of the voxel entered by the ray, zero
+    /// if the ray starts in the voxel.
+    pub normal      : Vector3<i32>,
+    /// The distance from the origin of the ray to the hit point.
+    pub distance    : f32,
+    /// The hit point on the face of the voxel.
+    pub position    : Vector3<f32>,
+    /// The empty voxel in front of the hit face, where a voxel is
+    /// placed.
+    pub adjacent    : Vector3<i32>,
+}
+
+/// Find the first solid voxel along a ray, with the DDA of Amanatides
+/// and Woo. The voxels of the chunks reported empty by
+/// [VoxelQuery::is_chunk_empty] aren't queried.
+///
+/// The ray stops after [MAX_RAYCAST_VOXELS] voxels, and nothing is
+/// hit if the maximum distance isn't finite and positive.
+///
+/// # Arguments
+///
+/// * `world`           - The voxels.
+/// * `ray`             - The ray.
+/// * `max_distance`    - The maximum distance of the hit.
+///
+pub fn raycast(world: &impl VoxelQuery, ray: &Ray, max_distance: f32) -> Option<RaycastHit> {
+    let (origin, direction) = (ray.origin, ray.direction);
+
+    let finite = origin.iter().chain(direction.iter()).all(|v| v.is_finite());
+
+    if !finite || !max_distance.is_finite() || max_distance <= 0.0 {
+        return None;
+    }
+
+    let step = direction.map(|d| if d > 0.0 { 1 } else if d < 0.0 { -1 } else { 0 });
+    let delta = direction.map(|d| if d != 0.0 { 1.0 / d.abs() } else { f32::INFINITY });
+
+    let mut voxel = origin.map(|v| v.floor() as i32);
+
+    // The distance to the next voxel border on each axis.
+    let mut side = Vector3::from_fn(|axis, _| match step[axis] {
+        1 => (voxel[axis] as f32 + 1.0 - origin[axis]) * delta[axis],
+        -1 => (origin[axis] - voxel[axis] as f32) * delta[axis],
+        _ => f32::INFINITY,
+    });
+
+    let mut distance = 0.0;
+    let mut normal = Vector3::zeros();
+    let mut chunk = None;
+
+    for _ in 0..MAX_RAYCAST_VOXELS {
+        if distance > max_distance {
+            break;
+        }
+
+        let pos = ChunkPos::from_voxel(voxel.x, voxel.y, voxel.z);
+
+        // Query the emptiness of a chunk once, when entering it.
+        if chunk.map(|(chunk, _)| chunk) != Some(pos) {
+            chunk = Some((pos, world.is_chunk_empty(pos)));
+        }
+
+        if chunk.is_some_and(|(_, empty)| !empty) && world.is_solid(voxel.x, voxel.y, voxel.z) {
+            return Some(RaycastHit {
+                voxel,
+                normal,
+                distance,
+                position: ray.at(distance),
+                adjacent: voxel + normal,
+            });
+        }
+
+        let axis = if side.x <= side.y {
+            if side.x <= side.z { 0 } else { 2 }
+        } else if side.y <= side.z {
+            1
+        } else {
+            2
+        };
+
+        if step[axis] == 0 {
+            break;
+        }
+
+        distance = side[axis];
+        side[axis] += delta[axis];
+        voxel[axis] += step[axis];
+
+        normal = Vector3::zeros();
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::voxel::world::VoxelWorld;
+
+    #[test]
+    fn hit() {
+        let mut world = VoxelWorld::new();
+        world.set_voxel(5, 0, 0, 1);
+
+        let ray = Ray::new(Vector3::new(0.5, 0.5, 0.5), Vector3::x());
+        let hit = raycast(&world, &ray, 10.0).unwrap();
+
+        assert_eq!(hit.voxel, Vector3::new(5, 0, 0));
+        assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+        assert_eq!(hit.adjacent, Vector3::new(4, 0, 0));
+        assert!((hit.distance - 4.5).abs() < 1e-5);
+
+        assert!(raycast(&world, &ray, 4.0).is_none());
+    }
+
+    #[test]
+    fn invalid_distances() {
+        let mut world = VoxelWorld::new();
+        world.set_voxel(0, 0, 0, 1);
+
+        let ray = Ray::new(Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, 2.0, 3.0));
+
+        for max_distance in [0.0, -1.0, f32::INFINITY, f32::NEG_INFINITY, f32::NAN] {
+            assert!(raycast(&world, &ray, max_distance).is_none());
+        }
+
+        let ray = Ray { origin: Vector3::new(f32::NAN, 0.0, 0.0), direction: Vector3::x() };
+        assert!(raycast(&world, &ray, 10.0).is_none());
+    }
+
+    #[test]
+    fn limited_voxels() {
+        // A miss in an empty world with a huge distance stops after
+        // the maximum amount of voxels.
+        let world = VoxelWorld::new();
+        let ray = Ray::new(Vector3::new(0.5, 0.5, 0.5), Vector3::new(1.0, -0.3, 0.7));
+
+        assert!(raycast(&world, &ray, f32::MAX).is_none());
+
+        let mut world = VoxelWorld::new();
+        world.set_voxel(MAX_RAYCAST_VOXELS as i32 + 10, 0, 0, 1);
+
+        assert!(raycast(&world, &Ray::new(Vector3::new(0.5, 0.5, 0.5), Vector3::x()), f32::MAX).is_none());
+    }
+}
@@ -0,0 +1,558 @@
+//! The region files, where the chunks of a world are saved.
+//!
+//! A region groups [REGION_SIZE]³ chunks in one file named
+//! `r.<x>.<y>.<z>.region`. All the numbers are little endian:
+//!
+//! | Size                      | Content                                       |
+//! |---------------------------|-----------------------------------------------|
+//! | 4                         | The magic bytes `VXRG`.                       |
+//! | 4                         | The format version ([FORMAT_VERSION]).        |
+//! | 4                         | The [Compression] of the chunks.              |
+//! | 4                         | Reserved (`0`).                               |
+//! | 8 × [REGION_VOLUME]       | The offset table: for each chunk its offset and its size in the file, an offset of `0` means the chunk isn't saved. |
+//! | ...                       | The compressed chunks.                        |
+//!
+//! A chunk is stored as its [CHUNK_VOLUME] voxels (`u16`) in the
+//! order of [Chunk::index], then compressed.
+//!
+//! The files are never modified in place: a region is written to a
+//! temporary file that replaces the previous one once complete, so a
+//! crash while saving can't corrupt a region.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::engine::logging;
+
+use super::{
+    chunk::{Chunk, ChunkPos, CHUNK_VOLUME},
+    streaming::{ChunkSource, EmptySource},
+    world::VoxelWorld,
+};
+
+/// The amount of chunks on each side of a region.
+pub const REGION_SIZE: i32 = 8;
+
+/// The amount of chunks in a region.
+pub const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+/// The version of the format written by this engine. The files with
+/// an older version are migrated when they are read (see [migrate]).
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: [u8; 4] = *b"VXRG";
+
+const HEADER_SIZE: u64 = 16;
+
+const TABLE_SIZE: u64 = 8 * REGION_VOLUME as u64;
+
+/// The size of an uncompressed chunk.
+const CHUNK_BYTES: usize = CHUNK_VOLUME * std::mem::size_of::<u16>();
+
+/// Convert the chunk data of a version to the next version.
+type Migration = fn(Vec<u8>) -> io::Result<Vec<u8>>;
+
+/// The migrations of the chunk data, the migration `i` converts the
+/// version `i + 1` to the version `i + 2`. A new format version must
+/// add its migration here.
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize - 1] = [];
+
+/// The compression of the chunks of a region file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    None    = 0,
+    #[default]
+    Lz4     = 1,
+}
+
+impl Compression {
+    fn from_u32(value: u32) -> io::Result<Self> {
+        match value {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Lz4),
+            _ => Err(invalid_data(format!("Unknown region compression {}", value))),
+        }
+    }
+
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::None   => data.to_vec(),
+            Compression::Lz4    => lz4_flex::compress_prepend_size(data),
+        }
+    }
+
+    fn decompress(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None   => Ok(data.to_vec()),
+            Compression::Lz4    => lz4_flex::decompress_size_prepended(data).map_err(|error| invalid_data(error.to_string())),
+        }
+    }
+}
+
+/// A chunk of a region being rewritten.
+#[derive(Clone)]
+enum RegionEntry {
+    /// The chunk data, compressed when the region is written.
+    Data(Vec<u8>),
+    /// The chunk data already compressed with the current format
+    /// and compression, copied as is.
+    Compressed(Vec<u8>),
+}
+
+/// The coordinates of a region.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RegionPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl RegionPos {
+    /// Get the region that contains a chunk.
+    pub fn from_chunk(pos: &ChunkPos) -> Self {
+        Self {
+            x: pos.x.div_euclid(REGION_SIZE),
+            y: pos.y.div_euclid(REGION_SIZE),
+            z: pos.z.div_euclid(REGION_SIZE),
+        }
+    }
+
+    /// Get the name of the file of the region.
+    pub fn file_name(&self) -> String {
+        format!("r.{}.{}.{}.region", self.x, self.y, self.z)
+    }
+}
+
+/// Get the index of a chunk in the offset table of its region.
+fn chunk_index(pos: &ChunkPos) -> usize {
+    let (x, y, z) = (
+        pos.x.rem_euclid(REGION_SIZE),
+        pos.y.rem_euclid(REGION_SIZE),
+        pos.z.rem_euclid(REGION_SIZE),
+    );
+
+    (x + z * REGION_SIZE + y * REGION_SIZE * REGION_SIZE) as usize
+}
+
+/// A directory of region files. It is a [ChunkSource], the chunks
+/// that aren't saved are loaded from a fallback source (e.g. a
+/// [TerrainGenerator](super::terrain::TerrainGenerator)), and only
+/// the modified chunks are saved.
+pub struct RegionStorage {
+    directory   : PathBuf,
+    compression : Compression,
+    fallback    : Box<dyn ChunkSource>,
+    /// Serialize the writes of a region, the reads don't need it
+    /// because the files are replaced atomically.
+    writes      : Mutex<HashMap<RegionPos, Arc<Mutex<()>>>>,
+}
+
+impl RegionStorage {
+    /// Create a new [RegionStorage], the directory is created if
+    /// it doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The directory of the region files.
+    ///
+    pub fn new(directory: impl Into<PathBuf>) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            compression : Compression::default(),
+            fallback    : Box::new(EmptySource),
+            writes      : Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Set the source of the chunks that aren't saved.
+    pub fn with_fallback(mut self, fallback: impl ChunkSource + 'static) -> Self {
+        self.fallback = Box::new(fallback);
+        self
+    }
+
+    /// Set the compression of the regions written from now.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Get the directory of the region files.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Get the path of the file of a region.
+    pub fn region_path(&self, region: &RegionPos) -> PathBuf {
+        self.directory.join(region.file_name())
+    }
+
+    /// Read a saved chunk, `Ok(None)` if it isn't saved.
+    pub fn read_chunk(&self, pos: &ChunkPos) -> io::Result<Option<Chunk>> {
+        let path = self.region_path(&RegionPos::from_chunk(pos));
+
+        let mut file = match File::open(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        let (version, compression) = read_header(&mut file)?;
+        let (offset, size) = read_table_entry(&mut file, chunk_index(pos))?;
+
+        if offset == 0 {
+            return Ok(None);
+        }
+
+        let mut data = vec![0; size as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut data)?;
+
+        let data = migrate(version, compression.decompress(&data)?)?;
+
+        decode_chunk(&data).map(Some)
+    }
+
+    /// Save chunks, the chunks are grouped by region so each
+    /// region file is written once.
+    pub fn write_chunks<'a>(&self, chunks: impl IntoIterator<Item = (ChunkPos, &'a Chunk)>) -> io::Result<()> {
+        let mut regions: HashMap<RegionPos, Vec<(ChunkPos, &Chunk)>> = HashMap::new();
+
+        for (pos, chunk) in chunks {
+            regions.entry(RegionPos::from_chunk(&pos)).or_default().push((pos, chunk));
+        }
+
+        for (region, chunks) in regions {
+            self.write_region(&region, &chunks)?;
+        }
+
+        Ok(())
+    }
+
+    /// Save all the modified chunks of a world, return the amount
+    /// of saved chunks.
+    pub fn save_world(&self, world: &mut VoxelWorld) -> io::Result<usize> {
+        let modified = world.chunks()
+            .filter(|(_, chunk)| chunk.is_modified())
+            .map(|(pos, chunk)| (*pos, chunk))
+            .collect::<Vec<_>>();
+
+        let count = modified.len();
+        self.write_chunks(modified)?;
+
+        for (_, chunk) in world.chunks_mut() {
+            chunk.set_modified(false);
+        }
+
+        log::debug!(target: logging::ENGINE, "Saved {} chunks in {:?}", count, self.directory);
+
+        Ok(count)
+    }
+
+    /// Load chunks into a world, the saved chunks replace the loaded
+    /// ones and the others are loaded from the fallback source.
+    /// Return the amount of chunks read from the region files.
+    pub fn load_world(&self, world: &mut VoxelWorld, chunks: impl IntoIterator<Item = ChunkPos>) -> io::Result<usize> {
+        let mut count = 0;
+
+        for pos in chunks {
+            let chunk = match self.read_chunk(&pos)? {
+                Some(chunk) => {
+                    count += 1;
+                    chunk
+                },
+                None => match self.fallback.load(pos) {
+                    Some(chunk) => chunk,
+                    None => continue,
+                },
+            };
+
+            world.insert_chunk(pos, chunk);
+        }
+
+        Ok(count)
+    }
+
+    /// Rewrite a region with some of its chunks replaced.
+    fn write_region(&self, region: &RegionPos, chunks: &[(ChunkPos, &Chunk)]) -> io::Result<()> {
+        let lock = self.writes.lock().unwrap().entry(*region).or_default().clone();
+        let _guard = lock.lock().unwrap();
+
+        let path = self.region_path(region);
+        let mut entries = read_region(&path, self.compression)?;
+
+        for (pos, chunk) in chunks {
+            entries[chunk_index(pos)] = Some(RegionEntry::Data(encode_chunk(chunk)));
+        }
+
+        let temporary = path.with_extension("region.tmp");
+
+        {
+            let mut file = File::create(&temporary)?;
+            write_region(&mut file, &entries, self.compression)?;
+            file.sync_all()?;
+        }
+
+        fs::rename(&temporary, &path)?;
+
+        Ok(())
+    }
+}
+
+impl ChunkSource for RegionStorage {
+    fn load(&self, pos: ChunkPos) -> Option<Chunk> {
+        match self.read_chunk(&pos) {
+            Ok(Some(chunk)) => Some(chunk),
+            Ok(None) => self.fallback.load(pos),
+            Err(error) => {
+                log::error!(target: logging::ENGINE, "Failed to read the chunk {:?}: {}", pos, error);
+                self.fallback.load(pos)
+            },
+        }
+    }
+
+    fn save(&self, pos: ChunkPos, chunk: &Chunk) {
+        if let Err(error) = self.write_chunks([(pos, chunk)]) {
+            log::error!(target: logging::ENGINE, "Failed to save the chunk {:?}: {}", pos, error);
+        }
+    }
+}
+
+/// Convert the chunk data of a version to the current version.
+fn migrate(version: u32, mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        data = migration(data)?;
+    }
+
+    Ok(data)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Read and check the header, return the version and compression.
+fn read_header(reader: &mut impl Read) -> io::Result<(u32, Compression)> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+
+    if magic != MAGIC {
+        return Err(invalid_data("Not a region file".to_owned()));
+    }
+
+    let version = read_u32(reader)?;
+
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(invalid_data(format!("Unsupported region format version {} (expected at most {})", version, FORMAT_VERSION)));
+    }
+
+    let compression = Compression::from_u32(read_u32(reader)?)?;
+    let _reserved = read_u32(reader)?;
+
+    Ok((version, compression))
+}
+
+fn read_table_entry<R: Read + Seek>(reader: &mut R, index: usize) -> io::Result<(u32, u32)> {
+    reader.seek(SeekFrom::Start(HEADER_SIZE + 8 * index as u64))?;
+
+    Ok((read_u32(reader)?, read_u32(reader)?))
+}
+
+/// Read all the chunks of a region, the region is empty if the file
+/// doesn't exist. The chunks are only decompressed and migrated if
+/// the region has an older version or another compression than the
+/// region that will be written.
+fn read_region(path: &Path, target: Compression) -> io::Result<Vec<Option<RegionEntry>>> {
+    let mut entries = vec![None; REGION_VOLUME];
+
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(entries),
+        Err(error) => return Err(error),
+    };
+
+    let mut reader = io::Cursor::new(&data);
+    let (version, compression) = read_header(&mut reader)?;
+    let up_to_date = version == FORMAT_VERSION && compression == target;
+
+    for (index, entry) in entries.iter_mut().enumerate() {
+        let (offset, size) = read_table_entry(&mut reader, index)?;
+
+        if offset == 0 {
+            continue;
+        }
+
+        let chunk = data
+            .get(offset as usize..offset as usize + size as usize)
+            .ok_or_else(|| invalid_data(format!("The chunk {} is outside the region file", index)))?;
+
+        *entry = Some(if up_to_date {
+            RegionEntry::Compressed(chunk.to_vec())
+        } else {
+            RegionEntry::Data(migrate(version, compression.decompress(chunk)?)?)
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Write a region with the current format version.
+fn write_region(writer: &mut impl Write, entries: &[Option<RegionEntry>], compression: Compression) -> io::Result<()> {
+    let mut table = Vec::with_capacity(TABLE_SIZE as usize);
+    let mut chunks = Vec::new();
+
+    for entry in entries {
+        let data = match entry {
+            Some(RegionEntry::Data(data)) => Some(compression.compress(data)),
+            Some(RegionEntry::Compressed(data)) => Some(data.clone()),
+            None => None,
+        };
+
+        match data {
+            Some(data) => {
+                let offset = HEADER_SIZE + TABLE_SIZE + chunks.len() as u64;
+
+                table.extend_from_slice(&(offset as u32).to_le_bytes());
+                table.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                chunks.extend_from_slice(&data);
+            },
+            None => table.extend_from_slice(&[0; 8]),
+        }
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+    writer.write_all(&(compression as u32).to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&table)?;
+    writer.write_all(&chunks)?;
+
+    Ok(())
+}
+
+fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    chunk.voxels().iter().flat_map(|voxel| voxel.to_le_bytes()).collect()
+}
+
+fn decode_chunk(data: &[u8]) -> io::Result<Chunk> {
+    if data.len() != CHUNK_BYTES {
+        return Err(invalid_data(format!("Invalid chunk size {} (expected {})", data.len(), CHUNK_BYTES)));
+    }
+
+    let voxels = data
+        .chunks_exact(2)
+        .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+        .collect();
+
+    Chunk::from_voxels(voxels).ok_or_else(|| invalid_data("Invalid chunk".to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get an empty directory for the region files of a test.
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("voxel-region-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+
+        directory
+    }
+
+    fn test_chunk(voxel: u16) -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.set(0, 0, 0, voxel);
+        chunk.set(3, 7, 1, voxel + 1);
+        chunk
+    }
+
+    fn assert_chunk(storage: &RegionStorage, pos: ChunkPos, expected: &Chunk) {
+        let chunk = storage.read_chunk(&pos).unwrap().expect("The chunk isn't saved");
+        assert_eq!(chunk.voxels(), expected.voxels());
+    }
+
+    #[test]
+    fn write_then_read() {
+        for compression in [Compression::None, Compression::Lz4] {
+            let directory = test_directory(&format!("{:?}", compression));
+            let storage = RegionStorage::new(&directory).unwrap().with_compression(compression);
+
+            // Two chunks in the same region and one in another region.
+            let chunks = [
+                (ChunkPos::new(0, 0, 0), test_chunk(1)),
+                (ChunkPos::new(1, 2, 5), test_chunk(3)),
+                (ChunkPos::new(-9, 0, 0), test_chunk(5)),
+            ];
+
+            storage.write_chunks(chunks.iter().map(|(pos, chunk)| (*pos, chunk))).unwrap();
+
+            for (pos, chunk) in &chunks {
+                assert_chunk(&storage, *pos, chunk);
+            }
+
+            assert!(storage.read_chunk(&ChunkPos::new(1, 0, 0)).unwrap().is_none());
+            assert!(storage.read_chunk(&ChunkPos::new(100, 0, 0)).unwrap().is_none());
+
+            fs::remove_dir_all(&directory).unwrap();
+        }
+    }
+
+    #[test]
+    fn rewrite_keeps_other_chunks() {
+        let directory = test_directory("rewrite");
+        let (first, second) = (ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0));
+
+        let storage = RegionStorage::new(&directory).unwrap();
+        storage.write_chunks([(first, &test_chunk(1))]).unwrap();
+        storage.write_chunks([(second, &test_chunk(3))]).unwrap();
+
+        assert_chunk(&storage, first, &test_chunk(1));
+        assert_chunk(&storage, second, &test_chunk(3));
+
+        // The other chunks are recompressed when the compression changes.
+        let storage = storage.with_compression(Compression::None);
+        storage.write_chunks([(second, &test_chunk(5))]).unwrap();
+
+        assert_chunk(&storage, first, &test_chunk(1));
+        assert_chunk(&storage, second, &test_chunk(5));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let directory = test_directory("version");
+        let pos = ChunkPos::new(0, 0, 0);
+
+        let storage = RegionStorage::new(&directory).unwrap();
+        storage.write_chunks([(pos, &test_chunk(1))]).unwrap();
+
+        let path = storage.region_path(&RegionPos::from_chunk(&pos));
+
+        for version in [0, FORMAT_VERSION + 1] {
+            let mut data = fs::read(&path).unwrap();
+            data[4..8].copy_from_slice(&version.to_le_bytes());
+            fs::write(&path, data).unwrap();
+
+            let error = storage.read_chunk(&pos).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+            // The region isn't overwritten either.
+            assert!(storage.write_chunks([(pos, &test_chunk(3))]).is_err());
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
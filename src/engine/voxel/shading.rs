@@ -0,0 +1,309 @@
+//! The secondary rays of the voxel raymarchers: the shadows of a
+//! directional sun and the ambient occlusion, traced through the
+//! voxels stored by the [GpuChunkStore](super::gpu::GpuChunkStore).
+//!
+//! The quality is set by the [ShadingSettings] resource, uploaded
+//! with the [Globals](crate::engine::globals::Globals) each frame, so
+//! it can be changed at runtime without rebuilding the pipelines.
+
+use nalgebra::Vector3;
+
+crate::wgsl_struct! {
+    /// The lighting and the quality of the secondary rays, available
+    /// to the shaders as `globals.shading`. A ray count of `0`
+    /// disables the effect.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct ShadingSettings {
+        /// The direction toward the sun, normalized by the shaders.
+        pub sun_direction   : Vector3<f32>,
+        /// The amount of shadow rays per pixel, more than one gives
+        /// soft shadows.
+        pub shadow_rays     : u32,
+        /// The maximum distance of the shadow rays, in voxels.
+        pub shadow_distance : f32,
+        /// The angular radius of the sun in radians, the size of the
+        /// penumbra of the soft shadows.
+        pub shadow_softness : f32,
+        /// The amount of ambient occlusion rays per pixel.
+        pub ao_rays         : u32,
+        /// The maximum distance of the ambient occlusion rays, in voxels.
+        pub ao_distance     : f32,
+        /// The maximum amount of cells visited by a ray from the
+        /// camera, the farther surfaces are not drawn.
+        pub max_ray_steps   : u32,
+        /// The maximum amount of cells visited by a shadow or an
+        /// ambient occlusion ray.
+        pub max_trace_steps : u32,
+    }
+}
+
+impl Default for ShadingSettings {
+    fn default() -> Self {
+        Self::medium()
+    }
+}
+
+impl ShadingSettings {
+    /// Get the settings without shadows nor ambient occlusion.
+    pub fn disabled() -> Self {
+        Self {
+            sun_direction   : Vector3::new(0.4, 1.0, 0.3),
+            shadow_rays     : 0,
+            shadow_distance : 0.0,
+            shadow_softness : 0.0,
+            ao_rays         : 0,
+            ao_distance     : 0.0,
+            max_ray_steps   : 256,
+            max_trace_steps : 192,
+        }
+    }
+
+    /// Get the settings with hard shadows and a few short ambient
+    /// occlusion rays.
+    pub fn low() -> Self {
+        Self {
+            shadow_rays     : 1,
+            shadow_distance : 64.0,
+            ao_rays         : 2,
+            ao_distance     : 2.0,
+            ..Self::disabled()
+        }
+    }
+
+    /// Get the settings with soft shadows and ambient occlusion.
+    pub fn medium() -> Self {
+        Self {
+            shadow_rays     : 2,
+            shadow_distance : 128.0,
+            shadow_softness : 0.02,
+            ao_rays         : 4,
+            ao_distance     : 4.0,
+            ..Self::disabled()
+        }
+    }
+
+    /// Get the settings with many long rays, for screenshots.
+    pub fn high() -> Self {
+        Self {
+            shadow_rays     : 8,
+            shadow_distance : 256.0,
+            shadow_softness : 0.02,
+            ao_rays         : 16,
+            ao_distance     : 8.0,
+            max_ray_steps   : 512,
+            max_trace_steps : 384,
+            ..Self::disabled()
+        }
+    }
+}
+
+/// Get the WGSL functions that trace the secondary rays, to prepend
+/// to the source of a shader after [globals_wgsl](crate::engine::globals::globals_wgsl),
+/// [chunks_wgsl](super::gpu::chunks_wgsl) and [materials_wgsl](super::material::materials_wgsl).
+/// It declares the functions:
+///
+/// * `random_seed(pixel: vec2<u32>, frame: u32) -> u32` and
+///   `random_f32(state: ptr<function, u32>) -> f32` that generate
+///   random numbers from `0` to `1`, different for each pixel and frame.
+/// * `sample_cosine_hemisphere(normal: vec3<f32>, u: vec2<f32>) -> vec3<f32>`
+///   and `sample_cone(direction: vec3<f32>, angle: f32, u: vec2<f32>) -> vec3<f32>`
+///   that map two random numbers to a direction.
+/// * `trace_voxels(origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) -> VoxelHit`
+///   that find the first solid voxel along a ray (`hit` is `false`
+///   if there is none), skipping the empty nodes of the chunks.
+/// * `sun_shadow(position: vec3<f32>, normal: vec3<f32>, seed: ptr<function, u32>) -> f32`
+///   that return the fraction of the sun light reaching a point,
+///   the transparent voxels let a part of it through.
+/// * `ambient_occlusion(position: vec3<f32>, normal: vec3<f32>, seed: ptr<function, u32>) -> f32`
+///   that return the fraction of the hemisphere around the normal
+///   that isn't occluded nearby.
+///
+/// The `position` of the shading functions is a point on the face
+/// of a voxel and `normal` the normal of this face.
+pub fn shading_wgsl() -> String {
+    r#"let MAX_SHADOW_LAYERS: i32 = 4;
+let SHADING_BIAS: f32 = 1e-3;
+
+struct VoxelHit {
+    hit     : bool,
+    distance: f32,
+    voxel   : vec3<i32>,
+    normal  : vec3<f32>,
+    value   : u32,
+};
+
+fn hash_u32(value: u32) -> u32 {
+    // PCG hash.
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random_seed(pixel: vec2<u32>, frame: u32) -> u32 {
+    return hash_u32(pixel.x + hash_u32(pixel.y + hash_u32(frame)));
+}
+
+fn random_f32(state: ptr<function, u32>) -> f32 {
+    *state = hash_u32(*state);
+    return f32(*state >> 8u) / 16777216.0;
+}
+
+// A rotation from the z axis to a direction.
+fn direction_basis(n: vec3<f32>) -> mat3x3<f32> {
+    let s = select(-1.0, 1.0, n.z >= 0.0);
+    let a = -1.0 / (s + n.z);
+    let b = n.x * n.y * a;
+
+    return mat3x3<f32>(
+        vec3<f32>(1.0 + s * n.x * n.x * a, s * b, -s * n.x),
+        vec3<f32>(b, s + n.y * n.y * a, -n.y),
+        n,
+    );
+}
+
+fn sample_cosine_hemisphere(normal: vec3<f32>, u: vec2<f32>) -> vec3<f32> {
+    let radius = sqrt(u.x);
+    let phi = 6.28318531 * u.y;
+    let local = vec3<f32>(radius * cos(phi), radius * sin(phi), sqrt(max(1.0 - u.x, 0.0)));
+
+    return normalize(direction_basis(normal) * local);
+}
+
+fn sample_cone(direction: vec3<f32>, angle: f32, u: vec2<f32>) -> vec3<f32> {
+    let cos_theta = mix(1.0, cos(angle), u.x);
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+    let phi = 6.28318531 * u.y;
+    let local = vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+
+    return normalize(direction_basis(direction) * local);
+}
+
+fn trace_voxels(origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) -> VoxelHit {
+    var distance = 0.0;
+    var normal = vec3<f32>(0.0);
+
+    for (var i = 0u; i < globals.shading.max_trace_steps && distance < max_distance; i++) {
+        let voxel = vec3<i32>(floor(origin + direction * (distance + SHADING_BIAS)));
+
+        // Skip the largest empty node that contains the voxel.
+        var cell_level = 0u;
+
+        for (var node = LOD_LEVELS; node >= 1u; node--) {
+            if (mip_at(voxel, node).w == 0.0) {
+                cell_level = node;
+                break;
+            }
+        }
+
+        if (cell_level == 0u) {
+            let value = voxel_at(voxel);
+
+            if (value != 0u) {
+                return VoxelHit(true, distance, voxel, normal, value);
+            }
+        }
+
+        let cell_size = f32(1 << cell_level);
+        let cell_min = vec3<f32>((voxel >> vec3<u32>(cell_level)) << vec3<u32>(cell_level));
+        let bounds = cell_min + select(vec3<f32>(0.0), vec3<f32>(cell_size), direction > vec3<f32>(0.0));
+        let exits = select(vec3<f32>(1e30), (bounds - origin) / direction, direction != vec3<f32>(0.0));
+
+        if (exits.x <= exits.y && exits.x <= exits.z) {
+            distance = exits.x;
+            normal = vec3<f32>(-sign(direction.x), 0.0, 0.0);
+        } else if (exits.y <= exits.z) {
+            distance = exits.y;
+            normal = vec3<f32>(0.0, -sign(direction.y), 0.0);
+        } else {
+            distance = exits.z;
+            normal = vec3<f32>(0.0, 0.0, -sign(direction.z));
+        }
+    }
+
+    return VoxelHit(false, max_distance, vec3<i32>(0), vec3<f32>(0.0), 0u);
+}
+
+// The fraction of the light going through the voxels along a ray,
+// a block of the same transparent voxels is only counted once.
+fn transmittance(origin: vec3<f32>, direction: vec3<f32>, max_distance: f32) -> f32 {
+    var transmittance = 1.0;
+    var start = 0.0;
+    var last_voxel = 0u;
+
+    for (var layer: i32 = 0; layer < MAX_SHADOW_LAYERS && transmittance > 0.01; layer++) {
+        let hit = trace_voxels(origin + direction * start, direction, max_distance - start);
+
+        if (!hit.hit) {
+            return transmittance;
+        }
+
+        if (hit.value != last_voxel || hit.distance > SHADING_BIAS) {
+            transmittance *= material_at(hit.value).transparency;
+        }
+
+        last_voxel = hit.value;
+
+        // Continue from the face where the ray exits the voxel.
+        let position = origin + direction * start;
+        let bounds = vec3<f32>(hit.voxel) + select(vec3<f32>(0.0), vec3<f32>(1.0), direction > vec3<f32>(0.0));
+        let exits = select(vec3<f32>(1e30), (bounds - position) / direction, direction != vec3<f32>(0.0));
+        start += min(exits.x, min(exits.y, exits.z));
+    }
+
+    return select(0.0, transmittance, transmittance > 0.01);
+}
+
+fn sun_shadow(position: vec3<f32>, normal: vec3<f32>, seed: ptr<function, u32>) -> f32 {
+    let settings = globals.shading;
+    let sun = normalize(settings.sun_direction);
+
+    if (settings.shadow_rays == 0u) {
+        return 1.0;
+    }
+
+    if (dot(normal, sun) <= 0.0) {
+        return 0.0;
+    }
+
+    let origin = position + normal * SHADING_BIAS;
+    var visibility = 0.0;
+
+    for (var i = 0u; i < settings.shadow_rays; i++) {
+        var direction = sun;
+
+        if (settings.shadow_rays > 1u) {
+            direction = sample_cone(sun, settings.shadow_softness, vec2<f32>(random_f32(seed), random_f32(seed)));
+        }
+
+        visibility += transmittance(origin, direction, settings.shadow_distance);
+    }
+
+    return visibility / f32(settings.shadow_rays);
+}
+
+fn ambient_occlusion(position: vec3<f32>, normal: vec3<f32>, seed: ptr<function, u32>) -> f32 {
+    let settings = globals.shading;
+
+    if (settings.ao_rays == 0u || settings.ao_distance <= 0.0) {
+        return 1.0;
+    }
+
+    let origin = position + normal * SHADING_BIAS;
+    var occlusion = 0.0;
+
+    // The near occluders hide more light than the far ones.
+    for (var i = 0u; i < settings.ao_rays; i++) {
+        let direction = sample_cosine_hemisphere(normal, vec2<f32>(random_f32(seed), random_f32(seed)));
+        let hit = trace_voxels(origin, direction, settings.ao_distance);
+
+        if (hit.hit) {
+            occlusion += 1.0 - hit.distance / settings.ao_distance;
+        }
+    }
+
+    return 1.0 - occlusion / f32(settings.ao_rays);
+}
+
+"#
+    .to_owned()
+}
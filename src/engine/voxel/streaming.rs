@@ -0,0 +1,352 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::engine::logging;
+
+use super::{
+    chunk::{Chunk, ChunkPos},
+    world::VoxelWorld,
+};
+
+/// Where the chunks come from and go to when they are streamed.
+///
+/// The methods are called from the worker threads of the
+/// [ChunkStreamer].
+pub trait ChunkSource: Send + Sync {
+    /// Load or generate a chunk, `None` means the chunk is empty.
+    fn load(&self, pos: ChunkPos) -> Option<Chunk>;
+
+    /// Save a modified chunk before it is unloaded. By default
+    /// the changes are discarded.
+    fn save(&self, _pos: ChunkPos, _chunk: &Chunk) {}
+}
+
+/// A [ChunkSource] where all the chunks are empty.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EmptySource;
+
+impl ChunkSource for EmptySource {
+    fn load(&self, _pos: ChunkPos) -> Option<Chunk> {
+        None
+    }
+}
+
+/// The settings of a [ChunkStreamer].
+#[derive(Clone, Copy, Debug)]
+pub struct StreamingSettings {
+    /// The horizontal radius (in chunks) around the center where
+    /// the chunks are loaded.
+    pub radius          : i32,
+    /// The vertical radius (in chunks) around the center.
+    pub vertical_radius : i32,
+    /// The extra distance (in chunks) before a chunk is unloaded,
+    /// so moving back and forth around a border don't reload it.
+    pub unload_margin   : i32,
+    /// The amount of worker threads.
+    pub workers         : usize,
+    /// The maximum amount of chunks being loaded at the same time.
+    pub max_in_flight   : usize,
+}
+
+impl Default for StreamingSettings {
+    fn default() -> Self {
+        Self {
+            radius          : 8,
+            vertical_radius : 4,
+            unload_margin   : 1,
+            workers         : std::thread::available_parallelism().map(|n| n.get().saturating_sub(1).max(1)).unwrap_or(2),
+            max_in_flight   : 32,
+        }
+    }
+}
+
+impl StreamingSettings {
+    /// Check if a chunk is in the loading area of `center`.
+    pub fn in_range(&self, center: &ChunkPos, pos: &ChunkPos, margin: i32) -> bool {
+        let (dx, dy, dz) = (pos.x - center.x, pos.y - center.y, pos.z - center.z);
+        let radius = self.radius + margin;
+
+        dx * dx + dz * dz <= radius * radius && dy.abs() <= self.vertical_radius + margin
+    }
+}
+
+enum Job {
+    Load(ChunkPos),
+    Save(ChunkPos, u64, Chunk),
+}
+
+enum JobResult {
+    Loaded(ChunkPos, Option<Chunk>),
+    Saved(ChunkPos, u64),
+}
+
+/// Load and unload the chunks of a [VoxelWorld] around a center
+/// (usually the camera), the chunks are loaded and saved by a
+/// [ChunkSource] on worker threads.
+pub struct ChunkStreamer {
+    settings    : StreamingSettings,
+    /// The offsets of the chunks in the loading area, nearest first.
+    offsets     : Vec<ChunkPos>,
+    /// The chunks being loaded.
+    pending     : HashSet<ChunkPos>,
+    /// The chunks being saved, with the generation of the save,
+    /// so they can be reloaded before the save is done.
+    saving      : HashMap<ChunkPos, (u64, Chunk)>,
+    /// The generation of the save running for each chunk. A chunk
+    /// has at most one save running, otherwise an older version
+    /// could be written after a newer one.
+    writing     : HashMap<ChunkPos, u64>,
+    generation  : u64,
+    jobs        : Option<mpsc::Sender<Job>>,
+    results     : mpsc::Receiver<JobResult>,
+    workers     : Vec<JoinHandle<()>>,
+}
+
+impl ChunkStreamer {
+    /// Create a new [ChunkStreamer] and start its worker threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`      - Where the chunks are loaded from and saved to.
+    /// * `settings`    - The streaming settings.
+    ///
+    pub fn new(source: Arc<dyn ChunkSource>, settings: StreamingSettings) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+
+        let workers = (0..settings.workers.max(1))
+            .map(|index| {
+                let source = source.clone();
+                let jobs = job_receiver.clone();
+                let results = result_sender.clone();
+
+                std::thread::Builder::new()
+                    .name(format!("chunk-worker-{}", index))
+                    .spawn(move || worker(source, jobs, results))
+                    .expect("Failed to spawn a chunk worker")
+            })
+            .collect();
+
+        let mut offsets = Vec::new();
+        let (radius, vertical) = (settings.radius, settings.vertical_radius);
+
+        for y in -vertical..=vertical {
+            for z in -radius..=radius {
+                for x in -radius..=radius {
+                    let offset = ChunkPos::new(x, y, z);
+
+                    if settings.in_range(&ChunkPos::default(), &offset, 0) {
+                        offsets.push(offset);
+                    }
+                }
+            }
+        }
+
+        offsets.sort_by_key(|offset| offset.distance_squared(&ChunkPos::default()));
+
+        Self {
+            settings,
+            offsets,
+            pending     : HashSet::new(),
+            saving      : HashMap::new(),
+            writing     : HashMap::new(),
+            generation  : 0,
+            jobs        : Some(jobs),
+            results,
+            workers,
+        }
+    }
+
+    /// Get the streaming settings.
+    pub fn settings(&self) -> &StreamingSettings {
+        &self.settings
+    }
+
+    /// Check if no chunk is being loaded or saved.
+    pub fn is_idle(&self) -> bool {
+        self.pending.is_empty() && self.saving.is_empty()
+    }
+
+    /// Insert the loaded chunks, unload the chunks that are too
+    /// far from the center and request the missing ones (nearest
+    /// first).
+    ///
+    /// # Arguments
+    ///
+    /// * `world`   - The world to stream.
+    /// * `center`  - The chunk around which the chunks are loaded.
+    ///
+    pub fn update(&mut self, world: &mut VoxelWorld, center: ChunkPos) {
+        self.receive(world, Some(center));
+
+        let margin = self.settings.unload_margin.max(0);
+        let unloaded = world.chunks()
+            .map(|(pos, _)| *pos)
+            .filter(|pos| !self.settings.in_range(&center, pos, margin))
+            .collect::<Vec<_>>();
+
+        for pos in unloaded {
+            if let Some(chunk) = world.remove_chunk(&pos) {
+                if chunk.is_modified() {
+                    self.save(pos, chunk);
+                }
+            }
+        }
+
+        for offset in &self.offsets {
+            if self.pending.len() >= self.settings.max_in_flight {
+                break;
+            }
+
+            let pos = center.offset(offset.x, offset.y, offset.z);
+
+            if world.contains_chunk(&pos) || self.pending.contains(&pos) {
+                continue;
+            }
+
+            // The chunk is still being saved, reuse it instead
+            // of loading the old version from the source.
+            if let Some((_, chunk)) = self.saving.get(&pos) {
+                let mut chunk = chunk.clone();
+                chunk.set_dirty(true);
+                world.insert_chunk(pos, chunk);
+                continue;
+            }
+
+            if let Some(jobs) = &self.jobs {
+                self.pending.insert(pos);
+                let _ = jobs.send(Job::Load(pos));
+            }
+        }
+    }
+
+    /// Save all the modified chunks of the world (without unloading
+    /// them) and wait until all the chunks are saved.
+    pub fn save_all(&mut self, world: &mut VoxelWorld) {
+        for (pos, chunk) in world.chunks_mut() {
+            if chunk.is_modified() {
+                chunk.set_modified(false);
+                self.save(*pos, chunk.clone());
+            }
+        }
+
+        while !self.saving.is_empty() {
+            match self.results.recv() {
+                Ok(result) => self.handle_result(world, result, None),
+                Err(_) => break,
+            }
+        }
+
+        log::debug!(target: logging::ENGINE, "All the modified chunks are saved");
+    }
+
+    /// Save a chunk. If the chunk is already being saved, it is
+    /// saved again once the running save is done.
+    fn save(&mut self, pos: ChunkPos, mut chunk: Chunk) {
+        chunk.set_modified(false);
+        self.generation += 1;
+
+        if !self.writing.contains_key(&pos) {
+            self.write(pos, self.generation, chunk.clone());
+        }
+
+        self.saving.insert(pos, (self.generation, chunk));
+    }
+
+    fn write(&mut self, pos: ChunkPos, generation: u64, chunk: Chunk) {
+        if let Some(jobs) = &self.jobs {
+            self.writing.insert(pos, generation);
+            let _ = jobs.send(Job::Save(pos, generation, chunk));
+        }
+    }
+
+    /// Handle the end of a save, and start the save of the newer
+    /// version of the chunk if it changed meanwhile.
+    fn saved(&mut self, pos: ChunkPos, generation: u64) {
+        self.writing.remove(&pos);
+
+        match self.saving.get(&pos) {
+            Some((current, _)) if *current == generation => {
+                self.saving.remove(&pos);
+            },
+            Some((current, chunk)) => {
+                let (current, chunk) = (*current, chunk.clone());
+                self.write(pos, current, chunk);
+            },
+            None => {},
+        }
+    }
+
+    fn receive(&mut self, world: &mut VoxelWorld, center: Option<ChunkPos>) {
+        while let Ok(result) = self.results.try_recv() {
+            self.handle_result(world, result, center);
+        }
+    }
+
+    fn handle_result(&mut self, world: &mut VoxelWorld, result: JobResult, center: Option<ChunkPos>) {
+        match result {
+            JobResult::Loaded(pos, chunk) => {
+                if !self.pending.remove(&pos) {
+                    return;
+                }
+
+                // The center moved while the chunk was loading.
+                let wanted = center.is_none_or(|center| self.settings.in_range(&center, &pos, self.settings.unload_margin.max(0)));
+
+                if wanted && !world.contains_chunk(&pos) {
+                    let mut chunk = chunk.unwrap_or_default();
+                    chunk.set_modified(false);
+                    chunk.set_dirty(true);
+                    world.insert_chunk(pos, chunk);
+                }
+            },
+
+            JobResult::Saved(pos, generation) => self.saved(pos, generation),
+        }
+    }
+}
+
+impl Drop for ChunkStreamer {
+    fn drop(&mut self) {
+        // Wait for the newer versions of the chunks that were
+        // being saved, they are only sent once the running save
+        // is done.
+        while !self.writing.is_empty() {
+            match self.results.recv() {
+                Ok(JobResult::Saved(pos, generation)) => self.saved(pos, generation),
+                Ok(JobResult::Loaded(..)) => {},
+                Err(_) => break,
+            }
+        }
+
+        // Closing the channel stop the workers once all the
+        // queued jobs (and so the saves) are done.
+        self.jobs = None;
+
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn worker(source: Arc<dyn ChunkSource>, jobs: Arc<Mutex<mpsc::Receiver<Job>>>, results: mpsc::Sender<JobResult>) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        let result = match job {
+            Job::Load(pos) => JobResult::Loaded(pos, source.load(pos)),
+            Job::Save(pos, generation, chunk) => {
+                source.save(pos, &chunk);
+                JobResult::Saved(pos, generation)
+            },
+        };
+
+        // The streamer may be dropped while the last jobs run.
+        let _ = results.send(result);
+    }
+}
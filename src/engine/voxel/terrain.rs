@@ -0,0 +1,846 @@
+//! A deterministic terrain generator.
+//!
+//! All the computations use integers (the noise values are fixed
+//! point numbers with [FRACTION_BITS] bits of fraction), so the
+//! generator produce exactly the same voxels on the CPU and on the
+//! GPU (`terrain.wgsl` is a line by line translation of this file).
+//! The arithmetic wraps on overflow, like the WGSL one, so any seed
+//! and offset gives the same voxels on both.
+
+use nalgebra::Vector2;
+
+use crate::engine::{
+    logging,
+    renderer::{Buffer, BufferUsage, ComputePipeline, RendererTrait},
+    shader_type::{ShaderStruct, ShaderType},
+};
+
+use super::{
+    chunk::{Chunk, ChunkPos, CHUNK_SIZE, CHUNK_VOLUME},
+    isosurface::DensityField,
+    material,
+    streaming::ChunkSource,
+    Voxel, AIR,
+};
+
+/// The materials placed by the generator.
+pub mod materials {
+    use super::{
+        material::{Material, MaterialPalette},
+        Voxel,
+    };
+
+    pub const STONE     : Voxel = 1;
+    pub const DIRT      : Voxel = 2;
+    pub const GRASS     : Voxel = 3;
+    pub const SAND      : Voxel = 4;
+    pub const SNOW      : Voxel = 5;
+    pub const COAL_ORE  : Voxel = 6;
+    pub const IRON_ORE  : Voxel = 7;
+    pub const WOOD      : Voxel = 8;
+    pub const LEAVES    : Voxel = 9;
+
+    /// Get a palette with the materials of the generator, the other
+    /// voxels have the default material.
+    pub fn palette() -> MaterialPalette {
+        let mut palette = MaterialPalette::default();
+
+        palette.set(STONE,      Material::from_srgb(125, 125, 125).with_roughness(0.9));
+        palette.set(DIRT,       Material::from_srgb(121, 85, 58));
+        palette.set(GRASS,      Material::from_srgb(95, 159, 53));
+        palette.set(SAND,       Material::from_srgb(219, 207, 163));
+        palette.set(SNOW,       Material::from_srgb(240, 245, 250).with_roughness(0.6));
+        palette.set(COAL_ORE,   Material::from_srgb(45, 45, 48).with_roughness(0.7));
+        palette.set(IRON_ORE,   Material::from_srgb(196, 160, 135).with_roughness(0.4).with_metalness(0.8));
+        palette.set(WOOD,       Material::from_srgb(102, 76, 51));
+        palette.set(LEAVES,     Material::from_srgb(58, 120, 38).with_transparency(0.25));
+
+        palette
+    }
+}
+
+/// The amount of bits of fraction of the noise values.
+pub const FRACTION_BITS: i32 = 12;
+
+/// The fixed point `1.0`.
+pub const ONE: i32 = 1 << FRACTION_BITS;
+
+/// The maximum period of the noises, the fixed point position in
+/// a noise cell must fit in an `i32`.
+pub const MAX_PERIOD: i32 = 1 << (30 - FRACTION_BITS);
+
+/// The maximum amount of octaves of the heightmap.
+pub const MAX_OCTAVES: u32 = 16;
+
+/// The seed offsets of the noise layers, so the layers are not
+/// correlated.
+const HEIGHT_LAYER  : u32 = 0x0000_0000;
+const TEMPERATURE_LAYER: u32 = 0x1b87_3593;
+const MOISTURE_LAYER: u32 = 0x2c1b_3c6d;
+const CAVE_LAYER    : u32 = 0x3f84_d5b5;
+const ORE_LAYER     : u32 = 0x4b8b_82d1;
+const TREE_LAYER    : u32 = 0x5f35_6495;
+
+/// The biome of a column, chosen from a temperature and a moisture noise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains  = 0,
+    Forest  = 1,
+    Desert  = 2,
+    Snow    = 3,
+}
+
+impl Biome {
+    /// Get the material of the surface.
+    pub fn surface(&self) -> Voxel {
+        match self {
+            Biome::Plains | Biome::Forest   => materials::GRASS,
+            Biome::Desert                   => materials::SAND,
+            Biome::Snow                     => materials::SNOW,
+        }
+    }
+
+    /// Get the material under the surface.
+    pub fn subsurface(&self) -> Voxel {
+        match self {
+            Biome::Desert   => materials::SAND,
+            _               => materials::DIRT,
+        }
+    }
+}
+
+/// The settings of a [TerrainGenerator]. The thresholds are fixed
+/// point numbers (`ONE` is `1.0`), the periods and heights are in voxels.
+///
+/// The generators use the [TerrainSettings::validated] settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TerrainSettings {
+    pub seed            : u32,
+    /// The mean height of the surface.
+    pub base_height     : i32,
+    /// The height variation of the surface.
+    pub height_amplitude: i32,
+    /// The size of the biggest hills.
+    pub height_period   : i32,
+    /// The amount of noise octaves of the heightmap.
+    pub octaves         : u32,
+    /// The size of the biomes.
+    pub biome_period    : i32,
+    /// The size of the caves.
+    pub cave_period     : i32,
+    /// The noise value above which the voxels are carved, higher
+    /// values make less caves.
+    pub cave_threshold  : i32,
+    /// The thickness of the subsurface layer.
+    pub soil_depth      : i32,
+    /// The chance (per thousand) to place a coal ore in the stone.
+    pub coal_chance     : u32,
+    /// The chance (per thousand) to place an iron ore in the stone.
+    pub iron_chance     : u32,
+    /// The maximum height of the iron ores.
+    pub iron_max_y      : i32,
+    /// One column in `tree_spacing` has a tree in the forests.
+    pub tree_spacing    : u32,
+}
+
+impl TerrainSettings {
+    /// Get the settings clamped to the supported ranges: the
+    /// periods from `1` to [MAX_PERIOD] voxels and at most
+    /// [MAX_OCTAVES] octaves.
+    pub fn validated(&self) -> Self {
+        let period = |period: i32| period.clamp(1, MAX_PERIOD);
+
+        let validated = Self {
+            height_period   : period(self.height_period),
+            octaves         : self.octaves.min(MAX_OCTAVES),
+            biome_period    : period(self.biome_period),
+            cave_period     : period(self.cave_period),
+            ..*self
+        };
+
+        if validated != *self {
+            log::warn!(target: logging::ENGINE, "Invalid terrain settings {:?}, clamped to {:?}", self, validated);
+        }
+
+        validated
+    }
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        Self {
+            seed            : 0,
+            base_height     : 0,
+            height_amplitude: 24,
+            height_period   : 128,
+            octaves         : 4,
+            biome_period    : 512,
+            cave_period     : 48,
+            cave_threshold  : ONE / 2,
+            soil_depth      : 3,
+            coal_chance     : 8,
+            iron_chance     : 4,
+            iron_max_y      : -16,
+            tree_spacing    : 24,
+        }
+    }
+}
+
+/// A seedable terrain generator: a fractal heightmap, biomes that
+/// choose the surface materials, caves carved by a 3D noise, ores
+/// and trees.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TerrainGenerator {
+    settings: TerrainSettings,
+}
+
+impl TerrainGenerator {
+    /// Create a new [TerrainGenerator].
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The generator settings.
+    ///
+    pub fn new(settings: TerrainSettings) -> Self {
+        Self { settings: settings.validated() }
+    }
+
+    /// Get the generator settings.
+    pub fn settings(&self) -> &TerrainSettings {
+        &self.settings
+    }
+
+    /// Get the height of the surface of a column.
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let s = &self.settings;
+        let noise = fbm2(x, z, s.seed.wrapping_add(HEIGHT_LAYER), s.height_period, s.octaves);
+
+        s.base_height.wrapping_add(noise.wrapping_mul(s.height_amplitude) >> FRACTION_BITS)
+    }
+
+    /// Get the biome of a column.
+    pub fn biome(&self, x: i32, z: i32) -> Biome {
+        let s = &self.settings;
+        let temperature = fbm2(x, z, s.seed.wrapping_add(TEMPERATURE_LAYER), s.biome_period, 2);
+        let moisture = fbm2(x, z, s.seed.wrapping_add(MOISTURE_LAYER), s.biome_period, 2);
+
+        if temperature < -ONE / 4 {
+            Biome::Snow
+        } else if temperature > ONE / 4 && moisture < 0 {
+            Biome::Desert
+        } else if moisture > ONE / 8 {
+            Biome::Forest
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /// Get the voxel at world coordinates.
+    pub fn voxel(&self, x: i32, y: i32, z: i32) -> Voxel {
+        self.voxel_in_column(x, y, z, self.height(x, z), None)
+    }
+
+    /// Generate a chunk.
+    pub fn generate(&self, pos: ChunkPos) -> Chunk {
+        let min = pos.min_voxel();
+        let mut chunk = Chunk::new();
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let (wx, wz) = (min.x.wrapping_add(x), min.z.wrapping_add(z));
+                let height = self.height(wx, wz);
+                let mut biome = None;
+
+                for y in 0..CHUNK_SIZE {
+                    let voxel = self.voxel_in_column(wx, min.y.wrapping_add(y), wz, height, Some(&mut biome));
+
+                    if voxel != AIR {
+                        chunk.set(x as usize, y as usize, z as usize, voxel);
+                    }
+                }
+            }
+        }
+
+        chunk.set_modified(false);
+        chunk
+    }
+
+    /// Get a voxel of a column, the height of the column is already
+    /// known and its biome is computed lazily (once per column).
+    fn voxel_in_column(&self, x: i32, y: i32, z: i32, height: i32, biome: Option<&mut Option<Biome>>) -> Voxel {
+        let s = &self.settings;
+
+        if y > height {
+            return self.feature(x, y, z, height);
+        }
+
+        let cave = noise3(x, y, z, s.seed.wrapping_add(CAVE_LAYER), s.cave_period);
+
+        if cave > s.cave_threshold {
+            return AIR;
+        }
+
+        let depth = height.wrapping_sub(y);
+
+        if depth < s.soil_depth {
+            let biome = match biome {
+                Some(cached) => *cached.get_or_insert_with(|| self.biome(x, z)),
+                None => self.biome(x, z),
+            };
+
+            return if depth == 0 { biome.surface() } else { biome.subsurface() };
+        }
+
+        let ore = hash(x, y, z, s.seed.wrapping_add(ORE_LAYER)) % 1000;
+
+        if ore < s.coal_chance {
+            materials::COAL_ORE
+        } else if y <= s.iron_max_y && ore < s.coal_chance.wrapping_add(s.iron_chance) {
+            materials::IRON_ORE
+        } else {
+            materials::STONE
+        }
+    }
+
+    /// Get the height of the trunk of the tree of a column, `0`
+    /// if there is no tree.
+    fn tree(&self, x: i32, z: i32) -> i32 {
+        let s = &self.settings;
+        let h = hash(x, 0, z, s.seed.wrapping_add(TREE_LAYER));
+
+        if s.tree_spacing == 0 || !h.is_multiple_of(s.tree_spacing) {
+            return 0;
+        }
+
+        match self.biome(x, z) {
+            Biome::Forest => 4 + ((h >> 16) % 3) as i32,
+            _ => 0,
+        }
+    }
+
+    /// Get the voxel of the features (trees) above the surface.
+    fn feature(&self, x: i32, y: i32, z: i32, height: i32) -> Voxel {
+        let trunk = self.tree(x, z);
+
+        if trunk > 0 && y <= height.wrapping_add(trunk) {
+            return materials::WOOD;
+        }
+
+        // The leaves of the trees of the neighbor columns.
+        for dz in -2..=2 {
+            for dx in -2..=2i32 {
+                let (nx, nz) = (x.wrapping_add(dx), z.wrapping_add(dz));
+                let trunk = self.tree(nx, nz);
+
+                if trunk == 0 {
+                    continue;
+                }
+
+                let top = self.height(nx, nz).wrapping_add(trunk);
+                let dy = y.wrapping_sub(top);
+
+                if dy >= -1 && dx.abs() + dz.abs() + dy.wrapping_abs() <= 2 {
+                    return materials::LEAVES;
+                }
+            }
+        }
+
+        AIR
+    }
+}
+
+impl ChunkSource for TerrainGenerator {
+    fn load(&self, pos: ChunkPos) -> Option<Chunk> {
+        let chunk = self.generate(pos);
+
+        if chunk.is_empty() { None } else { Some(chunk) }
+    }
+}
+
+/// The terrain as a continuous density: the distance to the surface
+/// (with the fraction of the height noise) carved by the caves. The
+/// trees aren't part of the density.
+impl DensityField for TerrainGenerator {
+    fn density(&self, x: i32, y: i32, z: i32) -> f32 {
+        let s = &self.settings;
+        let noise = fbm2(x, z, s.seed.wrapping_add(HEIGHT_LAYER), s.height_period, s.octaves);
+        let height = s.base_height as f32 + noise.wrapping_mul(s.height_amplitude) as f32 / ONE as f32;
+
+        // The cave noise changes of about `ONE` per period.
+        let cave = noise3(x, y, z, s.seed.wrapping_add(CAVE_LAYER), s.cave_period);
+        let cave = s.cave_threshold.wrapping_sub(cave) as f32 / ONE as f32 * s.cave_period as f32;
+
+        (height - y as f32 + 0.5).min(cave)
+    }
+
+    fn material(&self, x: i32, y: i32, z: i32) -> Voxel {
+        match self.voxel(x, y, z) {
+            AIR => self.biome(x, z).surface(),
+            voxel => voxel,
+        }
+    }
+}
+
+crate::wgsl_struct! {
+    #[derive(Clone, Copy, Debug, Default)]
+    struct TerrainParams {
+        seed            : u32,
+        base_height     : i32,
+        height_amplitude: i32,
+        height_period   : i32,
+        octaves         : u32,
+        biome_period    : i32,
+        cave_period     : i32,
+        cave_threshold  : i32,
+        soil_depth      : i32,
+        coal_chance     : u32,
+        iron_chance     : u32,
+        iron_max_y      : i32,
+        tree_spacing    : u32,
+        chunk_count     : u32,
+        padding         : Vector2<u32>,
+    }
+}
+
+/// The workgroup size of `terrain.wgsl`.
+const TERRAIN_WORKGROUP_SIZE: u32 = 64;
+
+/// The amount of `u32` of a generated chunk, the voxels are
+/// packed two by two.
+const CHUNK_WORDS: usize = CHUNK_VOLUME / 2;
+
+/// Generate chunks on the GPU with a compute shader, the chunks
+/// are exactly the same as the ones of a [TerrainGenerator] with
+/// the same settings.
+///
+/// The generation is recorded in a frame with [GpuTerrainGenerator::generate]
+/// (in a render system) and the chunks are read back once the frame
+/// is submitted with [GpuTerrainGenerator::read].
+pub struct GpuTerrainGenerator {
+    pipeline    : ComputePipeline,
+    params      : Buffer,
+    requests    : Buffer,
+    output      : Buffer,
+    settings    : TerrainSettings,
+    capacity    : usize,
+    /// The chunks generated by the last dispatch, not read yet.
+    pending     : Vec<ChunkPos>,
+}
+
+impl GpuTerrainGenerator {
+    /// Create a new [GpuTerrainGenerator].
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer used to create the GPU resources.
+    /// * `settings`    - The generator settings.
+    /// * `capacity`    - The maximum amount of chunks generated per dispatch.
+    ///
+    pub fn new<R: RendererTrait>(renderer: &mut R, settings: TerrainSettings, capacity: usize) -> Self {
+        let source = TerrainParams::wgsl_definition() + &terrain_constants_wgsl() + include_str!("../../shaders/terrain.wgsl");
+        let shader = renderer.compile_shader(source);
+        let pipeline = renderer.create_compute_pipeline(shader, None);
+
+        // One workgroup dimension is limited to 65535 workgroups.
+        let max_capacity = (65535 * TERRAIN_WORKGROUP_SIZE as usize) / CHUNK_WORDS;
+        let capacity = capacity.clamp(1, max_capacity);
+
+        let params = renderer.create_buffer(TerrainParams::SIZE as u64, BufferUsage::UNIFORM, true);
+        let requests = renderer.create_buffer((capacity * std::mem::size_of::<[i32; 4]>()) as u64, BufferUsage::STORAGE, true);
+        let output = renderer.create_buffer((capacity * CHUNK_WORDS * std::mem::size_of::<u32>()) as u64, BufferUsage::STORAGE, false);
+
+        renderer.set_binding_data(pipeline, 0, &[params, requests, output]);
+
+        Self {
+            pipeline,
+            params,
+            requests,
+            output,
+            settings    : settings.validated(),
+            capacity,
+            pending     : Vec::new(),
+        }
+    }
+
+    /// Get the maximum amount of chunks generated per dispatch.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Check if chunks were generated and not read yet.
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Record the generation of chunks, only the first
+    /// [GpuTerrainGenerator::capacity] chunks are generated. The
+    /// chunks not read yet are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer`    - The renderer.
+    /// * `chunks`      - The chunks to generate.
+    ///
+    pub fn generate<R: RendererTrait>(&mut self, renderer: &mut R, chunks: &[ChunkPos]) {
+        let chunks = &chunks[..chunks.len().min(self.capacity)];
+
+        self.pending = chunks.to_vec();
+
+        if chunks.is_empty() {
+            return;
+        }
+
+        let s = &self.settings;
+        let params = TerrainParams {
+            seed            : s.seed,
+            base_height     : s.base_height,
+            height_amplitude: s.height_amplitude,
+            height_period   : s.height_period,
+            octaves         : s.octaves,
+            biome_period    : s.biome_period,
+            cave_period     : s.cave_period,
+            cave_threshold  : s.cave_threshold,
+            soil_depth      : s.soil_depth,
+            coal_chance     : s.coal_chance,
+            iron_chance     : s.iron_chance,
+            iron_max_y      : s.iron_max_y,
+            tree_spacing    : s.tree_spacing,
+            chunk_count     : chunks.len() as u32,
+            padding         : Vector2::zeros(),
+        };
+
+        let requests = chunks.iter()
+            .map(|pos| [pos.x, pos.y, pos.z, 0])
+            .collect::<Vec<_>>();
+
+        renderer.update_buffer(self.params, &params, 0);
+        renderer.update_buffer_with_slice(self.requests, &requests, 0);
+
+        let words = (chunks.len() * CHUNK_WORDS) as u32;
+        renderer.dispatch_compute_pipeline(self.pipeline, (words.div_ceil(TERRAIN_WORKGROUP_SIZE), 1, 1));
+    }
+
+    /// Read back the chunks generated by the last call to
+    /// [GpuTerrainGenerator::generate], must be called after the
+    /// frame is submitted. The empty chunks are included.
+    ///
+    /// # Arguments
+    ///
+    /// * `renderer` - The renderer.
+    ///
+    pub fn read<R: RendererTrait>(&mut self, renderer: &mut R) -> Vec<(ChunkPos, Chunk)> {
+        let pending = std::mem::take(&mut self.pending);
+
+        if pending.is_empty() {
+            return Vec::new();
+        }
+
+        let size = (pending.len() * CHUNK_WORDS * std::mem::size_of::<u32>()) as u64;
+        let data = renderer.read_buffer(self.output, 0, size);
+        let words: &[u32] = bytemuck::cast_slice(&data);
+
+        log::debug!(target: logging::RENDERER, "Read {} chunks generated on the GPU", pending.len());
+
+        pending.into_iter()
+            .zip(words.chunks_exact(CHUNK_WORDS))
+            .filter_map(|(pos, words)| {
+                let voxels = words.iter()
+                    .flat_map(|word| [(*word & 0xffff) as Voxel, (*word >> 16) as Voxel])
+                    .collect();
+
+                Chunk::from_voxels(voxels).map(|chunk| (pos, chunk))
+            })
+            .collect()
+    }
+}
+
+/// Get the constants shared by the generator and `terrain.wgsl`.
+fn terrain_constants_wgsl() -> String {
+    format!(
+        r#"let CHUNK_SIZE: i32 = {chunk_size};
+let CHUNK_WORDS: u32 = {chunk_words}u;
+
+let FRACTION_BITS: u32 = {fraction_bits}u;
+let ONE: i32 = {one};
+
+let HEIGHT_LAYER: u32 = {height_layer}u;
+let TEMPERATURE_LAYER: u32 = {temperature_layer}u;
+let MOISTURE_LAYER: u32 = {moisture_layer}u;
+let CAVE_LAYER: u32 = {cave_layer}u;
+let ORE_LAYER: u32 = {ore_layer}u;
+let TREE_LAYER: u32 = {tree_layer}u;
+
+let AIR: u32 = {air}u;
+let STONE: u32 = {stone}u;
+let DIRT: u32 = {dirt}u;
+let GRASS: u32 = {grass}u;
+let SAND: u32 = {sand}u;
+let SNOW: u32 = {snow}u;
+let COAL_ORE: u32 = {coal_ore}u;
+let IRON_ORE: u32 = {iron_ore}u;
+let WOOD: u32 = {wood}u;
+let LEAVES: u32 = {leaves}u;
+
+let PLAINS: u32 = {plains}u;
+let FOREST: u32 = {forest}u;
+let DESERT: u32 = {desert}u;
+let BIOME_SNOW: u32 = {biome_snow}u;
+"#,
+        chunk_size          = CHUNK_SIZE,
+        chunk_words         = CHUNK_WORDS,
+        fraction_bits       = FRACTION_BITS,
+        one                 = ONE,
+        height_layer        = HEIGHT_LAYER,
+        temperature_layer   = TEMPERATURE_LAYER,
+        moisture_layer      = MOISTURE_LAYER,
+        cave_layer          = CAVE_LAYER,
+        ore_layer           = ORE_LAYER,
+        tree_layer          = TREE_LAYER,
+        air                 = AIR,
+        stone               = materials::STONE,
+        dirt                = materials::DIRT,
+        grass               = materials::GRASS,
+        sand                = materials::SAND,
+        snow                = materials::SNOW,
+        coal_ore            = materials::COAL_ORE,
+        iron_ore            = materials::IRON_ORE,
+        wood                = materials::WOOD,
+        leaves              = materials::LEAVES,
+        plains              = Biome::Plains as u32,
+        forest              = Biome::Forest as u32,
+        desert              = Biome::Desert as u32,
+        biome_snow          = Biome::Snow as u32,
+    )
+}
+
+/// Hash integer coordinates.
+pub fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed ^ 0x9e37_79b9;
+    h = (h ^ x as u32).wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = (h ^ y as u32).wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h = (h ^ z as u32).wrapping_mul(0x27d4_eb2f);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x1656_67b1);
+    h ^ (h >> 16)
+}
+
+/// Split a coordinate scaled by `scale / period` into the noise
+/// cell and the fixed point position in the cell.
+fn cell(v: i32, scale: i32, period: i32) -> (i32, i32) {
+    let n = v.wrapping_mul(scale);
+    let mut r = n % period;
+
+    if r < 0 {
+        r += period;
+    }
+
+    (n.wrapping_sub(r) / period, (r << FRACTION_BITS) / period)
+}
+
+/// The quintic fade curve of the Perlin noise.
+fn fade(t: i32) -> i32 {
+    let t2 = (t * t) >> FRACTION_BITS;
+    let t3 = (t2 * t) >> FRACTION_BITS;
+
+    (t3 * (6 * t2 - 15 * t + 10 * ONE)) >> FRACTION_BITS
+}
+
+fn lerp(a: i32, b: i32, t: i32) -> i32 {
+    a + (((b - a) * t) >> FRACTION_BITS)
+}
+
+fn grad2(h: u32, x: i32, y: i32) -> i32 {
+    match h & 7 {
+        0 => x,
+        1 => -x,
+        2 => y,
+        3 => -y,
+        4 => x + y,
+        5 => -x + y,
+        6 => x - y,
+        _ => -x - y,
+    }
+}
+
+fn grad3(h: u32, x: i32, y: i32, z: i32) -> i32 {
+    // The 12 edges of a cube (and 4 repeated to fill 16 values).
+    match h & 15 {
+        0 | 12  => x + y,
+        1 | 13  => -x + y,
+        2       => x - y,
+        3       => -x - y,
+        4       => x + z,
+        5       => -x + z,
+        6       => x - z,
+        7       => -x - z,
+        8       => y + z,
+        9 | 14  => -y + z,
+        10      => y - z,
+        _       => -y - z,
+    }
+}
+
+/// A 2D gradient noise, the result is roughly in `[-ONE, ONE]`.
+fn noise2(x: i32, z: i32, seed: u32, period: i32, scale: i32) -> i32 {
+    let (cx, fx) = cell(x, scale, period);
+    let (cz, fz) = cell(z, scale, period);
+
+    let n00 = grad2(hash(cx, 0, cz, seed), fx, fz);
+    let n10 = grad2(hash(cx.wrapping_add(1), 0, cz, seed), fx - ONE, fz);
+    let n01 = grad2(hash(cx, 0, cz.wrapping_add(1), seed), fx, fz - ONE);
+    let n11 = grad2(hash(cx.wrapping_add(1), 0, cz.wrapping_add(1), seed), fx - ONE, fz - ONE);
+
+    let u = fade(fx);
+    let v = fade(fz);
+
+    lerp(lerp(n00, n10, u), lerp(n01, n11, u), v)
+}
+
+/// A fractal sum of 2D noises, the amplitude is halved and the
+/// frequency doubled at each octave.
+pub fn fbm2(x: i32, z: i32, seed: u32, period: i32, octaves: u32) -> i32 {
+    let mut value = 0;
+
+    for octave in 0..octaves {
+        let seed = seed.wrapping_add(octave.wrapping_mul(0x68e3_1da4));
+        value += noise2(x, z, seed, period, 1 << octave) >> octave;
+    }
+
+    value
+}
+
+/// A 3D gradient noise, the result is roughly in `[-ONE, ONE]`.
+pub fn noise3(x: i32, y: i32, z: i32, seed: u32, period: i32) -> i32 {
+    let (cx, fx) = cell(x, 1, period);
+    let (cy, fy) = cell(y, 1, period);
+    let (cz, fz) = cell(z, 1, period);
+
+    let n000 = grad3(hash(cx, cy, cz, seed), fx, fy, fz);
+    let n100 = grad3(hash(cx.wrapping_add(1), cy, cz, seed), fx - ONE, fy, fz);
+    let n010 = grad3(hash(cx, cy.wrapping_add(1), cz, seed), fx, fy - ONE, fz);
+    let n110 = grad3(hash(cx.wrapping_add(1), cy.wrapping_add(1), cz, seed), fx - ONE, fy - ONE, fz);
+    let n001 = grad3(hash(cx, cy, cz.wrapping_add(1), seed), fx, fy, fz - ONE);
+    let n101 = grad3(hash(cx.wrapping_add(1), cy, cz.wrapping_add(1), seed), fx - ONE, fy, fz - ONE);
+    let n011 = grad3(hash(cx, cy.wrapping_add(1), cz.wrapping_add(1), seed), fx, fy - ONE, fz - ONE);
+    let n111 = grad3(hash(cx.wrapping_add(1), cy.wrapping_add(1), cz.wrapping_add(1), seed), fx - ONE, fy - ONE, fz - ONE);
+
+    let u = fade(fx);
+    let v = fade(fy);
+    let w = fade(fz);
+
+    lerp(
+        lerp(lerp(n000, n100, u), lerp(n010, n110, u), v),
+        lerp(lerp(n001, n101, u), lerp(n011, n111, u), v),
+        w,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_same_chunks(a: &Chunk, b: &Chunk) {
+        assert!(a.voxels() == b.voxels());
+    }
+
+    #[test]
+    fn deterministic() {
+        let settings = TerrainSettings { seed: 1234, ..Default::default() };
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(-3, -1, 5), ChunkPos::new(7, -4, -2)];
+
+        for pos in positions {
+            let a = TerrainGenerator::new(settings).generate(pos);
+            let b = TerrainGenerator::new(settings).generate(pos);
+
+            assert_same_chunks(&a, &b);
+        }
+
+        // The column of chunks around the surface has solid voxels
+        // and air, and depends on the seed.
+        let column = |seed| {
+            let generator = TerrainGenerator::new(TerrainSettings { seed, ..settings });
+
+            (-3..=3)
+                .flat_map(|y| generator.generate(ChunkPos::new(0, y, 0)).voxels().to_vec())
+                .collect::<Vec<_>>()
+        };
+
+        let surface = column(1234);
+        assert!(surface.contains(&AIR));
+        assert!(surface.iter().any(|voxel| *voxel != AIR));
+        assert!(surface != column(4321));
+    }
+
+    #[test]
+    fn chunks_match_voxels() {
+        let generator = TerrainGenerator::new(TerrainSettings { seed: 7, tree_spacing: 4, ..Default::default() });
+        let pos = ChunkPos::new(2, 0, -1);
+        let chunk = generator.generate(pos);
+        let min = pos.min_voxel();
+
+        for z in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let voxel = generator.voxel(min.x + x, min.y + y, min.z + z);
+                    assert_eq!(chunk.get(x as usize, y as usize, z as usize), voxel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let settings = TerrainSettings {
+            height_period   : 0,
+            octaves         : 40,
+            biome_period    : -8,
+            cave_period     : i32::MAX,
+            ..Default::default()
+        };
+
+        let validated = settings.validated();
+        assert_eq!(validated.height_period, 1);
+        assert_eq!(validated.octaves, MAX_OCTAVES);
+        assert_eq!(validated.biome_period, 1);
+        assert_eq!(validated.cave_period, MAX_PERIOD);
+        assert_eq!(TerrainSettings::default().validated(), TerrainSettings::default());
+
+        // The generator doesn't divide by zero.
+        TerrainGenerator::new(settings).generate(ChunkPos::new(0, -1, 0));
+    }
+
+    #[test]
+    fn extreme_seeds_and_offsets() {
+        let settings = TerrainSettings {
+            seed            : u32::MAX,
+            base_height     : i32::MAX - 8,
+            height_amplitude: i32::MAX,
+            cave_threshold  : i32::MIN,
+            coal_chance     : u32::MAX,
+            iron_chance     : u32::MAX,
+            tree_spacing    : 1,
+            ..Default::default()
+        };
+
+        // The arithmetic wraps instead of overflowing.
+        let generator = TerrainGenerator::new(settings);
+        generator.generate(ChunkPos::new(i32::MAX / CHUNK_SIZE, i32::MAX / CHUNK_SIZE, i32::MIN / CHUNK_SIZE));
+        generator.generate(ChunkPos::new(0, 0, 0));
+        generator.density(i32::MAX, i32::MIN, i32::MAX);
+    }
+
+    #[test]
+    fn shader_constants() {
+        let source = TerrainParams::wgsl_definition() + &terrain_constants_wgsl() + include_str!("../../shaders/terrain.wgsl");
+        let module = naga::front::wgsl::parse_str(&source).unwrap();
+
+        naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::empty())
+            .validate(&module)
+            .unwrap();
+
+        assert!(source.contains(&format!("let CHUNK_WORDS: u32 = {}u;", CHUNK_VOLUME / 2)));
+        assert!(source.contains(&format!("let LEAVES: u32 = {}u;", materials::LEAVES)));
+    }
+}
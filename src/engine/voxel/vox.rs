@@ -0,0 +1,776 @@
+//! Import and export of the MagicaVoxel `.vox` files.
+//!
+//! The models (`SIZE` and `XYZI`), the palette (`RGBA`), the materials
+//! (`MATL`), the layers (`LAYR`) and the scene graph (`nTRN`, `nGRP`
+//! and `nSHP`) are supported, the other chunks are ignored.
+//!
+//! The color index of a voxel (`1` to `255`) is used as is as the
+//! [Voxel] value, so it indexes the palette and the materials.
+//! MagicaVoxel is Z up, the `y` and `z` axes are swapped when the
+//! voxels are placed in a [VoxelWorld].
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use nalgebra::{Matrix3, Vector3};
+
+use crate::engine::logging;
+
+use super::{
+    material::{Material, MaterialPalette},
+    world::VoxelWorld,
+    Voxel, AIR,
+};
+
+/// The maximum size of a model on each axis.
+pub const MAX_MODEL_SIZE: u32 = 256;
+
+/// The version written in the exported files.
+const VERSION: u32 = 200;
+
+/// An ordered list of key-value pairs (a `DICT` of the format).
+pub type VoxDict = Vec<(String, String)>;
+
+/// Get a value of a [VoxDict].
+pub fn dict_get<'a>(dict: &'a VoxDict, key: &str) -> Option<&'a str> {
+    dict.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+}
+
+/// A model: a box of voxels, only the solid voxels are stored.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoxModel {
+    /// The size of the model (Z up).
+    pub size    : Vector3<u32>,
+    /// The voxels, `[x, y, z, color index]`.
+    pub voxels  : Vec<[u8; 4]>,
+}
+
+/// A material of the palette, its properties are kept as they are
+/// in the file (e.g. `_type`, `_rough`, `_metal`, `_emit`...).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoxMaterial {
+    /// The palette index of the material.
+    pub id          : u32,
+    pub properties  : VoxDict,
+}
+
+impl VoxMaterial {
+    /// Get the type of the material (`_diffuse`, `_metal`, `_glass`, `_emit`...).
+    pub fn kind(&self) -> &str {
+        dict_get(&self.properties, "_type").unwrap_or("_diffuse")
+    }
+
+    /// Get a numeric property of the material.
+    pub fn get(&self, key: &str) -> Option<f32> {
+        dict_get(&self.properties, key).and_then(|value| value.parse().ok())
+    }
+}
+
+/// A layer of the scene.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VoxLayer {
+    pub id          : u32,
+    pub attributes  : VoxDict,
+}
+
+/// A node of the scene graph.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VoxNode {
+    /// Place its child, the frames contain the rotation (`_r`)
+    /// and the translation (`_t`).
+    Transform {
+        attributes  : VoxDict,
+        child       : u32,
+        layer       : i32,
+        frames      : Vec<VoxDict>,
+    },
+    Group {
+        attributes  : VoxDict,
+        children    : Vec<u32>,
+    },
+    /// Reference models.
+    Shape {
+        attributes  : VoxDict,
+        models      : Vec<(u32, VoxDict)>,
+    },
+}
+
+/// A model placed in the scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoxInstance {
+    /// The index of the model.
+    pub model       : usize,
+    /// The rotation of the model (Z up).
+    pub rotation    : Matrix3<i32>,
+    /// The position of the center of the model (Z up).
+    pub translation : Vector3<i32>,
+}
+
+impl VoxInstance {
+    /// Get the position of a voxel of the model in the scene (Z up).
+    pub fn transform(&self, size: &Vector3<u32>, voxel: Vector3<i32>) -> Vector3<i32> {
+        // The models rotate around their center, the positions
+        // are doubled so the center of the voxels are integers.
+        let size = size.cast::<i32>();
+        let doubled = self.rotation * (voxel * 2 + Vector3::repeat(1) - size);
+
+        self.translation + doubled.map(|v| v.div_euclid(2))
+    }
+}
+
+/// A `.vox` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxFile {
+    pub models      : Vec<VoxModel>,
+    /// The colors (RGBA) by color index, the index `0` is unused.
+    pub palette     : [[u8; 4]; 256],
+    pub materials   : Vec<VoxMaterial>,
+    pub layers      : Vec<VoxLayer>,
+    /// The scene graph, the node `0` is the root. It is empty in
+    /// the old files, the models are then at the origin.
+    pub nodes       : BTreeMap<u32, VoxNode>,
+}
+
+impl Default for VoxFile {
+    fn default() -> Self {
+        Self {
+            models      : Vec::new(),
+            palette     : default_palette(),
+            materials   : Vec::new(),
+            layers      : Vec::new(),
+            nodes       : BTreeMap::new(),
+        }
+    }
+}
+
+impl VoxFile {
+    /// Read a `.vox` file.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Write a `.vox` file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    /// Parse a `.vox` file.
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+
+        if &magic != b"VOX " {
+            return Err(invalid_data("Not a .vox file".to_owned()));
+        }
+
+        let _version = read_u32(reader)?;
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let mut reader = ChunkReader::new(&data);
+        let (id, _, children) = reader.chunk()?;
+
+        if &id != b"MAIN" {
+            return Err(invalid_data("The MAIN chunk is missing".to_owned()));
+        }
+
+        let mut file = Self::default();
+        let mut reader = ChunkReader::new(children);
+        let mut size = None;
+
+        while !reader.is_empty() {
+            let (id, content, _) = reader.chunk()?;
+            let mut content = ChunkReader::new(content);
+
+            match &id {
+                b"SIZE" => {
+                    size = Some(Vector3::new(content.u32()?, content.u32()?, content.u32()?));
+                },
+                b"XYZI" => {
+                    let size = size.take().ok_or_else(|| invalid_data("XYZI chunk without SIZE chunk".to_owned()))?;
+                    let count = content.u32()? as usize;
+                    let voxels = (0..count)
+                        .map(|_| content.bytes(4).map(|bytes| [bytes[0], bytes[1], bytes[2], bytes[3]]))
+                        .collect::<io::Result<_>>()?;
+
+                    file.models.push(VoxModel { size, voxels });
+                },
+                b"RGBA" => {
+                    for index in 1..256 {
+                        let color = content.bytes(4)?;
+                        file.palette[index] = [color[0], color[1], color[2], color[3]];
+                    }
+                },
+                b"MATL" => {
+                    let id = content.u32()?;
+                    let properties = content.dict()?;
+                    file.materials.push(VoxMaterial { id, properties });
+                },
+                b"LAYR" => {
+                    let id = content.u32()?;
+                    let attributes = content.dict()?;
+                    file.layers.push(VoxLayer { id, attributes });
+                },
+                b"nTRN" => {
+                    let id = content.u32()?;
+                    let attributes = content.dict()?;
+                    let child = content.u32()?;
+                    let _reserved = content.u32()?;
+                    let layer = content.u32()? as i32;
+                    let frame_count = content.u32()?;
+                    let frames = (0..frame_count).map(|_| content.dict()).collect::<io::Result<_>>()?;
+
+                    file.nodes.insert(id, VoxNode::Transform { attributes, child, layer, frames });
+                },
+                b"nGRP" => {
+                    let id = content.u32()?;
+                    let attributes = content.dict()?;
+                    let count = content.u32()?;
+                    let children = (0..count).map(|_| content.u32()).collect::<io::Result<_>>()?;
+
+                    file.nodes.insert(id, VoxNode::Group { attributes, children });
+                },
+                b"nSHP" => {
+                    let id = content.u32()?;
+                    let attributes = content.dict()?;
+                    let count = content.u32()?;
+                    let models = (0..count).map(|_| Ok((content.u32()?, content.dict()?))).collect::<io::Result<_>>()?;
+
+                    file.nodes.insert(id, VoxNode::Shape { attributes, models });
+                },
+                _ => {
+                    log::debug!(target: logging::ENGINE, "Ignore the .vox chunk {}", String::from_utf8_lossy(&id));
+                },
+            }
+        }
+
+        Ok(file)
+    }
+
+    /// Write the file.
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut children = Vec::new();
+
+        for model in &self.models {
+            let mut size = Vec::new();
+            write_u32(&mut size, model.size.x);
+            write_u32(&mut size, model.size.y);
+            write_u32(&mut size, model.size.z);
+            write_chunk(&mut children, b"SIZE", &size);
+
+            let mut voxels = Vec::with_capacity(4 + model.voxels.len() * 4);
+            write_u32(&mut voxels, model.voxels.len() as u32);
+            model.voxels.iter().for_each(|voxel| voxels.extend_from_slice(voxel));
+            write_chunk(&mut children, b"XYZI", &voxels);
+        }
+
+        for (id, node) in &self.nodes {
+            let mut content = Vec::new();
+            write_u32(&mut content, *id);
+
+            match node {
+                VoxNode::Transform { attributes, child, layer, frames } => {
+                    write_dict(&mut content, attributes);
+                    write_u32(&mut content, *child);
+                    write_u32(&mut content, u32::MAX);
+                    write_u32(&mut content, *layer as u32);
+                    write_u32(&mut content, frames.len() as u32);
+                    frames.iter().for_each(|frame| write_dict(&mut content, frame));
+                    write_chunk(&mut children, b"nTRN", &content);
+                },
+                VoxNode::Group { attributes, children: nodes } => {
+                    write_dict(&mut content, attributes);
+                    write_u32(&mut content, nodes.len() as u32);
+                    nodes.iter().for_each(|node| write_u32(&mut content, *node));
+                    write_chunk(&mut children, b"nGRP", &content);
+                },
+                VoxNode::Shape { attributes, models } => {
+                    write_dict(&mut content, attributes);
+                    write_u32(&mut content, models.len() as u32);
+
+                    for (model, attributes) in models {
+                        write_u32(&mut content, *model);
+                        write_dict(&mut content, attributes);
+                    }
+
+                    write_chunk(&mut children, b"nSHP", &content);
+                },
+            }
+        }
+
+        for layer in &self.layers {
+            let mut content = Vec::new();
+            write_u32(&mut content, layer.id);
+            write_dict(&mut content, &layer.attributes);
+            write_u32(&mut content, u32::MAX);
+            write_chunk(&mut children, b"LAYR", &content);
+        }
+
+        let mut palette = Vec::with_capacity(256 * 4);
+        self.palette[1..].iter().for_each(|color| palette.extend_from_slice(color));
+        palette.extend_from_slice(&[0; 4]);
+        write_chunk(&mut children, b"RGBA", &palette);
+
+        for material in &self.materials {
+            let mut content = Vec::new();
+            write_u32(&mut content, material.id);
+            write_dict(&mut content, &material.properties);
+            write_chunk(&mut children, b"MATL", &content);
+        }
+
+        writer.write_all(b"VOX ")?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(b"MAIN")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&(children.len() as u32).to_le_bytes())?;
+        writer.write_all(&children)
+    }
+
+    /// Get the models placed by the scene graph, the hidden nodes
+    /// and layers are skipped. Without scene graph, all the models
+    /// are at the origin.
+    pub fn instances(&self) -> Vec<VoxInstance> {
+        let mut instances = Vec::new();
+
+        if self.nodes.is_empty() {
+            for (model, size) in self.models.iter().map(|model| model.size).enumerate() {
+                instances.push(VoxInstance {
+                    model,
+                    rotation    : Matrix3::identity(),
+                    translation : (size / 2).cast(),
+                });
+            }
+        } else {
+            self.visit(0, Matrix3::identity(), Vector3::zeros(), 0, &mut instances);
+        }
+
+        instances
+    }
+
+    fn visit(&self, id: u32, rotation: Matrix3<i32>, translation: Vector3<i32>, depth: usize, instances: &mut Vec<VoxInstance>) {
+        // Guard against the cycles of the invalid files.
+        if depth > 64 {
+            return;
+        }
+
+        match self.nodes.get(&id) {
+            Some(VoxNode::Transform { attributes, child, layer, frames }) => {
+                let hidden_layer = self.layers.iter()
+                    .any(|l| l.id as i32 == *layer && dict_get(&l.attributes, "_hidden") == Some("1"));
+
+                if hidden_layer || dict_get(attributes, "_hidden") == Some("1") {
+                    return;
+                }
+
+                let frame = frames.first();
+                let local_rotation = frame
+                    .and_then(|frame| dict_get(frame, "_r"))
+                    .and_then(|r| r.parse().ok())
+                    .map(decode_rotation)
+                    .unwrap_or_else(Matrix3::identity);
+                let local_translation = frame
+                    .and_then(|frame| dict_get(frame, "_t"))
+                    .map(parse_translation)
+                    .unwrap_or_else(Vector3::zeros);
+
+                self.visit(*child, rotation * local_rotation, translation + rotation * local_translation, depth + 1, instances);
+            },
+            Some(VoxNode::Group { attributes, children }) => {
+                if dict_get(attributes, "_hidden") == Some("1") {
+                    return;
+                }
+
+                for child in children {
+                    self.visit(*child, rotation, translation, depth + 1, instances);
+                }
+            },
+            Some(VoxNode::Shape { models, .. }) => {
+                for (model, _) in models {
+                    if (*model as usize) < self.models.len() {
+                        instances.push(VoxInstance { model: *model as usize, rotation, translation });
+                    }
+                }
+            },
+            None => log::warn!(target: logging::ENGINE, "The .vox node {} doesn't exist", id),
+        }
+    }
+
+    /// Place the voxels of the scene in a world, the color indices
+    /// are used as the voxel values. Return the amount of voxels.
+    ///
+    /// # Arguments
+    ///
+    /// * `world`   - The world to fill.
+    /// * `origin`  - The position of the origin of the scene in the world.
+    ///
+    pub fn to_world(&self, world: &mut VoxelWorld, origin: Vector3<i32>) -> usize {
+        let mut count = 0;
+
+        for instance in self.instances() {
+            let model = &self.models[instance.model];
+
+            for [x, y, z, color] in &model.voxels {
+                let position = from_vox_axes(instance.transform(&model.size, Vector3::new(*x, *y, *z).cast())) + origin;
+                world.set_voxel(position.x, position.y, position.z, *color as Voxel);
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Create a file from a box of a world, the voxels must be color
+    /// indices (from `1` to `255`). The box is split in models of at
+    /// most [MAX_MODEL_SIZE] voxels on each axis placed so importing
+    /// the file at `min` gives the same voxels.
+    ///
+    /// # Arguments
+    ///
+    /// * `world`   - The world to export.
+    /// * `min`     - The minimum corner of the box (included).
+    /// * `max`     - The maximum corner of the box (excluded).
+    ///
+    pub fn from_world(world: &VoxelWorld, min: Vector3<i32>, max: Vector3<i32>) -> Self {
+        let mut file = Self::default();
+        let extent = to_vox_axes(max - min).map(|v| v.max(0));
+        let tile = MAX_MODEL_SIZE as i32;
+
+        let mut group = Vec::new();
+        let mut skipped = 0;
+
+        for tile_z in (0..extent.z).step_by(tile as usize) {
+            for tile_y in (0..extent.y).step_by(tile as usize) {
+                for tile_x in (0..extent.x).step_by(tile as usize) {
+                    let start = Vector3::new(tile_x, tile_y, tile_z);
+                    let size = (extent - start).map(|v| v.min(tile));
+                    let mut voxels = Vec::new();
+
+                    for z in 0..size.z {
+                        for y in 0..size.y {
+                            for x in 0..size.x {
+                                let position = from_vox_axes(start + Vector3::new(x, y, z)) + min;
+                                let voxel = world.get_voxel(position.x, position.y, position.z);
+
+                                match u8::try_from(voxel) {
+                                    Ok(color) if voxel != AIR => voxels.push([x as u8, y as u8, z as u8, color]),
+                                    Ok(_) => {},
+                                    Err(_) => skipped += 1,
+                                }
+                            }
+                        }
+                    }
+
+                    let model = file.models.len() as u32;
+                    let transform = 2 + model * 2;
+                    let translation = start + size / 2;
+
+                    file.models.push(VoxModel { size: size.map(|v| v as u32), voxels });
+                    file.nodes.insert(transform, VoxNode::Transform {
+                        attributes  : VoxDict::new(),
+                        child       : transform + 1,
+                        layer       : 0,
+                        frames      : vec![vec![("_t".to_owned(), format!("{} {} {}", translation.x, translation.y, translation.z))]],
+                    });
+                    file.nodes.insert(transform + 1, VoxNode::Shape {
+                        attributes  : VoxDict::new(),
+                        models      : vec![(model, VoxDict::new())],
+                    });
+
+                    group.push(transform);
+                }
+            }
+        }
+
+        if skipped > 0 {
+            log::warn!(target: logging::ENGINE, "{} voxels aren't palette indices and aren't exported", skipped);
+        }
+
+        file.nodes.insert(0, VoxNode::Transform {
+            attributes  : VoxDict::new(),
+            child       : 1,
+            layer       : -1,
+            frames      : vec![VoxDict::new()],
+        });
+        file.nodes.insert(1, VoxNode::Group { attributes: VoxDict::new(), children: group });
+        file.layers.push(VoxLayer { id: 0, attributes: VoxDict::new() });
+
+        file
+    }
+
+    /// Create the material palette of the colors and materials of
+    /// the file, for the voxels imported with [VoxFile::to_world].
+    /// The roughness (`_rough`), the metalness (`_metal`), the
+    /// emission (`_emit` scaled by `2^_flux`) and the transparency
+    /// (`_trans` of the glass, `_alpha` of the blend) are converted.
+    pub fn material_palette(&self) -> MaterialPalette {
+        let mut palette = MaterialPalette::new(256);
+
+        for (index, [r, g, b, _]) in self.palette.iter().enumerate().skip(1) {
+            palette.set(index as Voxel, Material::from_srgb(*r, *g, *b));
+        }
+
+        for properties in &self.materials {
+            let Ok(voxel) = Voxel::try_from(properties.id) else { continue };
+
+            if voxel == AIR || voxel as usize >= palette.len() {
+                continue;
+            }
+
+            let mut material = *palette.get(voxel);
+
+            material = match properties.kind() {
+                "_metal" => material
+                    .with_metalness(properties.get("_metal").unwrap_or(1.0))
+                    .with_roughness(properties.get("_rough").unwrap_or(0.1)),
+                "_glass" => material
+                    .with_transparency(properties.get("_trans").unwrap_or(0.0))
+                    .with_roughness(properties.get("_rough").unwrap_or(0.1)),
+                "_blend" => material
+                    .with_transparency(properties.get("_alpha").map(|alpha| 1.0 - alpha).unwrap_or(0.0))
+                    .with_metalness(properties.get("_metal").unwrap_or(0.0))
+                    .with_roughness(properties.get("_rough").unwrap_or(1.0)),
+                "_emit" => {
+                    let power = properties.get("_emit").unwrap_or(1.0) * 2f32.powf(properties.get("_flux").unwrap_or(0.0));
+                    material.with_emission(material.albedo * power)
+                },
+                _ => material,
+            };
+
+            palette.set(voxel, material);
+        }
+
+        palette
+    }
+}
+
+/// Get the MagicaVoxel default palette.
+pub fn default_palette() -> [[u8; 4]; 256] {
+    let mut palette = [[0; 4]; 256];
+    let mut index = 1;
+
+    // A 6×6×6 color cube (without black)...
+    const LEVELS: [u8; 6] = [0xff, 0xcc, 0x99, 0x66, 0x33, 0x00];
+
+    for r in LEVELS {
+        for g in LEVELS {
+            for b in LEVELS {
+                if index < 216 {
+                    palette[index] = [r, g, b, 0xff];
+                    index += 1;
+                }
+            }
+        }
+    }
+
+    // ...then ramps of red, green, blue and gray.
+    const RAMP: [u8; 10] = [0xee, 0xdd, 0xbb, 0xaa, 0x88, 0x77, 0x55, 0x44, 0x22, 0x11];
+
+    for channel in 0..4 {
+        for level in RAMP {
+            palette[index] = match channel {
+                0 => [level, 0, 0, 0xff],
+                1 => [0, level, 0, 0xff],
+                2 => [0, 0, level, 0xff],
+                _ => [level, level, level, 0xff],
+            };
+            index += 1;
+        }
+    }
+
+    palette
+}
+
+/// Decode a packed rotation (`_r`): the indices of the non zero
+/// entries of the first two rows and the signs of the three rows.
+fn decode_rotation(packed: u8) -> Matrix3<i32> {
+    let first = (packed & 3) as usize;
+    let second = ((packed >> 2) & 3) as usize;
+
+    if first > 2 || second > 2 || first == second {
+        log::warn!(target: logging::ENGINE, "Invalid .vox rotation {}", packed);
+        return Matrix3::identity();
+    }
+
+    let third = 3 - first - second;
+    let sign = |bit: u8| if packed & (1 << bit) != 0 { -1 } else { 1 };
+
+    let mut rotation = Matrix3::zeros();
+    rotation[(0, first)] = sign(4);
+    rotation[(1, second)] = sign(5);
+    rotation[(2, third)] = sign(6);
+
+    rotation
+}
+
+fn parse_translation(value: &str) -> Vector3<i32> {
+    let mut values = value.split_whitespace().map(|v| v.parse().unwrap_or(0));
+
+    Vector3::new(
+        values.next().unwrap_or(0),
+        values.next().unwrap_or(0),
+        values.next().unwrap_or(0),
+    )
+}
+
+/// Convert MagicaVoxel coordinates (Z up) to the engine coordinates (Y up).
+fn from_vox_axes(v: Vector3<i32>) -> Vector3<i32> {
+    Vector3::new(v.x, v.z, v.y)
+}
+
+/// Convert the engine coordinates (Y up) to MagicaVoxel coordinates (Z up).
+fn to_vox_axes(v: Vector3<i32>) -> Vector3<i32> {
+    Vector3::new(v.x, v.z, v.y)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_u32(out, value.len() as u32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn write_dict(out: &mut Vec<u8>, dict: &VoxDict) {
+    write_u32(out, dict.len() as u32);
+
+    for (key, value) in dict {
+        write_string(out, key);
+        write_string(out, value);
+    }
+}
+
+fn write_chunk(out: &mut Vec<u8>, id: &[u8; 4], content: &[u8]) {
+    out.extend_from_slice(id);
+    write_u32(out, content.len() as u32);
+    write_u32(out, 0);
+    out.extend_from_slice(content);
+}
+
+/// Read the chunks and their content from a slice.
+struct ChunkReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> ChunkReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn bytes(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < count {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Truncated .vox file"));
+        }
+
+        let (bytes, rest) = self.data.split_at(count);
+        self.data = rest;
+
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?;
+
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn dict(&mut self) -> io::Result<VoxDict> {
+        let count = self.u32()?;
+
+        (0..count).map(|_| Ok((self.string()?, self.string()?))).collect()
+    }
+
+    /// Read a chunk, return its id, content and children.
+    fn chunk(&mut self) -> io::Result<([u8; 4], &'a [u8], &'a [u8])> {
+        let id = self.bytes(4)?;
+        let content_size = self.u32()? as usize;
+        let children_size = self.u32()? as usize;
+
+        Ok(([id[0], id[1], id[2], id[3]], self.bytes(content_size)?, self.bytes(children_size)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_round_trip() {
+        // The box is split in two models along `x`, the second one
+        // has an odd size.
+        let (min, max) = (Vector3::new(-5, 2, 3), Vector3::new(296, 6, 6));
+
+        let mut world = VoxelWorld::new();
+        let mut expected = Vec::new();
+
+        for (index, x) in [-5, 0, 250, 251, 252, 295].into_iter().enumerate() {
+            let position = Vector3::new(x, 2 + index as i32 % 4, 3 + index as i32 % 3);
+            let color = index as Voxel + 1;
+
+            world.set_voxel(position.x, position.y, position.z, color);
+            expected.push((position, color));
+        }
+
+        let file = VoxFile::from_world(&world, min, max);
+        assert_eq!(file.models.len(), 2);
+        assert_eq!(file.models[0].size, Vector3::new(MAX_MODEL_SIZE, 3, 4));
+        assert_eq!(file.models[1].size, Vector3::new(45, 3, 4));
+
+        let mut data = Vec::new();
+        file.write(&mut data).unwrap();
+
+        let read = VoxFile::read(&mut data.as_slice()).unwrap();
+        assert_eq!(read, file);
+
+        let mut imported = VoxelWorld::new();
+        assert_eq!(read.to_world(&mut imported, min), expected.len());
+
+        for (position, color) in expected {
+            assert_eq!(imported.get_voxel(position.x, position.y, position.z), color, "at {:?}", position);
+        }
+    }
+
+    #[test]
+    fn rotation_is_decoded() {
+        // The first row has its non zero entry in the column 1 and
+        // is negative, the second one in the column 0: a quarter
+        // turn around Z.
+        let rotation = decode_rotation(0b0010001);
+        assert_eq!(rotation, Matrix3::new(
+            0, -1, 0,
+            1,  0, 0,
+            0,  0, 1,
+        ));
+
+        assert_eq!(decode_rotation(0b0000100), Matrix3::identity());
+
+        // Both rows can't use the same column, the rotation is ignored.
+        assert_eq!(decode_rotation(0b0000000), Matrix3::identity());
+    }
+}
@@ -0,0 +1,222 @@
+use std::collections::{HashMap, HashSet};
+
+use nalgebra::Vector3;
+
+use super::{
+    chunk::{split_voxel, Chunk, ChunkPos},
+    isosurface::DensityField,
+    Voxel, VoxelQuery, AIR,
+};
+
+/// An infinite voxel world, made of [Chunk]s stored by their
+/// coordinates. The chunks that aren't loaded are made of [AIR].
+#[derive(Default)]
+pub struct VoxelWorld {
+    chunks       : HashMap<ChunkPos, Chunk>,
+    /// The voxels changed by [VoxelWorld::set_voxel], when the
+    /// changes are tracked.
+    changes      : Option<Vec<Vector3<i32>>>,
+    /// The chunks whose voxels changed, or that were inserted or
+    /// removed, when the chunk changes are tracked.
+    chunk_changes: Option<HashSet<ChunkPos>>,
+}
+
+impl VoxelWorld {
+    /// Create a new empty [VoxelWorld].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the amount of loaded chunks.
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Check if no chunk is loaded.
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Check if a chunk is loaded.
+    pub fn contains_chunk(&self, pos: &ChunkPos) -> bool {
+        self.chunks.contains_key(pos)
+    }
+
+    /// Get a loaded chunk.
+    pub fn chunk(&self, pos: &ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(pos)
+    }
+
+    /// Get a loaded chunk.
+    pub fn chunk_mut(&mut self, pos: &ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(pos)
+    }
+
+    /// Insert a chunk, return the chunk previously at this position.
+    pub fn insert_chunk(&mut self, pos: ChunkPos, chunk: Chunk) -> Option<Chunk> {
+        self.chunk_changed(pos);
+        self.chunks.insert(pos, chunk)
+    }
+
+    /// Remove a chunk from the world.
+    pub fn remove_chunk(&mut self, pos: &ChunkPos) -> Option<Chunk> {
+        let chunk = self.chunks.remove(pos);
+
+        if chunk.is_some() {
+            self.chunk_changed(*pos);
+        }
+
+        chunk
+    }
+
+    /// Iterate over the loaded chunks.
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    /// Iterate over the loaded chunks.
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item = (&ChunkPos, &mut Chunk)> {
+        self.chunks.iter_mut()
+    }
+
+    /// Get the voxel at world coordinates, [AIR] if its chunk
+    /// isn't loaded.
+    pub fn get_voxel(&self, x: i32, y: i32, z: i32) -> Voxel {
+        let (pos, (lx, ly, lz)) = split_voxel(x, y, z);
+
+        self.chunks
+            .get(&pos)
+            .map(|chunk| chunk.get(lx, ly, lz))
+            .unwrap_or(AIR)
+    }
+
+    /// Set the voxel at world coordinates, return the previous one.
+    ///
+    /// The chunk is created if it isn't loaded, setting [AIR] in
+    /// a chunk that isn't loaded does nothing.
+    pub fn set_voxel(&mut self, x: i32, y: i32, z: i32, voxel: Voxel) -> Voxel {
+        let (pos, (lx, ly, lz)) = split_voxel(x, y, z);
+
+        let previous = match self.chunks.get_mut(&pos) {
+            Some(chunk) => chunk.set(lx, ly, lz, voxel),
+            None if voxel == AIR => AIR,
+            None => self.chunks.entry(pos).or_default().set(lx, ly, lz, voxel),
+        };
+
+        if previous != voxel {
+            if let Some(changes) = self.changes.as_mut() {
+                changes.push(Vector3::new(x, y, z));
+            }
+
+            self.chunk_changed(pos);
+        }
+
+        previous
+    }
+
+    /// Enable or disable the tracking of the voxels changed by
+    /// [VoxelWorld::set_voxel] (e.g. to update the light of the
+    /// voxels around them). It is disabled by default.
+    pub fn set_change_tracking(&mut self, enabled: bool) {
+        match (enabled, self.changes.is_some()) {
+            (true, false) => self.changes = Some(Vec::new()),
+            (false, _) => self.changes = None,
+            _ => {},
+        }
+    }
+
+    /// Take the voxels changed since the last call, empty if the
+    /// changes aren't tracked.
+    pub fn take_changes(&mut self) -> Vec<Vector3<i32>> {
+        self.changes.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// Enable or disable the tracking of the chunks whose voxels
+    /// change, or that are inserted or removed (e.g. to mesh them
+    /// again). It is disabled by default.
+    ///
+    /// The voxels changed with [VoxelWorld::chunk_mut] aren't tracked.
+    pub fn set_chunk_change_tracking(&mut self, enabled: bool) {
+        match (enabled, self.chunk_changes.is_some()) {
+            (true, false) => self.chunk_changes = Some(HashSet::new()),
+            (false, _) => self.chunk_changes = None,
+            _ => {},
+        }
+    }
+
+    /// Take the chunks changed since the last call, empty if the
+    /// chunk changes aren't tracked.
+    pub fn take_chunk_changes(&mut self) -> Vec<ChunkPos> {
+        self.chunk_changes.as_mut().map(|changes| changes.drain().collect()).unwrap_or_default()
+    }
+
+    fn chunk_changed(&mut self, pos: ChunkPos) {
+        if let Some(changes) = self.chunk_changes.as_mut() {
+            changes.insert(pos);
+        }
+    }
+}
+
+impl VoxelQuery for VoxelWorld {
+    fn is_solid(&self, x: i32, y: i32, z: i32) -> bool {
+        self.get_voxel(x, y, z) != AIR
+    }
+
+    fn is_chunk_empty(&self, pos: ChunkPos) -> bool {
+        self.chunk(&pos).is_none_or(|chunk| chunk.is_empty())
+    }
+
+    fn is_loaded(&self, x: i32, y: i32, z: i32) -> bool {
+        self.contains_chunk(&ChunkPos::from_voxel(x, y, z))
+    }
+}
+
+/// The voxels as a step density (`1` if solid, `-1` otherwise), the
+/// surface is on the faces of the voxels until it is smoothed (see
+/// [IsosurfaceSettings](super::isosurface::IsosurfaceSettings)).
+impl DensityField for VoxelWorld {
+    fn density(&self, x: i32, y: i32, z: i32) -> f32 {
+        if self.get_voxel(x, y, z) != AIR { 1.0 } else { -1.0 }
+    }
+
+    fn material(&self, x: i32, y: i32, z: i32) -> Voxel {
+        // The smoothing can move the surface past the solid voxels,
+        // take the material of a solid neighbor.
+        [(0, 0, 0), (0, -1, 0), (-1, 0, 0), (1, 0, 0), (0, 0, -1), (0, 0, 1), (0, 1, 0)]
+            .into_iter()
+            .map(|(dx, dy, dz)| self.get_voxel(x + dx, y + dy, z + dz))
+            .find(|voxel| *voxel != AIR)
+            .unwrap_or(AIR)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_change_tracking() {
+        let mut world = VoxelWorld::new();
+        world.set_voxel(0, 0, 0, 1);
+        assert!(world.take_chunk_changes().is_empty());
+
+        world.set_chunk_change_tracking(true);
+        world.set_voxel(0, 0, 0, 1);
+        world.set_voxel(-1, 20, 0, AIR);
+        assert!(world.take_chunk_changes().is_empty());
+
+        world.set_voxel(1, 2, 3, 2);
+        world.set_voxel(4, 5, 6, 2);
+        world.set_voxel(-1, 20, 0, 3);
+        world.remove_chunk(&ChunkPos::new(5, 5, 5));
+
+        let mut changes = world.take_chunk_changes();
+        changes.sort();
+        assert_eq!(changes, [ChunkPos::new(-1, 1, 0), ChunkPos::new(0, 0, 0)]);
+        assert!(world.take_chunk_changes().is_empty());
+
+        world.insert_chunk(ChunkPos::new(2, 0, 0), Chunk::new());
+        world.remove_chunk(&ChunkPos::new(0, 0, 0));
+        assert_eq!(world.take_chunk_changes().len(), 2);
+    }
+}
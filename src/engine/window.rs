@@ -1,5 +1,7 @@
 use raw_window_handle::{HasRawWindowHandle, HasRawDisplayHandle};
-use winit::{event::{WindowEvent, KeyboardInput, ElementState, VirtualKeyCode, Event}, event_loop::ControlFlow};
+use winit::{event::{WindowEvent, KeyboardInput, ElementState, VirtualKeyCode, Event, StartCause}, event_loop::ControlFlow};
+
+use crate::engine::lifecycle::{LifecycleEvent, ExitResponse};
 
 
 pub struct Window {
@@ -19,6 +21,12 @@ unsafe impl HasRawDisplayHandle for Window {
     }
 }
 
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Window {
     /// Create a new [Window].
     pub fn new() -> Self {
@@ -40,15 +48,45 @@ impl Window {
         (size.width, size.height)
     }
 
-    pub fn run<C, R>(self, mut update_callback: C, mut resize_callback: R)
+    /// Run the window event loop. This method never returns.
+    /// 
+    /// # Arguments
+    /// 
+    /// * `update_callback` - Called each time the window must be redrawn.
+    /// * `resize_callback` - Called when the window is resized.
+    /// * `event_callback`      - Called for each event received by the window.
+    /// * `lifecycle_callback`  - Called for each [LifecycleEvent], the returned [ExitResponse]
+    ///   is only used for [LifecycleEvent::ExitRequested].
+    /// 
+    pub fn run<C, R, E, L>(self, mut update_callback: C, mut resize_callback: R, mut event_callback: E, mut lifecycle_callback: L)
     where
         C: FnMut() + 'static,
         R: FnMut((u32, u32)) + 'static,
+        E: FnMut(&WindowEvent) + 'static,
+        L: FnMut(LifecycleEvent) -> ExitResponse + 'static,
     {
+        // `true` while an exit request is pending (delayed by
+        // the application).
+        let mut exit_requested = false;
+
         self.event_loop.run(move |event, _, control_flow| {
             match event {
+                Event::NewEvents(StartCause::Init) => {
+                    lifecycle_callback(LifecycleEvent::Init);
+                },
+
+                Event::Suspended => {
+                    lifecycle_callback(LifecycleEvent::Suspended);
+                },
+
+                Event::Resumed => {
+                    lifecycle_callback(LifecycleEvent::Resumed);
+                },
+
                 Event::WindowEvent { ref event, window_id } => {
                     if window_id != self.window.id() { return; }
+
+                    event_callback(event);
                     
                     match event {
                         WindowEvent::CloseRequested | WindowEvent::KeyboardInput { 
@@ -58,7 +96,11 @@ impl Window {
                                 ..
                             }, 
                             .. 
-                        } => *control_flow = ControlFlow::Exit,
+                        } => exit_requested = true,
+
+                        WindowEvent::Focused(focused) => {
+                            lifecycle_callback(if *focused { LifecycleEvent::FocusGained } else { LifecycleEvent::FocusLost });
+                        },
 
                         WindowEvent::Resized(physical_size) => {
                             let w = physical_size.width;
@@ -85,9 +127,24 @@ impl Window {
                 },
 
                 Event::MainEventsCleared => {
+                    if exit_requested {
+                        match lifecycle_callback(LifecycleEvent::ExitRequested) {
+                            ExitResponse::Allow => {
+                                *control_flow = ControlFlow::Exit;
+                                return;
+                            },
+                            ExitResponse::Veto  => exit_requested = false,
+                            ExitResponse::Delay => {},
+                        }
+                    }
+
                     self.window.request_redraw();
                 },
 
+                Event::LoopDestroyed => {
+                    lifecycle_callback(LifecycleEvent::Shutdown);
+                },
+
                 _ => {}
             }
         });
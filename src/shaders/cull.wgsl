@@ -0,0 +1,64 @@
+// Frustum culling of a list of bounding boxes, the indices of the
+// visible boxes are written in a compacted list, preceded by the
+// arguments of an indirect dispatch (one workgroup of the consumer
+// per `group_size` visible boxes).
+
+struct Params {
+    planes      : array<vec4<f32>, 6>,
+    count       : u32,
+    group_size  : u32,
+    _padding    : vec2<u32>,
+};
+
+struct Bounds {
+    min : vec4<f32>,
+    max : vec4<f32>,
+};
+
+struct VisibleList {
+    dispatch_x  : atomic<u32>,
+    dispatch_y  : u32,
+    dispatch_z  : u32,
+    count       : atomic<u32>,
+    indices     : array<u32>,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+@group(0) @binding(1)
+var<storage, read> bounds: array<Bounds>;
+
+@group(0) @binding(2)
+var<storage, read_write> visible: VisibleList;
+
+fn is_visible(b: Bounds) -> bool {
+    for (var i = 0u; i < 6u; i = i + 1u) {
+        let plane = params.planes[i];
+
+        // The corner of the box the farthest along the plane normal.
+        let p = select(b.min.xyz, b.max.xyz, plane.xyz >= vec3<f32>(0.0));
+
+        if (dot(plane.xyz, p) + plane.w < 0.0) {
+            return false;
+        }
+    }
+
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+
+    if (index >= params.count || !is_visible(bounds[index])) {
+        return;
+    }
+
+    let slot = atomicAdd(&visible.count, 1u);
+    visible.indices[slot] = index;
+
+    if (slot % params.group_size == 0u) {
+        atomicAdd(&visible.dispatch_x, 1u);
+    }
+}
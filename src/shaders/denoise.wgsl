@@ -0,0 +1,241 @@
+// Reproject and denoise the G-buffer written by the raymarchers. The
+// `DenoiseParams` and `AtrousPass` structs are prepended by the
+// `Denoiser`, the `gbuffer` by the engine (see `gbuffer_wgsl()`). The
+// passes don't read the `globals` uniform, their camera matrices are
+// in the params.
+
+@group(0) @binding(0)
+var<uniform> params: DenoiseParams;
+
+// The G-buffer of the last frame.
+@group(1) @binding(1)
+var<storage, read_write> previous: array<GBufferPixel>;
+
+// The motion of each pixel since the last frame, in pixels.
+@group(1) @binding(2)
+var<storage, read_write> motion: array<vec2<f32>>;
+
+// The color of the last frame (rgb) and the amount of frames it
+// averages (a).
+@group(1) @binding(3)
+var<storage, read_write> history: array<vec4<f32>>;
+
+@group(1) @binding(4)
+var<storage, read_write> temporal: array<vec4<f32>>;
+
+// The input and the output of an iteration of the à-trous filter.
+@group(2) @binding(0)
+var<storage, read> filter_input: array<vec4<f32>>;
+
+@group(2) @binding(1)
+var<storage, read_write> filter_output: array<vec4<f32>>;
+
+@group(2) @binding(2)
+var<uniform> atrous: AtrousPass;
+
+// The minimum similarity of the normals of a surface and of its
+// history.
+let HISTORY_NORMAL_THRESHOLD: f32 = 0.9;
+
+fn pixel_index(pixel: vec2<i32>) -> u32 {
+    return u32(pixel.x) + u32(pixel.y) * params.size.x;
+}
+
+fn in_bounds(pixel: vec2<i32>) -> bool {
+    return all(pixel >= vec2<i32>(0)) && all(pixel < vec2<i32>(params.size));
+}
+
+fn is_sky(pixel: GBufferPixel) -> bool {
+    return all(pixel.normal == vec3<f32>(0.0));
+}
+
+// Get the point at a distance of the camera along the ray of a point
+// of the screen (in pixels).
+fn world_position(pixel: vec2<f32>, depth: f32) -> vec3<f32> {
+    var uv = (pixel / vec2<f32>(params.size)) * 2.0 - 1.0;
+    uv.y = 0.0 - uv.y;
+
+    let far_point = params.inv_proj_view * vec4<f32>(uv, 1.0, 1.0);
+    return params.camera_position + normalize(far_point.xyz / far_point.w - params.camera_position) * depth;
+}
+
+@compute
+@workgroup_size(8, 8)
+fn motion_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.size)) {
+        return;
+    }
+
+    let index = pixel_index(vec2<i32>(id.xy));
+    let center = vec2<f32>(id.xy) + 0.5;
+    let position = world_position(center, gbuffer[index].depth);
+
+    // Where the point was on the screen of the last frame.
+    let clip = params.previous_proj_view * vec4<f32>(position, 1.0);
+    var uv = clip.xy / clip.w;
+    uv.y = 0.0 - uv.y;
+
+    let previous_center = (uv * 0.5 + 0.5) * vec2<f32>(params.size);
+
+    // The points behind the last camera weren't on its screen.
+    motion[index] = select(vec2<f32>(1e9), center - previous_center, clip.w > 0.0);
+}
+
+@compute
+@workgroup_size(8, 8)
+fn temporal_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.size)) {
+        return;
+    }
+
+    let pixel = vec2<i32>(id.xy);
+    let index = pixel_index(pixel);
+    let current = gbuffer[index];
+
+    // The sky isn't noisy.
+    if (is_sky(current)) {
+        temporal[index] = vec4<f32>(current.color, 1.0);
+        return;
+    }
+
+    // The mean and the standard deviation of the colors around the
+    // pixel.
+    var sum = vec3<f32>(0.0);
+    var sum_squares = vec3<f32>(0.0);
+
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let neighbor = clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), vec2<i32>(params.size) - 1);
+            let color = gbuffer[pixel_index(neighbor)].color;
+
+            sum += color;
+            sum_squares += color * color;
+        }
+    }
+
+    let mean = sum / 9.0;
+    let deviation = sqrt(max(sum_squares / 9.0 - mean * mean, vec3<f32>(0.0)));
+
+    // The history is sampled bilinearly at the last position of the
+    // pixel, from the pixels that were on the same surface.
+    let position = world_position(vec2<f32>(pixel) + 0.5, current.depth);
+    let expected_depth = distance(params.previous_camera_position, position);
+
+    let previous_pixel = vec2<f32>(pixel) - motion[index];
+    let base = vec2<i32>(floor(previous_pixel));
+    let fraction = previous_pixel - floor(previous_pixel);
+
+    var history_color = vec3<f32>(0.0);
+    var history_frames = 0.0;
+    var weights = 0.0;
+
+    for (var i = 0; i < 4; i++) {
+        let offset = vec2<i32>(i & 1, i >> 1u);
+        let tap = base + offset;
+        let bilinear = select(1.0 - fraction, fraction, offset == vec2<i32>(1));
+
+        if (!in_bounds(tap)) {
+            continue;
+        }
+
+        let last = previous[pixel_index(tap)];
+
+        if (last.material != current.material
+            || dot(last.normal, current.normal) < HISTORY_NORMAL_THRESHOLD
+            || abs(last.depth - expected_depth) > params.depth_sigma * expected_depth) {
+            continue;
+        }
+
+        let weight = bilinear.x * bilinear.y;
+        let sample = history[pixel_index(tap)];
+
+        history_color += sample.xyz * weight;
+        history_frames += sample.w * weight;
+        weights += weight;
+    }
+
+    var frames = 1.0;
+
+    if (weights > 1e-3) {
+        // A history too far from the current colors is stale (e.g. a
+        // shadow moved), it is clamped to them.
+        let limit = deviation * params.clamp_sigma;
+        history_color = clamp(history_color / weights, mean - limit, mean + limit);
+        frames = min(history_frames / weights + 1.0, f32(params.max_history));
+    }
+
+    temporal[index] = vec4<f32>(mix(history_color, current.color, 1.0 / frames), frames);
+}
+
+// The weights of the B3 spline kernel of the à-trous filter.
+fn kernel_weight(offset: i32) -> f32 {
+    switch (abs(offset)) {
+        case 0: { return 0.375; }
+        case 1: { return 0.25; }
+        default: { return 0.0625; }
+    }
+}
+
+@compute
+@workgroup_size(8, 8)
+fn atrous_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.size)) {
+        return;
+    }
+
+    let pixel = vec2<i32>(id.xy);
+    let index = pixel_index(pixel);
+    let center = gbuffer[index];
+    let color = filter_input[index];
+
+    if (is_sky(center)) {
+        filter_output[index] = color;
+        return;
+    }
+
+    // The neighbors `step` pixels apart are only blurred if they are
+    // on the same surface.
+    let step = i32(atrous.step);
+    let depth_scale = 1.0 / max(params.depth_sigma * center.depth * f32(step), 1e-4);
+
+    var sum = vec3<f32>(0.0);
+    var weights = 0.0;
+
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let tap = pixel + vec2<i32>(x, y) * step;
+
+            if (!in_bounds(tap)) {
+                continue;
+            }
+
+            let neighbor = gbuffer[pixel_index(tap)];
+
+            if (neighbor.material != center.material) {
+                continue;
+            }
+
+            let normal_weight = pow(max(dot(neighbor.normal, center.normal), 0.0), params.normal_power);
+            let depth_weight = exp(-abs(neighbor.depth - center.depth) * depth_scale);
+            let weight = kernel_weight(x) * kernel_weight(y) * normal_weight * depth_weight;
+
+            sum += filter_input[pixel_index(tap)].xyz * weight;
+            weights += weight;
+        }
+    }
+
+    filter_output[index] = vec4<f32>(select(color.xyz, sum / weights, weights > 0.0), color.w);
+}
+
+@compute
+@workgroup_size(8, 8)
+fn history_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.size)) {
+        return;
+    }
+
+    let index = pixel_index(vec2<i32>(id.xy));
+
+    previous[index] = gbuffer[index];
+    history[index] = temporal[index];
+}
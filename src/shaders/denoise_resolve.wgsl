@@ -0,0 +1,22 @@
+// Write the denoised color into the render texture. The
+// `DenoiseParams` struct is prepended by the `Denoiser`.
+
+@group(0) @binding(0)
+var render_texture: texture_storage_2d<rgba8unorm, write>;
+
+@group(1) @binding(0)
+var<uniform> params: DenoiseParams;
+
+// The output of the last denoising pass.
+@group(1) @binding(1)
+var<storage, read> denoised: array<vec4<f32>>;
+
+@compute
+@workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.size)) {
+        return;
+    }
+
+    textureStore(render_texture, vec2<i32>(id.xy), vec4<f32>(denoised[id.x + id.y * params.size.x].xyz, 1.0));
+}
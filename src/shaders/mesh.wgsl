@@ -0,0 +1,53 @@
+// Draw the chunk meshes, the `globals` uniform is declared by the
+// engine (see `globals_wgsl()`).
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) material: u32,
+    @location(3) ao: f32,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) normal: vec3<f32>,
+    @location(1) @interpolate(flat) material: u32,
+    @location(2) ao: f32,
+};
+
+let LIGHT_DIRECTION: vec3<f32> = vec3<f32>(0.4, 1.0, 0.3);
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    var clip = globals.proj_view_matrix * vec4<f32>(in.position, 1.0);
+
+    // The camera depth is in [-1, 1], the rasterizer expects [0, 1].
+    clip.z = (clip.z + clip.w) * 0.5;
+
+    out.clip_position = clip;
+    out.normal = in.normal;
+    out.material = in.material;
+    out.ao = in.ao;
+
+    return out;
+}
+
+// A stable color for each material.
+fn material_color(material: u32) -> vec3<f32> {
+    var h = material * 0x9e3779b9u;
+    h = (h ^ (h >> 16u)) * 0x85ebca6bu;
+
+    let color = vec3<f32>(vec3<u32>(h & 0xffu, (h >> 8u) & 0xffu, (h >> 16u) & 0xffu)) / 255.0;
+
+    return 0.3 + color * 0.7;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let diffuse = 0.35 + 0.65 * max(dot(normalize(in.normal), normalize(LIGHT_DIRECTION)), 0.0);
+    let occlusion = 0.4 + 0.6 * in.ao;
+
+    return vec4<f32>(material_color(in.material) * diffuse * occlusion, 1.0);
+}
@@ -0,0 +1,38 @@
+// Tone map the radiance accumulated by `path_trace.wgsl` into the
+// render texture. The `PathTraceParams` struct is prepended by the
+// `PathTracer`.
+
+@group(0) @binding(0)
+var render_texture: texture_storage_2d<rgba8unorm, write>;
+
+@group(1) @binding(0)
+var<uniform> params: PathTraceParams;
+
+@group(1) @binding(1)
+var<storage, read_write> accumulation: array<vec4<f32>>;
+
+// The ACES filmic curve (fitted by Krzysztof Narkowicz).
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@compute
+@workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.output_size)) {
+        return;
+    }
+
+    // The accumulation is smaller than the render texture when it
+    // doesn't fit in the adapter limits.
+    let pixel = id.xy * params.size / params.output_size;
+    let radiance = accumulation[pixel.x + pixel.y * params.size.x].xyz;
+
+    textureStore(render_texture, vec2<i32>(id.xy), vec4<f32>(tone_map(radiance * params.exposure), 1.0));
+}
@@ -0,0 +1,148 @@
+// Trace paths through the voxels and accumulate their radiance.
+// The `PathTraceParams` struct is prepended by the `PathTracer`, the
+// `globals` uniform, the chunks, the materials and the secondary rays
+// by the engine (see `globals_wgsl()`, `chunks_wgsl()`,
+// `materials_wgsl()` and `shading_wgsl()`).
+
+@group(0) @binding(0)
+var<uniform> params: PathTraceParams;
+
+// The average radiance of each pixel (rgb), in linear HDR.
+@group(0) @binding(1)
+var<storage, read_write> accumulation: array<vec4<f32>>;
+
+// The paths that carry less than this are terminated at random.
+let RUSSIAN_ROULETTE_BOUNCE: u32 = 2u;
+
+fn sky_radiance(direction: vec3<f32>) -> vec3<f32> {
+    let height = clamp(direction.y, 0.0, 1.0);
+    let ground = 0.3 * smoothstep(-0.2, 0.0, direction.y);
+
+    return params.sky_color * select(ground, mix(0.6, 1.0, height), direction.y >= 0.0);
+}
+
+// Get the point of the screen (from -1 to 1) at a depth of the
+// camera (from -1 at the near plane to 1 at the far plane).
+fn unproject(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+    let position = globals.inv_proj_view_matrix * vec4<f32>(uv, depth, 1.0);
+    return position.xyz / position.w;
+}
+
+// Get the point where a ray leaves the voxel it hit.
+fn exit_voxel(origin: vec3<f32>, direction: vec3<f32>, hit: VoxelHit) -> vec3<f32> {
+    let position = origin + direction * hit.distance;
+    let bounds = vec3<f32>(hit.voxel) + select(vec3<f32>(0.0), vec3<f32>(1.0), direction > vec3<f32>(0.0));
+    let exits = select(vec3<f32>(1e30), (bounds - position) / direction, direction != vec3<f32>(0.0));
+
+    return position + direction * min(exits.x, min(exits.y, exits.z));
+}
+
+fn trace_path(ray_pos: vec3<f32>, ray_dir: vec3<f32>, seed: ptr<function, u32>) -> vec3<f32> {
+    let sun = normalize(globals.shading.sun_direction);
+
+    var origin = ray_pos;
+    var direction = ray_dir;
+    var throughput = vec3<f32>(1.0);
+    var radiance = vec3<f32>(0.0);
+
+    for (var bounce = 0u; bounce <= params.max_bounces; bounce++) {
+        let hit = trace_voxels(origin, direction, globals.far);
+
+        if (!hit.hit) {
+            radiance += throughput * sky_radiance(direction);
+            break;
+        }
+
+        // The path starts in a voxel.
+        if (all(hit.normal == vec3<f32>(0.0))) {
+            break;
+        }
+
+        var material = material_at(hit.value);
+        let position = origin + direction * hit.distance;
+
+        radiance += throughput * material.emission;
+        material.emission = vec3<f32>(0.0);
+
+        // The light going through a transparent voxel continues
+        // straight, tinted by its albedo.
+        if (random_f32(seed) < material.transparency) {
+            throughput *= material.albedo;
+            origin = exit_voxel(origin, direction, hit) + direction * SHADING_BIAS;
+            continue;
+        }
+
+        // The direct light of the sun (soft with the shadow settings).
+        if (dot(hit.normal, sun) > 0.0) {
+            let u = vec2<f32>(random_f32(seed), random_f32(seed));
+            let light = sample_cone(sun, globals.shading.shadow_softness, u);
+            let visibility = transmittance(position + hit.normal * SHADING_BIAS, light, globals.far);
+
+            if (visibility > 0.0) {
+                radiance += throughput * shade_material(material, hit.normal, -direction, light, params.sun_radiance * visibility);
+            }
+        }
+
+        // The next direction: a cosine weighted diffuse bounce, or a
+        // glossy reflection for the metals.
+        let u = vec2<f32>(random_f32(seed), random_f32(seed));
+        let diffuse = sample_cosine_hemisphere(hit.normal, u);
+
+        if (random_f32(seed) < material.metalness) {
+            let reflected = reflect(direction, hit.normal);
+            direction = normalize(mix(reflected, diffuse, material.roughness * material.roughness));
+
+            if (dot(direction, hit.normal) <= 0.0) {
+                direction = diffuse;
+            }
+        } else {
+            direction = diffuse;
+        }
+
+        throughput *= material.albedo;
+        origin = position + hit.normal * SHADING_BIAS;
+
+        if (bounce >= RUSSIAN_ROULETTE_BOUNCE) {
+            let survival = clamp(max(throughput.x, max(throughput.y, throughput.z)), 0.05, 1.0);
+
+            if (random_f32(seed) > survival) {
+                break;
+            }
+
+            throughput /= survival;
+        }
+    }
+
+    return radiance;
+}
+
+@compute
+@workgroup_size(8, 8)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (any(id.xy >= params.size)) {
+        return;
+    }
+
+    var seed = random_seed(id.xy, hash_u32(params.sample_count) ^ globals.frame);
+    var radiance = vec3<f32>(0.0);
+
+    for (var i = 0u; i < params.samples_per_frame; i++) {
+        // A random point in the pixel, to antialias the edges.
+        let jitter = vec2<f32>(random_f32(&seed), random_f32(&seed));
+        var uv = ((vec2<f32>(id.xy) + jitter) / vec2<f32>(params.size)) * 2.0 - 1.0;
+        uv.y = 0.0 - uv.y;
+
+        let ray_pos = unproject(uv, -1.0);
+        let ray_dir = normalize(unproject(uv, 1.0) - ray_pos);
+
+        radiance += trace_path(ray_pos, ray_dir, &seed);
+    }
+
+    // Add the samples of the frame to the running average.
+    let index = id.x + id.y * params.size.x;
+    let count = f32(params.sample_count);
+    let samples = f32(params.samples_per_frame);
+    let average = select(accumulation[index].xyz, vec3<f32>(0.0), params.sample_count == 0u);
+
+    accumulation[index] = vec4<f32>((average * count + radiance) / (count + samples), 1.0);
+}
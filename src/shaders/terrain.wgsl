@@ -0,0 +1,278 @@
+// A translation of src/engine/voxel/terrain.rs, every function must
+// give exactly the same result as its CPU version. The `TerrainParams`
+// struct and the constants (`CHUNK_SIZE`, `CHUNK_WORDS`, the fixed
+// point `ONE`, the noise layers, the materials and the biomes) are
+// prepended by the `GpuTerrainGenerator`.
+
+@group(0) @binding(0)
+var<uniform> params: TerrainParams;
+
+// The chunks to generate (xyz, w is unused).
+@group(0) @binding(1)
+var<storage, read> requests: array<vec4<i32>>;
+
+// The generated chunks, the voxels are packed two by two.
+@group(0) @binding(2)
+var<storage, read_write> output: array<u32>;
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    var h = seed ^ 0x9e3779b9u;
+    h = (h ^ u32(x)) * 0x85ebca6bu;
+    h = h ^ (h >> 13u);
+    h = (h ^ u32(y)) * 0xc2b2ae35u;
+    h = h ^ (h >> 16u);
+    h = (h ^ u32(z)) * 0x27d4eb2fu;
+    h = h ^ (h >> 15u);
+    h = h * 0x165667b1u;
+    return h ^ (h >> 16u);
+}
+
+// Return the noise cell and the fixed point position in the cell.
+fn cell(v: i32, scale: i32, period: i32) -> vec2<i32> {
+    let n = v * scale;
+
+    // The remainder of the division, the `%` of a negative `i32`
+    // is computed as unsigned by some backends.
+    var r = n - (n / period) * period;
+
+    if (r < 0) {
+        r = r + period;
+    }
+
+    return vec2<i32>((n - r) / period, (r << FRACTION_BITS) / period);
+}
+
+fn fade(t: i32) -> i32 {
+    let t2 = (t * t) >> FRACTION_BITS;
+    let t3 = (t2 * t) >> FRACTION_BITS;
+
+    return (t3 * (6 * t2 - 15 * t + 10 * ONE)) >> FRACTION_BITS;
+}
+
+fn lerp(a: i32, b: i32, t: i32) -> i32 {
+    return a + (((b - a) * t) >> FRACTION_BITS);
+}
+
+fn grad2(h: u32, x: i32, y: i32) -> i32 {
+    switch (h & 7u) {
+        case 0u: { return x; }
+        case 1u: { return -x; }
+        case 2u: { return y; }
+        case 3u: { return -y; }
+        case 4u: { return x + y; }
+        case 5u: { return -x + y; }
+        case 6u: { return x - y; }
+        default: { return -x - y; }
+    }
+}
+
+fn grad3(h: u32, x: i32, y: i32, z: i32) -> i32 {
+    switch (h & 15u) {
+        case 0u, 12u: { return x + y; }
+        case 1u, 13u: { return -x + y; }
+        case 2u: { return x - y; }
+        case 3u: { return -x - y; }
+        case 4u: { return x + z; }
+        case 5u: { return -x + z; }
+        case 6u: { return x - z; }
+        case 7u: { return -x - z; }
+        case 8u: { return y + z; }
+        case 9u, 14u: { return -y + z; }
+        case 10u: { return y - z; }
+        default: { return -y - z; }
+    }
+}
+
+fn noise2(x: i32, z: i32, seed: u32, period: i32, scale: i32) -> i32 {
+    let cx = cell(x, scale, period);
+    let cz = cell(z, scale, period);
+    let fx = cx.y;
+    let fz = cz.y;
+
+    let n00 = grad2(hash(cx.x, 0, cz.x, seed), fx, fz);
+    let n10 = grad2(hash(cx.x + 1, 0, cz.x, seed), fx - ONE, fz);
+    let n01 = grad2(hash(cx.x, 0, cz.x + 1, seed), fx, fz - ONE);
+    let n11 = grad2(hash(cx.x + 1, 0, cz.x + 1, seed), fx - ONE, fz - ONE);
+
+    let u = fade(fx);
+    let v = fade(fz);
+
+    return lerp(lerp(n00, n10, u), lerp(n01, n11, u), v);
+}
+
+fn fbm2(x: i32, z: i32, seed: u32, period: i32, octaves: u32) -> i32 {
+    var value = 0;
+
+    for (var octave = 0u; octave < octaves; octave = octave + 1u) {
+        let octave_seed = seed + octave * 0x68e31da4u;
+        value = value + (noise2(x, z, octave_seed, period, 1 << octave) >> octave);
+    }
+
+    return value;
+}
+
+fn noise3(x: i32, y: i32, z: i32, seed: u32, period: i32) -> i32 {
+    let cx = cell(x, 1, period);
+    let cy = cell(y, 1, period);
+    let cz = cell(z, 1, period);
+    let fx = cx.y;
+    let fy = cy.y;
+    let fz = cz.y;
+
+    let n000 = grad3(hash(cx.x, cy.x, cz.x, seed), fx, fy, fz);
+    let n100 = grad3(hash(cx.x + 1, cy.x, cz.x, seed), fx - ONE, fy, fz);
+    let n010 = grad3(hash(cx.x, cy.x + 1, cz.x, seed), fx, fy - ONE, fz);
+    let n110 = grad3(hash(cx.x + 1, cy.x + 1, cz.x, seed), fx - ONE, fy - ONE, fz);
+    let n001 = grad3(hash(cx.x, cy.x, cz.x + 1, seed), fx, fy, fz - ONE);
+    let n101 = grad3(hash(cx.x + 1, cy.x, cz.x + 1, seed), fx - ONE, fy, fz - ONE);
+    let n011 = grad3(hash(cx.x, cy.x + 1, cz.x + 1, seed), fx, fy - ONE, fz - ONE);
+    let n111 = grad3(hash(cx.x + 1, cy.x + 1, cz.x + 1, seed), fx - ONE, fy - ONE, fz - ONE);
+
+    let u = fade(fx);
+    let v = fade(fy);
+    let w = fade(fz);
+
+    return lerp(
+        lerp(lerp(n000, n100, u), lerp(n010, n110, u), v),
+        lerp(lerp(n001, n101, u), lerp(n011, n111, u), v),
+        w,
+    );
+}
+
+fn height(x: i32, z: i32) -> i32 {
+    let noise = fbm2(x, z, params.seed + HEIGHT_LAYER, params.height_period, params.octaves);
+
+    return params.base_height + ((noise * params.height_amplitude) >> FRACTION_BITS);
+}
+
+fn biome(x: i32, z: i32) -> u32 {
+    let temperature = fbm2(x, z, params.seed + TEMPERATURE_LAYER, params.biome_period, 2u);
+    let moisture = fbm2(x, z, params.seed + MOISTURE_LAYER, params.biome_period, 2u);
+
+    if (temperature < -ONE / 4) {
+        return BIOME_SNOW;
+    } else if (temperature > ONE / 4 && moisture < 0) {
+        return DESERT;
+    } else if (moisture > ONE / 8) {
+        return FOREST;
+    }
+
+    return PLAINS;
+}
+
+fn surface(column_biome: u32) -> u32 {
+    if (column_biome == DESERT) {
+        return SAND;
+    } else if (column_biome == BIOME_SNOW) {
+        return SNOW;
+    }
+
+    return GRASS;
+}
+
+fn subsurface(column_biome: u32) -> u32 {
+    if (column_biome == DESERT) {
+        return SAND;
+    }
+
+    return DIRT;
+}
+
+fn tree(x: i32, z: i32) -> i32 {
+    let h = hash(x, 0, z, params.seed + TREE_LAYER);
+
+    if (params.tree_spacing == 0u || h % params.tree_spacing != 0u) {
+        return 0;
+    }
+
+    if (biome(x, z) == FOREST) {
+        return 4 + i32((h >> 16u) % 3u);
+    }
+
+    return 0;
+}
+
+fn feature(x: i32, y: i32, z: i32, surface_height: i32) -> u32 {
+    let trunk = tree(x, z);
+
+    if (trunk > 0 && y <= surface_height + trunk) {
+        return WOOD;
+    }
+
+    for (var dz = -2; dz <= 2; dz = dz + 1) {
+        for (var dx = -2; dx <= 2; dx = dx + 1) {
+            let neighbor_trunk = tree(x + dx, z + dz);
+
+            if (neighbor_trunk == 0) {
+                continue;
+            }
+
+            let top = height(x + dx, z + dz) + neighbor_trunk;
+            let dy = y - top;
+
+            if (dy >= -1 && abs(dx) + abs(dz) + abs(dy) <= 2) {
+                return LEAVES;
+            }
+        }
+    }
+
+    return AIR;
+}
+
+fn voxel(x: i32, y: i32, z: i32) -> u32 {
+    let surface_height = height(x, z);
+
+    if (y > surface_height) {
+        return feature(x, y, z, surface_height);
+    }
+
+    let cave = noise3(x, y, z, params.seed + CAVE_LAYER, params.cave_period);
+
+    if (cave > params.cave_threshold) {
+        return AIR;
+    }
+
+    let depth = surface_height - y;
+
+    if (depth < params.soil_depth) {
+        let column_biome = biome(x, z);
+
+        if (depth == 0) {
+            return surface(column_biome);
+        }
+
+        return subsurface(column_biome);
+    }
+
+    let ore = hash(x, y, z, params.seed + ORE_LAYER) % 1000u;
+
+    if (ore < params.coal_chance) {
+        return COAL_ORE;
+    } else if (y <= params.iron_max_y && ore < params.coal_chance + params.iron_chance) {
+        return IRON_ORE;
+    }
+
+    return STONE;
+}
+
+// One invocation per word, so two voxels along the x axis.
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let word = id.x;
+    let chunk = word / CHUNK_WORDS;
+
+    if (chunk >= params.chunk_count) {
+        return;
+    }
+
+    let index = (word % CHUNK_WORDS) * 2u;
+    let size = u32(CHUNK_SIZE);
+    let local = vec3<i32>(vec3<u32>(index % size, index / (size * size), (index / size) % size));
+    let origin = requests[chunk].xyz * CHUNK_SIZE;
+    let position = origin + local;
+
+    let low = voxel(position.x, position.y, position.z);
+    let high = voxel(position.x + 1, position.y, position.z);
+
+    output[word] = low | (high << 16u);
+}